@@ -0,0 +1,67 @@
+#ifndef MATCHED_DATA_H
+#define MATCHED_DATA_H
+
+/* Generated with cbindgen from matched-data-ffi/src/lib.rs, do not edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Length in bytes of private and public keys
+ */
+#define MD_KEY_LENGTH 32
+
+/**
+ * Result codes returned by the functions of this library
+ */
+typedef enum MdResult {
+  MD_RESULT_OK = 0,
+  MD_RESULT_NULL_POINTER = 1,
+  MD_RESULT_INVALID_PRIVATE_KEY = 2,
+  MD_RESULT_INVALID_MATCHED_DATA = 3,
+  MD_RESULT_UNSUPPORTED_VERSION = 4,
+  MD_RESULT_DECRYPTION_FAILED = 5,
+} MdResult;
+
+/**
+ * Generates a public-private key pair, writing `MD_KEY_LENGTH` bytes to each buffer.
+ *
+ * # Safety
+ *
+ * `private_key` and `public_key` must point to buffers of at least `MD_KEY_LENGTH` bytes.
+ */
+enum MdResult md_generate_keypair(uint8_t *private_key, uint8_t *public_key);
+
+/**
+ * Decrypts matched data, already base64 decoded, with a private key of `MD_KEY_LENGTH` bytes.
+ *
+ * On success `*plaintext` points to `*plaintext_len` bytes that must be released with `md_free`.
+ *
+ * # Safety
+ *
+ * `private_key` and `matched_data` must point to buffers of the given lengths, and
+ * `plaintext` and `plaintext_len` must be valid for writes.
+ */
+enum MdResult md_decrypt(const uint8_t *private_key,
+                         size_t private_key_len,
+                         const uint8_t *matched_data,
+                         size_t matched_data_len,
+                         uint8_t **plaintext,
+                         size_t *plaintext_len);
+
+/**
+ * Wipes and releases plaintext returned by `md_decrypt`.
+ *
+ * # Safety
+ *
+ * `plaintext` and `plaintext_len` must be the values returned by `md_decrypt`, released only once.
+ */
+void md_free(uint8_t *plaintext, size_t plaintext_len);
+
+/**
+ * Returns a static, NUL terminated description of a result code, or of an unknown error for
+ * values that aren't an `MdResult`.
+ */
+const char *md_result_message(int result);
+
+#endif /* MATCHED_DATA_H */
@@ -0,0 +1,191 @@
+#![warn(rust_2018_idioms)]
+
+use hpke::Serializable;
+use matched_data_cli::matched_data::{
+    decrypt_any_version, generate_key_pair, DecryptAnyVersionError,
+};
+use std::os::raw::{c_char, c_int};
+use std::{ptr, slice};
+use zeroize::Zeroize;
+
+/// Length in bytes of private and public keys
+pub const MD_KEY_LENGTH: usize = 32;
+
+/// Result codes returned by the functions of this library
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MdResult {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidPrivateKey = 2,
+    InvalidMatchedData = 3,
+    UnsupportedVersion = 4,
+    DecryptionFailed = 5,
+}
+
+/// Generates a public-private key pair, writing `MD_KEY_LENGTH` bytes to each buffer.
+///
+/// # Safety
+///
+/// `private_key` and `public_key` must point to buffers of at least `MD_KEY_LENGTH` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn md_generate_keypair(
+    private_key: *mut u8,
+    public_key: *mut u8,
+) -> MdResult {
+    if private_key.is_null() || public_key.is_null() {
+        return MdResult::NullPointer;
+    }
+
+    let (generated_private_key, generated_public_key) = generate_key_pair();
+    ptr::copy_nonoverlapping(
+        generated_private_key.to_bytes().as_ptr(),
+        private_key,
+        MD_KEY_LENGTH,
+    );
+    ptr::copy_nonoverlapping(
+        generated_public_key.to_bytes().as_ptr(),
+        public_key,
+        MD_KEY_LENGTH,
+    );
+
+    MdResult::Ok
+}
+
+/// Decrypts matched data, already base64 decoded, with a private key of `MD_KEY_LENGTH` bytes.
+///
+/// On success `*plaintext` points to `*plaintext_len` bytes that must be released with `md_free`.
+///
+/// # Safety
+///
+/// `private_key` and `matched_data` must point to buffers of the given lengths, and
+/// `plaintext` and `plaintext_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn md_decrypt(
+    private_key: *const u8,
+    private_key_len: usize,
+    matched_data: *const u8,
+    matched_data_len: usize,
+    plaintext: *mut *mut u8,
+    plaintext_len: *mut usize,
+) -> MdResult {
+    if private_key.is_null()
+        || matched_data.is_null()
+        || plaintext.is_null()
+        || plaintext_len.is_null()
+    {
+        return MdResult::NullPointer;
+    }
+
+    let private_key_bytes = slice::from_raw_parts(private_key, private_key_len);
+    let matched_data_bytes = slice::from_raw_parts(matched_data, matched_data_len);
+
+    match decrypt_any_version(private_key_bytes, matched_data_bytes) {
+        Ok(decrypted) => {
+            // Copied into an exactly sized allocation, the decrypted buffer is wiped when dropped
+            let boxed_plaintext: Box<[u8]> = decrypted.as_slice().into();
+            *plaintext_len = boxed_plaintext.len();
+            *plaintext = Box::into_raw(boxed_plaintext) as *mut u8;
+            MdResult::Ok
+        }
+        Err(DecryptAnyVersionError::UnsupportedVersion(_)) => MdResult::UnsupportedVersion,
+        Err(DecryptAnyVersionError::InvalidPrivateKey(_)) => MdResult::InvalidPrivateKey,
+        // Only returned in auth mode, which the C bindings don't expose
+        Err(DecryptAnyVersionError::InvalidSenderPublicKey(_)) => MdResult::DecryptionFailed,
+        Err(DecryptAnyVersionError::Empty | DecryptAnyVersionError::InvalidMatchedData(_)) => {
+            MdResult::InvalidMatchedData
+        }
+        Err(DecryptAnyVersionError::Decrypt { .. }) => MdResult::DecryptionFailed,
+    }
+}
+
+/// Wipes and releases plaintext returned by `md_decrypt`.
+///
+/// # Safety
+///
+/// `plaintext` and `plaintext_len` must be the values returned by `md_decrypt`, released only once.
+#[no_mangle]
+pub unsafe extern "C" fn md_free(plaintext: *mut u8, plaintext_len: usize) {
+    if !plaintext.is_null() {
+        Box::from_raw(ptr::slice_from_raw_parts_mut(plaintext, plaintext_len)).zeroize();
+    }
+}
+
+/// Returns a static, NUL terminated description of a result code, or of an unknown error for
+/// values that aren't an `MdResult`.
+#[no_mangle]
+pub extern "C" fn md_result_message(result: c_int) -> *const c_char {
+    let message: &'static [u8] = match result {
+        0 => b"Success\0",
+        1 => b"A required pointer is null\0",
+        2 => b"Provided private key is invalid\0",
+        3 => b"Provided matched data is invalid\0",
+        4 => b"Encryption format not supported\0",
+        5 => b"Failed to decrypt matched data\0",
+        _ => b"Unknown error\0",
+    };
+
+    message.as_ptr() as *const c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md_decrypt() {
+        let private_key = radix64::STD
+            .decode("uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=")
+            .unwrap();
+        let matched_data = radix64::STD
+            .decode("AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=")
+            .unwrap();
+
+        let mut plaintext = ptr::null_mut();
+        let mut plaintext_len = 0;
+        let result = unsafe {
+            md_decrypt(
+                private_key.as_ptr(),
+                private_key.len(),
+                matched_data.as_ptr(),
+                matched_data.len(),
+                &mut plaintext,
+                &mut plaintext_len,
+            )
+        };
+
+        assert_eq!(MdResult::Ok, result);
+        assert_eq!(b"test matched data", unsafe {
+            slice::from_raw_parts(plaintext, plaintext_len)
+        });
+        unsafe { md_free(plaintext, plaintext_len) };
+
+        let result = unsafe {
+            md_decrypt(
+                private_key.as_ptr(),
+                private_key.len(),
+                matched_data.as_ptr(),
+                0,
+                &mut plaintext,
+                &mut plaintext_len,
+            )
+        };
+        assert_eq!(MdResult::InvalidMatchedData, result);
+    }
+
+    #[test]
+    fn test_md_result_message() {
+        let message = |result| {
+            unsafe { std::ffi::CStr::from_ptr(md_result_message(result)) }
+                .to_str()
+                .unwrap()
+        };
+
+        assert_eq!(
+            "Failed to decrypt matched data",
+            message(MdResult::DecryptionFailed as c_int)
+        );
+        assert_eq!("Unknown error", message(42));
+        assert_eq!("Unknown error", message(-1));
+    }
+}
@@ -0,0 +1,75 @@
+#![warn(rust_2018_idioms)]
+
+use hpke::Serializable;
+use matched_data_cli::matched_data::{decrypt_any_version, generate_key_pair as generate};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use zeroize::Zeroizing;
+
+/// Generates a public-private key pair, returned as a tuple of raw private and public key bytes.
+#[pyfunction]
+fn generate_key_pair(py: Python<'_>) -> (Bound<'_, PyBytes>, Bound<'_, PyBytes>) {
+    let (private_key, public_key) = generate();
+
+    (
+        PyBytes::new(py, &private_key.to_bytes()),
+        PyBytes::new(py, &public_key.to_bytes()),
+    )
+}
+
+/// Decrypts matched data, already base64 decoded, with raw private key bytes.
+///
+/// Raises ValueError if the private key or matched data is invalid or decryption fails. The
+/// plaintext is wiped from Rust memory once copied into the returned bytes object, which Python
+/// can't wipe.
+#[pyfunction]
+fn decrypt<'py>(
+    py: Python<'py>,
+    private_key: &[u8],
+    matched_data: &[u8],
+) -> PyResult<Bound<'py, PyBytes>> {
+    let plaintext =
+        decrypt_matched_data(private_key, matched_data).map_err(PyValueError::new_err)?;
+
+    Ok(PyBytes::new(py, &plaintext))
+}
+
+// Decrypts matched data bytes according to their encryption format version
+fn decrypt_matched_data(
+    private_key_bytes: &[u8],
+    matched_data_bytes: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, String> {
+    decrypt_any_version(private_key_bytes, matched_data_bytes).map_err(|e| e.to_string())
+}
+
+/// Decrypts firewall matched data.
+#[pymodule]
+#[pyo3(name = "matched_data")]
+fn matched_data(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(generate_key_pair, module)?)?;
+    module.add_function(wrap_pyfunction!(decrypt, module)?)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypt_matched_data() {
+        let private_key = radix64::STD
+            .decode("uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=")
+            .unwrap();
+        let matched_data = radix64::STD
+            .decode("AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=")
+            .unwrap();
+
+        assert_eq!(
+            b"test matched data".to_vec(),
+            *decrypt_matched_data(&private_key, &matched_data).unwrap()
+        );
+        assert!(decrypt_matched_data(&private_key, &[]).is_err());
+    }
+}
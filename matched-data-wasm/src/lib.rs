@@ -0,0 +1,112 @@
+#![warn(rust_2018_idioms)]
+
+use hpke::Serializable;
+use js_sys::Uint8Array;
+use matched_data_cli::matched_data::{
+    decrypt_any_version, encrypt_data, get_private_key_from_bytes, get_public_key,
+    get_public_key_from_bytes, serialize_encrypted_data,
+};
+use wasm_bindgen::prelude::*;
+use zeroize::Zeroizing;
+
+/// Decrypts matched data, already base64 decoded, with raw private key bytes.
+///
+/// Throws an Error if the private key or matched data is invalid or decryption fails. The
+/// plaintext is wiped from WebAssembly memory once copied into the returned Uint8Array, which
+/// JavaScript can't wipe.
+#[wasm_bindgen]
+pub fn decrypt(private_key: &[u8], matched_data: &[u8]) -> Result<Uint8Array, JsError> {
+    let plaintext =
+        decrypt_matched_data(private_key, matched_data).map_err(|e| JsError::new(&e))?;
+
+    Ok(Uint8Array::from(plaintext.as_slice()))
+}
+
+/// Encrypts a plaintext to raw public key bytes, returning serialized matched data in the format
+/// logged by Cloudflare, not base64 encoded, to generate test ciphertexts client-side.
+///
+/// Throws an Error if the public key is invalid.
+#[wasm_bindgen]
+pub fn encrypt(public_key: &[u8], plaintext: &[u8]) -> Result<Uint8Array, JsError> {
+    let matched_data = encrypt_matched_data(public_key, plaintext).map_err(|e| JsError::new(&e))?;
+
+    Ok(Uint8Array::from(matched_data.as_slice()))
+}
+
+/// Returns the raw public key bytes of raw private key bytes, to check that a private key matches
+/// the public key configured on a zone.
+///
+/// Throws an Error if the private key is invalid.
+#[wasm_bindgen]
+pub fn public_key_from_private(private_key: &[u8]) -> Result<Uint8Array, JsError> {
+    let public_key = derive_public_key(private_key).map_err(|e| JsError::new(&e))?;
+
+    Ok(Uint8Array::from(public_key.as_slice()))
+}
+
+// Decrypts matched data bytes according to their encryption format version
+fn decrypt_matched_data(
+    private_key_bytes: &[u8],
+    matched_data_bytes: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, String> {
+    decrypt_any_version(private_key_bytes, matched_data_bytes).map_err(|e| e.to_string())
+}
+
+// Encrypts plaintext bytes with the current encryption format version
+fn encrypt_matched_data(public_key_bytes: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let public_key = get_public_key_from_bytes(public_key_bytes)
+        .map_err(|_| "Provided public key is invalid".to_string())?;
+    let encrypted_data =
+        encrypt_data(plaintext, &public_key).map_err(|_| "Failed to encrypt matched data")?;
+
+    Ok(serialize_encrypted_data(&encrypted_data))
+}
+
+// Public key bytes of private key bytes
+fn derive_public_key(private_key_bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let private_key = get_private_key_from_bytes(private_key_bytes)
+        .map_err(|_| "Provided private key is invalid".to_string())?;
+
+    Ok(get_public_key(&private_key).to_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypt_matched_data() {
+        let private_key = radix64::STD
+            .decode("uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=")
+            .unwrap();
+        let matched_data = radix64::STD
+            .decode("AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=")
+            .unwrap();
+
+        assert_eq!(
+            b"test matched data".to_vec(),
+            *decrypt_matched_data(&private_key, &matched_data).unwrap()
+        );
+        assert!(decrypt_matched_data(&private_key, &[]).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_matched_data() {
+        let private_key = radix64::STD
+            .decode("uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=")
+            .unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        assert_eq!(
+            "Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=",
+            radix64::STD.encode(&public_key)
+        );
+        assert!(derive_public_key(&[1; 3]).is_err());
+
+        let matched_data = encrypt_matched_data(&public_key, b"test matched data").unwrap();
+        assert_eq!(
+            b"test matched data".to_vec(),
+            *decrypt_matched_data(&private_key, &matched_data).unwrap()
+        );
+        assert!(encrypt_matched_data(&[1; 3], b"test matched data").is_err());
+    }
+}
@@ -0,0 +1,68 @@
+// Page served by matched-data-cli serve-ui, decrypting matched data in the browser with the
+// WebAssembly module so the private key is never sent anywhere
+import init, { decrypt } from "/pkg/matched_data_wasm.js";
+
+const privateKeyInput = document.getElementById("private-key");
+const matchedDataInput = document.getElementById("matched-data");
+const decryptButton = document.getElementById("decrypt");
+const clearButton = document.getElementById("clear");
+const plaintextOutput = document.getElementById("plaintext");
+const errorOutput = document.getElementById("error");
+
+// Decodes standard or URL-safe base64, ignoring whitespace
+function decodeBase64(base64) {
+  const standard = base64.replace(/\s/g, "").replace(/-/g, "+").replace(/_/g, "/");
+  return Uint8Array.from(atob(standard), (c) => c.charCodeAt(0));
+}
+
+// Reads the private key file each time, so the key isn't kept in the page between decryptions
+async function readPrivateKey() {
+  const file = privateKeyInput.files[0];
+  if (file === undefined) {
+    throw new Error("Select the private key file");
+  }
+  try {
+    return decodeBase64(await file.text());
+  } catch {
+    throw new Error("Private key is not base64 encoded");
+  }
+}
+
+decryptButton.addEventListener("click", async () => {
+  plaintextOutput.textContent = "";
+  errorOutput.textContent = "";
+
+  let privateKey;
+  try {
+    privateKey = await readPrivateKey();
+    let matchedData;
+    try {
+      matchedData = decodeBase64(matchedDataInput.value);
+    } catch {
+      throw new Error("Matched data is not base64 encoded");
+    }
+    const plaintext = decrypt(privateKey, matchedData);
+    plaintextOutput.textContent = new TextDecoder().decode(plaintext);
+    plaintext.fill(0);
+  } catch (e) {
+    errorOutput.textContent = e.message;
+  } finally {
+    privateKey?.fill(0);
+  }
+});
+
+clearButton.addEventListener("click", () => {
+  privateKeyInput.value = "";
+  matchedDataInput.value = "";
+  plaintextOutput.textContent = "";
+  errorOutput.textContent = "";
+});
+
+init().then(
+  () => {
+    decryptButton.disabled = false;
+  },
+  () => {
+    errorOutput.textContent = "Failed to load the WebAssembly module";
+  }
+);
@@ -0,0 +1,62 @@
+// Example Worker enriching events with their decrypted matched data. POST a Logpush event, or an
+// array of them, as JSON and each event with encrypted matched data gets a decrypted_matched_data
+// field. The private key is a secret, set with: wrangler secret put MATCHED_DATA_PRIVATE_KEY.
+// Anyone who can reach the Worker can decrypt, so only expose it through a service binding or
+// behind Cloudflare Access
+import { decryptToString } from "./matched_data.js";
+
+// Key of the encrypted matched data, nested in the event such as under Metadata
+const ENCRYPTED_MATCHED_DATA = "encrypted_matched_data";
+
+// Value logged instead of matched data too large to be logged
+const TRUNCATED = "truncated";
+
+// Finds the first encrypted matched data in an event, at any depth
+function findEncryptedMatchedData(value) {
+  if (value === null || typeof value !== "object") {
+    return undefined;
+  }
+  if (typeof value[ENCRYPTED_MATCHED_DATA] === "string") {
+    return value[ENCRYPTED_MATCHED_DATA];
+  }
+  for (const child of Object.values(value)) {
+    const found = findEncryptedMatchedData(child);
+    if (found !== undefined) {
+      return found;
+    }
+  }
+  return undefined;
+}
+
+function enrich(event, privateKey) {
+  const encrypted = findEncryptedMatchedData(event);
+  if (encrypted === undefined || encrypted === TRUNCATED) {
+    return event;
+  }
+  return { ...event, decrypted_matched_data: decryptToString(privateKey, encrypted) };
+}
+
+export default {
+  async fetch(request, env) {
+    if (request.method !== "POST") {
+      return new Response("Method not allowed", { status: 405 });
+    }
+
+    let body;
+    try {
+      body = await request.json();
+    } catch {
+      return new Response("Request body is not JSON", { status: 400 });
+    }
+
+    try {
+      const privateKey = env.MATCHED_DATA_PRIVATE_KEY;
+      const enriched = Array.isArray(body)
+        ? body.map((event) => enrich(event, privateKey))
+        : enrich(body, privateKey);
+      return Response.json(enriched);
+    } catch (e) {
+      return new Response(`Failed to decrypt matched data: ${e.message}`, { status: 422 });
+    }
+  },
+};
@@ -0,0 +1,51 @@
+// Module-style wrapper of the wasm-bindgen output for the Workers runtime, which has no window
+// and can't fetch the WebAssembly module at runtime, so it is imported and instantiated at startup
+import {
+  initSync,
+  decrypt as decryptBytes,
+  encrypt as encryptBytes,
+  public_key_from_private as publicKeyBytesFromPrivate,
+} from "../pkg/matched_data_wasm.js";
+import wasmModule from "../pkg/matched_data_wasm_bg.wasm";
+
+initSync({ module: wasmModule });
+
+// Decodes standard or URL-safe base64, ignoring whitespace
+function decodeBase64(base64) {
+  const standard = base64.replace(/\s/g, "").replace(/-/g, "+").replace(/_/g, "/");
+  return Uint8Array.from(atob(standard), (c) => c.charCodeAt(0));
+}
+
+// Encodes bytes as standard base64
+function encodeBase64(bytes) {
+  let binary = "";
+  for (const byte of bytes) {
+    binary += String.fromCharCode(byte);
+  }
+  return btoa(binary);
+}
+
+// Decrypts base64 encoded matched data with a base64 encoded private key, returning the
+// plaintext bytes. Throws an Error if either is invalid or decryption fails
+export function decrypt(privateKeyBase64, matchedDataBase64) {
+  return decryptBytes(decodeBase64(privateKeyBase64), decodeBase64(matchedDataBase64));
+}
+
+// Decrypts matched data like decrypt, decoding the plaintext as UTF-8
+export function decryptToString(privateKeyBase64, matchedDataBase64) {
+  return new TextDecoder().decode(decrypt(privateKeyBase64, matchedDataBase64));
+}
+
+// Encrypts a plaintext, a string encoded as UTF-8 or bytes, to a base64 encoded public key,
+// returning base64 encoded matched data. Throws an Error if the public key is invalid
+export function encrypt(publicKeyBase64, plaintext) {
+  const plaintextBytes =
+    typeof plaintext === "string" ? new TextEncoder().encode(plaintext) : plaintext;
+  return encodeBase64(encryptBytes(decodeBase64(publicKeyBase64), plaintextBytes));
+}
+
+// Returns the base64 encoded public key of a base64 encoded private key, to check a key pair.
+// Throws an Error if the private key is invalid
+export function publicKeyFromPrivate(privateKeyBase64) {
+  return encodeBase64(publicKeyBytesFromPrivate(decodeBase64(privateKeyBase64)));
+}
@@ -0,0 +1,187 @@
+use crate::logs::{self, GZIP_MAGIC};
+use flate2::bufread::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+// Extensions of the archives of Logpush files read as a single log
+const ZIP_EXTENSIONS: &[&str] = &[".zip"];
+const TAR_EXTENSIONS: &[&str] = &[".tar", ".tar.gz", ".tgz"];
+
+// Size of the chunks members are sent in, and number of chunks read ahead of the decryption
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHUNKS_AHEAD: usize = 16;
+
+type Chunk = Result<Vec<u8>, String>;
+
+// Whether a log file is an archive of logs, from its extension
+pub fn is_archive(filename: &str) -> bool {
+    let filename = filename.to_ascii_lowercase();
+    ZIP_EXTENSIONS
+        .iter()
+        .chain(TAR_EXTENSIONS)
+        .any(|extension| filename.ends_with(extension))
+}
+
+// Reads the members of a zip or tar archive, gzipped or not, in the order they are archived
+pub struct Members {
+    receiver: Receiver<Chunk>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+// Opens an archive of logs, read on another thread that decompresses each member and joins them
+// as one log, a line break ending each, so tens of thousands of files need no unpacking
+pub fn open(filename: &str) -> Result<Members, String> {
+    if cfg!(target_os = "wasi") {
+        return Err("Log archives can't be read on WASI, which has no threads, extract them and pass their logs instead".to_string());
+    }
+    let file = File::open(filename).map_err(|_| "Failed to read log archive")?;
+    let is_zip = ZIP_EXTENSIONS
+        .iter()
+        .any(|extension| filename.to_ascii_lowercase().ends_with(extension));
+
+    let (sender, receiver) = mpsc::sync_channel(CHUNKS_AHEAD);
+    thread::spawn(move || {
+        let result = if is_zip {
+            send_zip_members(file, &sender)
+        } else {
+            send_tar_members(file, &sender)
+        };
+        if let Err(e) = result {
+            // Nothing reads the error if the log is no longer read
+            let _ = sender.send(Err(e));
+        }
+    });
+
+    Ok(Members {
+        receiver,
+        chunk: Vec::new(),
+        position: 0,
+    })
+}
+
+fn send_zip_members(file: File, sender: &SyncSender<Chunk>) -> Result<(), String> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(file))
+        .map_err(|_| "Log archive is not a valid zip file")?;
+    for i in 0..archive.len() {
+        let member = archive
+            .by_index(i)
+            .map_err(|e| format!("Failed to read member {} of log archive: {}", i, e))?;
+        if member.is_dir() || is_hidden(member.name()) {
+            continue;
+        }
+        let name = member.name().to_string();
+        if !send_member(&name, member, sender)? {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+fn send_tar_members(file: File, sender: &SyncSender<Chunk>) -> Result<(), String> {
+    let mut archive = tar::Archive::new(logs::decompress(BufReader::new(file))?);
+    let entries = archive
+        .entries()
+        .map_err(|_| "Log archive is not a valid tar file")?;
+    for entry in entries {
+        let entry = entry.map_err(|_| "Log archive is not a valid tar file")?;
+        let name = entry.path_bytes();
+        let name = String::from_utf8_lossy(&name).into_owned();
+        if !entry.header().entry_type().is_file() || is_hidden(&name) {
+            continue;
+        }
+        if !send_member(&name, entry, sender)? {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+// Metadata such as macOS resource forks and .DS_Store files, rather than logs
+fn is_hidden(name: &str) -> bool {
+    name.starts_with("__MACOSX/")
+        || Path::new(name)
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+// Sends a member, decompressed if gzipped, returning false if the log is no longer read
+fn send_member(name: &str, member: impl Read, sender: &SyncSender<Chunk>) -> Result<bool, String> {
+    let read_error = |_| format!("Failed to read {} in log archive", name);
+    let mut member = BufReader::new(member);
+    let mut member: Box<dyn Read> = if member
+        .fill_buf()
+        .map_err(read_error)?
+        .starts_with(GZIP_MAGIC)
+    {
+        Box::new(MultiGzDecoder::new(member))
+    } else {
+        Box::new(member)
+    };
+
+    let mut last_byte = b'\n';
+    loop {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let read = member.read(&mut chunk).map_err(read_error)?;
+        if read == 0 {
+            break;
+        }
+        chunk.truncate(read);
+        last_byte = chunk[read - 1];
+        if sender.send(Ok(chunk)).is_err() {
+            return Ok(false);
+        }
+    }
+
+    // The last event of a member without a final line break would run into the next member's
+    if last_byte != b'\n' && sender.send(Ok(b"\n".to_vec())).is_err() {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+impl Read for Members {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Ok(Err(e)) => return Err(io::Error::other(e)),
+                // Every member has been sent
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let read = buf.len().min(self.chunk.len() - self.position);
+        buf[..read].copy_from_slice(&self.chunk[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_archive() {
+        assert!(is_archive("logs.zip"));
+        assert!(is_archive("2022-01-01.TAR.GZ"));
+        assert!(is_archive("logs.tgz"));
+        assert!(!is_archive(
+            "20220101T000000Z_20220101T000100Z_abcdef.log.gz"
+        ));
+    }
+
+    #[test]
+    fn test_is_hidden() {
+        assert!(is_hidden("__MACOSX/logs/._a.log.gz"));
+        assert!(is_hidden("logs/.DS_Store"));
+        assert!(!is_hidden("logs/a.log.gz"));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::matched_data::{self, DecryptAnyVersionError, Decryptor, PrivateKey, PublicKey};
+use futures_core::Stream;
+use std::future::poll_fn;
+use std::panic;
+use std::pin::{pin, Pin};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinHandle};
+use zeroize::Zeroizing;
+
+pub type DecryptResult = Result<Zeroizing<Vec<u8>>, DecryptAnyVersionError>;
+
+// Waits for a blocking task, resuming its panic if it panicked
+async fn join<T>(handle: JoinHandle<T>) -> T {
+    match handle.await {
+        Ok(value) => value,
+        Err(error) => panic::resume_unwind(error.into_panic()),
+    }
+}
+
+// Decrypts serialized matched data on the blocking thread pool, so HPKE doesn't stall the
+// runtime's worker threads
+pub async fn decrypt(
+    decryptor: Arc<Decryptor>,
+    serialized_encrypted_data: Vec<u8>,
+) -> DecryptResult {
+    join(task::spawn_blocking(move || {
+        decryptor.decrypt(&serialized_encrypted_data)
+    }))
+    .await
+}
+
+// Decrypts serialized matched data on the blocking thread pool, deserializing the private key
+// every time, for one-off decryptions
+pub async fn decrypt_any_version(
+    private_key_bytes: Zeroizing<Vec<u8>>,
+    serialized_encrypted_data: Vec<u8>,
+) -> DecryptResult {
+    join(task::spawn_blocking(move || {
+        matched_data::decrypt_any_version(&private_key_bytes, &serialized_encrypted_data)
+    }))
+    .await
+}
+
+// Generates a public-private key pair on the blocking thread pool, as gathering entropy can block
+pub async fn generate_key_pair() -> (PrivateKey, PublicKey) {
+    join(task::spawn_blocking(matched_data::generate_key_pair)).await
+}
+
+// Plaintexts of a stream of serialized matched data, in the same order, decrypted in batches on
+// the blocking thread pool. Matched data is only read from the input once the plaintexts of the
+// previous batch have been taken, so a slow consumer slows the producer rather than letting
+// plaintexts pile up in memory
+pub struct DecryptStream {
+    receiver: mpsc::Receiver<DecryptResult>,
+}
+
+impl DecryptStream {
+    // Waits for the next plaintext, None once the input has ended and every plaintext was taken
+    pub async fn next(&mut self) -> Option<DecryptResult> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for DecryptStream {
+    type Item = DecryptResult;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<DecryptResult>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+// Decrypts a stream of serialized matched data with a decryptor shared by every batch, taking
+// up to batch_size matched data at a time as they are ready. Must be called from a tokio runtime,
+// on which the batches are scheduled
+pub fn decrypt_stream<S>(decryptor: Arc<Decryptor>, input: S, batch_size: usize) -> DecryptStream
+where
+    S: Stream<Item = Vec<u8>> + Send + 'static,
+{
+    let batch_size = batch_size.max(1);
+    let (sender, receiver) = mpsc::channel(batch_size);
+
+    task::spawn(async move {
+        let mut input = pin!(input);
+        loop {
+            let batch = next_batch(input.as_mut(), batch_size).await;
+            if batch.is_empty() {
+                return;
+            }
+
+            let decryptor = decryptor.clone();
+            let plaintexts = join(task::spawn_blocking(move || {
+                batch
+                    .iter()
+                    .map(|serialized_encrypted_data| decryptor.decrypt(serialized_encrypted_data))
+                    .collect::<Vec<_>>()
+            }))
+            .await;
+            for plaintext in plaintexts {
+                // The stream was dropped, so nothing reads the rest
+                if sender.send(plaintext).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    DecryptStream { receiver }
+}
+
+// Waits for the next matched data of the input, then takes those ready right away up to
+// batch_size, empty once the input has ended
+async fn next_batch<S>(mut input: Pin<&mut S>, batch_size: usize) -> Vec<Vec<u8>>
+where
+    S: Stream<Item = Vec<u8>>,
+{
+    let mut batch = Vec::new();
+    poll_fn(|cx| {
+        while batch.len() < batch_size {
+            match input.as_mut().poll_next(cx) {
+                Poll::Ready(Some(serialized_encrypted_data)) => {
+                    batch.push(serialized_encrypted_data)
+                }
+                Poll::Ready(None) => return Poll::Ready(()),
+                Poll::Pending if batch.is_empty() => return Poll::Pending,
+                Poll::Pending => return Poll::Ready(()),
+            }
+        }
+        Poll::Ready(())
+    })
+    .await;
+    batch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hpke::Serializable;
+
+    // Yields the items of a vector one at a time
+    struct Items(Vec<Vec<u8>>);
+
+    impl Stream for Items {
+        type Item = Vec<u8>;
+
+        fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Vec<u8>>> {
+            Poll::Ready((!self.0.is_empty()).then(|| self.0.remove(0)))
+        }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    }
+
+    fn encrypt(plaintext: &[u8], public_key: &PublicKey) -> Vec<u8> {
+        matched_data::serialize_encrypted_data(
+            &matched_data::encrypt_data(plaintext, public_key).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_decrypt() {
+        runtime().block_on(async {
+            let (private_key, public_key) = generate_key_pair().await;
+            let private_key_bytes = Zeroizing::new(private_key.to_bytes().to_vec());
+            let decryptor = Arc::new(Decryptor::new(&private_key_bytes).unwrap());
+
+            let encrypted = encrypt(b"test", &public_key);
+            assert_eq!(
+                b"test".to_vec(),
+                *decrypt(decryptor, encrypted.clone()).await.unwrap()
+            );
+            assert_eq!(
+                b"test".to_vec(),
+                *decrypt_any_version(private_key_bytes, encrypted)
+                    .await
+                    .unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn test_decrypt_stream() {
+        runtime().block_on(async {
+            let (private_key, public_key) = generate_key_pair().await;
+            let decryptor = Arc::new(Decryptor::new(&private_key.to_bytes()).unwrap());
+
+            let mut input: Vec<Vec<u8>> = (0..10u8).map(|i| encrypt(&[i], &public_key)).collect();
+            input.insert(5, Vec::new());
+            let mut stream = decrypt_stream(decryptor, Items(input), 3);
+
+            let mut plaintexts = Vec::new();
+            while let Some(plaintext) = stream.next().await {
+                plaintexts.push(plaintext.map(|plaintext| plaintext[0]).ok());
+            }
+            let mut expected: Vec<Option<u8>> = (0..10).map(Some).collect();
+            expected.insert(5, None);
+            assert_eq!(expected, plaintexts);
+        });
+    }
+}
@@ -0,0 +1,103 @@
+use crate::key_format;
+use hpke::Serializable;
+use matched_data_cli::matched_data::{get_private_key_from_bytes, get_public_key};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// A decryption recorded in the audit log, never including the plaintext
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    timestamp: String,
+    command: &'a str,
+    input_sha256: String,
+    key_fingerprint: &'a str,
+    outcome: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+// Appends a JSON line to a local audit file for each decryption
+pub struct AuditLog {
+    file: Mutex<fs::File>,
+    command: &'static str,
+    key_fingerprint: String,
+}
+
+impl AuditLog {
+    pub fn open(
+        path: &str,
+        command: &'static str,
+        private_key_bytes: &[u8],
+    ) -> Result<Self, String> {
+        let key_fingerprint = match get_private_key_from_bytes(private_key_bytes) {
+            Ok(private_key) => key_format::fingerprint(&get_public_key(&private_key).to_bytes()),
+            Err(_) => "invalid".to_string(),
+        };
+
+        Self::open_with_fingerprint(path, command, key_fingerprint)
+    }
+
+    // Opens the audit log for a key held elsewhere, such as on a YubiKey, from its public key
+    pub fn open_with_public_key(
+        path: &str,
+        command: &'static str,
+        public_key_bytes: &[u8],
+    ) -> Result<Self, String> {
+        Self::open_with_fingerprint(path, command, key_format::fingerprint(public_key_bytes))
+    }
+
+    fn open_with_fingerprint(
+        path: &str,
+        command: &'static str,
+        key_fingerprint: String,
+    ) -> Result<Self, String> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|_| format!("Failed to open audit log {}", path))?;
+
+        Ok(AuditLog {
+            file: Mutex::new(file),
+            command,
+            key_fingerprint,
+        })
+    }
+
+    // Records the outcome of decrypting matched data, failing if the record can't be written
+    pub fn record(
+        &self,
+        encrypted_matched_data_bytes: &[u8],
+        error: Option<&str>,
+    ) -> Result<(), String> {
+        let record = AuditRecord {
+            timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            command: self.command,
+            input_sha256: Sha256::digest(encrypted_matched_data_bytes)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+            key_fingerprint: &self.key_fingerprint,
+            outcome: if error.is_none() {
+                "success"
+            } else {
+                "failure"
+            },
+            error,
+        };
+        let line = format!(
+            "{}\n",
+            serde_json::to_string(&record).expect("Failed to serialize audit record")
+        );
+
+        self.file
+            .lock()
+            .expect("Audit log lock is poisoned")
+            .write_all(line.as_bytes())
+            .map_err(|_| "Failed to write to audit log".to_string())
+    }
+}
@@ -0,0 +1,159 @@
+use crate::encoding;
+use serde_json::Value;
+use std::process::Command;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+// Resource Key Vault access tokens are issued for
+const KEY_VAULT_RESOURCE: &str = "https://vault.azure.net";
+
+// Key Vault REST API version secrets are read with
+const KEY_VAULT_API_VERSION: &str = "7.4";
+
+// Instance metadata endpoint issuing managed identity tokens on Azure VMs
+const MANAGED_IDENTITY_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+// How long to wait for the instance metadata endpoint, which is unreachable outside of Azure
+const MANAGED_IDENTITY_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Fetches a base64 encoded private key from a Key Vault secret given as <vault>/<secret>, with a
+// managed identity token, or the az CLI credentials outside of Azure
+pub fn read_private_key(vault_secret: &str, strict: bool) -> Result<Zeroizing<Vec<u8>>, String> {
+    let (vault, secret) = parse_vault_secret(vault_secret)?;
+    let token = managed_identity_token().or_else(|_| az_cli_token())?;
+
+    let url = format!(
+        "{}/secrets/{}?api-version={}",
+        vault_url(vault),
+        secret,
+        KEY_VAULT_API_VERSION
+    );
+    let mut response: Value = match ureq::get(&url)
+        .set("Authorization", &format!("Bearer {}", token.as_str()))
+        .call()
+    {
+        Ok(response) => response
+            .into_json()
+            .map_err(|_| "Azure Key Vault response is not JSON")?,
+        Err(ureq::Error::Status(status, response)) => {
+            return Err(format!(
+                "Azure Key Vault request failed with status {}: {}",
+                status,
+                azure_error(&response.into_json().unwrap_or(Value::Null))
+            ))
+        }
+        Err(e) => return Err(format!("Azure Key Vault request failed: {}", e)),
+    };
+    let private_key_base64 = match response.get_mut("value").map(Value::take) {
+        Some(Value::String(private_key_base64)) => Zeroizing::new(private_key_base64),
+        _ => return Err("Azure Key Vault secret has no value".to_string()),
+    };
+
+    encoding::decode_base64(&private_key_base64, strict)
+        .map(Zeroizing::new)
+        .map_err(|_| "Private key in Azure Key Vault is not base64 encoded".to_string())
+}
+
+// Splits <vault>/<secret>
+fn parse_vault_secret(vault_secret: &str) -> Result<(&str, &str), String> {
+    match vault_secret.split_once('/') {
+        Some((vault, secret)) if !vault.is_empty() && !secret.is_empty() => Ok((vault, secret)),
+        _ => Err(format!(
+            "Azure Key Vault secret {} is not in the form <vault>/<secret>",
+            vault_secret
+        )),
+    }
+}
+
+// URL of a vault from its name, or from its host name in other clouds
+fn vault_url(vault: &str) -> String {
+    if vault.contains('.') {
+        format!("https://{}", vault)
+    } else {
+        format!("https://{}.vault.azure.net", vault)
+    }
+}
+
+// Access token of the managed identity of the Azure VM this runs on
+fn managed_identity_token() -> Result<Zeroizing<String>, String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(MANAGED_IDENTITY_TIMEOUT)
+        .build();
+    let response: Value = agent
+        .get(MANAGED_IDENTITY_URL)
+        .query("api-version", "2018-02-01")
+        .query("resource", KEY_VAULT_RESOURCE)
+        .set("Metadata", "true")
+        .call()
+        .map_err(|_| "No managed identity is available")?
+        .into_json()
+        .map_err(|_| "Managed identity response is not JSON")?;
+
+    response["access_token"]
+        .as_str()
+        .map(|token| Zeroizing::new(token.to_string()))
+        .ok_or_else(|| "Managed identity response has no access token".to_string())
+}
+
+// Access token of the account logged in with az login
+fn az_cli_token() -> Result<Zeroizing<String>, String> {
+    let output = Command::new("az")
+        .args([
+            "account",
+            "get-access-token",
+            "--resource",
+            KEY_VAULT_RESOURCE,
+            "--query",
+            "accessToken",
+            "--output",
+            "tsv",
+        ])
+        .output()
+        .map_err(|_| {
+            "No managed identity is available and the az CLI failed to run, check that it is installed"
+        })?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to get an Azure Key Vault token with the az CLI: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let token = Zeroizing::new(output.stdout);
+    Ok(Zeroizing::new(
+        String::from_utf8_lossy(&token).trim().to_string(),
+    ))
+}
+
+// Message of an Azure error response
+fn azure_error(response: &Value) -> String {
+    response["error"]["message"]
+        .as_str()
+        .unwrap_or("no error message")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vault_secret() {
+        assert_eq!(
+            ("waf-keys", "matched-data"),
+            parse_vault_secret("waf-keys/matched-data").unwrap()
+        );
+        assert!(parse_vault_secret("waf-keys").is_err());
+        assert!(parse_vault_secret("/matched-data").is_err());
+    }
+
+    #[test]
+    fn test_vault_url() {
+        assert_eq!("https://waf-keys.vault.azure.net", vault_url("waf-keys"));
+        assert_eq!(
+            "https://waf-keys.vault.azure.cn",
+            vault_url("waf-keys.vault.azure.cn")
+        );
+    }
+}
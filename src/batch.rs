@@ -0,0 +1,193 @@
+use crate::error::CliError;
+use crate::extract::Extractor;
+use crate::progress::Progress;
+use serde::Serialize;
+use std::fs;
+use std::io::{stdin, BufRead, BufReader, Read};
+use zeroize::{Zeroize, Zeroizing};
+
+// Decrypts one base64, hex, byte array or raw input into its plaintext
+pub type Decrypt<'a> = dyn Fn(&[u8]) -> Result<Zeroizing<Vec<u8>>, CliError> + 'a;
+
+pub struct BatchOptions {
+    // Print a record for every input rather than stopping at the first failure
+    pub continue_on_error: bool,
+    pub verify_only: bool,
+    // Each file is one input of raw serialized matched data, rather than a line of text
+    pub raw: bool,
+    // Records end with a NUL byte rather than a line break
+    pub null: bool,
+    // Inputs are read up to one byte past this size, the decrypt function rejecting larger ones
+    pub max_input_size: u64,
+    // Version of the --porcelain format, recorded in every record
+    pub porcelain: Option<&'static str>,
+}
+
+// Result of decrypting one input, printed as a JSON line
+#[derive(Serialize)]
+struct BatchRecord<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    porcelain: Option<&'static str>,
+    source: &'a str,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plaintext_length: Option<usize>,
+    // Left out with --verify-only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plaintext_base64: Option<String>,
+    // Only set when the plaintext is valid UTF-8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plaintext_utf8: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl Drop for BatchRecord<'_> {
+    fn drop(&mut self) {
+        self.plaintext_base64.zeroize();
+        self.plaintext_utf8.zeroize();
+    }
+}
+
+// Decrypts --data, or each non-empty line of the files (- for stdin), or each whole file of raw
+// matched data, printing a JSON line per input identified by its source, without the plaintext
+// when only verifying. Plaintexts are also written to the extract directory if any. Unless
+// continuing on errors, the first failure stops decryption once printed
+pub fn decrypt_all(
+    filenames: &[String],
+    data: Option<&str>,
+    options: &BatchOptions,
+    extractor: Option<&Extractor>,
+    progress: &mut Progress,
+    decrypt: &Decrypt<'_>,
+) -> Result<(), CliError> {
+    if let Some(data) = data {
+        return decrypt_one("data", data.as_bytes(), options, extractor, decrypt);
+    }
+
+    let mut inputs = 0;
+    let mut failures = 0;
+    let mut record = |source: &str, input: &[u8]| {
+        inputs += 1;
+        let result = decrypt_one(source, input, options, extractor, decrypt);
+        progress.record(result.is_err());
+        match result {
+            Err(_) if options.continue_on_error => {
+                failures += 1;
+                Ok(())
+            }
+            result => result,
+        }
+    };
+    for filename in filenames {
+        let mut reader: Box<dyn BufRead> = if filename == "-" {
+            Box::new(stdin().lock())
+        } else {
+            Box::new(BufReader::new(fs::File::open(filename).map_err(|_| {
+                format!("Failed to read matched data from {}", filename)
+            })?))
+        };
+        let name = if filename == "-" { "stdin" } else { filename };
+
+        let read_error = |_| format!("Failed to read matched data from {}", name);
+        if options.raw {
+            let mut input = Vec::new();
+            reader
+                .take(options.max_input_size + 1)
+                .read_to_end(&mut input)
+                .map_err(read_error)?;
+            record(name, &input)?;
+            continue;
+        }
+        let mut line = Zeroizing::new(Vec::new());
+        for line_number in 1.. {
+            line.clear();
+            let read = reader
+                .by_ref()
+                .take(options.max_input_size + 1)
+                .read_until(b'\n', &mut line)
+                .map_err(read_error)?;
+            if read == 0 {
+                break;
+            }
+            // The rest of an oversized line is skipped, the part read being rejected as too large
+            if line.last() != Some(&b'\n') {
+                reader.skip_until(b'\n').map_err(read_error)?;
+            }
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+            record(&format!("{}:{}", name, line_number), line.trim_ascii())?;
+        }
+    }
+    progress.finish();
+
+    match failures {
+        0 => Ok(()),
+        _ => Err(format!("Failed to decrypt {} of {} inputs", failures, inputs).into()),
+    }
+}
+
+// Decrypts one input and prints its record
+fn decrypt_one(
+    source: &str,
+    input: &[u8],
+    options: &BatchOptions,
+    extractor: Option<&Extractor>,
+    decrypt: &Decrypt<'_>,
+) -> Result<(), CliError> {
+    let decrypted = decrypt(input).and_then(|plaintext| {
+        if let Some(extractor) = extractor {
+            extractor.extract(source, &plaintext)?;
+        }
+        Ok(plaintext)
+    });
+    let (record, result) = match decrypted {
+        Ok(plaintext) if options.verify_only => (
+            BatchRecord {
+                porcelain: options.porcelain,
+                source,
+                status: "ok",
+                plaintext_length: Some(plaintext.len()),
+                plaintext_base64: None,
+                plaintext_utf8: None,
+                code: None,
+                message: None,
+            },
+            Ok(()),
+        ),
+        Ok(plaintext) => (
+            BatchRecord {
+                porcelain: options.porcelain,
+                source,
+                status: "ok",
+                plaintext_length: Some(plaintext.len()),
+                plaintext_base64: Some(radix64::STD.encode(plaintext.as_slice())),
+                plaintext_utf8: std::str::from_utf8(&plaintext).ok().map(str::to_string),
+                code: None,
+                message: None,
+            },
+            Ok(()),
+        ),
+        Err(e) => (
+            BatchRecord {
+                porcelain: options.porcelain,
+                source,
+                status: "error",
+                plaintext_length: None,
+                plaintext_base64: None,
+                plaintext_utf8: None,
+                code: Some(e.code.name()),
+                message: Some(e.message.clone()),
+            },
+            Err(e),
+        ),
+    };
+
+    let line = Zeroizing::new(serde_json::to_string(&record).expect("Failed to serialize result"));
+    print!("{}{}", *line, if options.null { '\0' } else { '\n' });
+
+    result
+}
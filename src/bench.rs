@@ -0,0 +1,142 @@
+use hpke::Serializable;
+use matched_data_cli::matched_data::{
+    encrypt_data, generate_key_pair, serialize_encrypted_data, Decryptor,
+};
+#[cfg(not(target_os = "wasi"))]
+use rayon::prelude::*;
+#[cfg(not(target_os = "wasi"))]
+use rayon::ThreadPoolBuilder;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+// Most memory the synthetic ciphertexts may take, so a typo in --count or --size can't exhaust it
+const MAX_TOTAL_SIZE: usize = 1 << 30;
+
+// Decryption throughput with a number of threads
+#[derive(Serialize)]
+pub struct Throughput {
+    pub threads: usize,
+    pub seconds: f64,
+    pub decryptions_per_second: f64,
+    // Plaintext bytes decrypted per second
+    pub bytes_per_second: f64,
+}
+
+// Throughput of decrypting synthetic matched data, with one thread then with several
+#[derive(Serialize)]
+pub struct Report {
+    pub count: usize,
+    pub size: usize,
+    pub results: Vec<Throughput>,
+}
+
+impl Throughput {
+    fn new(threads: usize, count: usize, size: usize, elapsed: Duration) -> Self {
+        // Timers of some platforms are too coarse to measure a few fast decryptions
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        Throughput {
+            threads,
+            seconds,
+            decryptions_per_second: count as f64 / seconds,
+            bytes_per_second: (count * size) as f64 / seconds,
+        }
+    }
+}
+
+impl Report {
+    // Formats the report as aligned lines for a terminal
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "Decrypted {} matched data of {} bytes\n{:<12}{:>16}{:>12}\n",
+            self.count, self.size, "THREADS", "DECRYPTIONS/S", "MIB/S"
+        );
+        for result in &self.results {
+            text.push_str(&format!(
+                "{:<12}{:>16.0}{:>12.1}\n",
+                result.threads,
+                result.decryptions_per_second,
+                result.bytes_per_second / (1 << 20) as f64
+            ));
+        }
+        text
+    }
+}
+
+// Encrypts count payloads of size bytes to an ephemeral key, then times decrypting all of them on
+// one thread and on jobs threads, as serve and batch jobs would. Generating the ciphertexts isn't
+// timed
+pub fn run(count: usize, size: usize, jobs: usize) -> Result<Report, String> {
+    if count == 0 || jobs == 0 {
+        return Err("Number of matched data and of jobs must be at least 1".to_string());
+    }
+    if count.saturating_mul(size) > MAX_TOTAL_SIZE {
+        return Err(format!(
+            "Benchmark matched data would take more than {} MiB, lower --count or --size",
+            MAX_TOTAL_SIZE >> 20
+        ));
+    }
+
+    let (private_key, public_key) = generate_key_pair();
+    let decryptor = Decryptor::new(&private_key.to_bytes()).map_err(|e| e.to_string())?;
+    let payload = vec![0x61; size];
+    let all_encrypted_data = (0..count)
+        .map(|_| {
+            encrypt_data(&payload, &public_key)
+                .map(|encrypted_data| serialize_encrypted_data(&encrypted_data))
+                .map_err(|e| format!("Failed to encrypt matched data: {:?}", e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let decrypt = |encrypted_data: &Vec<u8>| {
+        decryptor
+            .decrypt(encrypted_data)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to decrypt matched data: {}", e))
+    };
+
+    let mut results = Vec::new();
+    let start = Instant::now();
+    all_encrypted_data.iter().try_for_each(decrypt)?;
+    results.push(Throughput::new(1, count, size, start.elapsed()));
+
+    // WASI runtimes can't start threads, only the single thread is measured there
+    #[cfg(not(target_os = "wasi"))]
+    if jobs > 1 {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()
+            .map_err(|_| "Failed to start decryption threads")?;
+        let start = Instant::now();
+        pool.install(|| all_encrypted_data.par_iter().try_for_each(decrypt))?;
+        results.push(Throughput::new(jobs, count, size, start.elapsed()));
+    }
+
+    Ok(Report {
+        count,
+        size,
+        results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let report = run(8, 100, 2).unwrap();
+        assert_eq!(
+            vec![1, 2],
+            report
+                .results
+                .iter()
+                .map(|result| result.threads)
+                .collect::<Vec<_>>()
+        );
+        assert!(report
+            .to_text()
+            .contains("Decrypted 8 matched data of 100 bytes"));
+
+        assert!(run(0, 100, 1).is_err());
+        assert!(run(1 << 20, 1 << 20, 1).is_err());
+    }
+}
@@ -0,0 +1,335 @@
+use crate::logs::{find_all_encrypted_matched_data, Decrypt, RULE_ID_FIELDS};
+use crate::TRUNCATED;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::Terminal;
+use serde_json::{Deserializer, Value};
+use std::collections::HashMap;
+use std::io::{stdout, Read};
+use zeroize::Zeroizing;
+
+// Fields holding the event summary, in order of preference, across Logpush datasets and the API
+const ACTION_FIELDS: &[&str] = &["Action", "action", "SecurityAction", "WAFAction"];
+const TIMESTAMP_FIELDS: &[&str] = &["Datetime", "datetime", "EdgeStartTimestamp", "occurredAt"];
+
+// Rows taken by the borders and header of the event table
+const TABLE_CHROME_HEIGHT: u16 = 3;
+
+// An event listed in the browser
+pub struct BrowseEvent {
+    pub rule_id: String,
+    pub action: String,
+    pub timestamp: String,
+    // Each encrypted matched data of the event keyed by rule ID, events can match several rules
+    pub encrypted_matched_data: Vec<(String, String)>,
+}
+
+impl BrowseEvent {
+    fn new(event: &Value) -> Self {
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| match event.get(name) {
+                    Some(Value::String(value)) => Some(value.clone()),
+                    Some(Value::Null) | None => None,
+                    Some(value) => Some(value.to_string()),
+                })
+                .unwrap_or_default()
+        };
+
+        BrowseEvent {
+            rule_id: field(RULE_ID_FIELDS),
+            action: field(ACTION_FIELDS),
+            timestamp: field(TIMESTAMP_FIELDS),
+            encrypted_matched_data: find_all_encrypted_matched_data(event)
+                .into_iter()
+                .map(|(rule_id, data)| (rule_id, data.to_string()))
+                .collect(),
+        }
+    }
+
+    // Whether an object has any of the summary fields of an event
+    fn is_event(event: &Value) -> bool {
+        [RULE_ID_FIELDS, ACTION_FIELDS, TIMESTAMP_FIELDS]
+            .iter()
+            .flat_map(|names| names.iter())
+            .any(|name| event.get(name).is_some())
+    }
+}
+
+// Loads the events of an NDJSON log, a JSON array of events or a GraphQL API response, one JSON
+// value at a time and keeping only what the browser lists
+pub fn load_events<R: Read>(reader: R) -> Result<Vec<BrowseEvent>, String> {
+    let mut events = Vec::new();
+    for value in Deserializer::from_reader(reader).into_iter::<Value>() {
+        let value = value.map_err(|e| format!("Line {}: Events are not valid JSON", e.line()))?;
+        match value {
+            Value::Array(values) => events.extend(values.iter().map(BrowseEvent::new)),
+            Value::Object(ref response) if response.contains_key("data") => {
+                if let Some(Value::Array(errors)) = response.get("errors") {
+                    if let Some(error) = errors.first() {
+                        return Err(format!(
+                            "API query failed: {}",
+                            error
+                                .get("message")
+                                .and_then(Value::as_str)
+                                .unwrap_or("unknown error")
+                        ));
+                    }
+                }
+                collect_api_events(&response["data"], &mut events);
+            }
+            Value::Object(_) => events.push(BrowseEvent::new(&value)),
+            _ => return Err("Events are not JSON objects".to_string()),
+        }
+    }
+
+    Ok(events)
+}
+
+// Collects the events nested in the data of a GraphQL response, such as
+// viewer.zones[].firewallEventsAdaptive[]
+fn collect_api_events(value: &Value, events: &mut Vec<BrowseEvent>) {
+    match value {
+        Value::Object(_) if BrowseEvent::is_event(value) => events.push(BrowseEvent::new(value)),
+        Value::Object(object) => object
+            .values()
+            .for_each(|value| collect_api_events(value, events)),
+        Value::Array(values) => values
+            .iter()
+            .for_each(|value| collect_api_events(value, events)),
+        _ => {}
+    }
+}
+
+// Scrolls the first visible row so the selected row stays within a window of rows
+fn scroll_offset(offset: usize, selected: usize, height: usize) -> usize {
+    if selected < offset {
+        selected
+    } else if height > 0 && selected >= offset + height {
+        selected + 1 - height
+    } else {
+        offset
+    }
+}
+
+// Restores the terminal when the browser exits, including on errors
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(stdout(), LeaveAlternateScreen);
+    }
+}
+
+// Lists events in a terminal UI, decrypting the matched data of the selected event on demand
+pub fn browse(events: &[BrowseEvent], decrypt: &Decrypt<'_>) -> Result<(), String> {
+    enable_raw_mode().map_err(|_| "Failed to set up terminal")?;
+    let _guard = TerminalGuard;
+    execute!(stdout(), EnterAlternateScreen).map_err(|_| "Failed to set up terminal")?;
+    let mut terminal =
+        Terminal::new(CrosstermBackend::new(stdout())).map_err(|_| "Failed to set up terminal")?;
+
+    let mut selected = if events.is_empty() { None } else { Some(0) };
+    // First event shown in the table, which only builds the rows that fit on screen
+    let mut offset = 0;
+    // Lines scrolled in the matched data pane, and the lines it shows at once
+    let mut scroll = 0;
+    let mut page_height = 0;
+    let mut decrypted: HashMap<usize, Zeroizing<String>> = HashMap::new();
+
+    loop {
+        terminal
+            .draw(|frame| {
+                let areas = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(frame.size());
+
+                let height = areas[0].height.saturating_sub(TABLE_CHROME_HEIGHT) as usize;
+                offset = scroll_offset(offset, selected.unwrap_or(0), height);
+                let rows = events.iter().skip(offset).take(height).map(|event| {
+                    Row::new(vec![
+                        event.timestamp.clone(),
+                        event.rule_id.clone(),
+                        event.action.clone(),
+                        match event.encrypted_matched_data.as_slice() {
+                            [] => "no".to_string(),
+                            [(_, data)] if data == TRUNCATED => "truncated".to_string(),
+                            [_] => "yes".to_string(),
+                            entries => format!("{} rules", entries.len()),
+                        },
+                    ])
+                });
+                let table = Table::new(
+                    rows,
+                    [
+                        Constraint::Length(25),
+                        Constraint::Length(34),
+                        Constraint::Length(16),
+                        Constraint::Length(12),
+                    ],
+                )
+                .header(
+                    Row::new(vec!["Timestamp", "Rule ID", "Action", "Matched data"])
+                        .style(Style::default().add_modifier(Modifier::BOLD)),
+                )
+                .block(Block::default().borders(Borders::ALL).title(format!(
+                    "{} events (up/down to select, enter to decrypt, page up/down to scroll, q to quit)",
+                    events.len()
+                )))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                let mut state = TableState::default();
+                state.select(selected.map(|selected| selected - offset));
+                frame.render_stateful_widget(table, areas[0], &mut state);
+
+                let matched_data = selected
+                    .and_then(|selected| decrypted.get(&selected))
+                    .map(|matched_data| matched_data.as_str())
+                    .unwrap_or("Press enter to decrypt the matched data of the selected event");
+                let width = areas[1].width.saturating_sub(2).max(1) as usize;
+                page_height = areas[1].height.saturating_sub(2);
+                scroll = scroll.min(wrapped_line_count(matched_data, width).saturating_sub(1));
+                frame.render_widget(
+                    Paragraph::new(matched_data)
+                        .wrap(Wrap { trim: false })
+                        .scroll((scroll, 0))
+                        .block(Block::default().borders(Borders::ALL).title("Matched data")),
+                    areas[1],
+                );
+            })
+            .map_err(|_| "Failed to draw terminal")?;
+
+        let key = match event::read().map_err(|_| "Failed to read terminal input")? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Down | KeyCode::Char('j') => {
+                selected =
+                    selected.map(|selected| (selected + 1).min(events.len().saturating_sub(1)));
+                scroll = 0;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                selected = selected.map(|selected| selected.saturating_sub(1));
+                scroll = 0;
+            }
+            KeyCode::PageDown => scroll = scroll.saturating_add(page_height.max(1)),
+            KeyCode::PageUp => scroll = scroll.saturating_sub(page_height.max(1)),
+            KeyCode::Enter => {
+                if let Some(selected) = selected {
+                    let entries = &events[selected].encrypted_matched_data;
+                    let mut matched_data = Zeroizing::new(String::new());
+                    for (rule_id, encrypted_matched_data) in entries {
+                        // Each rule's matched data is headed by its rule ID when there are several
+                        if entries.len() > 1 {
+                            if !matched_data.is_empty() {
+                                matched_data.push_str("\n\n");
+                            }
+                            matched_data.push_str(&format!("Rule {}:\n", rule_id));
+                        }
+                        match encrypted_matched_data.as_str() {
+                            TRUNCATED => matched_data.push_str(
+                                "The payload match for this event is unavailable because it was too large.",
+                            ),
+                            _ => match decrypt(encrypted_matched_data) {
+                                Ok(plaintext) => {
+                                    matched_data.push_str(&String::from_utf8_lossy(&plaintext))
+                                }
+                                Err(e) => matched_data.push_str(&format!("Error: {}", e)),
+                            },
+                        }
+                    }
+                    if entries.is_empty() {
+                        matched_data.push_str("This event has no encrypted matched data");
+                    }
+                    decrypted.insert(selected, matched_data);
+                    scroll = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Counts the lines of text once wrapped to a width, to bound scrolling
+fn wrapped_line_count(text: &str, width: usize) -> u16 {
+    text.lines()
+        .map(|line| line.chars().count().max(1).div_ceil(width))
+        .sum::<usize>()
+        .min(u16::MAX as usize) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_events() {
+        let log = r#"{"Datetime":"2022-01-01T00:00:00Z","RuleID":"100000","Action":"block","Metadata":{"encrypted_matched_data":"AzTY"}}
+
+{"Datetime":"2022-01-01T00:00:01Z","RuleID":"100001","Action":"log"}
+"#;
+        let events = load_events(log.as_bytes()).unwrap();
+        assert_eq!(2, events.len());
+        assert_eq!("100000", events[0].rule_id);
+        assert_eq!("block", events[0].action);
+        assert_eq!(
+            vec![("100000".to_string(), "AzTY".to_string())],
+            events[0].encrypted_matched_data
+        );
+        assert!(events[1].encrypted_matched_data.is_empty());
+
+        let events = load_events(&br#"[{"ruleId":"abc","action":"block"}]"#[..]).unwrap();
+        assert_eq!("abc", events[0].rule_id);
+
+        let response = r#"{
+  "data": {
+    "viewer": {
+      "zones": [
+        {
+          "firewallEventsAdaptive": [
+            {"ruleId": "abc", "action": "block", "datetime": "2022-01-01T00:00:00Z", "metadata": [{"key": "encrypted_matched_data", "value": "AzTY"}]},
+            {"ruleId": "def", "action": "log", "datetime": "2022-01-01T00:00:01Z", "metadata": []}
+          ]
+        }
+      ]
+    }
+  },
+  "errors": null
+}"#;
+        let events = load_events(response.as_bytes()).unwrap();
+        assert_eq!(2, events.len());
+        assert_eq!("2022-01-01T00:00:00Z", events[0].timestamp);
+        assert_eq!(
+            vec![("abc".to_string(), "AzTY".to_string())],
+            events[0].encrypted_matched_data
+        );
+        assert_eq!("def", events[1].rule_id);
+
+        assert_eq!(
+            "API query failed: unknown field",
+            load_events(&br#"{"data":null,"errors":[{"message":"unknown field"}]}"#[..])
+                .err()
+                .unwrap()
+        );
+        assert!(load_events(&b"{\"RuleID\":\n"[..]).is_err());
+    }
+
+    #[test]
+    fn test_scroll_offset() {
+        assert_eq!(0, scroll_offset(0, 5, 10));
+        assert_eq!(6, scroll_offset(0, 15, 10));
+        assert_eq!(3, scroll_offset(6, 3, 10));
+        assert_eq!(6, scroll_offset(6, 15, 10));
+    }
+}
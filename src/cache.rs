@@ -0,0 +1,187 @@
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+const NONCE_LENGTH: usize = 12;
+
+// HKDF info deriving the key entries are encrypted with from the private key
+const KEY_INFO: &[u8] = b"matched-data-cli cache v1";
+
+// Plaintexts of decrypted matched data on disk, each named after the SHA-256 hash of its
+// ciphertext in a directory named after the first byte of the hash, and encrypted with a key
+// derived from the private key, so entries are only readable with the key that decrypted them
+pub struct Cache {
+    dir: PathBuf,
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl Cache {
+    pub fn open(dir: &str, private_key_bytes: &[u8]) -> Result<Self, String> {
+        create_private_dir(Path::new(dir))?;
+        let mut key = Zeroizing::new([0; 32]);
+        Hkdf::<Sha256>::new(None, private_key_bytes)
+            .expand(KEY_INFO, key.as_mut())
+            .expect("Cache key length is valid");
+
+        Ok(Cache {
+            dir: PathBuf::from(dir),
+            key,
+        })
+    }
+
+    fn entry(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2]).join(hash)
+    }
+
+    // Returns the plaintext of a ciphertext decrypted before, if any. Entries written with
+    // another key or corrupted are missed, and replaced once decrypted again
+    pub fn get(&self, ciphertext: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+        let hash = Sha256::digest(ciphertext);
+        let entry = fs::read(self.entry(&hex(&hash))).ok()?;
+        if entry.len() < NONCE_LENGTH {
+            return None;
+        }
+        let (nonce, sealed) = entry.split_at(NONCE_LENGTH);
+
+        ChaCha20Poly1305::new(Key::from_slice(self.key.as_ref()))
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: &hash,
+                },
+            )
+            .ok()
+            .map(Zeroizing::new)
+    }
+
+    // Stores the plaintext of a ciphertext, written under a temporary name then renamed so
+    // concurrent readers never see a partial entry
+    pub fn put(&self, ciphertext: &[u8], plaintext: &[u8]) -> Result<(), String> {
+        let hash = Sha256::digest(ciphertext);
+        let mut nonce = [0; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        // The hash is authenticated so an entry can't be swapped for another's
+        let sealed = ChaCha20Poly1305::new(Key::from_slice(self.key.as_ref()))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: &hash,
+                },
+            )
+            .map_err(|_| "Failed to encrypt cache entry")?;
+
+        let hash = hex(&hash);
+        let entry = self.entry(&hash);
+        let dir = entry.parent().expect("Cache entries are in a directory");
+        create_private_dir(dir)?;
+        let temporary = dir.join(format!("{}.{}.tmp", hash, hex(&nonce)));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&temporary)
+            .and_then(|mut file| file.write_all(&[&nonce[..], &sealed].concat()))
+            .and_then(|_| fs::rename(&temporary, &entry))
+            .map_err(|e| {
+                let _ = fs::remove_file(&temporary);
+                format!("Failed to write cache entry {}: {}", entry.display(), e)
+            })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn create_private_dir(dir: &Path) -> Result<(), String> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(dir)
+        .map_err(|e| format!("Failed to create cache directory {}: {}", dir.display(), e))
+}
+
+// Whether a file name is that of a cache entry or of one being written
+fn is_entry(name: &str) -> bool {
+    let hash = name.split('.').next().unwrap_or_default();
+    hash.len() == 64 && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+// Deletes every entry of a cache, returning how many there were and their size. Only files named
+// like entries are deleted, so a directory passed by mistake is left alone
+pub fn purge(dir: &str) -> Result<(usize, u64), String> {
+    let read_error = |e| format!("Failed to read cache directory {}: {}", dir, e);
+    let mut entries = 0;
+    let mut bytes = 0;
+    for subdir in fs::read_dir(dir).map_err(read_error)? {
+        let subdir = subdir.map_err(read_error)?;
+        let name = subdir.file_name().to_string_lossy().into_owned();
+        if name.len() != 2 || !subdir.path().is_dir() {
+            continue;
+        }
+
+        for entry in fs::read_dir(subdir.path()).map_err(read_error)? {
+            let entry = entry.map_err(read_error)?;
+            if !is_entry(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            bytes += entry.metadata().map_or(0, |metadata| metadata.len());
+            fs::remove_file(entry.path()).map_err(|e| {
+                format!(
+                    "Failed to delete cache entry {}: {}",
+                    entry.path().display(),
+                    e
+                )
+            })?;
+            entries += 1;
+        }
+        // Left in place if it holds anything else
+        let _ = fs::remove_dir(subdir.path());
+    }
+
+    Ok((entries, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_put_purge() {
+        let dir = std::env::temp_dir().join(format!("matched-data-cache-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let cache = Cache::open(dir, b"private key").unwrap();
+        assert!(cache.get(b"ciphertext").is_none());
+
+        cache.put(b"ciphertext", b"plaintext").unwrap();
+        assert_eq!(b"plaintext".to_vec(), *cache.get(b"ciphertext").unwrap());
+        assert!(cache.get(b"other ciphertext").is_none());
+        // Entries are never plaintext, nor readable with another key
+        let hash = hex(&Sha256::digest(b"ciphertext"));
+        assert!(!fs::read(cache.entry(&hash))
+            .unwrap()
+            .windows(9)
+            .any(|window| window == b"plaintext"));
+        assert!(Cache::open(dir, b"other private key")
+            .unwrap()
+            .get(b"ciphertext")
+            .is_none());
+
+        fs::write(Path::new(dir).join("notes.txt"), "kept").unwrap();
+        assert_eq!(1, purge(dir).unwrap().0);
+        assert!(cache.get(b"ciphertext").is_none());
+        assert!(Path::new(dir).join("notes.txt").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
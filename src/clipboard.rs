@@ -0,0 +1,131 @@
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+// Commands pasting from and copying to the clipboard, each starting with the program, usable
+// when the environment variable of their display server, if any, is set
+struct Tool {
+    paste: &'static [&'static str],
+    copy: &'static [&'static str],
+    display: Option<&'static str>,
+}
+
+#[cfg(target_os = "macos")]
+const TOOLS: &[Tool] = &[Tool {
+    paste: &["pbpaste"],
+    copy: &["pbcopy"],
+    display: None,
+}];
+
+#[cfg(windows)]
+const TOOLS: &[Tool] = &[Tool {
+    paste: &[
+        "powershell",
+        "-NoProfile",
+        "-Command",
+        "[Console]::OutputEncoding = [Text.Encoding]::UTF8; Get-Clipboard -Raw",
+    ],
+    copy: &[
+        "powershell",
+        "-NoProfile",
+        "-Command",
+        "[Console]::InputEncoding = [Text.Encoding]::UTF8; Set-Clipboard -Value ([Console]::In.ReadToEnd())",
+    ],
+    display: None,
+}];
+
+#[cfg(not(any(target_os = "macos", windows)))]
+const TOOLS: &[Tool] = &[
+    Tool {
+        paste: &["wl-paste", "--no-newline"],
+        copy: &["wl-copy"],
+        display: Some("WAYLAND_DISPLAY"),
+    },
+    Tool {
+        paste: &["xclip", "-selection", "clipboard", "-out"],
+        copy: &["xclip", "-selection", "clipboard", "-in"],
+        display: Some("DISPLAY"),
+    },
+    Tool {
+        paste: &["xsel", "--clipboard", "--output"],
+        copy: &["xsel", "--clipboard", "--input"],
+        display: Some("DISPLAY"),
+    },
+];
+
+#[cfg(not(any(target_os = "macos", windows)))]
+const NO_TOOL: &str =
+    "No clipboard tool found, install wl-clipboard on Wayland, or xclip or xsel on X11";
+#[cfg(any(target_os = "macos", windows))]
+const NO_TOOL: &str = "No clipboard tool found";
+
+fn usable_tools() -> impl Iterator<Item = &'static Tool> {
+    TOOLS.iter().filter(|tool| {
+        tool.display
+            .is_none_or(|display| env::var_os(display).is_some())
+    })
+}
+
+fn command(args: &'static [&'static str]) -> (&'static str, Command) {
+    let (program, args) = args.split_first().expect("Clipboard command is empty");
+    let mut command = Command::new(program);
+    command.args(args);
+    (program, command)
+}
+
+// Reads the text on the clipboard with the first clipboard tool installed
+pub fn paste() -> Result<String, String> {
+    for tool in usable_tools() {
+        let (program, mut command) = command(tool.paste);
+        let output = match command.stdin(Stdio::null()).output() {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(_) => return Err(format!("Failed to run {}", program)),
+            Ok(output) => output,
+        };
+        if !output.status.success() {
+            return Err(match String::from_utf8_lossy(&output.stderr).trim() {
+                "" => format!("{} failed", program),
+                stderr => format!("{} failed: {}", program, stderr),
+            });
+        }
+        return String::from_utf8(output.stdout)
+            .map_err(|_| "Clipboard does not contain text".to_string());
+    }
+
+    Err(NO_TOOL.to_string())
+}
+
+// Places text on the clipboard with the first clipboard tool installed
+pub fn copy(text: &str) -> Result<(), String> {
+    for tool in usable_tools() {
+        let (program, mut command) = command(tool.copy);
+        // X11 and Wayland tools stay in the background to serve the clipboard, so their output
+        // is not captured, as waiting for it to close would wait for them to exit
+        let mut child = match command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(_) => return Err(format!("Failed to run {}", program)),
+            Ok(child) => child,
+        };
+        // Dropping stdin once written lets the tool see the end of the text
+        child
+            .stdin
+            .take()
+            .expect("Failed to open stdin")
+            .write_all(text.as_bytes())
+            .map_err(|_| format!("Failed to write to {}", program))?;
+        let status = child
+            .wait()
+            .map_err(|_| format!("Failed to run {}", program))?;
+        if !status.success() {
+            return Err(format!("{} failed", program));
+        }
+        return Ok(());
+    }
+
+    Err(NO_TOOL.to_string())
+}
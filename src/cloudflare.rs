@@ -0,0 +1,108 @@
+use serde_json::Value;
+
+// Base URL of the Cloudflare API
+const API_URL: &str = "https://api.cloudflare.com/client/v4";
+
+// Phase of the managed rulesets whose execute rules configure payload logging
+const MANAGED_PHASE: &str = "http_request_firewall_managed";
+
+// Fetches the payload logging public keys configured on a zone's managed rulesets
+pub fn fetch_payload_logging_public_keys(
+    api_token: &str,
+    zone_id: &str,
+) -> Result<Vec<String>, String> {
+    let url = format!(
+        "{}/zones/{}/rulesets/phases/{}/entrypoint",
+        API_URL, zone_id, MANAGED_PHASE
+    );
+    let response: Value = match ureq::get(&url)
+        .set("Authorization", &format!("Bearer {}", api_token))
+        .call()
+    {
+        Ok(response) => response
+            .into_json()
+            .map_err(|_| "Cloudflare API response is not JSON")?,
+        Err(ureq::Error::Status(404, _)) => {
+            return Err("Zone has no managed rulesets deployed".to_string())
+        }
+        Err(ureq::Error::Status(status, response)) => {
+            return Err(format!(
+                "Cloudflare API request failed with status {}: {}",
+                status,
+                api_errors(&response.into_json().unwrap_or(Value::Null))
+            ))
+        }
+        Err(e) => return Err(format!("Cloudflare API request failed: {}", e)),
+    };
+
+    Ok(payload_logging_public_keys(&response))
+}
+
+// Collects the public keys of the execute rules of a ruleset entrypoint response
+fn payload_logging_public_keys(response: &Value) -> Vec<String> {
+    let rules = response["result"]["rules"].as_array().cloned();
+
+    let mut public_keys: Vec<String> = rules
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|rule| {
+            rule["action_parameters"]["matched_data"]["public_key"]
+                .as_str()
+                .map(str::to_string)
+        })
+        .collect();
+    public_keys.dedup();
+    public_keys
+}
+
+// Joins the error messages of a Cloudflare API response
+fn api_errors(response: &Value) -> String {
+    response["errors"]
+        .as_array()
+        .map(|errors| {
+            errors
+                .iter()
+                .filter_map(|error| error["message"].as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_logging_public_keys() {
+        let response: Value = serde_json::from_str(
+            r#"{
+                "result": {
+                    "rules": [
+                        {
+                            "action": "execute",
+                            "action_parameters": {
+                                "id": "efb7b8c949ac4650a09736fc376e9aee",
+                                "matched_data": {
+                                    "public_key": "Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8="
+                                }
+                            }
+                        },
+                        {
+                            "action": "execute",
+                            "action_parameters": { "id": "4814384a9e5d4991b9815dcfc25d2f1f" }
+                        }
+                    ]
+                },
+                "success": true,
+                "errors": []
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec!["Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=".to_string()],
+            payload_logging_public_keys(&response)
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use crate::output;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Defaults read from the configuration file, overridden by command line flags
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    // File containing the base64 encoded private key
+    pub private_key: Option<String>,
+    // Output format of decrypted matched data
+    pub output_format: Option<String>,
+    // Cloudflare API token
+    #[cfg_attr(not(feature = "api"), allow(dead_code))]
+    pub api_token: Option<String>,
+    // Cloudflare zone ID
+    #[cfg_attr(not(feature = "api"), allow(dead_code))]
+    pub zone_id: Option<String>,
+    // Cloudflare account ID of R2 buckets
+    pub r2_account_id: Option<String>,
+    // File each decryption is recorded in
+    pub audit_log: Option<String>,
+    // Directory decrypted matched data is cached in
+    pub cache_dir: Option<String>,
+    // Fail rather than warn when a private key has expired
+    #[serde(default)]
+    pub enforce_expiry: bool,
+    // Files containing the private keys of zones, by zone ID, or of keys tried for events of any
+    // zone, by key fingerprint
+    #[serde(default)]
+    pub keys: BTreeMap<String, String>,
+}
+
+impl Config {
+    // Returns the private key file from the command line, falling back to the configuration file
+    pub fn private_key_filename(
+        &self,
+        private_key_filename: Option<String>,
+    ) -> Result<String, String> {
+        private_key_filename
+            .or_else(|| self.private_key.clone())
+            .ok_or_else(|| {
+                "No private key provided, pass -k or set private_key in the configuration file"
+                    .to_string()
+            })
+    }
+
+    // Returns the output format from the command line, falling back to the configuration file
+    pub fn output_format(&self, output_format: Option<String>) -> Result<Option<String>, String> {
+        match (output_format, &self.output_format) {
+            (Some(output_format), _) => Ok(Some(output_format)),
+            (None, Some(name)) => {
+                let output_format = name.to_ascii_lowercase();
+                if output::find(&output_format).is_none() && output_format != output::NDJSON {
+                    return Err(format!(
+                        "Output format '{}' in the configuration file is invalid",
+                        name
+                    ));
+                }
+                Ok(Some(output_format))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+// Home directory of the current user
+pub fn home_dir() -> Option<PathBuf> {
+    match env::var_os("HOME") {
+        Some(home) => Some(PathBuf::from(home)),
+        None if cfg!(windows) => env::var_os("USERPROFILE").map(PathBuf::from),
+        None => None,
+    }
+}
+
+// Expands a leading ~/ to the home directory, as a shell would for paths in the configuration file
+fn expand_home(path: String) -> String {
+    match (path.strip_prefix("~/"), home_dir()) {
+        (Some(relative_path), Some(home)) => {
+            home.join(relative_path).to_string_lossy().into_owned()
+        }
+        _ => path,
+    }
+}
+
+// Path of the configuration file used when --config is not passed
+fn default_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config_dir) => PathBuf::from(config_dir),
+        None if cfg!(windows) => PathBuf::from(env::var_os("APPDATA")?),
+        None => home_dir()?.join(".config"),
+    };
+
+    Some(config_dir.join("matched-data-cli").join("config.toml"))
+}
+
+// Loads the configuration file, which is optional unless its path is passed explicitly
+pub fn load(path: Option<&str>) -> Result<Config, String> {
+    let contents = match path {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|_| format!("Failed to read configuration file {}", path))?,
+        None => match default_path().and_then(|path| fs::read_to_string(path).ok()) {
+            Some(contents) => contents,
+            None => return Ok(Config::default()),
+        },
+    };
+
+    parse(&contents)
+}
+
+// Parses the contents of a configuration file, expanding ~/ in paths
+fn parse(contents: &str) -> Result<Config, String> {
+    let mut config: Config =
+        toml::from_str(contents).map_err(|e| format!("Configuration file is invalid: {}", e))?;
+    config.private_key = config.private_key.map(expand_home);
+    config.audit_log = config.audit_log.map(expand_home);
+    config.cache_dir = config.cache_dir.map(expand_home);
+    for filename in config.keys.values_mut() {
+        *filename = expand_home(std::mem::take(filename));
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config = parse(
+            "private_key = \"~/keys/zone-a.key\"\noutput_format = \"base64\"\nzone_id = \"abc\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            "other.key",
+            config
+                .private_key_filename(Some("other.key".to_string()))
+                .unwrap()
+        );
+        assert_eq!(
+            home_dir()
+                .unwrap()
+                .join("keys/zone-a.key")
+                .to_string_lossy(),
+            config.private_key_filename(None).unwrap()
+        );
+        assert!(!config.private_key_filename(None).unwrap().starts_with('~'));
+        assert_eq!(
+            "keys/~/zone-a.key",
+            parse("private_key = \"keys/~/zone-a.key\"")
+                .unwrap()
+                .private_key_filename(None)
+                .unwrap()
+        );
+        assert!(parse("private_kee = \"typo\"").is_err());
+
+        let config = parse("[keys]\n023e105f4ecef8ad9ca31a8372d0c353 = \"~/keys/zone-a.key\"\n064f06a2 = \"b.key\"\n").unwrap();
+        assert_eq!(
+            home_dir()
+                .unwrap()
+                .join("keys/zone-a.key")
+                .to_string_lossy(),
+            config.keys["023e105f4ecef8ad9ca31a8372d0c353"]
+        );
+        assert_eq!("b.key", config.keys["064f06a2"]);
+    }
+}
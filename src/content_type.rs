@@ -0,0 +1,140 @@
+// Kind of payload detected in decrypted matched data
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContentType {
+    Jpeg,
+    Png,
+    Gzip,
+    Protobuf,
+    Text,
+    Binary,
+}
+
+impl ContentType {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ContentType::Jpeg => "image/jpeg",
+            ContentType::Png => "image/png",
+            ContentType::Gzip => "application/gzip",
+            ContentType::Protobuf => "application/x-protobuf",
+            ContentType::Text => "text/plain",
+            ContentType::Binary => "application/octet-stream",
+        }
+    }
+
+    // File extension of payloads written to --output-dir
+    pub fn extension(self) -> &'static str {
+        match self {
+            ContentType::Jpeg => "jpg",
+            ContentType::Png => "png",
+            ContentType::Gzip => "gz",
+            ContentType::Protobuf => "pb",
+            ContentType::Text => "txt",
+            ContentType::Binary => "bin",
+        }
+    }
+
+    pub fn is_binary(self) -> bool {
+        self != ContentType::Text
+    }
+}
+
+// Detects the content type of decrypted matched data from its magic bytes, falling back to
+// UTF-8 text and then to a well-formed protobuf message
+pub fn sniff(data: &[u8]) -> ContentType {
+    if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        ContentType::Jpeg
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        ContentType::Png
+    } else if data.starts_with(&[0x1f, 0x8b]) {
+        ContentType::Gzip
+    } else if is_text(data) {
+        ContentType::Text
+    } else if is_protobuf(data) {
+        ContentType::Protobuf
+    } else {
+        ContentType::Binary
+    }
+}
+
+// Whether data is UTF-8 without control characters other than whitespace
+pub fn is_text(data: &[u8]) -> bool {
+    match std::str::from_utf8(data) {
+        Ok(text) => text
+            .chars()
+            .all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r')),
+        Err(_) => false,
+    }
+}
+
+// Whether data parses as a sequence of protobuf fields, using every byte
+fn is_protobuf(mut data: &[u8]) -> bool {
+    if data.is_empty() {
+        return false;
+    }
+
+    while !data.is_empty() {
+        let key = match read_varint(&mut data) {
+            Some(key) => key,
+            None => return false,
+        };
+        if key >> 3 == 0 {
+            return false;
+        }
+        let length = match key & 7 {
+            0 => match read_varint(&mut data) {
+                Some(_) => 0,
+                None => return false,
+            },
+            1 => 8,
+            2 => match read_varint(&mut data) {
+                Some(length) => length,
+                None => return false,
+            },
+            5 => 4,
+            _ => return false,
+        };
+        if length > data.len() as u64 {
+            return false;
+        }
+        data = &data[length as usize..];
+    }
+
+    true
+}
+
+// Reads a base 128 varint from the front of data
+fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for (i, &byte) in data.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            *data = &data[i + 1..];
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(ContentType::Jpeg, sniff(&[0xff, 0xd8, 0xff, 0xe0, 0, 0x10]));
+        assert_eq!(ContentType::Png, sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert_eq!(ContentType::Gzip, sniff(&[0x1f, 0x8b, 8, 0]));
+        assert_eq!(
+            ContentType::Text,
+            sniff(b"{\"a\": \"test matched data\"}\n")
+        );
+        // Field 1 varint 150, field 2 string "é"
+        assert_eq!(
+            ContentType::Protobuf,
+            sniff(&[0x08, 0x96, 0x01, 0x12, 2, 0xc3, 0xa9])
+        );
+        assert_eq!(ContentType::Binary, sniff(&[0x12, 0x10, 0x00]));
+        assert_eq!(ContentType::Binary, sniff(&[0x00, 0xff]));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::logs::Decrypt;
+use crate::progress::Progress;
+use crate::TRUNCATED;
+use std::io::BufRead;
+use zeroize::Zeroizing;
+
+// Column added to each row holding its decrypted matched data
+const DECRYPTED_MATCHED_DATA: &str = "decrypted_matched_data";
+
+// Column added with --continue-on-error holding why a row couldn't be decrypted
+const DECRYPTION_ERROR: &str = "decryption_error";
+
+// Byte order mark spreadsheet applications put at the start of UTF-8 exports
+const BYTE_ORDER_MARK: char = '\u{feff}';
+
+// Reads the next record of an RFC 4180 CSV file, whose quoted fields may span several lines
+pub fn read_record<R: BufRead>(reader: &mut R) -> Result<Option<Vec<String>>, String> {
+    let mut record = String::new();
+    loop {
+        let read = reader
+            .read_line(&mut record)
+            .map_err(|_| "Failed to read CSV file")?;
+        if read == 0 {
+            if record.is_empty() {
+                return Ok(None);
+            }
+            break;
+        }
+        // A quoted field is still open while the number of quotes is odd
+        if record.matches('"').count().is_multiple_of(2) {
+            break;
+        }
+    }
+
+    let record = record.trim_end_matches(['\r', '\n']);
+    Ok(Some(parse_record(record)))
+}
+
+// Splits a record into its fields, unquoting quoted ones
+fn parse_record(record: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = record.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            (c, _) => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+// Joins fields into a record, quoting those that contain separators, quotes or line breaks
+pub fn write_record(fields: &[&str]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Finds the column holding the encrypted matched data, by name, by 1-based index or, by default,
+// as the first column whose name mentions matched data
+pub fn find_column(header: &[String], column: Option<&str>) -> Result<usize, String> {
+    match column {
+        Some(column) => match column.parse::<usize>() {
+            Ok(index) if (1..=header.len()).contains(&index) => Ok(index - 1),
+            Ok(index) => Err(format!(
+                "Column {} is out of range, the CSV file has {} columns",
+                index,
+                header.len()
+            )),
+            Err(_) => header
+                .iter()
+                .position(|name| name == column)
+                .ok_or_else(|| format!("The CSV file has no column named {}", column)),
+        },
+        None => header
+            .iter()
+            .position(|name| {
+                let name = name.to_lowercase().replace(['_', ' '], "");
+                name.contains("matcheddata")
+            })
+            .ok_or_else(|| {
+                "The CSV file has no matched data column, select one with --column".to_string()
+            }),
+    }
+}
+
+// Decrypts the matched data column of each row of a CSV file, printing the rows with an added
+// column holding the plaintext, empty for rows without matched data. Unless continuing on errors,
+// the first row that fails stops processing
+pub fn process_csv<R: BufRead>(
+    mut reader: R,
+    column: Option<&str>,
+    continue_on_error: bool,
+    progress: &mut Progress,
+    decrypt: &Decrypt<'_>,
+) -> Result<(), String> {
+    let mut header = read_record(&mut reader)?.ok_or("The CSV file is empty")?;
+    if let Some(name) = header.first_mut() {
+        *name = name.trim_start_matches(BYTE_ORDER_MARK).to_string();
+    }
+    let index = find_column(&header, column)?;
+
+    let mut header: Vec<&str> = header.iter().map(String::as_str).collect();
+    header.push(DECRYPTED_MATCHED_DATA);
+    if continue_on_error {
+        header.push(DECRYPTION_ERROR);
+    }
+    println!("{}", write_record(&header));
+
+    let mut row_number = 1;
+    let mut failures = 0;
+    while let Some(row) = read_record(&mut reader)? {
+        row_number += 1;
+        if row.iter().all(String::is_empty) {
+            continue;
+        }
+
+        let mut error = String::new();
+        let mut decrypted = true;
+        let matched_data = Zeroizing::new(match row.get(index).map(|cell| cell.trim()) {
+            Some("") | Some(TRUNCATED) | None => String::new(),
+            Some(encrypted_matched_data) => match decrypt(encrypted_matched_data) {
+                Ok(matched_data) => String::from_utf8_lossy(&matched_data).into_owned(),
+                Err(e) if continue_on_error => {
+                    failures += 1;
+                    decrypted = false;
+                    error = e;
+                    String::new()
+                }
+                Err(e) => {
+                    progress.record(true);
+                    return Err(format!("Row {}: {}", row_number, e));
+                }
+            },
+        });
+
+        let mut fields: Vec<&str> = row.iter().map(String::as_str).collect();
+        fields.push(matched_data.as_str());
+        if continue_on_error {
+            fields.push(&error);
+        }
+        let record = Zeroizing::new(write_record(&fields));
+        println!("{}", record.as_str());
+        progress.record(!decrypted);
+    }
+    progress.finish();
+
+    match failures {
+        0 => Ok(()),
+        _ => Err(format!(
+            "Failed to decrypt {} of {} rows",
+            failures,
+            row_number - 1
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_record() {
+        let mut csv = "a,\"b, \"\"c\"\"\",\"d\ne\"\r\n1,,3\n".as_bytes();
+        assert_eq!(
+            Some(vec![
+                "a".to_string(),
+                "b, \"c\"".to_string(),
+                "d\ne".to_string()
+            ]),
+            read_record(&mut csv).unwrap()
+        );
+        assert_eq!(
+            Some(vec!["1".to_string(), String::new(), "3".to_string()]),
+            read_record(&mut csv).unwrap()
+        );
+        assert_eq!(None, read_record(&mut csv).unwrap());
+    }
+
+    #[test]
+    fn test_write_record() {
+        assert_eq!(
+            "a,\"b, \"\"c\"\"\",\"d\ne\"",
+            write_record(&["a", "b, \"c\"", "d\ne"])
+        );
+    }
+
+    #[test]
+    fn test_find_column() {
+        let header = vec!["Ray ID".to_string(), "Encrypted matched data".to_string()];
+        assert_eq!(1, find_column(&header, None).unwrap());
+        assert_eq!(0, find_column(&header, Some("Ray ID")).unwrap());
+        assert_eq!(0, find_column(&header, Some("1")).unwrap());
+        assert!(find_column(&header, Some("3")).is_err());
+        assert!(find_column(&header, Some("Action")).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use crate::output;
+use std::fmt::Write;
+use std::str;
+use zeroize::Zeroizing;
+
+// Unchanged lines printed around each change, as diff -u does
+const CONTEXT: usize = 3;
+
+// Largest number of line pairs compared to find the smallest diff, beyond which the differing
+// middle of the payloads is printed as removed then added lines so memory stays bounded
+const MAX_COMPARISONS: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Edit {
+    Same,
+    Removed,
+    Added,
+}
+
+// Offset of the first byte that differs between two payloads, or the length of the shorter one
+// if it is a prefix of the other, None if they are identical
+pub fn first_difference(expected: &[u8], actual: &[u8]) -> Option<usize> {
+    if expected == actual {
+        return None;
+    }
+    Some(
+        expected
+            .iter()
+            .zip(actual)
+            .position(|(a, b)| a != b)
+            .unwrap_or_else(|| expected.len().min(actual.len())),
+    )
+}
+
+// Diffs two payloads, line by line in unified format if both are UTF-8 text and bytes is false,
+// otherwise as the hexdump rows that differ
+pub fn diff(
+    expected: &[u8],
+    actual: &[u8],
+    labels: (&str, &str),
+    bytes: bool,
+) -> Zeroizing<String> {
+    let mut diff = Zeroizing::new(format!("--- {}\n+++ {}\n", labels.0, labels.1));
+    match (str::from_utf8(expected), str::from_utf8(actual)) {
+        (Ok(expected), Ok(actual)) if !bytes => write_text_diff(&mut diff, expected, actual),
+        _ => write_byte_diff(&mut diff, expected, actual),
+    }
+    diff
+}
+
+fn write_text_diff(diff: &mut String, expected: &str, actual: &str) {
+    // Lines keep their line break, so a missing one at the end is a difference
+    let expected: Vec<&str> = expected.split_inclusive('\n').collect();
+    let actual: Vec<&str> = actual.split_inclusive('\n').collect();
+    let edits = edits(&expected, &actual);
+
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|&i| edits[i].0 != Edit::Same)
+        .collect();
+    let mut i = 0;
+    while i < changes.len() {
+        // Changes separated by no more than twice the context share a hunk
+        let mut last = i;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * CONTEXT + 1 {
+            last += 1;
+        }
+        let start = changes[i].saturating_sub(CONTEXT);
+        let end = (changes[last] + CONTEXT + 1).min(edits.len());
+        write_hunk(diff, &edits, start, end);
+        i = last + 1;
+    }
+}
+
+// Shortest edit script turning the expected lines into the actual ones, from their longest
+// common subsequence, with the line numbers each edit starts at
+fn edits<'a>(expected: &[&'a str], actual: &[&'a str]) -> Vec<(Edit, &'a str, usize, usize)> {
+    let prefix = expected
+        .iter()
+        .zip(actual)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = expected[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let removed = &expected[prefix..expected.len() - suffix];
+    let added = &actual[prefix..actual.len() - suffix];
+
+    // lengths[i][j] is the length of the longest common subsequence of removed[i..] and added[j..]
+    let width = added.len() + 1;
+    let mut lengths = Vec::new();
+    if removed.len().saturating_mul(added.len()) <= MAX_COMPARISONS {
+        lengths = vec![0u32; (removed.len() + 1) * width];
+        for i in (0..removed.len()).rev() {
+            for j in (0..added.len()).rev() {
+                lengths[i * width + j] = if removed[i] == added[j] {
+                    lengths[(i + 1) * width + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+                };
+            }
+        }
+    }
+    let length = |i: usize, j: usize| lengths.get(i * width + j).copied().unwrap_or(0);
+
+    let mut edits = Vec::new();
+    for (k, line) in expected[..prefix].iter().enumerate() {
+        edits.push((Edit::Same, *line, k, k));
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < removed.len() || j < added.len() {
+        let (old, new) = (prefix + i, prefix + j);
+        if i < removed.len() && j < added.len() && removed[i] == added[j] && !lengths.is_empty() {
+            edits.push((Edit::Same, removed[i], old, new));
+            i += 1;
+            j += 1;
+        } else if i < removed.len() && (j == added.len() || length(i + 1, j) >= length(i, j + 1)) {
+            edits.push((Edit::Removed, removed[i], old, new));
+            i += 1;
+        } else {
+            edits.push((Edit::Added, added[j], old, new));
+            j += 1;
+        }
+    }
+    for k in 0..suffix {
+        edits.push((
+            Edit::Same,
+            expected[expected.len() - suffix + k],
+            expected.len() - suffix + k,
+            actual.len() - suffix + k,
+        ));
+    }
+    edits
+}
+
+fn write_hunk(diff: &mut String, edits: &[(Edit, &str, usize, usize)], start: usize, end: usize) {
+    let hunk = &edits[start..end];
+    let (_, _, old_start, new_start) = hunk[0];
+    let old_count = hunk.iter().filter(|edit| edit.0 != Edit::Added).count();
+    let new_count = hunk.iter().filter(|edit| edit.0 != Edit::Removed).count();
+    // An empty range starts at the line before it, as in diff -u
+    writeln!(
+        diff,
+        "@@ -{},{} +{},{} @@",
+        old_start + usize::from(old_count > 0),
+        old_count,
+        new_start + usize::from(new_count > 0),
+        new_count
+    )
+    .expect("Failed to format diff");
+
+    for (edit, line, _, _) in hunk {
+        diff.push(match edit {
+            Edit::Same => ' ',
+            Edit::Removed => '-',
+            Edit::Added => '+',
+        });
+        diff.push_str(line);
+        if !line.ends_with('\n') {
+            diff.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+// Writes the 16 byte rows that differ at each offset, the expected row then the actual one
+fn write_byte_diff(diff: &mut String, expected: &[u8], actual: &[u8]) {
+    let rows = expected.len().max(actual.len()).div_ceil(16);
+    for row in 0..rows {
+        let offset = row * 16;
+        let expected_row = expected.get(offset..).unwrap_or_default();
+        let actual_row = actual.get(offset..).unwrap_or_default();
+        let expected_row = &expected_row[..expected_row.len().min(16)];
+        let actual_row = &actual_row[..actual_row.len().min(16)];
+        if expected_row == actual_row {
+            continue;
+        }
+        for (sign, row) in [('-', expected_row), ('+', actual_row)] {
+            if !row.is_empty() {
+                diff.push(sign);
+                output::write_hexdump_row(diff, offset, row);
+                diff.push('\n');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_difference() {
+        assert_eq!(None, first_difference(b"test", b"test"));
+        assert_eq!(Some(2), first_difference(b"test", b"text"));
+        assert_eq!(Some(4), first_difference(b"test", b"tests"));
+    }
+
+    #[test]
+    fn test_text_diff() {
+        assert_eq!(
+            "--- expected\n+++ decrypted\n\
+             @@ -2,5 +2,5 @@\n b\n c\n d\n-e\n+E\n f\n",
+            *diff(
+                b"a\nb\nc\nd\ne\nf\n",
+                b"a\nb\nc\nd\nE\nf\n",
+                ("expected", "decrypted"),
+                false
+            )
+        );
+        assert_eq!(
+            "--- expected\n+++ decrypted\n\
+             @@ -1,2 +1,2 @@\n a\n-b\n+b\n\\ No newline at end of file\n",
+            *diff(b"a\nb\n", b"a\nb", ("expected", "decrypted"), false)
+        );
+        assert_eq!(
+            "--- expected\n+++ decrypted\n@@ -0,0 +1,1 @@\n+a\n",
+            *diff(b"", b"a\n", ("expected", "decrypted"), false)
+        );
+    }
+
+    #[test]
+    fn test_byte_diff() {
+        assert_eq!(
+            "--- expected\n+++ decrypted\n\
+             -00000000  74 65 73 74                                       |test|\n\
+             +00000000  74 65 78 74 00                                    |text.|\n",
+            *diff(b"test", b"text\0", ("expected", "decrypted"), true)
+        );
+    }
+}
@@ -0,0 +1,407 @@
+use hpke::Serializable;
+use matched_data_cli::matched_data::{
+    decrypt_any_version, deserialize_encrypted_data, encrypt_data, generate_key_pair,
+    get_public_key_from_bytes, serialize_encrypted_data, CurrentSuite, DecryptAnyVersionError,
+    DecryptError, MalformedData,
+};
+use matched_data_cli::versions::supported_versions;
+use std::str;
+
+const PRIVATE_KEY_LENGTH: usize = 32;
+const PUBLIC_KEY_LENGTH: usize = 32;
+
+// X25519 public keys of small order, with the ignored top bit cleared. Encrypting to them gives a
+// shared secret anyone can compute
+const LOW_ORDER_POINTS: [[u8; PUBLIC_KEY_LENGTH]; 7] = [
+    [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ],
+    [
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ],
+    [
+        0xe0, 0xeb, 0x7a, 0x7c, 0x3b, 0x41, 0xb8, 0xae, 0x16, 0x56, 0xe3, 0xfa, 0xf1, 0x9f, 0xc4,
+        0x6a, 0xda, 0x09, 0x8d, 0xeb, 0x9c, 0x32, 0xb1, 0xfd, 0x86, 0x62, 0x05, 0x16, 0x5f, 0x49,
+        0xb8, 0x00,
+    ],
+    [
+        0x5f, 0x9c, 0x95, 0xbc, 0xa3, 0x50, 0x8c, 0x24, 0xb1, 0xd0, 0xb1, 0x55, 0x9c, 0x83, 0xef,
+        0x5b, 0x04, 0x44, 0x5c, 0xc4, 0x58, 0x1c, 0x8e, 0x86, 0xd8, 0x22, 0x4e, 0xdd, 0xd0, 0x9f,
+        0x11, 0x57,
+    ],
+    [
+        0xec, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+    [
+        0xed, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+    [
+        0xee, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+];
+
+// Payload encrypted when checking a public key
+const PAYLOAD: &[u8] = b"test matched data";
+
+// A likely problem with the provided input and how to fix it
+pub struct Finding {
+    pub problem: String,
+    pub fix: String,
+}
+
+impl Finding {
+    fn new(problem: String, fix: String) -> Self {
+        Finding { problem, fix }
+    }
+}
+
+// Examines a private key and matched data for common copy-paste and encoding problems
+pub fn diagnose(
+    private_key_filename: &str,
+    private_key: &str,
+    matched_data_filename: &str,
+    matched_data: &str,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if matched_data.trim() == "truncated" {
+        findings.push(Finding::new(
+            format!(
+                "{} holds no matched data, the payload was too large to be logged",
+                matched_data_filename
+            ),
+            "Nothing to decrypt for this event".to_string(),
+        ));
+        return findings;
+    }
+
+    let private_key_bytes = check_encoding(private_key_filename, private_key, &mut findings);
+    let matched_data_bytes = check_encoding(matched_data_filename, matched_data, &mut findings);
+
+    let (private_key_bytes, matched_data_bytes) = match (private_key_bytes, matched_data_bytes) {
+        (Some(private_key_bytes), Some(matched_data_bytes)) => {
+            (private_key_bytes, matched_data_bytes)
+        }
+        _ => return findings,
+    };
+
+    if private_key_bytes.len() != PRIVATE_KEY_LENGTH {
+        if matched_data_bytes.len() == PRIVATE_KEY_LENGTH {
+            findings.push(Finding::new(
+                "The private key and matched data files appear to be swapped".to_string(),
+                format!(
+                    "matched-data-cli decrypt -k {} {}",
+                    matched_data_filename, private_key_filename
+                ),
+            ));
+        } else {
+            findings.push(Finding::new(
+                format!(
+                    "{} decodes to {} bytes, expected a {} byte private key",
+                    private_key_filename,
+                    private_key_bytes.len(),
+                    PRIVATE_KEY_LENGTH
+                ),
+                "Use the \"private_key\" value printed by generate-key-pair".to_string(),
+            ));
+        }
+        return findings;
+    }
+
+    let problem = match decrypt_any_version(&private_key_bytes, &matched_data_bytes) {
+        Ok(_) => return findings,
+        Err(e) => e,
+    };
+    findings.push(match problem {
+        DecryptAnyVersionError::UnsupportedVersion(version) => Finding::new(
+            format!(
+                "{} uses encryption format version '{}', expected one of {:?}",
+                matched_data_filename,
+                version,
+                supported_versions()
+            ),
+            "Check that the file contains the encrypted matched data field of the event"
+                .to_string(),
+        ),
+        DecryptAnyVersionError::Empty => Finding::new(
+            format!("{} is empty", matched_data_filename),
+            "Copy the encrypted matched data field of the event into the file".to_string(),
+        ),
+        DecryptAnyVersionError::InvalidPrivateKey(_) => Finding::new(
+            format!("{} is not a valid private key", private_key_filename),
+            "Use the \"private_key\" value printed by generate-key-pair".to_string(),
+        ),
+        // doctor decrypts in base mode, without a sender public key
+        DecryptAnyVersionError::InvalidSenderPublicKey(_) => {
+            unreachable!("No sender public key is provided")
+        }
+        DecryptAnyVersionError::InvalidMatchedData(e @ MalformedData::TrailingBytes(_)) => {
+            Finding::new(
+                format!("{} is malformed, {}", matched_data_filename, e),
+                "Copy the encrypted matched data field of a single event".to_string(),
+            )
+        }
+        DecryptAnyVersionError::InvalidMatchedData(e) => Finding::new(
+            format!("{} is malformed or truncated, {}", matched_data_filename, e),
+            "Copy the complete encrypted matched data field of the event".to_string(),
+        ),
+        DecryptAnyVersionError::Decrypt {
+            error: DecryptError::Decapsulation(_),
+            ..
+        } => Finding::new(
+            format!("{} contains an invalid encapsulated key", matched_data_filename),
+            "Copy the complete encrypted matched data field of the event".to_string(),
+        ),
+        DecryptAnyVersionError::Decrypt {
+            error: DecryptError::Open(_),
+            ..
+        } => Finding::new(
+            format!(
+                "{} was not encrypted for the public key of {}",
+                matched_data_filename, private_key_filename
+            ),
+            "Use the private key matching the public key configured on the zone when the event was logged"
+                .to_string(),
+        ),
+    });
+
+    findings
+}
+
+// Examines a public key, as pasted into the dashboard, for copy-paste and encoding problems, and
+// checks that matched data encrypted to it has the layout of matched data that decrypts
+pub fn diagnose_public_key(public_key_filename: &str, public_key: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    let public_key_bytes = match check_encoding(public_key_filename, public_key, &mut findings) {
+        Some(public_key_bytes) => public_key_bytes,
+        None => return findings,
+    };
+
+    if public_key_bytes.len() != PUBLIC_KEY_LENGTH {
+        let problem = if public_key_bytes.len() < PUBLIC_KEY_LENGTH {
+            "it is probably truncated"
+        } else {
+            "it probably holds more than the key"
+        };
+        findings.push(Finding::new(
+            format!(
+                "{} decodes to {} bytes, expected a {} byte public key, {}",
+                public_key_filename,
+                public_key_bytes.len(),
+                PUBLIC_KEY_LENGTH,
+                problem
+            ),
+            "Use the \"public_key\" value printed by generate-key-pair".to_string(),
+        ));
+        return findings;
+    }
+
+    let unusable = |findings: &mut Vec<Finding>, reason: String| {
+        findings.push(Finding::new(
+            format!(
+                "Matched data can't be encrypted to {} ({})",
+                public_key_filename, reason
+            ),
+            "Generate a new key pair with generate-key-pair".to_string(),
+        ))
+    };
+    let public_key = match get_public_key_from_bytes(&public_key_bytes) {
+        Ok(public_key) => public_key,
+        Err(e) => {
+            unusable(&mut findings, format!("{:?}", e));
+            return findings;
+        }
+    };
+    let mut masked_public_key_bytes = [0; PUBLIC_KEY_LENGTH];
+    masked_public_key_bytes.copy_from_slice(&public_key_bytes);
+    masked_public_key_bytes[PUBLIC_KEY_LENGTH - 1] &= 0x7f;
+    if LOW_ORDER_POINTS.contains(&masked_public_key_bytes) {
+        unusable(&mut findings, "it is a low order point".to_string());
+        return findings;
+    }
+    let encrypted_data = match encrypt_data(PAYLOAD, &public_key) {
+        Ok(encrypted_data) => serialize_encrypted_data(&encrypted_data),
+        Err(e) => {
+            unusable(&mut findings, format!("{:?}", e));
+            return findings;
+        }
+    };
+
+    // Only the holder of the private key can decrypt, so the matched data is compared with a
+    // round trip through a throwaway key pair
+    let (throwaway_private_key, throwaway_public_key) = generate_key_pair();
+    let throwaway_encrypted_data = serialize_encrypted_data(
+        &encrypt_data(PAYLOAD, &throwaway_public_key).expect("Failed to encrypt to a new key"),
+    );
+    let round_trip =
+        decrypt_any_version(&throwaway_private_key.to_bytes(), &throwaway_encrypted_data);
+    if !matches!(round_trip, Ok(ref plaintext) if plaintext.as_slice() == PAYLOAD) {
+        unusable(
+            &mut findings,
+            "a round trip through a throwaway key pair failed".to_string(),
+        );
+        return findings;
+    }
+    let layout = |encrypted_data: &[u8]| {
+        deserialize_encrypted_data::<CurrentSuite>(encrypted_data)
+            .map(|encrypted_data| encrypted_data.component_lengths())
+            .ok()
+    };
+    if layout(&encrypted_data).is_none()
+        || layout(&encrypted_data) != layout(&throwaway_encrypted_data)
+    {
+        unusable(
+            &mut findings,
+            "the matched data differs in layout from matched data that decrypts".to_string(),
+        );
+    }
+
+    findings
+}
+
+// Reports encoding problems with a base64 input and returns the decoded bytes if recoverable
+fn check_encoding(filename: &str, contents: &str, findings: &mut Vec<Finding>) -> Option<Vec<u8>> {
+    // Trailing whitespace, such as the newline ending most files, is ignored by decrypt
+    let trimmed = contents.trim();
+    if contents.trim_start().len() != contents.len() {
+        findings.push(Finding::new(
+            format!("{} has leading whitespace or newlines", filename),
+            format!("tr -d '[:space:]' < {0} > {0}.fixed", filename),
+        ));
+    }
+
+    let mut cleaned: String = trimmed.split_whitespace().collect();
+    if cleaned.len() != trimmed.len() {
+        findings.push(Finding::new(
+            format!("{} is wrapped over multiple lines", filename),
+            format!("tr -d '[:space:]' < {0} > {0}.fixed", filename),
+        ));
+    }
+
+    if cleaned.len() >= 2 && cleaned.starts_with('"') && cleaned.ends_with('"') {
+        findings.push(Finding::new(
+            format!("{} is surrounded by JSON quotes", filename),
+            format!("jq -r . {0} > {0}.fixed", filename),
+        ));
+        cleaned = cleaned[1..cleaned.len() - 1].to_string();
+    }
+
+    if cleaned.contains(['-', '_']) {
+        findings.push(Finding::new(
+            format!("{} uses the URL-safe base64 alphabet", filename),
+            format!("tr '_-' '/+' < {0} > {0}.fixed", filename),
+        ));
+        cleaned = cleaned.replace('-', "+").replace('_', "/");
+    }
+
+    let decoded = match radix64::STD.decode(&cleaned) {
+        Ok(decoded) => decoded,
+        Err(_) => {
+            findings.push(Finding::new(
+                format!("{} is not base64 encoded", filename),
+                format!(
+                    "Copy the value into {} again, without modifications",
+                    filename
+                ),
+            ));
+            return None;
+        }
+    };
+
+    // Base64 text that decodes to more base64 text was encoded twice
+    match str::from_utf8(&decoded)
+        .ok()
+        .and_then(|inner| radix64::STD.decode(inner).ok())
+    {
+        Some(inner) if !inner.is_empty() => {
+            findings.push(Finding::new(
+                format!("{} is base64 encoded twice", filename),
+                format!("base64 -d < {0} > {0}.fixed", filename),
+            ));
+            Some(inner)
+        }
+        _ => Some(decoded),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnose() {
+        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+        let matched_data = "AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=";
+
+        assert!(diagnose("key.txt", private_key, "data.txt", matched_data).is_empty());
+
+        assert!(diagnose(
+            "key.txt",
+            &format!("{}\n", private_key),
+            "data.txt",
+            &format!("{}\r\n", matched_data)
+        )
+        .is_empty());
+
+        let findings = diagnose(
+            "key.txt",
+            &format!(" \"{}\"\n", private_key),
+            "data.txt",
+            matched_data,
+        );
+        assert_eq!(2, findings.len());
+        assert_eq!(
+            "tr -d '[:space:]' < key.txt > key.txt.fixed",
+            findings[0].fix
+        );
+        assert_eq!("jq -r . key.txt > key.txt.fixed", findings[1].fix);
+
+        let findings = diagnose("key.txt", matched_data, "data.txt", private_key);
+        assert_eq!(1, findings.len());
+        assert_eq!(
+            "matched-data-cli decrypt -k data.txt key.txt",
+            findings[0].fix
+        );
+
+        let matched_data_bytes = radix64::STD.decode(matched_data).unwrap();
+        let truncated = radix64::STD.encode(&matched_data_bytes[..matched_data_bytes.len() - 3]);
+        let findings = diagnose("key.txt", private_key, "data.txt", &truncated);
+        assert_eq!(1, findings.len());
+        assert_eq!(
+            "data.txt is malformed or truncated, the tag of 16 bytes is 3 bytes short",
+            findings[0].problem
+        );
+    }
+
+    #[test]
+    fn test_diagnose_public_key() {
+        let public_key = "Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=";
+
+        assert!(diagnose_public_key("key.txt", &format!("{}\n", public_key)).is_empty());
+
+        let findings = diagnose_public_key("key.txt", &public_key[..40]);
+        assert_eq!(1, findings.len());
+        assert!(findings[0].problem.contains("probably truncated"));
+
+        let findings = diagnose_public_key("key.txt", &radix64::STD.encode(public_key));
+        assert_eq!(1, findings.len());
+        assert_eq!("base64 -d < key.txt > key.txt.fixed", findings[0].fix);
+
+        let findings = diagnose_public_key("key.txt", &radix64::STD.encode(&[0; 32]));
+        assert_eq!(1, findings.len());
+        assert!(findings[0]
+            .problem
+            .starts_with("Matched data can't be encrypted"));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::sink::{self, Batch, Sink};
+use serde::Deserialize;
+use serde_json::Value;
+use std::io;
+use std::time::SystemTime;
+use zeroize::Zeroizing;
+
+// Time fields of Logpush events, RFC 3339 formatted unless the job picked another format
+#[derive(Deserialize)]
+struct EventTime {
+    #[serde(rename = "Datetime")]
+    datetime: Option<Value>,
+    #[serde(rename = "EdgeStartTimestamp")]
+    edge_start_timestamp: Option<Value>,
+}
+
+// Credentials sent with bulk requests
+pub enum Credentials {
+    ApiKey(Zeroizing<String>),
+    Basic {
+        username: String,
+        password: Zeroizing<String>,
+    },
+}
+
+// Writes events to Elasticsearch or OpenSearch with the bulk API, into an index named after the
+// date of each event
+pub struct Elasticsearch {
+    url: String,
+    index_template: String,
+    authorization: Option<Zeroizing<String>>,
+    batch: Batch,
+}
+
+impl Elasticsearch {
+    pub fn new(url: &str, index_template: &str, credentials: Option<Credentials>) -> Self {
+        let authorization = credentials.map(|credentials| match credentials {
+            Credentials::ApiKey(api_key) => Zeroizing::new(format!("ApiKey {}", api_key.as_str())),
+            Credentials::Basic { username, password } => {
+                let user_password = Zeroizing::new(format!("{}:{}", username, password.as_str()));
+                Zeroizing::new(format!(
+                    "Basic {}",
+                    Zeroizing::new(radix64::STD.encode(user_password.as_bytes())).as_str()
+                ))
+            }
+        });
+
+        Elasticsearch {
+            url: format!("{}/_bulk", url.trim_end_matches('/')),
+            index_template: index_template.to_string(),
+            authorization,
+            batch: Batch::new(),
+        }
+    }
+
+    fn post(&mut self) -> io::Result<()> {
+        let response = sink::post_with_retry(
+            || {
+                let request = ureq::post(&self.url).set("Content-Type", "application/x-ndjson");
+                match &self.authorization {
+                    Some(authorization) => request.set("Authorization", authorization),
+                    None => request,
+                }
+            },
+            &self.batch.body,
+            "Elasticsearch",
+        )?;
+        self.batch.clear();
+
+        // A bulk request succeeds even if some of its events were rejected
+        let response: Value = response
+            .into_json()
+            .map_err(|_| io::Error::other("Elasticsearch bulk response is not JSON"))?;
+        match bulk_errors(&response) {
+            Some(errors) => Err(io::Error::other(errors)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Sink for Elasticsearch {
+    fn write_event(&mut self, event: &str) -> io::Result<()> {
+        let index = index_name(&self.index_template, &event_date(event));
+        let action = serde_json::json!({ "index": { "_index": index } }).to_string();
+        self.batch
+            .push(Zeroizing::new(format!("{}\n{}\n", action, document(event).as_str())).as_bytes());
+        if self.batch.is_full() {
+            self.post()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush_events(&mut self) -> io::Result<()> {
+        if self.batch.is_full() || self.batch.is_due() {
+            self.post()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.batch.is_empty() {
+            self.post()?;
+        }
+
+        Ok(())
+    }
+}
+
+// Date of an event as YYYY-MM-DD, from its time field if it is RFC 3339 formatted, or today's
+fn event_date(event: &str) -> String {
+    serde_json::from_str::<EventTime>(event)
+        .ok()
+        .and_then(|time| {
+            [time.datetime, time.edge_start_timestamp]
+                .into_iter()
+                .flatten()
+                .find_map(|time| time.as_str().and_then(rfc3339_date).map(str::to_string))
+        })
+        .unwrap_or_else(|| {
+            humantime::format_rfc3339(SystemTime::now()).to_string()[..10].to_string()
+        })
+}
+
+// Date part of an RFC 3339 time
+fn rfc3339_date(time: &str) -> Option<&str> {
+    let date = time.get(..10)?;
+    let is_date = date.bytes().enumerate().all(|(i, byte)| match i {
+        4 | 7 => byte == b'-',
+        _ => byte.is_ascii_digit(),
+    });
+
+    is_date.then_some(date)
+}
+
+// Index name from a template where %Y, %m and %d are replaced by the parts of a YYYY-MM-DD date
+fn index_name(template: &str, date: &str) -> String {
+    template
+        .replace("%Y", &date[..4])
+        .replace("%m", &date[5..7])
+        .replace("%d", &date[8..10])
+}
+
+// Document indexed for an event, lines passed through by pipe that aren't JSON objects becoming
+// a message field
+fn document(event: &str) -> Zeroizing<String> {
+    match serde_json::from_str::<serde::de::IgnoredAny>(event) {
+        Ok(_) if event.trim_start().starts_with('{') => Zeroizing::new(event.to_string()),
+        _ => Zeroizing::new(serde_json::json!({ "message": event }).to_string()),
+    }
+}
+
+// Summary of the events a bulk request failed to index, if any
+fn bulk_errors(response: &Value) -> Option<String> {
+    if response["errors"] != Value::Bool(true) {
+        return None;
+    }
+
+    let errors: Vec<&Value> = response["items"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|item| &item["index"]["error"])
+        .filter(|error| error.is_object())
+        .collect();
+    let reason = errors
+        .first()
+        .and_then(|error| error["reason"].as_str())
+        .unwrap_or("no reason given");
+
+    Some(format!(
+        "Elasticsearch failed to index {} events: {}",
+        errors.len(),
+        reason
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_date() {
+        assert_eq!(
+            "2024-05-01",
+            event_date(r#"{"Datetime":"2024-05-01T12:00:00Z","RayID":"1"}"#)
+        );
+        assert_eq!(
+            "2024-05-02",
+            event_date(r#"{"EdgeStartTimestamp":"2024-05-02T00:00:00Z"}"#)
+        );
+        let today = humantime::format_rfc3339(SystemTime::now()).to_string()[..10].to_string();
+        assert_eq!(today, event_date(r#"{"Datetime":1714564800}"#));
+        assert_eq!(today, event_date("not JSON"));
+    }
+
+    #[test]
+    fn test_index_name() {
+        assert_eq!(
+            "cloudflare-firewall-events-2024.05.01",
+            index_name("cloudflare-firewall-events-%Y.%m.%d", "2024-05-01")
+        );
+        assert_eq!("events", index_name("events", "2024-05-01"));
+    }
+
+    #[test]
+    fn test_document() {
+        assert_eq!(r#"{"RayID":"1"}"#, document(r#"{"RayID":"1"}"#).as_str());
+        assert_eq!(r#"{"message":"not JSON"}"#, document("not JSON").as_str());
+        assert_eq!(r#"{"message":"[1]"}"#, document("[1]").as_str());
+    }
+
+    #[test]
+    fn test_bulk_errors() {
+        assert_eq!(
+            None,
+            bulk_errors(&serde_json::json!({"errors": false, "items": []}))
+        );
+        assert_eq!(
+            Some("Elasticsearch failed to index 1 events: mapper_parsing_exception".to_string()),
+            bulk_errors(&serde_json::json!({
+                "errors": true,
+                "items": [
+                    {"index": {"status": 201}},
+                    {"index": {"status": 400, "error": {"reason": "mapper_parsing_exception"}}}
+                ]
+            }))
+        );
+    }
+}
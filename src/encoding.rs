@@ -0,0 +1,258 @@
+use radix64::DecodeError;
+use zeroize::Zeroizing;
+
+// Decodes base64 keys and matched data. Unless strict, line breaks and other whitespace are
+// ignored, as introduced by emails and ticketing systems wrapping long lines, and the URL-safe
+// alphabet is accepted with or without padding
+pub fn decode_base64(input: &str, strict: bool) -> Result<Vec<u8>, DecodeError> {
+    if strict {
+        return radix64::STD.decode(input.trim_end());
+    }
+
+    let mut cleaned: Zeroizing<String> = Zeroizing::new(
+        input
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace())
+            .map(|c| match c {
+                '-' => '+',
+                '_' => '/',
+                c => c,
+            })
+            .collect(),
+    );
+    if !cleaned.ends_with('=') {
+        while !cleaned.len().is_multiple_of(4) {
+            cleaned.push('=');
+        }
+    }
+
+    radix64::STD.decode(cleaned.as_str())
+}
+
+// Lines around ASCII-armored matched data
+const ARMOR_BEGIN: &str = "-----BEGIN CF MATCHED DATA-----";
+const ARMOR_END: &str = "-----END CF MATCHED DATA-----";
+
+// Base64 line length of armored matched data, as in OpenPGP
+const ARMOR_LINE_LENGTH: usize = 64;
+
+// Wraps serialized matched data in an ASCII armor as OpenPGP does (RFC 4880), as base64 lines
+// followed by a CRC-24 checksum, so blobs mangled by email or chat are detected before decrypting
+pub fn armor(bytes: &[u8]) -> String {
+    let base64 = radix64::STD.encode(bytes);
+    let mut armored = format!("{}\n", ARMOR_BEGIN);
+    for line in base64.as_bytes().chunks(ARMOR_LINE_LENGTH) {
+        armored.push_str(std::str::from_utf8(line).expect("Base64 is ASCII"));
+        armored.push('\n');
+    }
+    armored.push_str(&format!(
+        "={}\n{}",
+        radix64::STD.encode(&crc24(bytes).to_be_bytes()[1..]),
+        ARMOR_END
+    ));
+    armored
+}
+
+pub fn is_armored(input: &str) -> bool {
+    input.contains(ARMOR_BEGIN)
+}
+
+// Decodes ASCII-armored matched data, ignoring text around the armor and indentation added by
+// quoting, and checking its checksum
+pub fn dearmor(input: &str) -> Result<Vec<u8>, String> {
+    let start = input
+        .find(ARMOR_BEGIN)
+        .ok_or("missing BEGIN CF MATCHED DATA line")?
+        + ARMOR_BEGIN.len();
+    let end = input[start..]
+        .find(ARMOR_END)
+        .ok_or("missing END CF MATCHED DATA line")?
+        + start;
+
+    let mut base64: Zeroizing<String> = Zeroizing::new(String::new());
+    let mut checksum = None;
+    for line in input[start..end].lines().map(str::trim) {
+        match line.strip_prefix('=') {
+            _ if line.is_empty() => {}
+            _ if checksum.is_some() => return Err("data after the checksum".to_string()),
+            Some(encoded) => checksum = Some(encoded),
+            None => base64.push_str(line),
+        }
+    }
+    let checksum = checksum.ok_or("missing checksum")?;
+
+    let bytes = radix64::STD
+        .decode(base64.as_str())
+        .map_err(|e| format!("{:?}", e))?;
+    let expected = radix64::STD
+        .decode(checksum)
+        .ok()
+        .filter(|checksum| checksum.len() == 3)
+        .ok_or("invalid checksum")?;
+    if crc24(&bytes).to_be_bytes()[1..] != expected[..] {
+        return Err("checksum mismatch, the armored block was altered".to_string());
+    }
+    Ok(bytes)
+}
+
+// CRC-24 of OpenPGP armor checksums
+fn crc24(bytes: &[u8]) -> u32 {
+    let mut crc: u32 = 0xb704ce;
+    for byte in bytes {
+        crc ^= (*byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= 0x1864cfb;
+            }
+        }
+    }
+    crc & 0xffffff
+}
+
+// Decodes hex encoded matched data in either case, ignoring whitespace and a 0x prefix
+pub fn decode_hex(input: &str) -> Result<Vec<u8>, String> {
+    let cleaned: Zeroizing<String> =
+        Zeroizing::new(input.chars().filter(|c| !c.is_ascii_whitespace()).collect());
+    let digits = cleaned.strip_prefix("0x").unwrap_or(&cleaned);
+    if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("invalid hex digit {:?}", c));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err("odd number of hex digits".to_string());
+    }
+
+    Ok(digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            u8::from_str_radix(std::str::from_utf8(pair).expect("Hex digits are ASCII"), 16)
+                .expect("Hex digits were checked")
+        })
+        .collect())
+}
+
+// Byte order marks of text files saved by Windows tools, such as Notepad or PowerShell 5, whose
+// > redirection writes UTF-16
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+const UTF16LE_BOM: &[u8] = b"\xff\xfe";
+const UTF16BE_BOM: &[u8] = b"\xfe\xff";
+
+// Decodes a key or matched data file as text, from UTF-8 or from UTF-16 with a byte order mark,
+// dropping the mark, so files saved on Windows read the same as elsewhere. Line breaks are left
+// as is, CRLF ones being whitespace to the decoders. None if it isn't text
+pub fn decode_text(bytes: &[u8]) -> Option<Zeroizing<String>> {
+    let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        if !bytes.len().is_multiple_of(2) {
+            return None;
+        }
+        let units = Zeroizing::new(
+            bytes
+                .chunks(2)
+                .map(|unit| from_bytes([unit[0], unit[1]]))
+                .collect::<Vec<_>>(),
+        );
+        String::from_utf16(&units).ok().map(Zeroizing::new)
+    };
+
+    if let Some(bytes) = bytes.strip_prefix(UTF16LE_BOM) {
+        utf16(bytes, u16::from_le_bytes)
+    } else if let Some(bytes) = bytes.strip_prefix(UTF16BE_BOM) {
+        utf16(bytes, u16::from_be_bytes)
+    } else {
+        let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+        std::str::from_utf8(bytes)
+            .ok()
+            .map(|text| Zeroizing::new(text.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_text() {
+        let text = "AzTY\r\n6FHa\r\n";
+        assert_eq!(text, decode_text(text.as_bytes()).unwrap().as_str());
+        assert_eq!(
+            text,
+            decode_text(&[UTF8_BOM, text.as_bytes()].concat())
+                .unwrap()
+                .as_str()
+        );
+        let utf16le: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(
+            text,
+            decode_text(&[UTF16LE_BOM, &utf16le].concat())
+                .unwrap()
+                .as_str()
+        );
+        let utf16be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(
+            text,
+            decode_text(&[UTF16BE_BOM, &utf16be].concat())
+                .unwrap()
+                .as_str()
+        );
+        assert!(decode_text(&[UTF16LE_BOM, b"A"].concat()).is_none());
+        assert!(decode_text(b"\x03\xff").is_none());
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        let bytes = vec![0xfb, 0xff, 0xbf, 0x01];
+        assert_eq!(bytes, decode_base64("+/+/AQ==\n", true).unwrap());
+        assert_eq!(bytes, decode_base64("+/+/\r\nAQ==", false).unwrap());
+        assert_eq!(bytes, decode_base64("-_-_AQ", false).unwrap());
+        assert!(decode_base64("-_-_AQ", true).is_err());
+        assert!(decode_base64("+/+/\nAQ==", true).is_err());
+    }
+
+    #[test]
+    fn test_armor() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let armored = armor(&bytes);
+        assert!(armored.starts_with("-----BEGIN CF MATCHED DATA-----\n"));
+        assert!(armored.ends_with("\n-----END CF MATCHED DATA-----"));
+        assert!(armored.lines().all(|line| line.len() <= 64));
+        assert!(is_armored(&armored));
+
+        // Quoted in an email reply
+        let quoted: String = armored
+            .lines()
+            .map(|line| format!("  {}\r\n", line))
+            .collect();
+        assert_eq!(
+            bytes,
+            dearmor(&format!("See below:\n{}-- \n", quoted)).unwrap()
+        );
+
+        let altered = armored.replacen("AAEC", "AAED", 1);
+        assert!(dearmor(&altered).unwrap_err().contains("checksum mismatch"));
+        let unchecked: String = armored
+            .lines()
+            .filter(|line| !line.starts_with('='))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert!(dearmor(&unchecked)
+            .unwrap_err()
+            .contains("missing checksum"));
+        assert!(dearmor("-----BEGIN CF MATCHED DATA-----\nAAEC").is_err());
+    }
+
+    #[test]
+    fn test_crc24() {
+        // Check value of CRC-24/OPENPGP
+        assert_eq!(0x21cf02, crc24(b"123456789"));
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        let bytes = vec![0xfb, 0xff, 0xbf, 0x01];
+        assert_eq!(bytes, decode_hex("fbffbf01\n").unwrap());
+        assert_eq!(bytes, decode_hex("0xFB FF\nBF 01").unwrap());
+        assert!(decode_hex("fbf").is_err());
+        assert!(decode_hex("+f").is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use serde::Serialize;
+
+// Stable identifier of a known error, for scripts reading --error-format json
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorCode {
+    PrivateKeyMissing,
+    PrivateKeyUnreadable,
+    PrivateKeyNotBase64,
+    PrivateKeyInvalid,
+    MatchedDataMissing,
+    MatchedDataUnreadable,
+    MatchedDataNotBase64,
+    MatchedDataNotHex,
+    MatchedDataNotByteArray,
+    MatchedDataArmorInvalid,
+    MatchedDataEmpty,
+    MatchedDataInvalid,
+    MatchedDataTruncated,
+    MatchedDataTooLarge,
+    UnsupportedVersion,
+    DecryptionFailed,
+    HardwareKeyFailed,
+    Other,
+}
+
+impl ErrorCode {
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::PrivateKeyMissing => "private_key_missing",
+            ErrorCode::PrivateKeyUnreadable => "private_key_unreadable",
+            ErrorCode::PrivateKeyNotBase64 => "private_key_not_base64",
+            ErrorCode::PrivateKeyInvalid => "private_key_invalid",
+            ErrorCode::MatchedDataMissing => "matched_data_missing",
+            ErrorCode::MatchedDataUnreadable => "matched_data_unreadable",
+            ErrorCode::MatchedDataNotBase64 => "matched_data_not_base64",
+            ErrorCode::MatchedDataNotHex => "matched_data_not_hex",
+            ErrorCode::MatchedDataNotByteArray => "matched_data_not_byte_array",
+            ErrorCode::MatchedDataArmorInvalid => "matched_data_armor_invalid",
+            ErrorCode::MatchedDataEmpty => "matched_data_empty",
+            ErrorCode::MatchedDataInvalid => "matched_data_invalid",
+            ErrorCode::MatchedDataTruncated => "matched_data_truncated",
+            ErrorCode::MatchedDataTooLarge => "matched_data_too_large",
+            ErrorCode::UnsupportedVersion => "unsupported_version",
+            ErrorCode::DecryptionFailed => "decryption_failed",
+            ErrorCode::HardwareKeyFailed => "hardware_key_failed",
+            ErrorCode::Other => "error",
+        }
+    }
+
+    // Suggested next step for the error, if there is a useful one
+    pub fn hint(self) -> Option<&'static str> {
+        match self {
+            ErrorCode::PrivateKeyMissing => {
+                Some("Pass -k or set private_key in the configuration file")
+            }
+            ErrorCode::PrivateKeyNotBase64 | ErrorCode::MatchedDataNotBase64 => {
+                Some("Run matched-data-cli doctor to find encoding problems")
+            }
+            ErrorCode::PrivateKeyInvalid => {
+                Some("Use the \"private_key\" value printed by generate-key-pair")
+            }
+            ErrorCode::MatchedDataInvalid => {
+                Some("Copy the complete encrypted matched data field of the event")
+            }
+            ErrorCode::MatchedDataArmorInvalid => {
+                Some("Copy the whole armored block, from its BEGIN line to its END line")
+            }
+            ErrorCode::MatchedDataTooLarge => {
+                Some("Pass a larger --max-input-size if the input is expected to be this large")
+            }
+            ErrorCode::DecryptionFailed => Some(
+                "Check that the private key matches the zone's public key with verify-remote-key",
+            ),
+            ErrorCode::HardwareKeyFailed => {
+                Some("Check that the YubiKey is inserted and that its PIN is correct")
+            }
+            _ => None,
+        }
+    }
+}
+
+// Error of a command, with the decryption stage that failed if any
+#[derive(Debug)]
+pub struct CliError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub stage: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonError<'a> {
+    code: &'static str,
+    message: &'a str,
+    stage: Option<&'a str>,
+    hint: Option<&'static str>,
+}
+
+impl CliError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        CliError {
+            code,
+            message: message.into(),
+            stage: None,
+        }
+    }
+
+    pub fn at_stage(mut self, stage: &str) -> Self {
+        self.stage = Some(stage.to_string());
+        self
+    }
+
+    // Serializes the error as a single line JSON object
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&JsonError {
+            code: self.code.name(),
+            message: &self.message,
+            stage: self.stage.as_deref(),
+            hint: self.code.hint(),
+        })
+        .expect("Failed to output error")
+    }
+}
+
+impl From<String> for CliError {
+    fn from(message: String) -> Self {
+        CliError::new(ErrorCode::Other, message)
+    }
+}
+
+impl From<&str> for CliError {
+    fn from(message: &str) -> Self {
+        CliError::new(ErrorCode::Other, message)
+    }
+}
+
+impl From<CliError> for String {
+    fn from(error: CliError) -> Self {
+        error.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(
+            r#"{"code":"decryption_failed","message":"Failed to decrypt matched data","stage":"AEAD open","hint":"Check that the private key matches the zone's public key with verify-remote-key"}"#,
+            CliError::new(
+                ErrorCode::DecryptionFailed,
+                "Failed to decrypt matched data"
+            )
+            .at_stage("AEAD open")
+            .to_json()
+        );
+        assert_eq!(
+            r#"{"code":"error","message":"Found 1 problem(s)","stage":null,"hint":null}"#,
+            CliError::from("Found 1 problem(s)".to_string()).to_json()
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+// Set by --enforce-expiry, so expired keys fail rather than only being warned about
+static ENFORCE: AtomicBool = AtomicBool::new(false);
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+pub fn set_enforced(enforced: bool) {
+    ENFORCE.store(enforced, Ordering::Relaxed);
+}
+
+pub fn is_enforced() -> bool {
+    ENFORCE.load(Ordering::Relaxed)
+}
+
+fn parse(expires_at: &str) -> Result<SystemTime, String> {
+    humantime::parse_rfc3339_weak(expires_at)
+        .map_err(|_| format!("Key expiry date {} is not an RFC 3339 date", expires_at))
+}
+
+// Expiry date of a key valid for a duration from now, as stored in its expires_at
+pub fn expires_at(valid_for: Duration, now: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(now + valid_for).to_string()
+}
+
+// Seconds left before an expiry date, negative once it has passed
+fn seconds_remaining(expires_at: &str, now: SystemTime) -> Result<i64, String> {
+    Ok(match parse(expires_at)?.duration_since(now) {
+        Ok(left) => left.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    })
+}
+
+// Whole days left before an expiry date, negative once it has passed
+pub fn days_remaining(expires_at: &str, now: SystemTime) -> Result<i64, String> {
+    Ok(seconds_remaining(expires_at, now)?.div_euclid(SECONDS_PER_DAY))
+}
+
+// Warns that a key being used has expired, or fails with --enforce-expiry, so keys past their
+// rotation date stop being used
+pub fn check(key_id: &str, expires_at: &str) -> Result<(), String> {
+    if seconds_remaining(expires_at, SystemTime::now())? > 0 {
+        return Ok(());
+    }
+    if is_enforced() {
+        return Err(format!(
+            "Private key {} expired on {}, rotate it, or drop --enforce-expiry to use it anyway",
+            key_id, expires_at
+        ));
+    }
+    tracing::warn!(
+        key_id,
+        expires_at,
+        "Using an expired private key, rotate it"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_days_remaining() {
+        let now = humantime::parse_rfc3339("2024-01-01T12:00:00Z").unwrap();
+        assert_eq!(12, days_remaining("2024-01-14T00:00:00Z", now).unwrap());
+        assert_eq!(0, days_remaining("2024-01-01T12:00:01Z", now).unwrap());
+        assert_eq!(-1, days_remaining("2024-01-01T11:59:59Z", now).unwrap());
+        assert_eq!(-3, days_remaining("2023-12-29T12:00:00Z", now).unwrap());
+        assert!(days_remaining("next year", now).is_err());
+
+        assert_eq!(
+            "2024-01-31T12:00:00Z",
+            expires_at(Duration::from_secs(30 * 24 * 60 * 60), now)
+        );
+    }
+}
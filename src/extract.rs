@@ -0,0 +1,79 @@
+use crate::content_type;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// File in the extract directory mapping each event to the payload it decrypted to
+const INDEX_FILENAME: &str = "index.ndjson";
+
+// A payload written to the extract directory, as a line of its index
+#[derive(Serialize)]
+struct IndexRecord<'a> {
+    event: &'a str,
+    file: &'a str,
+    bytes: usize,
+    content_type: &'static str,
+}
+
+// Writes decrypted payloads bit-exact to a directory, named by their SHA-256 hash and detected
+// type so identical payloads are stored once, appending a JSON line to its index for each event
+pub struct Extractor {
+    dir: PathBuf,
+    index: Mutex<fs::File>,
+}
+
+impl Extractor {
+    pub fn open(dir: &str) -> Result<Self, String> {
+        fs::create_dir_all(dir)
+            .map_err(|_| format!("Failed to create extract directory {}", dir))?;
+        let dir = PathBuf::from(dir);
+        let index = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(INDEX_FILENAME))
+            .map_err(|_| "Failed to open extract directory index".to_string())?;
+
+        Ok(Extractor {
+            dir,
+            index: Mutex::new(index),
+        })
+    }
+
+    // Writes the payload of an event unless it was already extracted, and records it in the index
+    pub fn extract(&self, event: &str, payload: &[u8]) -> Result<(), String> {
+        let content_type = content_type::sniff(payload);
+        let hash: String = Sha256::digest(payload)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let filename = format!("{}.{}", hash, content_type.extension());
+
+        let path = self.dir.join(&filename);
+        if !path.exists() {
+            // Written under a temporary name so a payload is never seen partially written
+            let partial_path = self.dir.join(format!("{}.partial", filename));
+            fs::write(&partial_path, payload)
+                .and_then(|_| fs::rename(&partial_path, &path))
+                .map_err(|_| format!("Failed to write extracted payload {}", filename))?;
+        }
+
+        let record = IndexRecord {
+            event,
+            file: &filename,
+            bytes: payload.len(),
+            content_type: content_type.mime_type(),
+        };
+        let line = format!(
+            "{}\n",
+            serde_json::to_string(&record).expect("Failed to serialize index record")
+        );
+        self.index
+            .lock()
+            .expect("Extract directory index lock is poisoned")
+            .write_all(line.as_bytes())
+            .map_err(|_| "Failed to write to extract directory index".to_string())
+    }
+}
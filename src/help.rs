@@ -0,0 +1,265 @@
+// Examples shown after the options in the help of each subcommand
+
+pub const GENERATE_KEY_PAIR: &str = "EXAMPLES:
+    Print a key pair as JSON:
+        $ matched-data-cli generate-key-pair
+
+    Write each key to its own file, readable only by the current user:
+        $ matched-data-cli generate-key-pair --private-key-file private_key.txt --public-key-file public_key.txt
+
+    Print a key pair labeled with what it is for, with its key ID:
+        $ matched-data-cli generate-key-pair --label \"zone-a 2024\"
+
+    Save a key pair that expires in a year, so using it afterwards is warned about:
+        $ matched-data-cli generate-key-pair --label zone-a --expires-in 365days > zone-a.json
+
+    Print the private key as a BIP39 mnemonic to back it up on paper:
+        $ matched-data-cli generate-key-pair -o mnemonic
+
+    Save the private key as a passphrase protected QR code, to scan on an air-gapped machine:
+        $ matched-data-cli generate-key-pair -o qr --qr-file key.png
+
+    Write the key pair to files and copy the public key to paste in the dashboard:
+        $ matched-data-cli generate-key-pair --private-key-file private_key.txt --public-key-file public_key.txt --copy
+
+    Print the key pair and its fingerprint as one JSON line in a stable format, for scripts:
+        $ matched-data-cli generate-key-pair --porcelain=v1";
+
+pub const DECRYPT: &str = "EXAMPLES:
+    Decrypt matched data from a file:
+        $ matched-data-cli decrypt -k private_key.txt matched_data.txt
+
+    Decrypt matched data copied from the dashboard, copying the plaintext back to the clipboard:
+        $ matched-data-cli decrypt -k private_key.txt --clipboard --copy
+
+    Decrypt matched data from stdin:
+        $ pbpaste | matched-data-cli decrypt -k private_key.txt -
+
+    Decrypt the matched data of an event copied as JSON:
+        $ jq -r .Metadata.encrypted_matched_data event.json | matched-data-cli decrypt -k private_key.txt -
+
+    Decrypt matched data copied from the dashboard as an array of bytes:
+        $ matched-data-cli decrypt -k private_key.txt --input-format bytes-json matched_data.json
+
+    Decrypt each line of several files in batch mode, one JSON line per input, going on after failures:
+        $ matched-data-cli decrypt -k private_key.txt -o ndjson --continue-on-error exports/*.txt
+
+    Decrypt matched data stored as serialized bytes, one input per file in batch mode:
+        $ matched-data-cli decrypt -k private_key.txt --input-encoding raw -o ndjson blobs/*.bin
+
+    Reassemble a payload split across several matched data:
+        $ matched-data-cli decrypt -k private_key.txt --concat --output-file body.bin segment1.txt segment2.txt
+
+    Pass each selected field to xargs, even if it contains line breaks:
+        $ matched-data-cli decrypt -k private_key.txt -0 --select '$.uri' matched_data.txt | xargs -0 -n 1 echo
+
+    Decrypt with a private key saved in the OS keychain, masking PII:
+        $ matched-data-cli decrypt --key-id zone-a --redact-pii matched_data.txt";
+
+pub const GENERATE_YUBIKEY_KEY: &str = "EXAMPLES:
+    Generate a private key in the default slot, saving its public key:
+        $ matched-data-cli generate-yubikey-key > yubikey_public_key.txt
+
+    Decrypt with it:
+        $ matched-data-cli decrypt --yubikey-slot 9d --yubikey-public-key yubikey_public_key.txt matched_data.txt";
+
+pub const ENCRYPT: &str = "EXAMPLES:
+    Encrypt a test fixture to two teams:
+        $ printf 'test matched data' | matched-data-cli encrypt -p team_a_public_key.txt -p team_b_public_key.txt -
+
+    Encrypt in HPKE auth mode, proving who encrypted it:
+        $ matched-data-cli encrypt -p public_key.txt --sender-key sender_private_key.txt fixture.txt
+
+    Encrypt a test fixture to paste in a ticket, decrypted from the ticket text as is:
+        $ matched-data-cli encrypt --armor -p public_key.txt fixture.txt
+        $ matched-data-cli decrypt -k private_key.txt ticket.txt";
+
+pub const REENCRYPT: &str = "EXAMPLES:
+    Hand matched data to another team without writing the plaintext:
+        $ matched-data-cli reencrypt -k private_key.txt -p incident_response_public_key.txt matched_data.txt";
+
+pub const REWRAP: &str = "EXAMPLES:
+    Rewrap a directory of Logpush archives to a new key:
+        $ matched-data-cli rewrap -k old_private_key.txt -p new_public_key.txt -o archive-rewrapped archive/
+
+    Rewrap a single gzipped archive in place:
+        $ matched-data-cli rewrap -k old_private_key.txt -p new_public_key.txt -o events.log.gz events.log.gz";
+
+pub const IMPORT_KEY: &str = "EXAMPLES:
+    Import an age identity:
+        $ matched-data-cli import-key ~/.config/age/key.txt
+
+    Import an OpenSSH ed25519 key:
+        $ matched-data-cli import-key --from ssh ~/.ssh/id_ed25519
+
+    Import a key from a QR code image, prompting for its passphrase:
+        $ zbarimg --raw -q key.png > key.txt
+        $ matched-data-cli import-key key.txt";
+
+pub const EXPORT_KEY: &str = "EXAMPLES:
+    Export a private key as an age identity:
+        $ matched-data-cli export-key -k private_key.txt > key.txt";
+
+pub const SPLIT_KEY: &str = "EXAMPLES:
+    Split a private key into 5 shares, any 3 of which recover it:
+        $ matched-data-cli split-key -k private_key.txt --threshold 3 --shares 5";
+
+pub const COMBINE_KEY: &str = "EXAMPLES:
+    Recover a private key from 3 shares:
+        $ matched-data-cli combine-key share1.txt share3.txt share4.txt > private_key.txt";
+
+pub const FINGERPRINT: &str = "EXAMPLES:
+    Check that two people hold the same key pair:
+        $ matched-data-cli fingerprint -k private_key.txt
+        $ matched-data-cli fingerprint -p public_key.txt";
+
+pub const KEY: &str = "EXAMPLES:
+    Save a private key in the OS keychain and decrypt with it:
+        $ matched-data-cli key store -k private_key.txt zone-a
+        $ matched-data-cli decrypt --key-id zone-a matched_data.txt
+
+    Print a saved private key:
+        $ matched-data-cli key load zone-a
+
+    Back up key pairs to an archive encrypted with a passphrase, and restore them to the keychain:
+        $ matched-data-cli key export --out backup.mdk -k zone-a.txt --key-id zone-b
+        $ matched-data-cli key import --keychain backup.mdk
+
+    Check how many days key files have left before they expire, failing if any has expired:
+        $ matched-data-cli key list --enforce-expiry zone-a.json zone-b.json";
+
+pub const CACHE: &str = "EXAMPLES:
+    Decrypt an archive twice, the second run reading the plaintexts from the cache:
+        $ matched-data-cli logs -k private_key.txt --cache-dir ~/.cache/matched-data-cli logpush-2024-05.tar.gz > decrypted.log
+        $ matched-data-cli logs -k private_key.txt --cache-dir ~/.cache/matched-data-cli logpush-2024-05.tar.gz | jq .decrypted_matched_data
+
+    Delete the cached plaintexts once the analysis is done:
+        $ matched-data-cli cache purge --cache-dir ~/.cache/matched-data-cli";
+
+#[cfg(feature = "api")]
+pub const VERIFY_REMOTE_KEY: &str = "EXAMPLES:
+    Check the private key against the key configured on a zone:
+        $ CLOUDFLARE_API_TOKEN=... matched-data-cli verify-remote-key -k private_key.txt --zone-id 023e105f4ecef8ad9ca31a8372d0c353";
+
+pub const SELF_TEST: &str = "EXAMPLES:
+    Check that this build can decrypt before relying on it:
+        $ matched-data-cli self-test";
+
+#[cfg(feature = "self-update")]
+pub const SELF_UPDATE: &str = "EXAMPLES:
+    Check whether a newer release is available:
+        $ matched-data-cli self-update --check
+
+    Install the latest release over this executable:
+        $ sudo matched-data-cli self-update";
+
+pub const BENCH: &str = "EXAMPLES:
+    Estimate how many decryptions per second serve and batch jobs can sustain:
+        $ matched-data-cli bench
+
+    Measure payloads of 64 KiB on 16 threads:
+        $ matched-data-cli bench -n 2000 --size 65536 -j 16 -o json";
+
+pub const GENERATE_TEST_VECTORS: &str = "EXAMPLES:
+    Save test vectors to check another implementation against:
+        $ matched-data-cli generate-test-vectors > test_vectors.json";
+
+pub const VERSIONS: &str = "EXAMPLES:
+    Check that this build can decrypt version 3 before shipping logs to it:
+        $ matched-data-cli versions -o json | jq -e 'any(.version == 3)'";
+
+pub const DOCTOR: &str = "EXAMPLES:
+    Find out why matched data doesn't decrypt:
+        $ matched-data-cli doctor -k private_key.txt matched_data.txt";
+
+pub const DIFF: &str = "EXAMPLES:
+    Check that matched data decrypts to the payload sent in a test request:
+        $ matched-data-cli diff -k private_key.txt matched_data.txt payload.txt
+
+    Check that a re-encrypted copy kept every byte, line breaks included:
+        $ matched-data-cli diff -k new_private_key.txt reencrypted.txt original.txt --bytes";
+
+pub const VALIDATE_PUBLIC_KEY: &str = "EXAMPLES:
+    Check a public key before pasting it into the dashboard:
+        $ matched-data-cli validate-public-key public_key.txt";
+
+pub const LOGS: &str = "EXAMPLES:
+    Decrypt the events of a Logpush file, gzipped or not:
+        $ matched-data-cli logs -k private_key.txt 20240501T000000Z_20240501T000030Z_abc.log.gz
+
+    Decrypt events as they are appended, on 4 threads:
+        $ matched-data-cli logs -k private_key.txt -f -j 4 events.log
+
+    Decrypt every Logpush file of an archive, without unpacking it:
+        $ matched-data-cli logs -k private_key.txt logpush-2024-05.tar.gz
+
+    Decrypt a Logpush object in R2, going on after failures:
+        $ matched-data-cli logs -k private_key.txt --continue-on-error r2://logs/20240501/events.log.gz
+
+    Post the decrypted events to Splunk:
+        $ SPLUNK_HEC_TOKEN=... matched-data-cli logs -k private_key.txt --sink splunk-hec --hec-url https://splunk.example.com:8088 events.log";
+
+pub const PIPE: &str = "EXAMPLES:
+    Decrypt events as they stream in:
+        $ tail -f events.log | matched-data-cli pipe -k private_key.txt | jq .decrypted_matched_data
+
+    Decrypt events into Elasticsearch:
+        $ ES_API_KEY=... matched-data-cli pipe -k private_key.txt --sink elasticsearch --es-url https://elasticsearch.example.com:9200 < events.log";
+
+pub const CSV: &str = "EXAMPLES:
+    Decrypt a CSV export of events from the dashboard:
+        $ matched-data-cli csv -k private_key.txt events.csv > decrypted_events.csv
+
+    Decrypt the third column of a CSV on stdin:
+        $ matched-data-cli csv -k private_key.txt --column 3 - < events.csv";
+
+pub const STATS: &str = "EXAMPLES:
+    Assess a month of Logpush files before decrypting them:
+        $ matched-data-cli stats -k private_key.txt logpush-2024-05.tar.gz
+
+    Find out which of two keys the matched data of a bucket is encrypted to:
+        $ matched-data-cli stats -k old_private_key.txt -k new_private_key.txt -o json r2://logs/20240501/events.log.gz";
+
+#[cfg(feature = "browse")]
+pub const BROWSE: &str = "EXAMPLES:
+    Browse the events of a Logpush file:
+        $ matched-data-cli browse -k private_key.txt events.log";
+
+#[cfg(feature = "serve")]
+pub const SERVE: &str = "EXAMPLES:
+    Serve decryption requests on the loopback interface:
+        $ matched-data-cli serve -k private_key.txt
+        $ curl -d 'AzTY6FHajXYXuDMUte82wrd+...' http://127.0.0.1:8080/decrypt
+
+    Answer with the matched data pretty-printed when it is JSON:
+        $ matched-data-cli serve -k private_key.txt -o json-pretty
+
+    Serve other hosts over TLS with a bearer token:
+        $ matched-data-cli serve -k private_key.txt -l 0.0.0.0:8443 --tls-cert cert.pem --tls-key key.pem --auth-token-file token.txt
+
+    Serve on a Unix socket only the current user can connect to:
+        $ matched-data-cli serve -k private_key.txt --unix-socket /run/user/1000/matched-data.sock";
+
+#[cfg(feature = "serve")]
+pub const WATCH: &str = "EXAMPLES:
+    Decrypt new Logpush objects announced to an SQS queue:
+        $ matched-data-cli watch -k private_key.txt --sqs-queue-url https://sqs.us-east-1.amazonaws.com/123456789012/logpush-objects > decrypted.log
+
+    Catch up on the queue from a scheduled job, then exit:
+        $ matched-data-cli watch -k private_key.txt --sqs-queue-url https://sqs.us-east-1.amazonaws.com/123456789012/logpush-objects --until-empty
+
+    Receive R2 notifications forwarded by a Worker, posting the events to Splunk:
+        $ R2_ACCOUNT_ID=... matched-data-cli watch -k private_key.txt --webhook 0.0.0.0:8090 --auth-token-file token.txt --bucket logpush --sink splunk-hec --hec-url https://splunk.example.com:8088";
+
+#[cfg(feature = "kafka")]
+pub const KAFKA: &str = "EXAMPLES:
+    Decrypt events between topics:
+        $ matched-data-cli kafka -k private_key.txt -b kafka:9092 --input-topic firewall-events --output-topic firewall-events-decrypted -g matched-data-cli
+
+    Connect over SASL with a properties file:
+        $ matched-data-cli kafka -k private_key.txt -b kafka:9093 -F client.properties --input-topic firewall-events --output-topic firewall-events-decrypted -g matched-data-cli";
+
+#[cfg(feature = "serve")]
+pub const SERVE_UI: &str = "EXAMPLES:
+    Serve the page with the WebAssembly module built in matched-data-wasm/pkg:
+        $ matched-data-cli serve-ui";
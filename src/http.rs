@@ -0,0 +1,331 @@
+use crate::content_type;
+use serde_json::{Map, Value};
+use std::fmt::Write;
+
+// Request line methods recognized at the start of raw HTTP matched data
+const METHODS: &[&str] = &[
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+// Request fields of matched data rendered as part of the request, the others are listed as
+// comments above it
+const METHOD_FIELD: &str = "http.request.method";
+const URI_FIELD: &str = "http.request.uri";
+const PATH_FIELD: &str = "http.request.uri.path";
+const QUERY_FIELD: &str = "http.request.uri.query";
+const HEADERS_FIELD: &str = "http.request.headers";
+const COOKIE_FIELD: &str = "http.cookie";
+const BODY_FIELD: &str = "http.request.body.raw";
+
+// Parts of an HTTP request found in matched data, any of which may be missing
+#[derive(Default)]
+struct Request {
+    method: Option<String>,
+    uri: Option<String>,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+    // Matched fields that aren't part of the request line, headers or body
+    other_fields: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// Renders matched data holding HTTP request fields, as JSON fields such as http.request.headers
+// and http.request.body.raw or as raw request text, as a readable request with URL encoded and
+// multipart bodies decoded. Returns None if it doesn't look like an HTTP request
+pub fn render(matched_data: &[u8]) -> Option<String> {
+    let request = match serde_json::from_slice::<Value>(matched_data) {
+        Ok(Value::Object(fields)) => from_fields(fields)?,
+        Ok(_) => return None,
+        Err(_) => from_raw(std::str::from_utf8(matched_data).ok()?)?,
+    };
+
+    let mut rendered = String::new();
+    for (name, value) in &request.other_fields {
+        let _ = writeln!(rendered, "# {}: {}", name, value);
+    }
+    match (&request.method, &request.uri) {
+        (None, None) => rendered.push_str("# Request line not in matched data\n"),
+        (method, uri) => {
+            let _ = writeln!(
+                rendered,
+                "{} {}",
+                method.as_deref().unwrap_or("?"),
+                uri.as_deref().unwrap_or("?")
+            );
+        }
+    }
+    for (name, value) in &request.headers {
+        let _ = writeln!(rendered, "{}: {}", name, value);
+    }
+    if let Some(body) = &request.body {
+        rendered.push('\n');
+        rendered.push_str(&render_body(body, request.header("content-type")));
+        if !rendered.ends_with('\n') {
+            rendered.push('\n');
+        }
+    }
+
+    Some(rendered)
+}
+
+// Collects the request from JSON matched data fields, if it has any HTTP request field
+fn from_fields(fields: Map<String, Value>) -> Option<Request> {
+    let mut request = Request::default();
+    let mut path = None;
+    let mut query = None;
+    let mut found = false;
+
+    for (name, value) in fields {
+        found |= name.starts_with("http.");
+        match (name.as_str(), value) {
+            (METHOD_FIELD, Value::String(method)) => request.method = Some(method),
+            (URI_FIELD, Value::String(uri)) => request.uri = Some(uri),
+            (PATH_FIELD, Value::String(value)) => path = Some(value),
+            (QUERY_FIELD, Value::String(value)) => query = Some(value),
+            (BODY_FIELD, Value::String(body)) => request.body = Some(body),
+            (HEADERS_FIELD, Value::Object(headers)) => {
+                for (header, values) in headers {
+                    match values {
+                        Value::Array(values) => request
+                            .headers
+                            .extend(values.iter().map(|value| (header.clone(), to_text(value)))),
+                        value => request.headers.push((header, to_text(&value))),
+                    }
+                }
+            }
+            (COOKIE_FIELD, value) => request
+                .headers
+                .push(("cookie".to_string(), to_text(&value))),
+            (_, value) => request.other_fields.push((name, to_text(&value))),
+        }
+    }
+    if !found {
+        return None;
+    }
+
+    if request.uri.is_none() {
+        request.uri = match (path, query) {
+            (Some(path), Some(query)) if !query.is_empty() => Some(format!("{}?{}", path, query)),
+            (Some(path), _) => Some(path),
+            (None, Some(query)) => Some(format!("?{}", query)),
+            (None, None) => None,
+        };
+    }
+
+    Some(request)
+}
+
+// Parses raw request text starting with a request line, if it does
+fn from_raw(text: &str) -> Option<Request> {
+    let (head, body) = match text.find("\r\n\r\n") {
+        Some(index) => (&text[..index], Some(&text[index + 4..])),
+        None => match text.find("\n\n") {
+            Some(index) => (&text[..index], Some(&text[index + 2..])),
+            None => (text, None),
+        },
+    };
+    let mut lines = head.lines();
+
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?;
+    if !METHODS.contains(&method) {
+        return None;
+    }
+
+    Some(Request {
+        method: Some(method.to_string()),
+        uri: request_line.next().map(str::to_string),
+        headers: lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect(),
+        body: body.filter(|body| !body.is_empty()).map(str::to_string),
+        other_fields: Vec::new(),
+    })
+}
+
+// Renders a body according to its content type, or its content when there is no header
+fn render_body(body: &str, content_type: Option<&str>) -> String {
+    let content_type = content_type.unwrap_or_default().to_ascii_lowercase();
+
+    if let Some(boundary) = multipart_boundary(body, &content_type) {
+        return render_multipart(body, &boundary);
+    }
+    if content_type.starts_with("application/x-www-form-urlencoded")
+        || (content_type.is_empty() && looks_url_encoded(body))
+    {
+        let mut rendered = "# Form fields, URL decoded\n".to_string();
+        for field in body.trim_end().split('&').filter(|field| !field.is_empty()) {
+            let (name, value) = field.split_once('=').unwrap_or((field, ""));
+            let _ = writeln!(
+                rendered,
+                "{}: {}",
+                percent_decode(name),
+                percent_decode(value)
+            );
+        }
+        return rendered;
+    }
+    if let Ok(value) = serde_json::from_str::<Value>(body) {
+        if value.is_object() || value.is_array() {
+            return serde_json::to_string_pretty(&value).expect("Failed to output body");
+        }
+    }
+
+    body.to_string()
+}
+
+// Boundary of a multipart body, from its content type or its first line
+fn multipart_boundary(body: &str, content_type: &str) -> Option<String> {
+    if content_type.starts_with("multipart/") {
+        if let Some((_, boundary)) = content_type.split_once("boundary=") {
+            let boundary = boundary.split(';').next()?.trim().trim_matches('"');
+            if !boundary.is_empty() {
+                return Some(boundary.to_string());
+            }
+        }
+    }
+
+    // Without a header, a first line of -- followed by a token is taken as the boundary when it
+    // delimits parts further down
+    let first_line = body.lines().next()?.trim_end();
+    let boundary = first_line.strip_prefix("--")?;
+    let rest = &body[first_line.len()..];
+    if boundary.is_empty() || boundary.contains(' ') || !rest.contains(first_line) {
+        return None;
+    }
+
+    Some(boundary.to_string())
+}
+
+// Renders each part of a multipart body with its headers, summarizing binary content
+fn render_multipart(body: &str, boundary: &str) -> String {
+    let delimiter = format!("--{}", boundary);
+    let mut rendered = String::new();
+
+    let parts = body
+        .split(delimiter.as_str())
+        .skip(1)
+        .take_while(|part| !part.starts_with("--"));
+    for (i, part) in parts.enumerate() {
+        let part = part
+            .strip_prefix("\r\n")
+            .or_else(|| part.strip_prefix('\n'));
+        let part = match part {
+            Some(part) => part
+                .strip_suffix("\r\n")
+                .or_else(|| part.strip_suffix('\n'))
+                .unwrap_or(part),
+            None => continue,
+        };
+        let (headers, content) = match part.find("\r\n\r\n") {
+            Some(index) => (&part[..index], &part[index + 4..]),
+            None => match part.find("\n\n") {
+                Some(index) => (&part[..index], &part[index + 2..]),
+                None => (part, ""),
+            },
+        };
+
+        let _ = writeln!(rendered, "# Part {}", i + 1);
+        for header in headers.lines() {
+            let _ = writeln!(rendered, "{}", header);
+        }
+        rendered.push('\n');
+        if content_type::is_text(content.as_bytes()) {
+            rendered.push_str(content);
+            rendered.push('\n');
+        } else {
+            let _ = writeln!(rendered, "[{} bytes of binary data]", content.len());
+        }
+        rendered.push('\n');
+    }
+
+    rendered
+}
+
+// Whether a body without a content type looks like URL encoded form fields
+fn looks_url_encoded(body: &str) -> bool {
+    let body = body.trim_end();
+    !body.is_empty()
+        && !body.contains(char::is_whitespace)
+        && body.split('&').all(|field| {
+            field
+                .split_once('=')
+                .is_some_and(|(name, _)| !name.is_empty())
+        })
+}
+
+// Decodes a URL encoded form field, including + as a space
+pub fn percent_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match encoded
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Text of a JSON value, strings without quotes
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let matched_data = br#"{"http.request.method":"POST","http.request.uri.path":"/login","http.request.uri.query":"next=%2F","http.request.headers":{"content-type":["multipart/form-data; boundary=xyz"]},"http.request.body.raw":"--xyz\r\nContent-Disposition: form-data; name=\"user\"\r\n\r\nalice\r\n--xyz--\r\n","ip.src":"192.0.2.1"}"#;
+        assert_eq!(
+            Some(
+                "# ip.src: 192.0.2.1\nPOST /login?next=%2F\ncontent-type: multipart/form-data; boundary=xyz\n\n# Part 1\nContent-Disposition: form-data; name=\"user\"\n\nalice\n\n"
+                    .to_string()
+            ),
+            render(matched_data)
+        );
+
+        let raw = b"GET /search HTTP/1.1\r\nHost: example.com\r\n\r\n{\"a\":1}";
+        assert_eq!(
+            Some("GET /search\nHost: example.com\n\n{\n  \"a\": 1\n}\n".to_string()),
+            render(raw)
+        );
+
+        assert_eq!(None, render(br#"{"ip.src":"192.0.2.1"}"#));
+        assert_eq!(None, render(b"test matched data"));
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!("a b/c%zz%", percent_decode("a+b%2Fc%zz%"));
+    }
+}
@@ -0,0 +1,270 @@
+use crate::logs::{self, Keys};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::error::KafkaError;
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext};
+use rdkafka::types::RDKafkaErrorCode;
+use rdkafka::{ClientContext, Message};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+// Most events decrypted before they are produced and their offsets committed
+const BATCH_SIZE: usize = 500;
+
+// How long to wait for more events before producing a partial batch
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+// How long to wait for a batch to be acknowledged by the output topic's brokers
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Topics and client configuration to consume events from and produce enriched events to
+pub struct KafkaOptions<'a> {
+    pub brokers: &'a str,
+    pub input_topic: &'a str,
+    pub output_topic: &'a str,
+    pub group_id: &'a str,
+    // librdkafka properties such as security.protocol, sasl.mechanism or ssl.ca.location, set on
+    // both the consumer and the producer
+    pub properties: &'a [(String, String)],
+}
+
+// Counts the events that failed to be delivered to the output topic
+#[derive(Default)]
+struct DeliveryContext {
+    failures: AtomicUsize,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        if let Err((e, _)) = delivery_result {
+            tracing::warn!(error = %e, "Failed to produce event");
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// Consumes events from a topic and produces them enriched with their decrypted matched data to
+// another one until the process is stopped. Offsets are only committed once the events they
+// cover are acknowledged, so each event is produced at least once
+pub fn run(
+    options: &KafkaOptions<'_>,
+    continue_on_error: bool,
+    keys: &Keys<'_>,
+) -> Result<(), String> {
+    let mut config = ClientConfig::new();
+    config.set("bootstrap.servers", options.brokers);
+    for (key, value) in options.properties {
+        config.set(key, value);
+    }
+
+    let consumer: BaseConsumer = config
+        .clone()
+        .set("group.id", options.group_id)
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .create()
+        .map_err(|e| format!("Failed to create Kafka consumer: {}", e))?;
+    let producer: BaseProducer<DeliveryContext> = config
+        .set("acks", "all")
+        .create_with_context(DeliveryContext::default())
+        .map_err(|e| format!("Failed to create Kafka producer: {}", e))?;
+    consumer
+        .subscribe(&[options.input_topic])
+        .map_err(|e| format!("Failed to subscribe to {}: {}", options.input_topic, e))?;
+    tracing::info!(
+        input_topic = options.input_topic,
+        output_topic = options.output_topic,
+        "Consuming"
+    );
+
+    loop {
+        let mut batch = 0;
+        while batch < BATCH_SIZE {
+            let message = match consumer.poll(POLL_TIMEOUT) {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => {
+                    tracing::warn!(error = %e, "Failed to consume events");
+                    break;
+                }
+                None => break,
+            };
+            batch += 1;
+
+            let event = enrich(
+                message.payload().unwrap_or_default(),
+                message.offset(),
+                continue_on_error,
+                keys,
+            )
+            .map_err(|e| {
+                format!(
+                    "Event at offset {} of partition {}: {}",
+                    message.offset(),
+                    message.partition(),
+                    e
+                )
+            })?;
+            let mut record = BaseRecord::to(options.output_topic).payload(event.as_bytes());
+            if let Some(key) = message.key() {
+                record = record.key(key);
+            }
+            produce(&producer, record)?;
+        }
+        producer.poll(Duration::ZERO);
+        if batch == 0 {
+            continue;
+        }
+
+        // Nothing is committed if any event of the batch wasn't delivered, so they are consumed
+        // again on restart
+        producer
+            .flush(FLUSH_TIMEOUT)
+            .map_err(|e| format!("Failed to produce events: {}", e))?;
+        let failures = producer.context().failures.swap(0, Ordering::Relaxed);
+        if failures > 0 {
+            return Err(format!(
+                "Failed to produce {} events to {}",
+                failures, options.output_topic
+            ));
+        }
+        consumer
+            .commit_consumer_state(CommitMode::Sync)
+            .map_err(|e| format!("Failed to commit offsets: {}", e))?;
+        tracing::debug!(events = batch, "Committed batch");
+    }
+}
+
+// Event to produce for a consumed one: enriched if it has matched data, unchanged otherwise, or
+// with the decryption error when continuing on errors
+fn enrich(
+    payload: &[u8],
+    offset: i64,
+    continue_on_error: bool,
+    keys: &Keys<'_>,
+) -> Result<Zeroizing<String>, String> {
+    let line = String::from_utf8_lossy(payload);
+    let line_number = offset.max(0) as usize;
+
+    match logs::process_line(&line, line_number, None, keys) {
+        Ok(Some(event)) => Ok(event),
+        Ok(None) => Ok(Zeroizing::new(line.into_owned())),
+        Err(e) if continue_on_error => {
+            Ok(Zeroizing::new(logs::error_record(&line, line_number, &e)))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Queues an event to be produced, waiting for room in the queue if it is full
+fn produce(
+    producer: &BaseProducer<DeliveryContext>,
+    mut record: BaseRecord<'_, [u8], [u8]>,
+) -> Result<(), String> {
+    loop {
+        match producer.send(record) {
+            Ok(()) => return Ok(()),
+            Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), returned)) => {
+                producer.poll(POLL_TIMEOUT);
+                record = returned;
+            }
+            Err((e, _)) => return Err(format!("Failed to produce event: {}", e)),
+        }
+    }
+}
+
+// Parses librdkafka properties given as key=value
+pub fn parse_property(property: &str) -> Result<(String, String), String> {
+    match property.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("Kafka property {} is not key=value", property)),
+    }
+}
+
+// Parses a file of librdkafka properties, one key=value per line, skipping blank lines and
+// comments, so secrets such as SASL passwords don't have to be passed as arguments
+pub fn parse_properties_file(contents: &str) -> Result<Vec<(String, String)>, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_property)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_properties_file() {
+        assert_eq!(
+            vec![
+                ("security.protocol".to_string(), "SASL_SSL".to_string()),
+                ("sasl.password".to_string(), "a=b".to_string()),
+            ],
+            parse_properties_file("# SASL\nsecurity.protocol=SASL_SSL\n\nsasl.password = a=b\n")
+                .unwrap()
+        );
+        assert!(parse_properties_file("security.protocol").is_err());
+        assert!(parse_property("=SASL_SSL").is_err());
+    }
+
+    #[test]
+    fn test_enrich() {
+        let decrypt = |encrypted_matched_data: &str| match encrypted_matched_data {
+            "valid" => Ok(Zeroizing::new(b"test matched data".to_vec())),
+            _ => Err("Failed to decrypt matched data".to_string()),
+        };
+        let keys = Keys::by_zone(
+            std::collections::HashMap::new(),
+            vec![logs::Key {
+                key_id: "064f06a2".to_string(),
+                label: None,
+                decrypt: Box::new(decrypt),
+            }],
+        );
+
+        assert_eq!(
+            r#"{"Metadata":{"encrypted_matched_data":"valid"},"decrypted_matched_data":"test matched data"}"#,
+            enrich(
+                br#"{"Metadata":{"encrypted_matched_data":"valid"}}"#,
+                0,
+                false,
+                &keys
+            )
+            .unwrap()
+            .as_str()
+        );
+        assert_eq!(
+            r#"{"RayID":"1"}"#,
+            enrich(br#"{"RayID":"1"}"#, 1, false, &keys)
+                .unwrap()
+                .as_str()
+        );
+        assert!(enrich(
+            br#"{"Metadata":{"encrypted_matched_data":"invalid"}}"#,
+            2,
+            false,
+            &keys
+        )
+        .is_err());
+        assert_eq!(
+            r#"{"Metadata":{"encrypted_matched_data":"invalid"},"decryption_error":"Failed to decrypt matched data"}"#,
+            enrich(
+                br#"{"Metadata":{"encrypted_matched_data":"invalid"}}"#,
+                2,
+                true,
+                &keys
+            )
+            .unwrap()
+            .as_str()
+        );
+    }
+}
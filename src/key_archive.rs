@@ -0,0 +1,96 @@
+use crate::passphrase;
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+// Magic bytes at the start of key archives, followed by the passphrase sealed JSON of their keys
+const MAGIC: &[u8] = b"matched-data-keys-v1\n";
+
+// Key pair in an archive, under the label it is restored with
+#[derive(Serialize, Deserialize)]
+pub struct ArchivedKey {
+    pub label: String,
+    pub private_key: String,
+    pub public_key: String,
+    // Fingerprint of the public key, missing from archives written before key IDs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    // RFC 3339 date the key file was created, unknown for keys from the keychain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    // RFC 3339 date the key expires, from the JSON key pair it was exported from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+impl Drop for ArchivedKey {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
+// Encrypts key pairs into an archive with a passphrase
+pub fn seal(keys: &[ArchivedKey], passphrase: &str) -> Result<Vec<u8>, String> {
+    for (i, key) in keys.iter().enumerate() {
+        if keys[..i].iter().any(|other| other.label == key.label) {
+            return Err(format!("Several keys are labeled {}", key.label));
+        }
+    }
+
+    let json = Zeroizing::new(serde_json::to_vec(keys).expect("Failed to serialize keys"));
+    Ok([MAGIC, &passphrase::seal(&json, passphrase)?].concat())
+}
+
+// Decrypts the key pairs of an archive
+pub fn open(archive: &[u8], passphrase: &str) -> Result<Vec<ArchivedKey>, String> {
+    let sealed = archive
+        .strip_prefix(MAGIC)
+        .ok_or("Provided file is not a key archive")?;
+    let json = passphrase::open(sealed, passphrase)?;
+    serde_json::from_slice(&json).map_err(|_| "Provided key archive is invalid".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let mut keys = vec![
+            ArchivedKey {
+                label: "zone-a".to_string(),
+                private_key: "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=".to_string(),
+                public_key: "Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=".to_string(),
+                key_id: Some("064f06a2".to_string()),
+                created: Some("2022-01-01T00:00:00Z".to_string()),
+                expires_at: Some("2023-01-01T00:00:00Z".to_string()),
+            },
+            ArchivedKey {
+                label: "zone-b".to_string(),
+                private_key: "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=".to_string(),
+                public_key: "Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=".to_string(),
+                key_id: Some("064f06a2".to_string()),
+                created: None,
+                expires_at: None,
+            },
+        ];
+        let archive = seal(&keys, "correct horse").unwrap();
+        assert!(archive.starts_with(MAGIC));
+
+        let opened = open(&archive, "correct horse").unwrap();
+        assert_eq!(2, opened.len());
+        assert_eq!("zone-a", opened[0].label);
+        assert_eq!(keys[0].private_key, opened[0].private_key);
+        assert_eq!(keys[0].created, opened[0].created);
+        assert_eq!(keys[0].key_id, opened[0].key_id);
+        assert_eq!(keys[0].expires_at, opened[0].expires_at);
+        assert_eq!(None, opened[1].created);
+
+        assert!(open(&archive, "wrong horse").is_err());
+        assert!(open(b"not an archive", "correct horse").is_err());
+
+        keys[1].label = "zone-a".to_string();
+        assert!(seal(&keys, "correct horse")
+            .unwrap_err()
+            .contains("Several keys"));
+    }
+}
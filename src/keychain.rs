@@ -0,0 +1,179 @@
+use crate::encoding;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use zeroize::Zeroizing;
+
+// Service private keys are stored under in the keychain, each one named by its label
+const SERVICE: &str = "matched-data-cli";
+
+// Saves a base64 encoded private key under a label in the OS keychain: the macOS Keychain, the
+// Secret Service on Linux, or a file in the user's application data encrypted with DPAPI on
+// Windows. The key is passed to the keychain tool on stdin, never in its arguments
+pub fn store(label: &str, private_key_base64: &str) -> Result<(), String> {
+    check_label(label)?;
+    let private_key_base64 = private_key_base64.trim();
+
+    #[cfg(target_os = "macos")]
+    {
+        // security reads commands from stdin in interactive mode, keeping the key out of ps
+        let command = Zeroizing::new(format!(
+            "add-generic-password -U -s {} -a {} -w {}\n",
+            SERVICE, label, private_key_base64
+        ));
+        run(
+            Command::new("security").arg("-i"),
+            command.as_bytes(),
+            "security",
+        )?;
+    }
+    #[cfg(windows)]
+    {
+        let script = format!(
+            "$ErrorActionPreference = 'Stop'; \
+             Add-Type -AssemblyName System.Security; \
+             $key = [Text.Encoding]::UTF8.GetBytes([Console]::In.ReadToEnd()); \
+             $protected = [Security.Cryptography.ProtectedData]::Protect($key, $null, 'CurrentUser'); \
+             $dir = Join-Path $env:APPDATA '{service}\\keys'; \
+             New-Item -ItemType Directory -Force -Path $dir | Out-Null; \
+             [IO.File]::WriteAllBytes((Join-Path $dir '{label}.dpapi'), $protected)",
+            service = SERVICE,
+            label = label
+        );
+        run(
+            Command::new("powershell").args(["-NoProfile", "-Command", &script]),
+            private_key_base64.as_bytes(),
+            "powershell",
+        )?;
+    }
+    #[cfg(not(any(target_os = "macos", windows)))]
+    run(
+        Command::new("secret-tool").args([
+            "store",
+            "--label",
+            &format!("{} {}", SERVICE, label),
+            "service",
+            SERVICE,
+            "account",
+            label,
+        ]),
+        private_key_base64.as_bytes(),
+        "secret-tool",
+    )?;
+
+    Ok(())
+}
+
+// Loads the base64 encoded private key saved under a label in the OS keychain
+pub fn load(label: &str) -> Result<Zeroizing<String>, String> {
+    check_label(label)?;
+
+    #[cfg(target_os = "macos")]
+    let output = run(
+        Command::new("security").args(["find-generic-password", "-s", SERVICE, "-a", label, "-w"]),
+        &[],
+        "security",
+    );
+    #[cfg(windows)]
+    let output = {
+        let script = format!(
+            "$ErrorActionPreference = 'Stop'; \
+             Add-Type -AssemblyName System.Security; \
+             $protected = [IO.File]::ReadAllBytes((Join-Path $env:APPDATA '{service}\\keys\\{label}.dpapi')); \
+             $key = [Security.Cryptography.ProtectedData]::Unprotect($protected, $null, 'CurrentUser'); \
+             [Console]::Out.Write([Text.Encoding]::UTF8.GetString($key))",
+            service = SERVICE,
+            label = label
+        );
+        run(
+            Command::new("powershell").args(["-NoProfile", "-Command", &script]),
+            &[],
+            "powershell",
+        )
+    };
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let output = run(
+        Command::new("secret-tool").args(["lookup", "service", SERVICE, "account", label]),
+        &[],
+        "secret-tool",
+    );
+
+    let output = output.map_err(|e| format!("No private key {} in the keychain: {}", label, e))?;
+    let private_key_base64 = Zeroizing::new(String::from_utf8_lossy(&output).trim().to_string());
+    if private_key_base64.is_empty() {
+        return Err(format!("No private key {} in the keychain", label));
+    }
+
+    Ok(private_key_base64)
+}
+
+// Loads and decodes the private key saved under a label in the OS keychain
+pub fn read_private_key(label: &str, strict: bool) -> Result<Zeroizing<Vec<u8>>, String> {
+    let private_key_base64 = load(label)?;
+
+    encoding::decode_base64(&private_key_base64, strict)
+        .map(Zeroizing::new)
+        .map_err(|_| "Private key in the keychain is not base64 encoded".to_string())
+}
+
+// Labels are passed to keychain tools and used as file names, so are restricted to a safe set of
+// characters
+pub fn check_label(label: &str) -> Result<(), String> {
+    let valid = !label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !label.starts_with('.');
+
+    if !valid {
+        return Err(format!(
+            "Key label {} is invalid, use letters, digits, -, _ and .",
+            label
+        ));
+    }
+
+    Ok(())
+}
+
+// Runs a keychain tool writing input to its stdin, returning its output
+fn run(command: &mut Command, input: &[u8], tool: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|_| format!("Failed to run {}, check that it is installed", tool))?;
+
+    child
+        .stdin
+        .take()
+        .expect("Failed to open stdin")
+        .write_all(input)
+        .map_err(|_| format!("Failed to write to {}", tool))?;
+    let output = child
+        .wait_with_output()
+        .map_err(|_| format!("Failed to run {}", tool))?;
+    let stdout = Zeroizing::new(output.stdout);
+
+    if !output.status.success() {
+        return Err(match String::from_utf8_lossy(&output.stderr).trim() {
+            "" => format!("{} failed", tool),
+            stderr => format!("{} failed: {}", tool, stderr),
+        });
+    }
+
+    Ok(stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_label() {
+        assert!(check_label("zone-a_2024.key").is_ok());
+        assert!(check_label("").is_err());
+        assert!(check_label(".hidden").is_err());
+        assert!(check_label("../key").is_err());
+        assert!(check_label("a b").is_err());
+    }
+}
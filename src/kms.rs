@@ -0,0 +1,93 @@
+use std::process::Command;
+use zeroize::Zeroizing;
+
+// Unwraps a private key file encrypted by AWS KMS, using the aws CLI and its ambient credentials
+pub fn unwrap_aws(key_id: &str, wrapped_key_filename: &str) -> Result<Vec<u8>, String> {
+    let output = Command::new("aws")
+        .args([
+            "kms",
+            "decrypt",
+            "--key-id",
+            key_id,
+            "--ciphertext-blob",
+            &format!("fileb://{}", wrapped_key_filename),
+            "--output",
+            "text",
+            "--query",
+            "Plaintext",
+        ])
+        .output()
+        .map_err(|_| "Failed to run the aws CLI, check that it is installed")?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to unwrap private key with AWS KMS: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let plaintext = Zeroizing::new(output.stdout);
+    decode_plaintext(&String::from_utf8_lossy(&plaintext))
+}
+
+// Unwraps a private key file encrypted by Google Cloud KMS, using the gcloud CLI and its ambient
+// credentials, the key being a full resource name such as
+// projects/<project>/locations/<location>/keyRings/<key ring>/cryptoKeys/<key>
+pub fn unwrap_gcp(key: &str, wrapped_key_filename: &str) -> Result<Vec<u8>, String> {
+    let output = Command::new("gcloud")
+        .args([
+            "kms",
+            "decrypt",
+            "--key",
+            key,
+            "--ciphertext-file",
+            wrapped_key_filename,
+            "--plaintext-file",
+            "-",
+        ])
+        .output()
+        .map_err(|_| "Failed to run the gcloud CLI, check that it is installed")?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to unwrap private key with Google Cloud KMS: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    // Unlike aws, gcloud writes the plaintext itself, the base64 encoded private key
+    let plaintext = Zeroizing::new(output.stdout);
+    radix64::STD
+        .decode(String::from_utf8_lossy(&plaintext).trim_end())
+        .map_err(|_| "Unwrapped private key is not base64 encoded".to_string())
+}
+
+// Decodes the base64 plaintext returned by AWS KMS, which is itself a base64 encoded private key
+fn decode_plaintext(plaintext: &str) -> Result<Vec<u8>, String> {
+    let private_key_base64 = Zeroizing::new(
+        radix64::STD
+            .decode(plaintext.trim())
+            .map_err(|_| "Unwrapped private key is not base64 encoded")?,
+    );
+
+    radix64::STD
+        .decode(String::from_utf8_lossy(&private_key_base64).trim_end())
+        .map_err(|_| "Unwrapped private key is not base64 encoded".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_plaintext() {
+        // base64 of "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=\n"
+        let plaintext = "dUJTNWVCdHRIcnFrZFk0MWtiWlBkdlluTno4VmowVHZLSVVwakIxeS9HQT0K\n";
+
+        assert_eq!(
+            "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=",
+            radix64::STD.encode(&decode_plaintext(plaintext).unwrap())
+        );
+        assert!(decode_plaintext("not base64").is_err());
+    }
+}
@@ -0,0 +1,10 @@
+#![warn(rust_2018_idioms)]
+// Only needs alloc without the std feature, for targets without an operating system
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod matched_data;
+pub mod versions;
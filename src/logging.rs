@@ -0,0 +1,46 @@
+use clap::ArgEnum;
+use std::io::stderr;
+use tracing::level_filters::LevelFilter;
+
+// Least severe level of the events logged
+#[derive(ArgEnum, Clone, Copy)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::OFF,
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+// Logs events on stderr, keeping stdout for the output of commands. Events never carry matched
+// data, encrypted or not, nor keys
+pub fn init(level: LogLevel, format: LogFormat) {
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(stderr)
+        .with_max_level(LevelFilter::from(level))
+        .with_target(false);
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().flatten_event(true).init(),
+    }
+}
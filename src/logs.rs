@@ -0,0 +1,746 @@
+use crate::extract::Extractor;
+use crate::progress::Progress;
+use crate::sink::Sink;
+use crate::TRUNCATED;
+use flate2::bufread::MultiGzDecoder;
+#[cfg(not(target_os = "wasi"))]
+use rayon::prelude::*;
+#[cfg(not(target_os = "wasi"))]
+use rayon::ThreadPoolBuilder;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read};
+use std::mem;
+use std::thread;
+use std::time::Duration;
+use zeroize::{Zeroize, Zeroizing};
+
+// Decrypts base64 encoded matched data, shared by the threads decrypting in parallel
+pub type Decrypt<'a> = dyn Fn(&str) -> Result<Zeroizing<Vec<u8>>, String> + Sync + 'a;
+
+// Field holding the encrypted matched data of an event
+const ENCRYPTED_MATCHED_DATA: &str = "encrypted_matched_data";
+
+// Field added to an event holding its decrypted matched data
+const DECRYPTED_MATCHED_DATA: &str = "decrypted_matched_data";
+
+// Field added to an event whose matched data couldn't be decrypted, with --continue-on-error
+const DECRYPTION_ERROR: &str = "decryption_error";
+
+// Field added to an event decrypted with one of several private keys, identifying the key so the
+// plaintext can be attributed to it
+const DECRYPTION_KEY: &str = "decryption_key";
+
+// Field added to an event with several encrypted matched data, holding each one decrypted keyed
+// by rule ID
+const DECRYPTED_MATCHED_DATA_BY_RULE: &str = "decrypted_matched_data_by_rule";
+
+// Fields holding the rule ID of an event or of one of its rule matches, across Logpush datasets
+// and the API
+pub const RULE_ID_FIELDS: &[&str] = &["RuleID", "ruleId", "SecurityRuleID", "WAFRuleID", "rule_id"];
+
+// Fields identifying an event in the index of extracted payloads, across Logpush datasets and the
+// API, its line number being used when it has none
+const RAY_ID_FIELDS: &[&str] = &["RayID", "rayName", "RayName", "ray_id"];
+
+// Fields holding the zone ID of an event, across Logpush datasets and the API
+const ZONE_ID_FIELDS: &[&str] = &["ZoneID", "ZoneTag", "zoneTag", "zone_id"];
+
+// How long to wait for new lines at the end of a followed log
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+// Magic bytes at the start of gzip files
+pub const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+// Maximum number of lines decrypted in parallel before their events are printed in order
+const BATCH_SIZE: usize = 4096;
+
+// Longest line a pipe reads, so a stream without line breaks can't exhaust memory
+const MAX_PIPE_LINE_LENGTH: usize = 16 << 20;
+
+// Size of the buffer log lines are read from, a batch is decrypted once it has been consumed
+const READ_BUFFER_SIZE: usize = 1 << 20;
+
+// Archives report why they failed to be read, such as the member that is corrupt
+pub fn read_error(error: io::Error) -> String {
+    match error.kind() {
+        ErrorKind::Other => error.to_string(),
+        _ => "Failed to read log".to_string(),
+    }
+}
+
+// Decompresses a log on the fly if it starts with the gzip magic bytes, as delivered by Logpush
+pub fn decompress<R: BufRead + 'static>(mut reader: R) -> Result<BufReader<Box<dyn Read>>, String> {
+    let is_gzip = reader
+        .fill_buf()
+        .map_err(read_error)?
+        .starts_with(GZIP_MAGIC);
+
+    let reader: Box<dyn Read> = if is_gzip {
+        Box::new(MultiGzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+    Ok(BufReader::with_capacity(READ_BUFFER_SIZE, reader))
+}
+
+// Finds every encrypted matched data of an event, as a field or as a metadata key-value pair,
+// keyed by the rule ID of the closest object holding one, or by their position if there's none,
+// with repeated keys numbered
+pub fn find_all_encrypted_matched_data(event: &Value) -> Vec<(String, &str)> {
+    let mut found = Vec::new();
+    collect_encrypted_matched_data(event, None, &mut found);
+
+    let mut keyed: Vec<(String, &str)> = Vec::new();
+    for (i, (rule_id, data)) in found.into_iter().enumerate() {
+        let key = rule_id.unwrap_or_else(|| format!("matched_data_{}", i + 1));
+        let repeats = keyed
+            .iter()
+            .filter(|(existing, _)| existing == &key || existing.starts_with(&format!("{}#", key)))
+            .count();
+        keyed.push(match repeats {
+            0 => (key, data),
+            _ => (format!("{}#{}", key, repeats + 1), data),
+        });
+    }
+
+    keyed
+}
+
+// Collects encrypted matched data with the rule ID inherited from the enclosing objects
+fn collect_encrypted_matched_data<'a>(
+    value: &'a Value,
+    rule_id: Option<String>,
+    found: &mut Vec<(Option<String>, &'a str)>,
+) {
+    match value {
+        Value::Object(object) => {
+            let rule_id = RULE_ID_FIELDS
+                .iter()
+                .find_map(|name| match object.get(*name) {
+                    Some(Value::String(rule_id)) => Some(rule_id.clone()),
+                    Some(Value::Number(rule_id)) => Some(rule_id.to_string()),
+                    _ => None,
+                })
+                .or(rule_id);
+            if let Some(data) = object.get(ENCRYPTED_MATCHED_DATA).and_then(Value::as_str) {
+                found.push((rule_id.clone(), data));
+            }
+            if object.get("key").and_then(Value::as_str) == Some(ENCRYPTED_MATCHED_DATA) {
+                if let Some(data) = object.get("value").and_then(Value::as_str) {
+                    found.push((rule_id.clone(), data));
+                }
+            }
+            for value in object.values() {
+                collect_encrypted_matched_data(value, rule_id.clone(), found);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                collect_encrypted_matched_data(value, rule_id.clone(), found);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Private key the matched data of events is decrypted with, with what identifies it in the events
+pub struct Key<'a> {
+    pub key_id: String,
+    pub label: Option<String>,
+    pub decrypt: Box<Decrypt<'a>>,
+}
+
+// Private keys the matched data of events is decrypted with, picked by the zone ID of each event so
+// logs of zones with distinct keys can be processed together. Events of zones without a key of
+// their own, or without a zone ID, are tried with each of the other keys in turn
+pub struct Keys<'a> {
+    zones: HashMap<String, Key<'a>>,
+    others: Vec<Key<'a>>,
+}
+
+impl<'a> Keys<'a> {
+    pub fn by_zone(zones: HashMap<String, Key<'a>>, others: Vec<Key<'a>>) -> Self {
+        Keys { zones, others }
+    }
+
+    // Whether events record the key that decrypted them, which is only ambiguous with several
+    fn several(&self) -> bool {
+        self.zones.len() + self.others.len() > 1
+    }
+
+    // Plaintext of matched data with the key that decrypted it
+    fn decrypt(
+        &self,
+        event: &Value,
+        encrypted_matched_data: &str,
+    ) -> Result<(Zeroizing<Vec<u8>>, &Key<'a>), String> {
+        let zone_id = zone_id(event);
+        if let Some(key) = zone_id.as_ref().and_then(|zone_id| self.zones.get(zone_id)) {
+            return (key.decrypt)(encrypted_matched_data).map(|plaintext| (plaintext, key));
+        }
+
+        let mut error = match zone_id {
+            Some(zone_id) => format!(
+                "No private key for zone {}, add it to the keys of the configuration file",
+                zone_id
+            ),
+            None => "No private key for events without a zone ID, pass -k".to_string(),
+        };
+        for key in &self.others {
+            match (key.decrypt)(encrypted_matched_data) {
+                Ok(plaintext) => return Ok((plaintext, key)),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+}
+
+// Zone ID of an event, which some datasets hold as a number
+fn zone_id(event: &Value) -> Option<String> {
+    ZONE_ID_FIELDS
+        .iter()
+        .find_map(|name| match event.get(*name)? {
+            Value::String(zone_id) => Some(zone_id.clone()),
+            Value::Number(zone_id) => Some(zone_id.to_string()),
+            _ => None,
+        })
+}
+
+// Decrypts the matched data of a log line, returning the enriched event if it has matched data.
+// Payloads are also written bit-exact to the extract directory if any
+pub fn process_line(
+    line: &str,
+    line_number: usize,
+    extractor: Option<&Extractor>,
+    keys: &Keys<'_>,
+) -> Result<Option<Zeroizing<String>>, String> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let mut event: Value =
+        serde_json::from_str(line).map_err(|_| "Log line is not a JSON event".to_string())?;
+
+    let all_encrypted_matched_data = find_all_encrypted_matched_data(&event);
+    let several = all_encrypted_matched_data.len() > 1;
+    let mut matched_data = Vec::new();
+    let mut decryption_key = None;
+    for (rule_id, encrypted_matched_data) in all_encrypted_matched_data {
+        let decrypted = match encrypted_matched_data {
+            TRUNCATED => Ok(Value::Null),
+            _ => keys
+                .decrypt(&event, encrypted_matched_data)
+                .and_then(|(decrypted, key)| {
+                    decryption_key.get_or_insert(key);
+                    if let Some(extractor) = extractor {
+                        let mut event_id = event_id(&event, line_number);
+                        if several {
+                            event_id = format!("{}/{}", event_id, rule_id);
+                        }
+                        extractor.extract(&event_id, &decrypted)?;
+                    }
+                    Ok(Value::String(
+                        String::from_utf8_lossy(&decrypted).into_owned(),
+                    ))
+                }),
+        };
+        let decrypted = match decrypted {
+            Ok(decrypted) => decrypted,
+            Err(e) => {
+                matched_data
+                    .iter_mut()
+                    .for_each(|(_, decrypted)| zeroize_strings(decrypted));
+                return Err(e);
+            }
+        };
+        matched_data.push((rule_id, decrypted));
+    }
+    if matched_data.is_empty() {
+        return Ok(None);
+    }
+
+    // The first one is a string as for single rule events, and all of them are keyed by rule ID
+    // when there are several
+    match &mut event {
+        Value::Object(object) => {
+            if matched_data.len() > 1 {
+                object.insert(
+                    DECRYPTED_MATCHED_DATA.to_string(),
+                    matched_data[0].1.clone(),
+                );
+                object.insert(
+                    DECRYPTED_MATCHED_DATA_BY_RULE.to_string(),
+                    Value::Object(matched_data.drain(..).collect()),
+                );
+            } else if let Some((_, decrypted)) = matched_data.pop() {
+                object.insert(DECRYPTED_MATCHED_DATA.to_string(), decrypted);
+            }
+            if let Some(key) = decryption_key.filter(|_| keys.several()) {
+                let mut record = serde_json::Map::new();
+                record.insert("key_id".to_string(), Value::String(key.key_id.clone()));
+                if let Some(label) = &key.label {
+                    record.insert("label".to_string(), Value::String(label.clone()));
+                }
+                object.insert(DECRYPTION_KEY.to_string(), Value::Object(record));
+            }
+        }
+        _ => matched_data
+            .iter_mut()
+            .for_each(|(_, decrypted)| zeroize_strings(decrypted)),
+    }
+    let enriched_event = Zeroizing::new(event.to_string());
+
+    // Wipe the plaintext held in the event once it is serialized
+    for field in [DECRYPTED_MATCHED_DATA, DECRYPTED_MATCHED_DATA_BY_RULE] {
+        if let Some(matched_data) = event.get_mut(field) {
+            zeroize_strings(matched_data);
+        }
+    }
+
+    Ok(Some(enriched_event))
+}
+
+// Ray ID of an event, or its line number in the log if it has none
+fn event_id(event: &Value, line_number: usize) -> String {
+    RAY_ID_FIELDS
+        .iter()
+        .find_map(|name| event.get(*name).and_then(Value::as_str))
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("line {}", line_number))
+}
+
+// Records why a line failed in its event, or in a record of its line number if it isn't an event
+pub fn error_record(line: &str, line_number: usize, error: &str) -> String {
+    match serde_json::from_str(line) {
+        Ok(Value::Object(mut event)) => {
+            event.insert(
+                DECRYPTION_ERROR.to_string(),
+                Value::String(error.to_string()),
+            );
+            Value::Object(event).to_string()
+        }
+        _ => serde_json::json!({ "line": line_number, DECRYPTION_ERROR: error }).to_string(),
+    }
+}
+
+// Message of a failure to write events to the sink
+fn output_error(error: io::Error) -> String {
+    format!("Failed to write output: {}", error)
+}
+
+// Wipes the strings held in a JSON value
+fn zeroize_strings(value: &mut Value) {
+    match value {
+        Value::String(string) => string.zeroize(),
+        Value::Array(values) => values.iter_mut().for_each(zeroize_strings),
+        Value::Object(object) => object.values_mut().for_each(zeroize_strings),
+        _ => {}
+    }
+}
+
+// How a log is processed
+pub struct LogOptions {
+    // Wait for new lines at the end of the log
+    pub follow: bool,
+    // Threads decrypting events in parallel
+    pub jobs: usize,
+    // Write events that fail with the reason rather than stopping at the first one
+    pub continue_on_error: bool,
+}
+
+// Lines of a log processed, and those that failed with continue_on_error
+pub struct LogSummary {
+    pub lines: usize,
+    pub failures: usize,
+}
+
+// Decrypts the matched data of each event in a log across a number of threads, writing the
+// enriched events to the sink and their payloads to the extract directory if any
+pub fn process_log<R: Read>(
+    reader: BufReader<R>,
+    options: &LogOptions,
+    extractor: Option<&Extractor>,
+    progress: &mut Progress,
+    sink: &mut dyn Sink,
+    keys: &Keys<'_>,
+) -> Result<(), String> {
+    let summary = decrypt_log(reader, options, extractor, progress, sink, keys)?;
+    match summary.failures {
+        0 => Ok(()),
+        failures => Err(format!(
+            "Failed to decrypt {} of {} lines",
+            failures, summary.lines
+        )),
+    }
+}
+
+// Processes a log as process_log does, returning how many lines failed rather than an error
+pub fn decrypt_log<R: Read>(
+    mut reader: BufReader<R>,
+    options: &LogOptions,
+    extractor: Option<&Extractor>,
+    progress: &mut Progress,
+    sink: &mut dyn Sink,
+    keys: &Keys<'_>,
+) -> Result<LogSummary, String> {
+    // Rayon would pick a number of threads itself
+    if options.jobs == 0 {
+        return Err("Number of jobs must be at least 1".to_string());
+    }
+
+    // WASI runtimes can't start threads, lines are decrypted one after the other there
+    #[cfg(not(target_os = "wasi"))]
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(options.jobs)
+        .build()
+        .map_err(|_| "Failed to start decryption threads")?;
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_number = 0;
+    let mut failures = 0;
+
+    loop {
+        let read = reader.read_line(&mut line).map_err(read_error)?;
+
+        // Wait for the rest of a line that is still being written
+        let complete = read != 0 && line.ends_with('\n');
+        if complete {
+            lines.push(mem::take(&mut line));
+            // Lines still buffered join the batch, events written to a followed log since the
+            // last read are printed as soon as they are decrypted
+            if lines.len() < BATCH_SIZE && !reader.buffer().is_empty() {
+                continue;
+            }
+        } else if !options.follow && !line.is_empty() {
+            lines.push(mem::take(&mut line));
+        }
+
+        let process =
+            |(i, line): (usize, &String)| process_line(line, line_number + i + 1, extractor, keys);
+        #[cfg(not(target_os = "wasi"))]
+        let events: Vec<_> = pool.install(|| lines.par_iter().enumerate().map(process).collect());
+        #[cfg(target_os = "wasi")]
+        let events: Vec<_> = lines.iter().enumerate().map(process).collect();
+        for (event, line) in events.into_iter().zip(&lines) {
+            line_number += 1;
+            progress.record(event.is_err());
+            match event {
+                Ok(Some(event)) => sink.write_event(&event),
+                Ok(None) => Ok(()),
+                Err(e) if options.continue_on_error => {
+                    failures += 1;
+                    tracing::warn!(line = line_number, error = %e, "Failed to decrypt event");
+                    sink.write_event(&error_record(line, line_number, &e))
+                }
+                Err(e) => {
+                    sink.finish().map_err(output_error)?;
+                    return Err(format!("Line {}: {}", line_number, e));
+                }
+            }
+            .map_err(output_error)?;
+        }
+        lines.clear();
+        sink.flush_events().map_err(output_error)?;
+
+        if !complete {
+            if !options.follow {
+                sink.finish().map_err(output_error)?;
+                progress.finish();
+                return Ok(LogSummary {
+                    lines: line_number,
+                    failures,
+                });
+            }
+            thread::sleep(FOLLOW_INTERVAL);
+        }
+    }
+}
+
+// Decrypts the matched data of each NDJSON line as soon as it is read, writing the enriched event
+// and flushing it before reading the next, so memory is bounded by the longest line. Lines without
+// matched data are passed through, and with --continue-on-error so are lines that fail, with the
+// reason added. Stops quietly when the reader of the output goes away
+pub fn pipe<R: BufRead>(
+    mut reader: R,
+    sink: &mut dyn Sink,
+    continue_on_error: bool,
+    keys: &Keys<'_>,
+) -> Result<(), String> {
+    let mut line = String::new();
+    let mut line_number = 0;
+    let mut failures = 0;
+
+    loop {
+        line.clear();
+        let read = reader
+            .by_ref()
+            .take(MAX_PIPE_LINE_LENGTH as u64 + 1)
+            .read_line(&mut line)
+            .map_err(|_| "Failed to read input")?;
+        if read == 0 {
+            break;
+        }
+        line_number += 1;
+
+        let event = if line.len() > MAX_PIPE_LINE_LENGTH {
+            reader
+                .skip_until(b'\n')
+                .map_err(|_| "Failed to read input")?;
+            line.clear();
+            Err(format!(
+                "Line is longer than {} bytes",
+                MAX_PIPE_LINE_LENGTH
+            ))
+        } else {
+            process_line(&line, line_number, None, keys)
+        };
+        let output = match event {
+            Ok(Some(event)) => event,
+            Ok(None) => Zeroizing::new(line.trim_end_matches(['\r', '\n']).to_string()),
+            Err(e) if continue_on_error => {
+                failures += 1;
+                tracing::warn!(line = line_number, error = %e, "Failed to decrypt event");
+                Zeroizing::new(error_record(line.trim_end(), line_number, &e))
+            }
+            Err(e) => {
+                sink.finish().map_err(output_error)?;
+                return Err(format!("Line {}: {}", line_number, e));
+            }
+        };
+
+        match sink.write_event(&output).and_then(|_| sink.flush_events()) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(()),
+            Err(e) => return Err(output_error(e)),
+        }
+    }
+    match sink.finish() {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(()),
+        Err(e) => return Err(output_error(e)),
+    }
+
+    match failures {
+        0 => Ok(()),
+        _ => Err(format!(
+            "Failed to decrypt {} of {} lines",
+            failures, line_number
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Key decrypting matched data with a function
+    fn key<'a>(
+        key_id: &str,
+        decrypt: impl Fn(&str) -> Result<Zeroizing<Vec<u8>>, String> + Sync + 'a,
+    ) -> Key<'a> {
+        Key {
+            key_id: key_id.to_string(),
+            label: None,
+            decrypt: Box::new(decrypt),
+        }
+    }
+
+    #[test]
+    fn test_find_encrypted_matched_data() {
+        let event: Value =
+            serde_json::from_str(r#"{"Metadata":{"encrypted_matched_data":"AzTY"}}"#).unwrap();
+        assert_eq!(
+            vec![("matched_data_1".to_string(), "AzTY")],
+            find_all_encrypted_matched_data(&event)
+        );
+
+        let event: Value = serde_json::from_str(
+            r#"{"metadata":[{"key":"ruleset","value":"abc"},{"key":"encrypted_matched_data","value":"AzTY"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![("matched_data_1".to_string(), "AzTY")],
+            find_all_encrypted_matched_data(&event)
+        );
+
+        let event: Value = serde_json::from_str(r#"{"Action":"block"}"#).unwrap();
+        assert!(find_all_encrypted_matched_data(&event).is_empty());
+    }
+
+    #[test]
+    fn test_find_all_encrypted_matched_data() {
+        let event: Value = serde_json::from_str(
+            r#"{"RuleID":"100000","Metadata":{"encrypted_matched_data":"AzTY"},"matches":[{"ruleId":"100001","metadata":[{"key":"encrypted_matched_data","value":"AzTZ"}]},{"ruleId":"100001","encrypted_matched_data":"AzTa"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                ("100000".to_string(), "AzTY"),
+                ("100001".to_string(), "AzTZ"),
+                ("100001#2".to_string(), "AzTa")
+            ],
+            find_all_encrypted_matched_data(&event)
+        );
+
+        let event: Value = serde_json::from_str(
+            r#"[{"encrypted_matched_data":"AzTY"},{"encrypted_matched_data":"AzTZ"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                ("matched_data_1".to_string(), "AzTY"),
+                ("matched_data_2".to_string(), "AzTZ")
+            ],
+            find_all_encrypted_matched_data(&event)
+        );
+    }
+
+    #[test]
+    fn test_process_line_several_rules() {
+        let decrypt = |encrypted_matched_data: &str| {
+            Ok(Zeroizing::new(
+                format!("plaintext of {}", encrypted_matched_data).into_bytes(),
+            ))
+        };
+        let event = process_line(
+            r#"{"matches":[{"ruleId":"a","encrypted_matched_data":"1"},{"ruleId":"b","encrypted_matched_data":"2"}]}"#,
+            1,
+            None,
+            &Keys::by_zone(HashMap::new(), vec![key("064f06a2", decrypt)]),
+        )
+        .unwrap()
+        .unwrap();
+        let event: Value = serde_json::from_str(&event).unwrap();
+        assert_eq!("plaintext of 1", event[DECRYPTED_MATCHED_DATA]);
+        assert_eq!("plaintext of 2", event[DECRYPTED_MATCHED_DATA_BY_RULE]["b"]);
+    }
+
+    #[test]
+    fn test_pipe() {
+        let decrypt = |encrypted_matched_data: &str| match encrypted_matched_data {
+            "1" => Ok(Zeroizing::new(b"plaintext".to_vec())),
+            _ => Err("Provided matched data is invalid".to_string()),
+        };
+        let input = "{\"encrypted_matched_data\":\"1\"}\n{\"RayID\":\"2\"}\n\n{\"encrypted_matched_data\":\"2\"}";
+        let mut output = Vec::new();
+        assert!(pipe(
+            input.as_bytes(),
+            &mut output,
+            true,
+            &Keys::by_zone(HashMap::new(), vec![key("064f06a2", decrypt)])
+        )
+        .is_err());
+
+        let events: Vec<Value> = std::str::from_utf8(&output)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!("plaintext", events[0][DECRYPTED_MATCHED_DATA]);
+        assert_eq!("2", events[1]["RayID"]);
+        assert_eq!(
+            "Provided matched data is invalid",
+            events[2][DECRYPTION_ERROR]
+        );
+
+        let mut output = Vec::new();
+        assert!(pipe(
+            input.as_bytes(),
+            &mut output,
+            false,
+            &Keys::by_zone(HashMap::new(), vec![key("064f06a2", decrypt)])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_keys_by_zone() {
+        let zone_a = |_: &str| Ok(Zeroizing::new(b"zone a".to_vec()));
+        let other = |encrypted_matched_data: &str| match encrypted_matched_data {
+            "1" => Ok(Zeroizing::new(b"other".to_vec())),
+            _ => Err("Failed to decrypt matched data".to_string()),
+        };
+        let keys = Keys::by_zone(
+            HashMap::from([("a".to_string(), key("1a2b3c4d", zone_a))]),
+            vec![Key {
+                label: Some("legacy".to_string()),
+                ..key("064f06a2", other)
+            }],
+        );
+        let decrypt = |line: &str| {
+            process_line(line, 1, None, &keys)
+                .map(|event| serde_json::from_str::<Value>(&event.unwrap()).unwrap())
+        };
+
+        let event = decrypt(r#"{"ZoneTag":"a","encrypted_matched_data":"2"}"#).unwrap();
+        assert_eq!("zone a", event[DECRYPTED_MATCHED_DATA]);
+        assert_eq!(
+            serde_json::json!({"key_id": "1a2b3c4d"}),
+            event[DECRYPTION_KEY]
+        );
+        let event = decrypt(r#"{"ZoneID":1,"encrypted_matched_data":"1"}"#).unwrap();
+        assert_eq!("other", event[DECRYPTED_MATCHED_DATA]);
+        assert_eq!(
+            serde_json::json!({"key_id": "064f06a2", "label": "legacy"}),
+            event[DECRYPTION_KEY]
+        );
+        assert_eq!(
+            "Failed to decrypt matched data",
+            decrypt(r#"{"ZoneTag":"b","encrypted_matched_data":"2"}"#).unwrap_err()
+        );
+        assert_eq!(
+            "No private key for zone b, add it to the keys of the configuration file",
+            Keys::by_zone(HashMap::new(), Vec::new())
+                .decrypt(&serde_json::json!({"ZoneTag": "b"}), "1")
+                .err()
+                .unwrap()
+        );
+
+        // A single key is not recorded
+        let keys = Keys::by_zone(HashMap::new(), vec![key("064f06a2", other)]);
+        let event = process_line(r#"{"encrypted_matched_data":"1"}"#, 1, None, &keys)
+            .unwrap()
+            .unwrap();
+        assert!(!event.contains(DECRYPTION_KEY));
+    }
+
+    #[test]
+    fn test_error_record() {
+        let event: Value = serde_json::from_str(&error_record(
+            r#"{"RayID":"1"}"#,
+            1,
+            "Provided matched data is invalid",
+        ))
+        .unwrap();
+        assert_eq!("1", event["RayID"]);
+        assert_eq!("Provided matched data is invalid", event[DECRYPTION_ERROR]);
+
+        let record: Value =
+            serde_json::from_str(&error_record("not json", 2, "Log line is not a JSON event"))
+                .unwrap();
+        assert_eq!(2, record["line"]);
+    }
+
+    #[test]
+    fn test_decompress() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::{Cursor, Read, Write};
+
+        let log = "{\"RayID\":\"1\"}\n";
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(log.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        for input in [compressed, log.as_bytes().to_vec()] {
+            let mut output = String::new();
+            decompress(Cursor::new(input))
+                .unwrap()
+                .read_to_string(&mut output)
+                .unwrap();
+            assert_eq!(log, output);
+        }
+    }
+}
@@ -1,24 +1,230 @@
 #![warn(rust_2018_idioms)]
 
-mod matched_data;
+mod archive;
+mod audit;
+#[cfg(feature = "api")]
+mod azure;
+mod batch;
+mod bench;
+#[cfg(feature = "browse")]
+mod browse;
+mod cache;
+mod clipboard;
+#[cfg(feature = "api")]
+mod cloudflare;
+mod config;
+mod content_type;
+mod csv;
+mod diff;
+mod doctor;
+#[cfg(feature = "api")]
+mod elasticsearch;
+mod encoding;
+mod error;
+mod expiry;
+mod extract;
+mod help;
+mod http;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod key_archive;
+mod key_format;
+mod keychain;
+mod kms;
+mod logging;
+mod logs;
+mod memory;
+#[cfg(feature = "serve")]
+mod metrics;
+mod object_storage;
+mod output;
+mod passphrase;
+mod progress;
+mod qr;
+mod redact;
+mod rewrap;
+mod select;
+mod self_test;
+#[cfg(feature = "self-update")]
+mod self_update;
+#[cfg(feature = "serve")]
+mod serve;
+mod sink;
+#[cfg(feature = "api")]
+mod splunk;
+mod stats;
+mod test_vectors;
+#[cfg(feature = "api")]
+mod vault;
+#[cfg(feature = "serve")]
+mod watch;
+mod yubikey;
 
-use crate::matched_data::generate_key_pair;
-use clap::{ArgEnum, Parser};
+use clap::{ArgEnum, CommandFactory, Parser};
+use error::{CliError, ErrorCode};
 use hpke::Serializable;
+use matched_data_cli::matched_data::{
+    self, generate_key_pair, DecryptAnyVersionError, DecryptError,
+};
+use matched_data_cli::versions::{self, VersionedDecryptor};
 use serde::{Deserialize, Serialize};
-use std::io::{stdin, stdout, Write};
-use std::{fs, str};
+use std::io::{self, stdin, stdout, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use std::{fs, process, str};
+use zeroize::{Zeroize, Zeroizing};
 
 #[derive(Parser)]
 #[clap(about, author, version)]
 struct Options {
+    #[clap(
+        help_heading = "GLOBAL OPTIONS",
+        long,
+        global = true,
+        value_name = "path",
+        help = "Configuration file with defaults, instead of ~/.config/matched-data-cli/config.toml"
+    )]
+    config: Option<String>,
+
+    #[clap(
+        help_heading = "GLOBAL OPTIONS",
+        long,
+        global = true,
+        value_name = "path",
+        help = "Append a record of each decryption, without the plaintext, to an audit file"
+    )]
+    audit_log: Option<String>,
+
+    #[clap(
+        help_heading = "GLOBAL OPTIONS",
+        long,
+        global = true,
+        value_name = "dir",
+        help = "Cache decrypted matched data in a directory, encrypted with a key derived from the private key, so decrypting it again is a lookup"
+    )]
+    cache_dir: Option<String>,
+
+    #[clap(
+        help_heading = "GLOBAL OPTIONS",
+        arg_enum,
+        long,
+        global = true,
+        default_value = "human",
+        help = "Format of errors printed on stderr"
+    )]
+    error_format: ErrorFormat,
+
+    #[clap(
+        help_heading = "GLOBAL OPTIONS",
+        arg_enum,
+        long,
+        global = true,
+        value_name = "version",
+        min_values = 0,
+        multiple_values = false,
+        require_equals = true,
+        default_missing_value = "v1",
+        help = "Print the output of generate-key-pair and decrypt as JSON lines in a stable format, documented in the README, and errors as JSON"
+    )]
+    porcelain: Option<Porcelain>,
+
+    #[clap(
+        help_heading = "GLOBAL OPTIONS",
+        arg_enum,
+        long,
+        global = true,
+        default_value = "info",
+        help = "Least severe level of the events logged on stderr by long-running commands"
+    )]
+    log_level: logging::LogLevel,
+
+    #[clap(
+        help_heading = "GLOBAL OPTIONS",
+        arg_enum,
+        long,
+        global = true,
+        default_value = "text",
+        help = "Format of the events logged on stderr, json for one object per line"
+    )]
+    log_format: logging::LogFormat,
+
+    #[clap(
+        help_heading = "GLOBAL OPTIONS",
+        short,
+        long,
+        global = true,
+        help = "Don't report the progress of batch, logs and csv jobs on stderr"
+    )]
+    quiet: bool,
+
+    #[clap(
+        help_heading = "GLOBAL OPTIONS",
+        long,
+        global = true,
+        help = "Only accept padded standard base64 on a single line for keys and matched data"
+    )]
+    strict: bool,
+
+    #[clap(
+        help_heading = "GLOBAL OPTIONS",
+        long,
+        global = true,
+        help = "Don't lock private keys in memory, where the locked memory limit is too low, still disabling core dumps"
+    )]
+    no_mlock: bool,
+
+    #[clap(
+        help_heading = "GLOBAL OPTIONS",
+        long,
+        global = true,
+        help = "Fail rather than warn when a private key has passed the expires_at date of its JSON key pair"
+    )]
+    enforce_expiry: bool,
+
+    #[clap(
+        long,
+        help = "Print the encryption format versions that can be decrypted and exit, like the versions subcommand"
+    )]
+    supported_versions: bool,
+
     #[clap(subcommand)]
-    command: Command,
+    command: Option<Command>,
+}
+
+#[derive(ArgEnum, Clone)]
+enum TextJsonFormat {
+    Text,
+    Json,
+}
+
+#[derive(ArgEnum, Clone)]
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
+// Versions of the --porcelain output, whose fields are never renamed nor removed within a version
+#[derive(ArgEnum, Clone, Copy)]
+enum Porcelain {
+    V1,
+}
+
+impl Porcelain {
+    fn name(self) -> &'static str {
+        match self {
+            Porcelain::V1 => "v1",
+        }
+    }
 }
 
 #[derive(ArgEnum, Clone)]
 enum KeyPairOutputFormat {
     Json,
+    Mnemonic,
+    Env,
+    Dotenv,
+    Pem,
+    Qr,
 }
 
 #[derive(Parser)]
@@ -32,256 +238,6854 @@ struct GenerateKeyPairOptions {
         default_value = "json"
     )]
     output_format: KeyPairOutputFormat,
+
+    #[clap(
+        long,
+        value_name = "path",
+        requires = "public-key-file",
+        conflicts_with = "output-format",
+        help = "File to write the base64 encoded private key to, readable only by the current user on Unix, instead of stdout"
+    )]
+    private_key_file: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "path",
+        requires = "private-key-file",
+        help = "File to write the base64 encoded public key to"
+    )]
+    public_key_file: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "path",
+        help = "With -o qr, PNG image to write the QR code to, readable only by the current user on Unix, instead of the terminal"
+    )]
+    qr_file: Option<String>,
+
+    #[clap(
+        long,
+        help = "Also place the base64 encoded public key on the system clipboard, to paste in the dashboard"
+    )]
+    copy: bool,
+
+    #[clap(
+        long,
+        value_name = "label",
+        help = "Label stored in the JSON key pair with the key ID, such as the zone and year the key is for"
+    )]
+    label: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "duration",
+        parse(try_from_str = humantime::parse_duration),
+        help = "How long the key is valid for, such as 365days, stored as the expires_at date of the JSON key pair"
+    )]
+    expires_in: Option<Duration>,
 }
 
 #[derive(ArgEnum, Clone)]
-enum DecryptOutputFormat {
+enum DecryptInputFormat {
+    Auto,
+    Base64,
+    Hex,
+    BytesJson,
+    Armor,
     Raw,
-    Utf8Lossy,
 }
 
 #[derive(Parser)]
 struct DecryptOptions {
-    #[clap(help = "File containing the base64 encoded encrypted matched data")]
-    matched_data_filename: String,
+    #[clap(
+        help = "File containing the base64 encoded encrypted matched data, several files with -o ndjson or --concat",
+        required_unless_present_any = &["data", "clipboard"]
+    )]
+    matched_data_filenames: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "base64",
+        conflicts_with = "matched-data-filenames",
+        help = "Base64 encoded encrypted matched data, instead of a file"
+    )]
+    data: Option<String>,
+
+    #[clap(
+        long,
+        conflicts_with_all = &["matched-data-filenames", "data"],
+        help = "Read the encrypted matched data from the system clipboard, as copied from the dashboard, instead of a file"
+    )]
+    clipboard: bool,
 
     #[clap(
+        help_heading = "KEY SOURCE OPTIONS",
         short = 'k',
         long,
         help = "File containing the base64 encoded private key"
     )]
-    private_key_filename: String,
+    private_key_filename: Option<String>,
 
     #[clap(
-        arg_enum,
-        short,
+        help_heading = "KEY SOURCE OPTIONS",
         long,
-        value_name = "format",
-        help = "Output format of matched data",
-        default_value = "utf8-lossy"
+        value_name = "path",
+        conflicts_with = "private-key-filename",
+        help = "File containing the private key as a BIP39 mnemonic, instead of base64"
     )]
-    output_format: DecryptOutputFormat,
-}
+    key_mnemonic: Option<String>,
 
-#[derive(Parser)]
-enum Command {
-    /// Generates a public-private key pair
-    GenerateKeyPair(GenerateKeyPairOptions),
+    #[clap(
+        help_heading = "KEY SOURCE OPTIONS",
+        long,
+        conflicts_with_all = &["private-key-filename", "key-mnemonic"],
+        help = "Prompt for private key shares created by split-key until enough are entered"
+    )]
+    key_shares: bool,
 
-    /// Decrypts data
-    Decrypt(DecryptOptions),
-}
+    #[clap(
+        help_heading = "KEY SOURCE OPTIONS",
+        long,
+        value_name = "id",
+        conflicts_with_all = &["key-mnemonic", "key-shares"],
+        help = "AWS KMS key that wrapped the private key file, unwrapped with ambient AWS credentials"
+    )]
+    kms_key_id: Option<String>,
 
-#[derive(Serialize, Deserialize)]
-struct KeyPair {
-    private_key: String,
-    public_key: String,
-}
+    #[clap(
+        help_heading = "KEY SOURCE OPTIONS",
+        long,
+        value_name = "key",
+        conflicts_with_all = &["key-mnemonic", "key-shares", "kms-key-id"],
+        help = "Google Cloud KMS key that wrapped the private key file, as projects/<project>/locations/<location>/keyRings/<key ring>/cryptoKeys/<key>, unwrapped with the gcloud CLI credentials"
+    )]
+    gcp_kms_key: Option<String>,
 
-const TRUNCATED: &str = "truncated";
+    #[clap(
+        help_heading = "KEY SOURCE OPTIONS",
+        long,
+        value_name = "label",
+        conflicts_with_all = &["private-key-filename", "key-mnemonic", "key-shares", "kms-key-id", "gcp-kms-key", "yubikey-slot"],
+        help = "Label of a private key saved in the OS keychain by key store, instead of a private key file"
+    )]
+    key_id: Option<String>,
 
-fn run(options: Options) -> Result<(), String> {
-    match options.command {
-        Command::GenerateKeyPair(command) => {
-            // Generate key pair
-            let (private_key, public_key) = generate_key_pair();
+    #[cfg(feature = "api")]
+    #[clap(
+        help_heading = "KEY SOURCE OPTIONS",
+        long,
+        value_name = "path",
+        conflicts_with_all = &["private-key-filename", "key-mnemonic", "key-shares", "kms-key-id", "gcp-kms-key", "key-id", "yubikey-slot"],
+        help = "Path of a Vault KV secret holding the base64 encoded private key, such as secret/waf/matched-data, read from VAULT_ADDR with VAULT_TOKEN instead of a private key file"
+    )]
+    vault_path: Option<String>,
 
-            let key_pair = KeyPair {
-                private_key: radix64::STD.encode(&private_key.to_bytes()),
-                public_key: radix64::STD.encode(&public_key.to_bytes()),
-            };
+    #[cfg(feature = "api")]
+    #[clap(
+        help_heading = "KEY SOURCE OPTIONS",
+        long,
+        value_name = "field",
+        requires = "vault-path",
+        help = "Field of the Vault secret holding the private key",
+        default_value = "private_key"
+    )]
+    vault_field: String,
 
-            match command.output_format {
-                KeyPairOutputFormat::Json => {
-                    println!(
-                        "{}",
-                        serde_json::to_string_pretty(&key_pair).expect("Failed to output key pair")
-                    );
-                }
-            }
-        }
-        Command::Decrypt(command) => {
-            // Validate and construct private key from input
-            let private_key_base64 = fs::read_to_string(command.private_key_filename)
-                .map_err(|_| "Failed to read private key from file")?;
-
-            let private_key_bytes = radix64::STD
-                .decode(&private_key_base64.trim_end())
-                .map_err(|_| "Provided private key is not base64 encoded")?;
-
-            // Validate and construct matched data from input
-            let matched_data_base64 = if command.matched_data_filename == "-" {
-                let mut buffer = String::new();
-                stdin()
-                    .read_line(&mut buffer)
-                    .map_err(|_| "Failed to read matched data from stdin")?;
-                buffer
-            } else {
-                fs::read_to_string(command.matched_data_filename)
-                    .map_err(|_| "Failed to read matched data from file")?
-            };
+    #[cfg(feature = "api")]
+    #[clap(
+        help_heading = "KEY SOURCE OPTIONS",
+        long,
+        value_name = "vault/secret",
+        conflicts_with_all = &["private-key-filename", "key-mnemonic", "key-shares", "kms-key-id", "gcp-kms-key", "key-id", "vault-path", "yubikey-slot"],
+        help = "Azure Key Vault secret holding the base64 encoded private key, as <vault>/<secret>, read with a managed identity or the az CLI credentials instead of a private key file"
+    )]
+    azure_key_vault: Option<String>,
 
-            if matched_data_base64 == TRUNCATED {
-                return Err(
-                    "The payload match for this event is unavailable because it was too large."
-                        .to_string(),
-                );
-            };
+    #[clap(
+        help_heading = "KEY SOURCE OPTIONS",
+        long,
+        value_name = "slot",
+        requires = "yubikey-public-key",
+        conflicts_with_all = &["private-key-filename", "key-mnemonic", "key-shares", "kms-key-id", "gcp-kms-key", "key-id", "vault-path", "azure-key-vault"],
+        help = "YubiKey PIV slot holding the private key, created by generate-yubikey-key, instead of a private key file"
+    )]
+    yubikey_slot: Option<String>,
 
-            let encrypted_matched_data_bytes = radix64::STD
-                .decode(&matched_data_base64.trim_end())
-                .map_err(|_| "Provided matched data is not base64 encoded")?;
+    #[clap(
+        help_heading = "KEY SOURCE OPTIONS",
+        long,
+        value_name = "path",
+        requires = "yubikey-slot",
+        help = "File containing the base64 encoded public key of the YubiKey slot"
+    )]
+    yubikey_public_key: Option<String>,
 
-            macro_rules! decrypt {
-                ($modname:ident) => {{
-                    use $modname::{
-                        decrypt_data, deserialize_encrypted_data, get_private_key_from_bytes,
-                    };
+    #[clap(
+        help_heading = "HPKE MODE OPTIONS",
+        long,
+        value_name = "path",
+        conflicts_with = "yubikey-slot",
+        help = "File containing the base64 encoded public key of the sender, to decrypt matched data encrypted in HPKE auth mode and verify it was encrypted by the sender"
+    )]
+    sender_public_key: Option<String>,
 
-                    let private_key = get_private_key_from_bytes(&private_key_bytes)
-                        .map_err(|_| "Provided private key is invalid")?;
+    #[clap(
+        help_heading = "HPKE MODE OPTIONS",
+        long,
+        value_name = "path",
+        requires = "psk-id",
+        conflicts_with = "yubikey-slot",
+        help = "File containing a base64 encoded pre-shared key of at least 32 bytes, for HPKE PSK mode, or auth PSK mode with a sender key"
+    )]
+    psk_file: Option<String>,
 
-                    let encrypted_matched_data =
-                        deserialize_encrypted_data(&encrypted_matched_data_bytes)
-                            .map_err(|_| "Provided matched data is invalid")?;
+    #[clap(
+        help_heading = "HPKE MODE OPTIONS",
+        long,
+        value_name = "id",
+        requires = "psk-file",
+        help = "Identifier of the pre-shared key"
+    )]
+    psk_id: Option<String>,
 
-                    // Decrypt matched data
-                    decrypt_data(&encrypted_matched_data, &private_key)
-                        .map_err(|_| "Failed to decrypt matched data")?
-                }};
-            }
+    #[clap(
+        help_heading = "HPKE MODE OPTIONS",
+        long,
+        value_name = "text",
+        conflicts_with = "aad-file",
+        help = "Associated data the matched data was encrypted with, such as event metadata it is bound to"
+    )]
+    aad: Option<String>,
 
-            // Get encryption version
-            let encryption_format_version = encrypted_matched_data_bytes[0];
-            let matched_data = match encryption_format_version {
-                3 => decrypt!(matched_data),
-                _ => {
-                    let available_versions = "'3'";
+    #[clap(
+        help_heading = "HPKE MODE OPTIONS",
+        long,
+        value_name = "path",
+        help = "File containing the associated data the matched data was encrypted with, read as is"
+    )]
+    aad_file: Option<String>,
 
-                    return Err(format!(
-                        "Encryption format not supported, expected {}, got '{}'",
-                        available_versions, encryption_format_version
-                    ));
-                }
-            };
+    #[clap(
+        arg_enum,
+        long,
+        alias = "input-encoding",
+        value_name = "format",
+        help = "Input format of matched data, auto detects base64, a JSON array of bytes as shown in the dashboard or the armor of encrypt --armor, raw reads serialized bytes as stored by some pipelines, a whole file per input with -o ndjson",
+        default_value = "auto"
+    )]
+    input_format: DecryptInputFormat,
 
-            match command.output_format {
-                DecryptOutputFormat::Raw => {
-                    let mut out = stdout();
-                    out.write_all(&matched_data)
-                        .map_err(|_| "Failed to output matched data")?;
-                    out.flush().expect("Failed to flush stdout");
-                }
-                DecryptOutputFormat::Utf8Lossy => {
-                    println!("{}", String::from_utf8_lossy(&matched_data));
-                }
-            }
-        }
-    }
+    #[clap(
+        long,
+        value_name = "bytes",
+        default_value = "16777216",
+        help = "Largest matched data input read, each line or raw file with -o ndjson and each segment with --concat, so hostile input can't exhaust memory"
+    )]
+    max_input_size: u64,
 
-    Ok(())
-}
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        short,
+        long,
+        value_name = "format",
+        possible_values = output::possible_values(true),
+        help = "Output format of matched data, json-pretty and http fall back to utf8-lossy if it isn't JSON or an HTTP request [default: utf8-lossy]"
+    )]
+    output_format: Option<String>,
 
-fn main() -> Result<(), String> {
-    run(Options::parse())?;
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        long,
+        value_name = "path",
+        conflicts_with = "output-format",
+        help = "File to write the raw decrypted matched data bytes to, instead of stdout"
+    )]
+    output_file: Option<String>,
 
-    Ok(())
-}
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        long,
+        value_name = "path",
+        conflicts_with_all = &["output-file", "select"],
+        help = "Directory to write binary matched data such as images to, named after its detected type, printing a summary instead"
+    )]
+    output_dir: Option<String>,
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use assert_cmd::Command;
-    use assert_fs::prelude::*;
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        long,
+        help = "Overwrite output files if they exist, or write binary matched data to a terminal"
+    )]
+    force: bool,
 
-    #[test]
-    fn test_generate_key_pair() {
-        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
-        let out = cmd.args(&["generate-key-pair"]).output().unwrap();
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        long,
+        value_name = "jsonpath",
+        multiple_occurrences = true,
+        conflicts_with = "output-file",
+        help = "Only print the fields of JSON matched data selected by a JSONPath, such as $['http.request.body.raw'], repeat to select several"
+    )]
+    select: Vec<String>,
 
-        let key_pair: KeyPair =
-            serde_json::from_str(std::str::from_utf8(&out.stdout).unwrap()).unwrap();
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        long,
+        value_name = "regex",
+        multiple_occurrences = true,
+        help = "Mask matches of a regular expression in the matched data before output, repeat to mask several"
+    )]
+    redact: Vec<String>,
 
-        radix64::STD.decode(&key_pair.private_key).unwrap();
-        radix64::STD.decode(&key_pair.public_key).unwrap();
-    }
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        long,
+        help = "Mask credit card numbers and email addresses before output"
+    )]
+    redact_pii: bool,
 
-    #[test]
-    fn test_decrypt() {
-        let matched_data = "test matched data";
-        // Encrypted with public key:
-        // Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=
-        let encrypted_matched_data = "AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=";
-        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+    #[clap(
+        long,
+        help = "With -o ndjson, keep decrypting after an input fails and exit with an error summarizing the failures"
+    )]
+    continue_on_error: bool,
+
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        long,
+        value_name = "dir",
+        conflicts_with_all = &["redact", "redact-pii"],
+        help = "With -o ndjson, also write each plaintext bit-exact to <sha256>.<detected extension> in a directory, with an index.ndjson mapping each input to its file"
+    )]
+    extract_dir: Option<String>,
+
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        long,
+        conflicts_with_all = &["output-file", "output-dir", "select", "redact", "redact-pii", "extract-dir"],
+        help = "Only report whether the matched data decrypts and the plaintext length, never printing the plaintext"
+    )]
+    verify_only: bool,
+
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        long,
+        conflicts_with_all = &["data", "output-format", "output-dir", "select", "redact", "redact-pii", "extract-dir", "verify-only"],
+        help = "Decrypt each file as a segment of one payload split across several matched data, writing the plaintexts as is one after another in order"
+    )]
+    concat: bool,
+
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        short = '0',
+        long,
+        conflicts_with = "output-file",
+        help = "End each plaintext, selected field or -o ndjson record with a NUL byte instead of a line break, for xargs -0"
+    )]
+    null: bool,
+
+    #[clap(
+        help_heading = "OUTPUT OPTIONS",
+        long,
+        conflicts_with_all = &["output-format", "output-file", "output-dir", "select", "verify-only", "concat", "null"],
+        help = "Place the decrypted matched data on the system clipboard instead of printing it"
+    )]
+    copy: bool,
+
+    #[clap(
+        short,
+        long,
+        help = "Report the stage at which decryption failed, with component lengths"
+    )]
+    verbose: bool,
+}
+
+#[derive(ArgEnum, Clone)]
+enum ImportKeyFormat {
+    Auto,
+    Age,
+    Ssh,
+    Qr,
+}
+
+#[derive(Parser)]
+struct GenerateYubiKeyKeyOptions {
+    #[clap(
+        long,
+        value_name = "slot",
+        help = "PIV slot to generate the key in, overwriting any key it holds",
+        default_value = "9d"
+    )]
+    slot: String,
+}
+
+#[derive(Parser)]
+struct ReencryptOptions {
+    #[clap(help = "File containing the base64 encoded matched data to re-encrypt, or - for stdin")]
+    matched_data_filename: String,
+
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key the matched data is encrypted to"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        short = 'p',
+        long = "public-key",
+        value_name = "public-key-filename",
+        required = true,
+        multiple_occurrences = true,
+        help = "File containing a base64 encoded public key to re-encrypt to, repeat for several recipients"
+    )]
+    public_key_filenames: Vec<String>,
+}
+
+#[derive(Parser)]
+struct RewrapOptions {
+    #[clap(help = "NDJSON archive, gzipped or not, or directory of archives to rewrap")]
+    input: String,
+
+    #[clap(
+        short = 'o',
+        long,
+        help = "File or directory to write the rewrapped archives to, replaced only once complete"
+    )]
+    output: String,
+
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key the matched data is encrypted to"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        short = 'p',
+        long = "public-key",
+        value_name = "public-key-filename",
+        help = "File containing the base64 encoded public key to rewrap the matched data to"
+    )]
+    public_key_filename: String,
+}
+
+#[derive(Parser)]
+struct ImportKeyOptions {
+    #[clap(
+        help = "File containing an age identity, an unencrypted OpenSSH ed25519 private key, or the text of a QR code from generate-key-pair -o qr"
+    )]
+    key_filename: String,
+
+    #[clap(
+        arg_enum,
+        long,
+        value_name = "format",
+        help = "Format of the key file",
+        default_value = "auto"
+    )]
+    from: ImportKeyFormat,
+}
+
+#[derive(Parser)]
+struct ExportKeyOptions {
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+}
+
+#[derive(Parser)]
+struct SplitKeyOptions {
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        short,
+        long,
+        help = "Number of shares needed to recover the private key"
+    )]
+    threshold: u8,
+
+    #[clap(short, long, help = "Number of shares to create")]
+    shares: u8,
+}
+
+#[derive(Parser)]
+struct CombineKeyOptions {
+    #[clap(
+        required = true,
+        help = "Files each containing a private key share created by split-key"
+    )]
+    share_filenames: Vec<String>,
+}
+
+#[derive(Parser)]
+struct EncryptOptions {
+    #[clap(help = "File containing the matched data to encrypt, or - for stdin")]
+    matched_data_filename: String,
+
+    #[clap(
+        short = 'p',
+        long = "public-key",
+        value_name = "public-key-filename",
+        required = true,
+        multiple_occurrences = true,
+        help = "File containing a base64 encoded public key, repeat to encrypt to several recipients"
+    )]
+    public_key_filenames: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "path",
+        help = "File containing the base64 encoded private key of the sender, to encrypt in HPKE auth mode"
+    )]
+    sender_key: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "path",
+        requires = "psk-id",
+        help = "File containing a base64 encoded pre-shared key of at least 32 bytes, for HPKE PSK mode, or auth PSK mode with a sender key"
+    )]
+    psk_file: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "id",
+        requires = "psk-file",
+        help = "Identifier of the pre-shared key"
+    )]
+    psk_id: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "text",
+        conflicts_with = "aad-file",
+        help = "Associated data to bind the matched data to, which has to be passed to decrypt it too"
+    )]
+    aad: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "path",
+        help = "File containing associated data to bind the matched data to, read as is"
+    )]
+    aad_file: Option<String>,
+
+    #[clap(
+        short = 'a',
+        long,
+        help = "Print each blob in an ASCII armor with a checksum, to paste in emails, chats and tickets"
+    )]
+    armor: bool,
+}
+
+#[derive(Parser)]
+struct FingerprintOptions {
+    #[clap(
+        short = 'k',
+        long,
+        conflicts_with = "public-key-filename",
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        short = 'p',
+        long = "public-key",
+        value_name = "public-key-filename",
+        help = "File containing a base64 encoded public key"
+    )]
+    public_key_filename: Option<String>,
+}
+
+#[derive(Parser)]
+struct KeyOptions {
+    #[clap(subcommand)]
+    command: KeyCommand,
+}
+
+#[derive(Parser)]
+struct CacheOptions {
+    #[clap(subcommand)]
+    command: CacheCommand,
+}
+
+#[derive(Parser)]
+enum CacheCommand {
+    /// Deletes every entry of the cache directory passed with --cache-dir or set in the configuration file
+    Purge,
+}
+
+#[derive(Parser)]
+enum KeyCommand {
+    /// Saves a private key in the OS keychain under a label, for decrypt --key-id
+    Store(KeyStoreOptions),
+
+    /// Prints the base64 encoded private key saved in the OS keychain under a label
+    Load(KeyLoadOptions),
+
+    /// Writes key pairs to an archive encrypted with a passphrase, with their labels and creation dates
+    Export(KeyExportOptions),
+
+    /// Restores the key pairs of an archive written by key export
+    Import(KeyImportOptions),
+
+    /// Lists key files with their key IDs, labels and the days left before they expire
+    List(KeyListOptions),
+}
+
+#[derive(Parser)]
+struct KeyStoreOptions {
+    #[clap(help = "Label of the private key, letters, digits, -, _ and .")]
+    label: String,
+
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+}
+
+#[derive(Parser)]
+struct KeyLoadOptions {
+    #[clap(help = "Label of the private key")]
+    label: String,
+}
+
+#[derive(Parser)]
+struct KeyExportOptions {
+    #[clap(
+        long,
+        value_name = "path",
+        help = "File to write the archive to, readable only by the current user on Unix"
+    )]
+    out: String,
+
+    #[clap(
+        short = 'k',
+        long = "private-key-filename",
+        value_name = "path",
+        multiple_occurrences = true,
+        help = "File containing a base64 encoded private key to export, labeled with the file name without its extension"
+    )]
+    private_key_filenames: Vec<String>,
+
+    #[clap(
+        long = "key-id",
+        value_name = "label",
+        multiple_occurrences = true,
+        help = "Label of a private key saved in the OS keychain to export"
+    )]
+    key_ids: Vec<String>,
+}
+
+#[derive(Parser)]
+struct KeyListOptions {
+    #[clap(
+        required = true,
+        help = "Files containing a JSON key pair or a base64 encoded private key"
+    )]
+    key_filenames: Vec<String>,
+
+    #[clap(
+        arg_enum,
+        short,
+        long,
+        value_name = "format",
+        default_value = "text",
+        help = "Output format of the keys"
+    )]
+    output_format: TextJsonFormat,
+}
+
+// Key listed by key list
+#[derive(Serialize)]
+struct ListedKey {
+    file: String,
+    key_id: String,
+    label: Option<String>,
+    expires_at: Option<String>,
+    // Negative once the key has expired
+    days_remaining: Option<i64>,
+}
+
+#[derive(Parser)]
+struct KeyImportOptions {
+    #[clap(help = "Key archive written by key export")]
+    archive_filename: String,
+
+    #[clap(
+        long,
+        help = "Save each private key in the OS keychain under its label rather than printing the key pairs"
+    )]
+    keychain: bool,
+}
+
+#[cfg(feature = "api")]
+#[derive(Parser)]
+struct VerifyRemoteKeyOptions {
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "id",
+        help = "Zone whose payload logging key to check"
+    )]
+    zone_id: Option<String>,
+}
+
+#[derive(Parser)]
+struct BenchOptions {
+    #[clap(
+        short = 'n',
+        long,
+        value_name = "N",
+        default_value = "10000",
+        help = "Number of synthetic matched data to decrypt"
+    )]
+    count: usize,
+
+    #[clap(
+        short,
+        long,
+        value_name = "bytes",
+        default_value = "1024",
+        help = "Size of the plaintext of each matched data"
+    )]
+    size: usize,
+
+    #[clap(
+        short,
+        long,
+        value_name = "N",
+        help = "Number of threads of the multi-thread run, at least 1 [default: the number of CPUs]"
+    )]
+    jobs: Option<usize>,
+
+    #[clap(short, long, arg_enum, default_value = "text", help = "Output format")]
+    output_format: TextJsonFormat,
+}
+
+#[derive(Parser)]
+struct VersionsOptions {
+    #[clap(
+        short,
+        long,
+        arg_enum,
+        default_value = "text",
+        help = "Output format, json lists the RFC 9180 identifiers of each cipher suite"
+    )]
+    output_format: TextJsonFormat,
+}
+
+#[cfg(feature = "self-update")]
+#[derive(Parser)]
+struct SelfUpdateOptions {
+    #[clap(
+        long,
+        help = "Only report whether a newer release is available, without installing it"
+    )]
+    check: bool,
+
+    #[clap(
+        long,
+        value_name = "tag",
+        help = "Install this release, such as v0.6.0, even if it is older than the running one"
+    )]
+    tag: Option<String>,
+}
+
+#[derive(Parser)]
+struct DoctorOptions {
+    #[clap(help = "File containing the base64 encoded encrypted matched data")]
+    matched_data_filename: String,
+
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+}
+
+#[derive(Parser)]
+struct DiffOptions {
+    #[clap(help = "File containing the encrypted matched data, or - for stdin")]
+    matched_data_filename: String,
+
+    #[clap(help = "File containing the plaintext the matched data is expected to decrypt to")]
+    expected_filename: String,
+
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        long,
+        help = "Compare hexdump rows even if both payloads are text, to see line break and whitespace differences"
+    )]
+    bytes: bool,
+}
+
+#[derive(Parser)]
+struct ValidatePublicKeyOptions {
+    #[clap(help = "File containing the base64 encoded public key, as pasted into the dashboard")]
+    public_key_filename: String,
+}
+
+#[derive(Parser)]
+struct LogsOptions {
+    #[clap(
+        help = "Logpush NDJSON file, .zip or .tar.gz archive of them, or r2:// or s3:// object, containing events with encrypted matched data"
+    )]
+    log_filename: String,
+
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        short,
+        long,
+        help = "Keep waiting for new events appended to the log file"
+    )]
+    follow: bool,
+
+    #[clap(
+        short,
+        long,
+        value_name = "N",
+        help = "Number of threads decrypting events in parallel, at least 1",
+        default_value = "1"
+    )]
+    jobs: usize,
+
+    #[clap(
+        long,
+        help = "Keep going after an event fails, adding a decryption_error field, and exit with an error summarizing the failures"
+    )]
+    continue_on_error: bool,
+
+    #[clap(
+        long,
+        value_name = "dir",
+        help = "Also write each decrypted payload bit-exact to <sha256>.<detected extension> in a directory, with an index.ndjson mapping each event's Ray ID to its file"
+    )]
+    extract_dir: Option<String>,
+
+    #[cfg(feature = "api")]
+    #[clap(flatten)]
+    sink: SinkOptions,
+}
+
+#[cfg(feature = "api")]
+#[derive(ArgEnum, Clone)]
+enum SinkKind {
+    SplunkHec,
+    Elasticsearch,
+}
+
+#[cfg(feature = "api")]
+#[derive(Parser)]
+struct SinkOptions {
+    #[clap(
+        help_heading = "SINK OPTIONS",
+        arg_enum,
+        long,
+        value_name = "sink",
+        help = "SIEM to post enriched events to instead of printing them"
+    )]
+    sink: Option<SinkKind>,
+
+    #[clap(
+        help_heading = "SINK OPTIONS",
+        long,
+        value_name = "url",
+        requires = "sink",
+        help = "URL of the Splunk HTTP Event Collector, such as https://splunk.example.com:8088"
+    )]
+    hec_url: Option<String>,
+
+    #[clap(
+        help_heading = "SINK OPTIONS",
+        long,
+        value_name = "token",
+        requires = "sink",
+        help = "Splunk HEC token, read from SPLUNK_HEC_TOKEN if not passed"
+    )]
+    hec_token: Option<String>,
+
+    #[clap(
+        help_heading = "SINK OPTIONS",
+        long,
+        value_name = "url",
+        requires = "sink",
+        help = "URL of the Elasticsearch or OpenSearch cluster, such as https://elasticsearch.example.com:9200"
+    )]
+    es_url: Option<String>,
+
+    #[clap(
+        help_heading = "SINK OPTIONS",
+        long,
+        value_name = "template",
+        requires = "sink",
+        help = "Index to write events to, with %Y, %m and %d replaced by the date of the event",
+        default_value = "cloudflare-firewall-events-%Y.%m.%d"
+    )]
+    es_index: String,
+
+    #[clap(
+        help_heading = "SINK OPTIONS",
+        long,
+        value_name = "key",
+        requires = "sink",
+        conflicts_with = "es-username",
+        help = "Elasticsearch API key, read from ES_API_KEY if not passed"
+    )]
+    es_api_key: Option<String>,
+
+    #[clap(
+        help_heading = "SINK OPTIONS",
+        long,
+        value_name = "username",
+        requires = "sink",
+        help = "User to authenticate as with basic authentication, with the password in ES_PASSWORD"
+    )]
+    es_username: Option<String>,
+}
+
+#[derive(Parser)]
+struct PipeOptions {
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        long,
+        help = "Keep going after an event fails, adding a decryption_error field, and exit with an error summarizing the failures"
+    )]
+    continue_on_error: bool,
+
+    #[cfg(feature = "api")]
+    #[clap(flatten)]
+    sink: SinkOptions,
+}
+
+#[derive(Parser)]
+struct CsvOptions {
+    #[clap(
+        help = "CSV export of events, such as from the dashboard, or - for stdin, with a header row"
+    )]
+    csv_filename: String,
+
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "name|index",
+        help = "Column holding the encrypted matched data, by name or 1-based index [default: the first column named like matched data]"
+    )]
+    column: Option<String>,
+
+    #[clap(
+        long,
+        help = "Keep going after a row fails, adding a decryption_error column, and exit with an error summarizing the failures"
+    )]
+    continue_on_error: bool,
+}
+
+#[derive(Parser)]
+struct StatsOptions {
+    #[clap(
+        required = true,
+        help = "Logpush NDJSON files, .zip or .tar.gz archives of them, or r2:// or s3:// objects, or - for stdin"
+    )]
+    log_filenames: Vec<String>,
+
+    #[clap(
+        short = 'k',
+        long = "private-key-filename",
+        value_name = "private-key-filename",
+        multiple_occurrences = true,
+        help = "File containing a base64 encoded private key to count the matched data it decrypts, repeat for several keys [default: the private key of the configuration file, if any]"
+    )]
+    private_key_filenames: Vec<String>,
+
+    #[clap(short, long, arg_enum, default_value = "text", help = "Output format")]
+    output_format: TextJsonFormat,
+}
+
+#[cfg(feature = "browse")]
+#[derive(Parser)]
+struct BrowseOptions {
+    #[clap(help = "Logpush NDJSON file, JSON array of events or GraphQL API response")]
+    events_filename: String,
+
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+}
+
+#[cfg(feature = "serve")]
+#[derive(Parser)]
+struct ServeOptions {
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        short,
+        long,
+        value_name = "address",
+        help = "Address to listen on for POST /decrypt requests with base64 encoded matched data",
+        default_value = "127.0.0.1:8080"
+    )]
+    listen: String,
+
+    #[clap(
+        help_heading = "ACCESS OPTIONS",
+        long,
+        help = "Allow listening on an address reachable from other hosts without TLS and an auth token"
+    )]
+    allow_remote: bool,
+
+    #[clap(
+        help_heading = "ACCESS OPTIONS",
+        long,
+        value_name = "file",
+        requires = "tls-key",
+        help = "PEM file containing the certificate chain to serve HTTPS with"
+    )]
+    tls_cert: Option<String>,
+
+    #[clap(
+        help_heading = "ACCESS OPTIONS",
+        long,
+        value_name = "file",
+        requires = "tls-cert",
+        help = "PEM file containing the PKCS #8 or RSA private key of the TLS certificate"
+    )]
+    tls_key: Option<String>,
+
+    #[clap(
+        help_heading = "ACCESS OPTIONS",
+        long,
+        value_name = "file",
+        help = "File containing a token clients must send in an Authorization: Bearer header"
+    )]
+    auth_token_file: Option<String>,
+
+    #[clap(
+        help_heading = "LIMIT OPTIONS",
+        long,
+        value_name = "N",
+        help = "Number of requests handled at the same time, at least 1",
+        default_value = "4"
+    )]
+    max_in_flight: usize,
+
+    #[clap(
+        help_heading = "LIMIT OPTIONS",
+        long,
+        value_name = "N",
+        help = "Requests per second allowed from each client address, answering others with 429"
+    )]
+    rate_limit: Option<u32>,
+
+    #[clap(
+        help_heading = "LIMIT OPTIONS",
+        long,
+        value_name = "bytes",
+        help = "Largest request body accepted, answering larger ones with 413",
+        default_value = "1048576"
+    )]
+    max_body_size: u64,
+
+    #[clap(
+        short,
+        long,
+        value_name = "format",
+        possible_values = output::possible_values(false),
+        help = "Output format of the matched data in responses, as with decrypt",
+        default_value = "raw"
+    )]
+    output_format: String,
+
+    #[cfg(unix)]
+    #[clap(
+        long,
+        value_name = "path",
+        conflicts_with_all = &["tls-cert", "auth-token-file", "rate-limit"],
+        help = "Unix socket to listen on instead, answering JSON lines such as {\"data\": \"<base64>\"}"
+    )]
+    unix_socket: Option<String>,
+}
+
+#[cfg(feature = "serve")]
+#[derive(Parser)]
+struct WatchOptions {
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "url",
+        required_unless_present = "webhook",
+        conflicts_with = "webhook",
+        help = "SQS queue receiving the S3 or R2 event notifications of new Logpush objects, with the aws CLI"
+    )]
+    sqs_queue_url: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "address",
+        help = "Address to listen on for event notifications POSTed by SNS or a Worker instead"
+    )]
+    webhook: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "bucket",
+        multiple_occurrences = true,
+        help = "Only process objects of this bucket, repeat for several buckets"
+    )]
+    bucket: Vec<String>,
+
+    #[clap(
+        long,
+        requires = "sqs-queue-url",
+        help = "Exit once the queue is empty rather than waiting for new notifications"
+    )]
+    until_empty: bool,
+
+    #[clap(
+        short,
+        long,
+        value_name = "N",
+        help = "Number of threads decrypting the events of an object in parallel, at least 1",
+        default_value = "1"
+    )]
+    jobs: usize,
+
+    #[clap(
+        long,
+        help = "Keep going after an event fails, adding a decryption_error field, rather than leaving its notification to be retried"
+    )]
+    continue_on_error: bool,
+
+    #[clap(
+        help_heading = "ACCESS OPTIONS",
+        long,
+        requires = "webhook",
+        help = "Allow the webhook to listen on an address reachable from other hosts without an auth token"
+    )]
+    allow_remote: bool,
+
+    #[clap(
+        help_heading = "ACCESS OPTIONS",
+        long,
+        value_name = "file",
+        requires = "webhook",
+        help = "File containing a token webhook requests must send in an Authorization: Bearer header"
+    )]
+    auth_token_file: Option<String>,
+
+    #[cfg(feature = "api")]
+    #[clap(flatten)]
+    sink: SinkOptions,
+}
+
+#[derive(Parser)]
+struct ServeUiOptions {
+    #[clap(
+        short,
+        long,
+        value_name = "address",
+        help = "Loopback address to serve the page on",
+        default_value = "127.0.0.1:8081"
+    )]
+    listen: String,
+
+    #[clap(
+        long,
+        value_name = "dir",
+        help = "Directory of the WebAssembly module built by wasm-pack for the web",
+        default_value = "matched-data-wasm/pkg"
+    )]
+    wasm_dir: String,
+}
+
+#[cfg(feature = "kafka")]
+#[derive(Parser)]
+struct KafkaOptions {
+    #[clap(
+        short = 'k',
+        long,
+        help = "File containing the base64 encoded private key"
+    )]
+    private_key_filename: Option<String>,
+
+    #[clap(
+        short,
+        long,
+        value_name = "host:port,...",
+        help = "Kafka brokers to connect to"
+    )]
+    brokers: String,
+
+    #[clap(
+        long,
+        value_name = "topic",
+        help = "Topic to consume Logpush events from"
+    )]
+    input_topic: String,
+
+    #[clap(
+        long,
+        value_name = "topic",
+        help = "Topic to produce events enriched with their decrypted matched data to"
+    )]
+    output_topic: String,
+
+    #[clap(
+        short,
+        long,
+        value_name = "group",
+        help = "Consumer group committing the offsets of produced events",
+        default_value = "matched-data-cli"
+    )]
+    group_id: String,
+
+    #[clap(
+        short = 'X',
+        long = "property",
+        value_name = "key=value",
+        help = "librdkafka property, such as security.protocol=SASL_SSL, can be repeated"
+    )]
+    properties: Vec<String>,
+
+    #[clap(
+        short = 'F',
+        long,
+        value_name = "file",
+        help = "File of librdkafka properties, one key=value per line, for secrets such as sasl.password"
+    )]
+    properties_file: Option<String>,
+
+    #[clap(
+        long,
+        help = "Keep going after an event fails, adding a decryption_error field to it"
+    )]
+    continue_on_error: bool,
+}
+
+// Parsed once, so the size of the largest options doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Parser)]
+enum Command {
+    /// Generates a public-private key pair
+    #[clap(after_help = help::GENERATE_KEY_PAIR)]
+    GenerateKeyPair(GenerateKeyPairOptions),
+
+    /// Decrypts data
+    #[clap(after_help = help::DECRYPT)]
+    Decrypt(DecryptOptions),
+
+    /// Generates a private key on a YubiKey that never leaves it, printing its public key
+    #[clap(after_help = help::GENERATE_YUBIKEY_KEY)]
+    GenerateYubikeyKey(GenerateYubiKeyKeyOptions),
+
+    /// Encrypts data to one or more public keys, printing one blob per recipient
+    #[clap(after_help = help::ENCRYPT)]
+    Encrypt(EncryptOptions),
+
+    /// Decrypts matched data and encrypts it to other public keys, without writing the plaintext
+    #[clap(after_help = help::REENCRYPT)]
+    Reencrypt(ReencryptOptions),
+
+    /// Re-encrypts every matched data of archived logs to a new public key, for key rotation
+    #[clap(after_help = help::REWRAP)]
+    Rewrap(RewrapOptions),
+
+    /// Imports an X25519 key pair from an age identity, OpenSSH ed25519 key or key QR code
+    #[clap(after_help = help::IMPORT_KEY)]
+    ImportKey(ImportKeyOptions),
+
+    /// Exports a private key as an age identity
+    #[clap(after_help = help::EXPORT_KEY)]
+    ExportKey(ExportKeyOptions),
+
+    /// Splits a private key into shares, a threshold of which recover it
+    #[clap(after_help = help::SPLIT_KEY)]
+    SplitKey(SplitKeyOptions),
+
+    /// Recovers a private key from shares created by split-key
+    #[clap(after_help = help::COMBINE_KEY)]
+    CombineKey(CombineKeyOptions),
+
+    /// Prints a short fingerprint of a key pair from its private or public key
+    #[clap(after_help = help::FINGERPRINT)]
+    Fingerprint(FingerprintOptions),
+
+    /// Saves private keys in the macOS Keychain, the Linux Secret Service or with Windows DPAPI
+    #[clap(after_help = help::KEY)]
+    Key(KeyOptions),
+
+    /// Manages the cache of decrypted matched data kept with --cache-dir
+    #[clap(after_help = help::CACHE)]
+    Cache(CacheOptions),
+
+    /// Checks that a private key matches the payload logging public key configured on a zone
+    #[cfg(feature = "api")]
+    #[clap(after_help = help::VERIFY_REMOTE_KEY)]
+    VerifyRemoteKey(VerifyRemoteKeyOptions),
+
+    /// Checks that key generation, encryption and decryption work on this build and platform
+    #[clap(after_help = help::SELF_TEST)]
+    SelfTest,
+
+    /// Measures the decryption throughput of this machine with one thread and with several
+    #[clap(after_help = help::BENCH)]
+    Bench(BenchOptions),
+
+    /// Replaces this executable with the latest GitHub release, verified against its published checksums
+    #[cfg(feature = "self-update")]
+    #[clap(after_help = help::SELF_UPDATE)]
+    SelfUpdate(SelfUpdateOptions),
+
+    /// Prints deterministic test vectors for each supported format version as JSON
+    #[clap(after_help = help::GENERATE_TEST_VECTORS)]
+    GenerateTestVectors,
+
+    /// Lists the encryption format versions this build can decrypt and their cipher suites
+    #[clap(after_help = help::VERSIONS)]
+    Versions(VersionsOptions),
+
+    /// Diagnoses common problems with a private key and matched data
+    #[clap(after_help = help::DOCTOR)]
+    Doctor(DoctorOptions),
+
+    /// Decrypts matched data and compares it to an expected plaintext, printing their differences
+    #[clap(after_help = help::DIFF)]
+    Diff(DiffOptions),
+
+    /// Checks that a public key is well formed and can be encrypted to before deploying it
+    #[clap(after_help = help::VALIDATE_PUBLIC_KEY)]
+    ValidatePublicKey(ValidatePublicKeyOptions),
+
+    /// Decrypts the matched data of events in Logpush logs
+    #[clap(after_help = help::LOGS)]
+    Logs(LogsOptions),
+
+    /// Decrypts the matched data of NDJSON events on stdin, writing each one to stdout as soon as it is read
+    #[clap(after_help = help::PIPE)]
+    Pipe(PipeOptions),
+
+    /// Decrypts the matched data column of a CSV export, adding a column with the plaintext
+    #[clap(after_help = help::CSV)]
+    Csv(CsvOptions),
+
+    /// Counts the events of Logpush logs with matched data, their format versions, sizes and how
+    /// many decrypt, without printing any plaintext
+    #[clap(after_help = help::STATS)]
+    Stats(StatsOptions),
+
+    /// Browses events in a terminal UI, decrypting matched data on demand
+    #[cfg(feature = "browse")]
+    #[clap(after_help = help::BROWSE)]
+    Browse(BrowseOptions),
+
+    /// Serves decryption requests over HTTP with a private key loaded once
+    #[cfg(feature = "serve")]
+    #[clap(after_help = help::SERVE)]
+    Serve(ServeOptions),
+
+    /// Consumes Logpush events from a Kafka topic and produces them with their decrypted matched
+    /// data to another one
+    #[cfg(feature = "kafka")]
+    #[clap(after_help = help::KAFKA)]
+    Kafka(KafkaOptions),
+
+    /// Decrypts each new Logpush object announced by S3 or R2 event notifications, received from
+    /// SQS or a webhook, to the sink
+    #[cfg(feature = "serve")]
+    #[clap(after_help = help::WATCH)]
+    Watch(WatchOptions),
+
+    /// Serves a local web page decrypting matched data in the browser, with a key file that never
+    /// leaves it
+    #[cfg(feature = "serve")]
+    #[clap(after_help = help::SERVE_UI)]
+    ServeUi(ServeUiOptions),
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyPair {
+    private_key: String,
+    // Derived from the private key when a key file only holds the private key
+    #[serde(default)]
+    public_key: String,
+    // Set by generate-key-pair --label, to tell keys apart once several are in use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    // Fingerprint of the public key, named in decryption errors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<String>,
+    // RFC 3339 date after which using the key is warned about, or fails with --enforce-expiry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
+}
+
+// Key pair printed by generate-key-pair with --porcelain, without the private key once written
+// to a file
+#[derive(Serialize)]
+struct PorcelainKeyPair<'a> {
+    porcelain: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    private_key: Option<&'a str>,
+    public_key: &'a str,
+    fingerprint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<&'a str>,
+}
+
+impl Drop for KeyPair {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
+const TRUNCATED: &str = "truncated";
+
+// Records the outcome of each decryption stage so failures can be diagnosed
+struct DecryptTrace {
+    verbose: bool,
+    stages: Vec<String>,
+}
+
+impl DecryptTrace {
+    fn new(verbose: bool) -> Self {
+        DecryptTrace {
+            verbose,
+            stages: Vec::new(),
+        }
+    }
+
+    // Records a stage that completed successfully
+    fn ok(&mut self, stage: &str, detail: String) {
+        self.stages.push(format!("{}: ok ({})", stage, detail));
+    }
+
+    // Reports the completed stages and the failed one in verbose mode and returns the error
+    fn fail(&self, stage: &str, detail: String, error: CliError) -> CliError {
+        if self.verbose {
+            for completed in &self.stages {
+                eprintln!("{}", completed);
+            }
+            eprintln!("{}: failed ({})", stage, detail);
+        }
+        error.at_stage(stage)
+    }
+}
+
+// Reads and base64 decodes the private key from a file
+fn read_private_key(
+    private_key_filename: &str,
+    strict: bool,
+    trace: &mut DecryptTrace,
+) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    Ok(read_private_key_file(private_key_filename, strict, trace)?.0)
+}
+
+// Reads and base64 decodes the private key from a file, with the JSON key pair holding it, None for
+// files holding only the base64 encoded key
+fn read_private_key_file(
+    private_key_filename: &str,
+    strict: bool,
+    trace: &mut DecryptTrace,
+) -> Result<(Zeroizing<Vec<u8>>, Option<KeyPair>), CliError> {
+    let private_key_file = read_text_file(private_key_filename).ok_or_else(|| {
+        CliError::new(
+            ErrorCode::PrivateKeyUnreadable,
+            "Failed to read private key from file",
+        )
+    })?;
+
+    memory::lock_bytes(private_key_file.as_bytes())?;
+
+    let key_pair = parse_key_pair_file(&private_key_file)?;
+    let private_key_base64 = match &key_pair {
+        Some(key_pair) => {
+            if let Some(expires_at) = &key_pair.expires_at {
+                expiry::check(
+                    key_pair.key_id.as_deref().unwrap_or(private_key_filename),
+                    expires_at,
+                )?;
+            }
+            Zeroizing::new(key_pair.private_key.clone())
+        }
+        None => private_key_file,
+    };
+
+    let private_key_bytes = encoding::decode_base64(&private_key_base64, strict).map_err(|e| {
+        trace.fail(
+            "private key base64 decode",
+            format!("{:?}", e),
+            CliError::new(
+                ErrorCode::PrivateKeyNotBase64,
+                "Provided private key is not base64 encoded",
+            ),
+        )
+    })?;
+    trace.ok(
+        "private key base64 decode",
+        format!("{} bytes", private_key_bytes.len()),
+    );
+
+    let private_key_bytes = Zeroizing::new(private_key_bytes);
+    memory::lock_bytes(&private_key_bytes)?;
+    Ok((private_key_bytes, key_pair))
+}
+
+// Parses a key file holding a JSON key pair, as printed by generate-key-pair, None for files
+// holding only the base64 encoded key
+fn parse_key_pair_file(key_file: &str) -> Result<Option<KeyPair>, CliError> {
+    if !key_file.trim_start().starts_with('{') {
+        return Ok(None);
+    }
+    let mut key_pair: KeyPair = serde_json::from_str(key_file).map_err(|_| {
+        let message = match serde_json::from_str::<serde_json::Value>(key_file) {
+            Ok(serde_json::Value::Object(fields)) if !fields.contains_key("private_key") => {
+                "Provided private key file is a JSON key pair without its private_key, such as \
+                 generate-key-pair prints when the keys are written to files"
+            }
+            _ => "Provided private key file is not a valid JSON key pair",
+        };
+        CliError::new(ErrorCode::PrivateKeyInvalid, message)
+    })?;
+
+    // Keys that don't decode are reported by the callers, with the usual decryption stages
+    let derived_key_pair = encoding::decode_base64(&key_pair.private_key, false)
+        .ok()
+        .and_then(|private_key_bytes| {
+            key_pair_from_private_key(&Zeroizing::new(private_key_bytes)).ok()
+        });
+    if let Some(derived_key_pair) = derived_key_pair {
+        if key_pair.public_key.is_empty() {
+            key_pair.public_key = derived_key_pair.public_key.clone();
+        } else if key_pair.public_key.trim() != derived_key_pair.public_key {
+            return Err(CliError::new(
+                ErrorCode::PrivateKeyInvalid,
+                "Private key of the JSON key pair doesn't match its public key, it may have been \
+                 truncated or edited",
+            ));
+        }
+    }
+    Ok(Some(key_pair))
+}
+
+// Reads a key or matched data file saved as UTF-8, or as UTF-16 by Windows tools, None if it is
+// unreadable or not text
+fn read_text_file(filename: &str) -> Option<Zeroizing<String>> {
+    encoding::decode_text(&Zeroizing::new(fs::read(filename).ok()?))
+}
+
+// Reads the JSON key pair of a key file, None for files holding only the base64 encoded key
+fn read_key_pair_file(filename: &str) -> Result<Option<KeyPair>, CliError> {
+    let key_file = read_text_file(filename).ok_or_else(|| {
+        CliError::new(
+            ErrorCode::PrivateKeyUnreadable,
+            "Failed to read private key from file",
+        )
+    })?;
+    parse_key_pair_file(&key_file)
+}
+
+// Lists key files with their key IDs, labels and expiry, failing with --enforce-expiry if any has
+// expired
+fn list_keys(command: KeyListOptions, strict: bool) -> Result<(), CliError> {
+    let now = SystemTime::now();
+    let mut keys = Vec::new();
+    for filename in &command.key_filenames {
+        let key = match read_key_pair_file(filename)? {
+            Some(key_pair) => ListedKey {
+                file: filename.clone(),
+                key_id: match key_pair.key_id.clone() {
+                    Some(key_id) => key_id,
+                    None => key_format::fingerprint(
+                        &encoding::decode_base64(&key_pair.public_key, strict)
+                            .map_err(|_| "Provided public key is not base64 encoded")?,
+                    ),
+                },
+                label: key_pair.label.clone(),
+                days_remaining: key_pair
+                    .expires_at
+                    .as_deref()
+                    .map(|expires_at| expiry::days_remaining(expires_at, now))
+                    .transpose()?,
+                expires_at: key_pair.expires_at.clone(),
+            },
+            None => {
+                let private_key_bytes =
+                    read_private_key(filename, strict, &mut DecryptTrace::new(false))?;
+                ListedKey {
+                    file: filename.clone(),
+                    key_id: key_pair_from_private_key(&private_key_bytes)?
+                        .key_id
+                        .clone()
+                        .unwrap_or_default(),
+                    label: None,
+                    expires_at: None,
+                    days_remaining: None,
+                }
+            }
+        };
+        keys.push(key);
+    }
+
+    match command.output_format {
+        TextJsonFormat::Text => {
+            println!(
+                "{:<8}  {:>9}  {:<20}  LABEL",
+                "KEY ID", "DAYS LEFT", "EXPIRES"
+            );
+            for key in &keys {
+                let days_left = match key.days_remaining {
+                    Some(days) if days < 0 => "expired".to_string(),
+                    Some(days) => days.to_string(),
+                    None => "-".to_string(),
+                };
+                println!(
+                    "{:<8}  {:>9}  {:<20}  {}",
+                    key.key_id,
+                    days_left,
+                    key.expires_at.as_deref().unwrap_or("-"),
+                    key.label.as_deref().unwrap_or(&key.file)
+                );
+            }
+        }
+        TextJsonFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&keys).expect("Failed to output keys")
+        ),
+    }
+
+    let expired = keys
+        .iter()
+        .filter(|key| key.days_remaining.is_some_and(|days| days < 0))
+        .count();
+    if expired > 0 && expiry::is_enforced() {
+        return Err(format!("{} of {} keys have expired", expired, keys.len()).into());
+    }
+
+    Ok(())
+}
+
+// Prints the problems found by doctor, or that there are none
+fn report_findings(findings: &[doctor::Finding], no_problems: &str) -> Result<(), String> {
+    if findings.is_empty() {
+        println!("{}", no_problems);
+        return Ok(());
+    }
+
+    for finding in findings {
+        println!("problem: {}\n    fix: {}", finding.problem, finding.fix);
+    }
+    Err(format!("Found {} problem(s)", findings.len()))
+}
+
+// Reads a base64 encoded public key from a file
+fn read_public_key(public_key_filename: &str, strict: bool) -> Result<Vec<u8>, String> {
+    let public_key_base64 =
+        read_text_file(public_key_filename).ok_or("Failed to read public key from file")?;
+    encoding::decode_base64(&public_key_base64, strict)
+        .map_err(|_| "Provided public key is not base64 encoded".to_string())
+}
+
+// Minimum length of a pre-shared key, as required by RFC 9180
+const MIN_PSK_LENGTH: usize = 32;
+
+// Reads a base64 encoded pre-shared key from a file
+fn read_psk(psk_filename: &str, strict: bool) -> Result<Zeroizing<Vec<u8>>, String> {
+    let psk_base64 =
+        read_text_file(psk_filename).ok_or("Failed to read pre-shared key from file")?;
+    let psk = Zeroizing::new(
+        encoding::decode_base64(&psk_base64, strict)
+            .map_err(|_| "Provided pre-shared key is not base64 encoded")?,
+    );
+    if psk.len() < MIN_PSK_LENGTH {
+        return Err(format!(
+            "Provided pre-shared key is {} bytes, expected at least {}",
+            psk.len(),
+            MIN_PSK_LENGTH
+        ));
+    }
+
+    Ok(psk)
+}
+
+// Reads associated data given on the command line or in a file, empty when neither is
+fn read_aad(aad: Option<String>, aad_filename: Option<&str>) -> Result<Vec<u8>, String> {
+    match (aad, aad_filename) {
+        (Some(aad), _) => Ok(aad.into_bytes()),
+        (None, Some(filename)) => {
+            fs::read(filename).map_err(|_| "Failed to read associated data from file".to_string())
+        }
+        (None, None) => Ok(Vec::new()),
+    }
+}
+
+// Pairs a pre-shared key with its ID, both being required together
+fn psk_bundle<'a>(psk: Option<&'a [u8]>, psk_id: Option<&'a str>) -> Option<matched_data::Psk<'a>> {
+    Some(matched_data::Psk {
+        psk: psk?,
+        psk_id: psk_id?.as_bytes(),
+    })
+}
+
+// Deserializes a private key once for all the matched data it decrypts
+fn private_key_decryptor(
+    private_key_bytes: &[u8],
+    trace: &mut DecryptTrace,
+) -> Result<Box<matched_data::Decryptor>, CliError> {
+    let decryptor = matched_data::Decryptor::new(private_key_bytes).map_err(|error| {
+        let message = error.to_string();
+        let detail = match error {
+            DecryptAnyVersionError::InvalidPrivateKey(e) => format!("{:?}", e),
+            error => format!("{:?}", error),
+        };
+        trace.fail(
+            "private key",
+            detail,
+            CliError::new(ErrorCode::PrivateKeyInvalid, message),
+        )
+    })?;
+
+    // Boxed so the locked memory doesn't move
+    let decryptor = Box::new(decryptor);
+    for (address, len) in decryptor.memory_regions() {
+        memory::lock(address, len)?;
+    }
+    Ok(decryptor)
+}
+
+// Decrypts matched data bytes according to their encryption format version, in the HPKE mode
+// they were encrypted in, tracing each stage
+fn decrypt_matched_data(
+    decryptor: &matched_data::Decryptor,
+    encrypted_matched_data_bytes: &[u8],
+    mode: &matched_data::Mode<'_>,
+    trace: &mut DecryptTrace,
+) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    let error = match decryptor.decrypt_in_mode(encrypted_matched_data_bytes, mode) {
+        Ok(matched_data) => return Ok(matched_data),
+        Err(error) => error,
+    };
+
+    let message = error.to_string();
+    if let Some(version) = encrypted_matched_data_bytes.first() {
+        if !matches!(error, DecryptAnyVersionError::UnsupportedVersion(_)) {
+            trace.ok("version byte", version.to_string());
+        }
+    }
+
+    Err(match error {
+        DecryptAnyVersionError::Empty => trace.fail(
+            "version byte",
+            "missing".to_string(),
+            CliError::new(ErrorCode::MatchedDataEmpty, message),
+        ),
+        DecryptAnyVersionError::UnsupportedVersion(version) => trace.fail(
+            "version byte",
+            version.to_string(),
+            CliError::new(ErrorCode::UnsupportedVersion, message),
+        ),
+        DecryptAnyVersionError::InvalidPrivateKey(e) => trace.fail(
+            "private key",
+            format!("{:?}", e),
+            CliError::new(ErrorCode::PrivateKeyInvalid, message),
+        ),
+        DecryptAnyVersionError::InvalidSenderPublicKey(e) => trace.fail(
+            "sender public key",
+            format!("{:?}", e),
+            CliError::new(ErrorCode::Other, message),
+        ),
+        DecryptAnyVersionError::InvalidMatchedData(e) => trace.fail(
+            "deserialization",
+            e.to_string(),
+            CliError::new(ErrorCode::MatchedDataInvalid, message),
+        ),
+        DecryptAnyVersionError::Decrypt {
+            component_lengths: (encapped_key_len, ciphertext_len, tag_len),
+            error,
+        } => {
+            trace.ok(
+                "deserialization",
+                format!(
+                    "encapped key {} bytes, ciphertext {} bytes, tag {} bytes",
+                    encapped_key_len, ciphertext_len, tag_len
+                ),
+            );
+            let (stage, error) = match error {
+                DecryptError::Decapsulation(error) => ("HPKE decapsulation", error),
+                DecryptError::Open(error) => {
+                    trace.ok("HPKE decapsulation", "shared secret derived".to_string());
+                    ("AEAD open", error)
+                }
+            };
+            trace.fail(
+                stage,
+                format!("{:?}", error),
+                CliError::new(
+                    ErrorCode::DecryptionFailed,
+                    tried_key_id(&message, &decryptor.public_key().to_bytes()),
+                ),
+            )
+        }
+    })
+}
+
+// Names the key that failed to decrypt matched data in the error message, so failures can be told
+// apart when several keys are in use
+fn tried_key_id(message: &str, public_key_bytes: &[u8]) -> String {
+    format!(
+        "{}, tried key id {}",
+        message,
+        key_format::fingerprint(public_key_bytes)
+    )
+}
+
+// Decrypts matched data bytes with a private key held in a YubiKey slot, which performs the
+// Diffie-Hellman step of HPKE decapsulation
+fn decrypt_matched_data_with_yubikey(
+    slot: &yubikey::Slot,
+    public_key_bytes: &[u8],
+    encrypted_matched_data_bytes: &[u8],
+    aad: &[u8],
+    trace: &mut DecryptTrace,
+) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    match encrypted_matched_data_bytes.first() {
+        Some(3) => trace.ok("version byte", "3".to_string()),
+        Some(&version) => {
+            return Err(trace.fail(
+                "version byte",
+                version.to_string(),
+                CliError::new(
+                    ErrorCode::UnsupportedVersion,
+                    DecryptAnyVersionError::UnsupportedVersion(version).to_string(),
+                ),
+            ))
+        }
+        None => {
+            return Err(trace.fail(
+                "version byte",
+                "missing".to_string(),
+                CliError::new(
+                    ErrorCode::MatchedDataEmpty,
+                    DecryptAnyVersionError::Empty.to_string(),
+                ),
+            ))
+        }
+    }
+
+    let public_key = matched_data::get_public_key_from_bytes(public_key_bytes).map_err(|e| {
+        trace.fail(
+            "public key",
+            format!("{:?}", e),
+            CliError::new(ErrorCode::Other, "Provided public key is invalid"),
+        )
+    })?;
+    let encrypted_data = matched_data::deserialize_encrypted_data(encrypted_matched_data_bytes)
+        .map_err(|e| {
+            trace.fail(
+                "deserialization",
+                e.to_string(),
+                CliError::new(
+                    ErrorCode::MatchedDataInvalid,
+                    DecryptAnyVersionError::InvalidMatchedData(e).to_string(),
+                ),
+            )
+        })?;
+    let (encapped_key_len, ciphertext_len, tag_len) = encrypted_data.component_lengths();
+    trace.ok(
+        "deserialization",
+        format!(
+            "encapped key {} bytes, ciphertext {} bytes, tag {} bytes",
+            encapped_key_len, ciphertext_len, tag_len
+        ),
+    );
+
+    let pin = Zeroizing::new(
+        rpassword::prompt_password("YubiKey PIN: ").map_err(|_| "Failed to read YubiKey PIN")?,
+    );
+    let dh =
+        yubikey::diffie_hellman(slot, &pin, &encrypted_data.encapped_key_bytes()).map_err(|e| {
+            trace.fail(
+                "YubiKey Diffie-Hellman",
+                e.clone(),
+                CliError::new(ErrorCode::HardwareKeyFailed, e),
+            )
+        })?;
+    trace.ok(
+        "YubiKey Diffie-Hellman",
+        "shared secret derived".to_string(),
+    );
+
+    matched_data::decrypt_data_with_dh(&encrypted_data, &public_key, &dh, aad).map_err(|error| {
+        let (stage, error) = match error {
+            DecryptError::Decapsulation(error) => ("HPKE decapsulation", error),
+            DecryptError::Open(error) => ("AEAD open", error),
+        };
+        trace.fail(
+            stage,
+            format!("{:?}", error),
+            CliError::new(
+                ErrorCode::DecryptionFailed,
+                tried_key_id("Failed to decrypt matched data", public_key_bytes),
+            ),
+        )
+    })
+}
+
+// Decrypts base64 encoded matched data without reporting stages, recording it in the audit log
+// and looking it up in the cache first if any
+fn decrypt_base64_matched_data(
+    decryptor: &matched_data::Decryptor,
+    encrypted_matched_data: &str,
+    audit_log: Option<&audit::AuditLog>,
+    cache: Option<&cache::Cache>,
+    strict: bool,
+) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    let encrypted_matched_data_bytes = encoding::decode_base64(encrypted_matched_data, strict)
+        .map_err(|_| {
+            CliError::new(
+                ErrorCode::MatchedDataNotBase64,
+                "Provided matched data is not base64 encoded",
+            )
+        })?;
+
+    let result = match cache.and_then(|cache| cache.get(&encrypted_matched_data_bytes)) {
+        Some(matched_data) => Ok(matched_data),
+        None => {
+            let result = decrypt_matched_data(
+                decryptor,
+                &encrypted_matched_data_bytes,
+                &matched_data::Mode::default(),
+                &mut DecryptTrace::new(false),
+            );
+            // A cache that can't be written to only loses its speedup
+            if let (Some(cache), Ok(matched_data)) = (cache, &result) {
+                if let Err(e) = cache.put(&encrypted_matched_data_bytes, matched_data) {
+                    tracing::warn!(error = %e, "Failed to cache matched data");
+                }
+            }
+            result
+        }
+    };
+    // Cache hits are recorded too, as they reveal the plaintext all the same
+    if let Some(audit_log) = audit_log {
+        audit_log.record(
+            &encrypted_matched_data_bytes,
+            result.as_ref().err().map(|e| e.message.as_str()),
+        )?;
+    }
+
+    result
+}
+
+// Opens the SIEM sink chosen on the command line, or stdout
+#[cfg(feature = "api")]
+fn open_sink(options: SinkOptions) -> Result<Box<dyn sink::Sink>, String> {
+    match options.sink {
+        None => Ok(Box::new(stdout().lock())),
+        Some(SinkKind::SplunkHec) => {
+            let url = options
+                .hec_url
+                .ok_or("Pass the URL of the Splunk HTTP Event Collector with --hec-url")?;
+            let token = options
+                .hec_token
+                .or_else(|| std::env::var("SPLUNK_HEC_TOKEN").ok())
+                .map(Zeroizing::new)
+                .ok_or("Pass the Splunk HEC token with --hec-token or SPLUNK_HEC_TOKEN")?;
+            Ok(Box::new(splunk::SplunkHec::new(&url, token)))
+        }
+        Some(SinkKind::Elasticsearch) => {
+            let url = options
+                .es_url
+                .ok_or("Pass the URL of the Elasticsearch cluster with --es-url")?;
+            let credentials = match options.es_username {
+                Some(username) => Some(elasticsearch::Credentials::Basic {
+                    username,
+                    password: std::env::var("ES_PASSWORD")
+                        .map(Zeroizing::new)
+                        .map_err(|_| "Set the password of the Elasticsearch user in ES_PASSWORD")?,
+                }),
+                None => options
+                    .es_api_key
+                    .or_else(|| std::env::var("ES_API_KEY").ok())
+                    .map(|api_key| elasticsearch::Credentials::ApiKey(Zeroizing::new(api_key))),
+            };
+            Ok(Box::new(elasticsearch::Elasticsearch::new(
+                &url,
+                &options.es_index,
+                credentials,
+            )))
+        }
+    }
+}
+
+// Reads and validates the public keys to encrypt to
+fn read_public_keys(
+    filenames: &[String],
+    strict: bool,
+) -> Result<Vec<matched_data::PublicKey>, String> {
+    filenames
+        .iter()
+        .map(|filename| {
+            matched_data::get_public_key_from_bytes(&read_public_key(filename, strict)?)
+                .map_err(|_| "Provided public key is invalid".to_string())
+        })
+        .collect()
+}
+
+// Decrypts base64 encoded matched data and encrypts the plaintext to another public key, returning
+// the new base64 encoded matched data. The plaintext is only held in wiped memory
+fn reencrypt_matched_data(
+    decryptor: &matched_data::Decryptor,
+    encrypted_matched_data: &str,
+    public_key: &matched_data::PublicKey,
+    audit_log: Option<&audit::AuditLog>,
+    strict: bool,
+) -> Result<String, CliError> {
+    let matched_data =
+        decrypt_base64_matched_data(decryptor, encrypted_matched_data, audit_log, None, strict)?;
+    let encrypted_data = matched_data::encrypt_data(&matched_data, public_key)
+        .map_err(|_| "Failed to encrypt matched data")?;
+
+    Ok(radix64::STD.encode(&matched_data::serialize_encrypted_data(&encrypted_data)))
+}
+
+// Opens a Logpush log from stdin, an R2 or S3 object, an archive of logs or a file, decompressed
+// if gzipped
+fn open_log(
+    log_filename: &str,
+    r2_account_id: Option<&str>,
+) -> Result<BufReader<Box<dyn Read>>, String> {
+    if log_filename == "-" {
+        logs::decompress(stdin().lock())
+    } else if object_storage::is_url(log_filename) {
+        logs::decompress(BufReader::new(object_storage::open(
+            log_filename,
+            r2_account_id,
+        )?))
+    } else if archive::is_archive(log_filename) {
+        logs::decompress(BufReader::new(archive::open(log_filename)?))
+    } else {
+        let file = fs::File::open(log_filename).map_err(|_| "Failed to read log file")?;
+        logs::decompress(BufReader::new(file))
+    }
+}
+
+// Opens the cache directory passed on the command line or set in the configuration file, if any
+fn open_cache(
+    cache_dir: Option<&str>,
+    private_key_bytes: &[u8],
+) -> Result<Option<cache::Cache>, String> {
+    cache_dir
+        .map(|dir| cache::Cache::open(dir, private_key_bytes))
+        .transpose()
+}
+
+// Opens the audit log passed on the command line or set in the configuration file, if any
+fn open_audit_log(
+    audit_log_filename: Option<&str>,
+    command: &'static str,
+    private_key_bytes: &[u8],
+) -> Result<Option<audit::AuditLog>, String> {
+    audit_log_filename
+        .map(|filename| audit::AuditLog::open(filename, command, private_key_bytes))
+        .transpose()
+}
+
+// Private key decrypting the events of logs, with the audit log and cache of its decryptions
+struct LogKey {
+    key_id: String,
+    label: Option<String>,
+    decryptor: Box<matched_data::Decryptor>,
+    audit_log: Option<audit::AuditLog>,
+    cache: Option<cache::Cache>,
+}
+
+impl LogKey {
+    fn open(
+        private_key_filename: &str,
+        command: &'static str,
+        audit_log_filename: Option<&str>,
+        cache_dir: Option<&str>,
+        strict: bool,
+    ) -> Result<Self, CliError> {
+        let (private_key_bytes, key_pair) =
+            read_private_key_file(private_key_filename, strict, &mut DecryptTrace::new(false))?;
+        Ok(LogKey {
+            key_id: key_pair_from_private_key(&private_key_bytes)?
+                .key_id
+                .clone()
+                .expect("Key pairs of private keys have a key ID"),
+            label: key_pair.and_then(|key_pair| key_pair.label.clone()),
+            audit_log: open_audit_log(audit_log_filename, command, &private_key_bytes)?,
+            cache: open_cache(cache_dir, &private_key_bytes)?,
+            decryptor: private_key_decryptor(&private_key_bytes, &mut DecryptTrace::new(false))?,
+        })
+    }
+
+    fn decrypt(
+        &self,
+        encrypted_matched_data: &str,
+        strict: bool,
+    ) -> Result<Zeroizing<Vec<u8>>, String> {
+        decrypt_base64_matched_data(
+            &self.decryptor,
+            encrypted_matched_data,
+            self.audit_log.as_ref(),
+            self.cache.as_ref(),
+            strict,
+        )
+        .map_err(String::from)
+    }
+}
+
+// Whether a name of the keys of the configuration file is a key fingerprint rather than a zone ID
+fn is_fingerprint(name: &str) -> bool {
+    name.len() == 8 && name.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+// Private keys decrypting the events of logs: those of zones in the keys of the configuration file,
+// and the others tried in turn for events of any other zone
+struct LogKeys {
+    zones: Vec<(String, LogKey)>,
+    others: Vec<LogKey>,
+}
+
+impl LogKeys {
+    // Opens the keys of zones in the configuration file, then the one passed with -k or set in the
+    // configuration file, then those configured by fingerprint, checking that they match
+    fn open(
+        config: &config::Config,
+        private_key_filename: Option<String>,
+        command: &'static str,
+        audit_log_filename: Option<&str>,
+        cache_dir: Option<&str>,
+        strict: bool,
+    ) -> Result<Self, CliError> {
+        let open =
+            |filename: &str| LogKey::open(filename, command, audit_log_filename, cache_dir, strict);
+
+        let mut keys = LogKeys {
+            zones: Vec::new(),
+            others: Vec::new(),
+        };
+        let private_key_filename = match config.keys.is_empty() {
+            true => Some(config.private_key_filename(private_key_filename)?),
+            false => private_key_filename.or_else(|| config.private_key.clone()),
+        };
+        if let Some(filename) = private_key_filename {
+            keys.others.push(open(&filename)?);
+        }
+        for (name, filename) in &config.keys {
+            let key = open(filename)?;
+            if !is_fingerprint(name) {
+                keys.zones.push((name.clone(), key));
+            } else if name.eq_ignore_ascii_case(&key.key_id) {
+                keys.others.push(key);
+            } else {
+                return Err(format!(
+                    "Private key file {} of key {} in the configuration file has key id {}",
+                    filename, name, key.key_id
+                )
+                .into());
+            }
+        }
+        Ok(keys)
+    }
+
+    // Keys decrypting the matched data of events, picking the one of each event's zone
+    fn decrypts<'a>(&'a self, strict: bool) -> logs::Keys<'a> {
+        let decrypt = |key: &'a LogKey| logs::Key {
+            key_id: key.key_id.clone(),
+            label: key.label.clone(),
+            decrypt: Box::new(move |encrypted_matched_data: &str| {
+                key.decrypt(encrypted_matched_data, strict)
+            }),
+        };
+        logs::Keys::by_zone(
+            self.zones
+                .iter()
+                .map(|(zone_id, key)| (zone_id.clone(), decrypt(key)))
+                .collect(),
+            self.others.iter().map(decrypt).collect(),
+        )
+    }
+}
+
+// Prompts for private key shares without echoing them until the threshold is reached
+fn read_key_shares() -> Result<Zeroizing<Vec<u8>>, String> {
+    let mut shares = Zeroizing::new(Vec::new());
+    let mut threshold = 1;
+
+    while shares.len() < threshold as usize {
+        let share = rpassword::prompt_password(format!("Private key share {}: ", shares.len() + 1))
+            .map_err(|_| "Failed to read private key share")?;
+        threshold = key_format::share_threshold(&share)?;
+        shares.push(share);
+    }
+
+    key_format::combine_key(&shares)
+}
+
+// Creates a file readable only by the current user, refusing to overwrite an existing one
+fn create_private_file(filename: &str) -> Result<fs::File, String> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(filename).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => {
+            format!("{} already exists, refusing to overwrite it", filename)
+        }
+        _ => format!("Failed to create {}", filename),
+    })
+}
+
+// Prints the encryption format versions that can be decrypted, the one encrypted to marked current
+fn print_versions(output_format: &TextJsonFormat) {
+    match output_format {
+        TextJsonFormat::Text => {
+            for decryptor in versions::DECRYPTORS {
+                println!("{}\t{}", decryptor.version(), decryptor.description());
+            }
+        }
+        TextJsonFormat::Json => {
+            let versions: Vec<_> = versions::DECRYPTORS
+                .iter()
+                .map(|decryptor| {
+                    serde_json::json!({
+                        "version": decryptor.version(),
+                        "description": decryptor.description(),
+                        "current": decryptor.version() == versions::CURRENT.version(),
+                        "hpke_suite": decryptor.hpke_suite().map(|suite| serde_json::json!({
+                            "kem_id": suite.kem,
+                            "kdf_id": suite.kdf,
+                            "aead_id": suite.aead,
+                        })),
+                    })
+                })
+                .collect();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&versions).expect("Failed to output versions")
+            );
+        }
+    }
+}
+
+// Encodes a private key and its derived public key as a key pair
+fn key_pair_from_private_key(private_key_bytes: &[u8]) -> Result<KeyPair, String> {
+    let private_key = matched_data::get_private_key_from_bytes(private_key_bytes)
+        .map_err(|_| "Provided private key is invalid")?;
+
+    let public_key_bytes = matched_data::get_public_key(&private_key).to_bytes();
+    Ok(KeyPair {
+        private_key: radix64::STD.encode(&private_key.to_bytes()),
+        public_key: radix64::STD.encode(&public_key_bytes),
+        label: None,
+        key_id: Some(key_format::fingerprint(&public_key_bytes)),
+        expires_at: None,
+    })
+}
+
+// Key that decrypts matched data, in memory or held by a YubiKey
+enum DecryptionKey {
+    PrivateKey(Box<matched_data::Decryptor>),
+    YubiKey {
+        slot: yubikey::Slot,
+        public_key_bytes: Vec<u8>,
+    },
+}
+
+// Decodes base64, hex, byte array or raw matched data and decrypts it with the key, recording the
+// attempt in the audit log and each stage in the trace
+fn decrypt_input(
+    matched_data_input: &[u8],
+    input_format: &DecryptInputFormat,
+    key: &DecryptionKey,
+    mode: &matched_data::Mode<'_>,
+    audit_log: Option<&audit::AuditLog>,
+    strict: bool,
+    trace: &mut DecryptTrace,
+) -> Result<Zeroizing<Vec<u8>>, CliError> {
+    if matched_data_input == TRUNCATED.as_bytes() {
+        return Err(CliError::new(
+            ErrorCode::MatchedDataTruncated,
+            "The payload match for this event is unavailable because it was too large.",
+        ));
+    };
+
+    let encrypted_matched_data_bytes = match input_format {
+        DecryptInputFormat::Raw => {
+            trace.ok("raw input", format!("{} bytes", matched_data_input.len()));
+            matched_data_input.to_vec()
+        }
+        input_format => decode_text_input(matched_data_input, input_format, strict, trace)?,
+    };
+
+    let result = match key {
+        DecryptionKey::PrivateKey(decryptor) => {
+            decrypt_matched_data(decryptor, &encrypted_matched_data_bytes, mode, trace)
+        }
+        DecryptionKey::YubiKey {
+            slot,
+            public_key_bytes,
+        } => decrypt_matched_data_with_yubikey(
+            slot,
+            public_key_bytes,
+            &encrypted_matched_data_bytes,
+            mode.aad,
+            trace,
+        ),
+    };
+    if let Some(audit_log) = audit_log {
+        audit_log.record(
+            &encrypted_matched_data_bytes,
+            result.as_ref().err().map(|e| e.message.as_str()),
+        )?;
+    }
+
+    result
+}
+
+// Decodes matched data given as text in a format, or detected from it
+fn decode_text_input(
+    matched_data_input: &[u8],
+    input_format: &DecryptInputFormat,
+    strict: bool,
+    trace: &mut DecryptTrace,
+) -> Result<Vec<u8>, CliError> {
+    let not_text_code = match input_format {
+        DecryptInputFormat::Hex => ErrorCode::MatchedDataNotHex,
+        DecryptInputFormat::BytesJson => ErrorCode::MatchedDataNotByteArray,
+        DecryptInputFormat::Armor => ErrorCode::MatchedDataArmorInvalid,
+        _ => ErrorCode::MatchedDataNotBase64,
+    };
+    let matched_data_text = encoding::decode_text(matched_data_input).ok_or_else(|| {
+        trace.fail(
+            "text decode",
+            "not UTF-8, nor UTF-16 with a byte order mark".to_string(),
+            CliError::new(
+                not_text_code,
+                "Provided matched data is not text, pass --input-format raw if it is serialized bytes",
+            ),
+        )
+    })?;
+    let matched_data_input = matched_data_text.as_str();
+
+    let input_format = match input_format {
+        DecryptInputFormat::Auto if matched_data_input.trim_start().starts_with('[') => {
+            DecryptInputFormat::BytesJson
+        }
+        DecryptInputFormat::Auto if encoding::is_armored(matched_data_input) => {
+            DecryptInputFormat::Armor
+        }
+        DecryptInputFormat::Auto => DecryptInputFormat::Base64,
+        input_format => input_format.clone(),
+    };
+
+    match input_format {
+        DecryptInputFormat::BytesJson => {
+            let bytes: Vec<u8> = serde_json::from_str(matched_data_input).map_err(|e| {
+                trace.fail(
+                    "byte array decode",
+                    e.to_string(),
+                    CliError::new(
+                        ErrorCode::MatchedDataNotByteArray,
+                        "Provided matched data is not a JSON array of bytes",
+                    ),
+                )
+            })?;
+            trace.ok("byte array decode", format!("{} bytes", bytes.len()));
+            Ok(bytes)
+        }
+        DecryptInputFormat::Hex => {
+            let bytes = encoding::decode_hex(matched_data_input).map_err(|e| {
+                trace.fail(
+                    "hex decode",
+                    e,
+                    CliError::new(
+                        ErrorCode::MatchedDataNotHex,
+                        "Provided matched data is not hex encoded",
+                    ),
+                )
+            })?;
+            trace.ok("hex decode", format!("{} bytes", bytes.len()));
+            Ok(bytes)
+        }
+        DecryptInputFormat::Armor => {
+            let bytes = encoding::dearmor(matched_data_input).map_err(|e| {
+                trace.fail(
+                    "armor decode",
+                    e.clone(),
+                    CliError::new(
+                        ErrorCode::MatchedDataArmorInvalid,
+                        format!("Provided armored matched data is invalid: {}", e),
+                    ),
+                )
+            })?;
+            trace.ok("armor decode", format!("{} bytes", bytes.len()));
+            Ok(bytes)
+        }
+        _ => {
+            let bytes = encoding::decode_base64(matched_data_input, strict).map_err(|e| {
+                trace.fail(
+                    "base64 decode",
+                    format!("{:?}", e),
+                    CliError::new(
+                        ErrorCode::MatchedDataNotBase64,
+                        "Provided matched data is not base64 encoded",
+                    ),
+                )
+            })?;
+            trace.ok("base64 decode", format!("{} bytes", bytes.len()));
+            Ok(bytes)
+        }
+    }
+}
+
+// Decrypts the matched data passed to the decrypt command, recording each stage in the trace
+fn decrypt(
+    mut command: DecryptOptions,
+    config: &config::Config,
+    audit_log_filename: Option<&str>,
+    strict: bool,
+    quiet: bool,
+    porcelain: Option<Porcelain>,
+    trace: &mut DecryptTrace,
+) -> Result<(), CliError> {
+    // Validate and construct private key from input
+    let private_key_filename = command
+        .private_key_filename
+        .or_else(|| config.private_key.clone());
+    // Private keys held by the OS keychain or a secrets manager rather than in a file
+    #[cfg(feature = "api")]
+    let stored_private_key = match (command.key_id, command.vault_path, command.azure_key_vault) {
+        (Some(label), _, _) => Some(keychain::read_private_key(&label, strict)?),
+        (None, Some(path), _) => Some(vault::read_private_key(
+            &path,
+            &command.vault_field,
+            strict,
+        )?),
+        (None, None, Some(vault_secret)) => Some(azure::read_private_key(&vault_secret, strict)?),
+        (None, None, None) => None,
+    };
+    #[cfg(not(feature = "api"))]
+    let stored_private_key = command
+        .key_id
+        .map(|label| keychain::read_private_key(&label, strict))
+        .transpose()?;
+    let key = match (
+        command.yubikey_slot,
+        command.yubikey_public_key,
+        command.key_mnemonic,
+        stored_private_key,
+        private_key_filename,
+    ) {
+        (Some(slot), Some(public_key_filename), _, _, _) => {
+            let public_key_base64 = read_text_file(&public_key_filename)
+                .ok_or("Failed to read public key from file")?;
+            DecryptionKey::YubiKey {
+                slot: yubikey::Slot::parse(&slot)?,
+                public_key_bytes: encoding::decode_base64(&public_key_base64, strict)
+                    .map_err(|_| "Provided public key is not base64 encoded")?,
+            }
+        }
+        (_, _, Some(mnemonic_filename), _, _) => {
+            let mnemonic = read_text_file(&mnemonic_filename)
+                .ok_or("Failed to read private key mnemonic from file")?;
+            let private_key_bytes = Zeroizing::new(key_format::from_mnemonic(&mnemonic)?);
+            DecryptionKey::PrivateKey(private_key_decryptor(&private_key_bytes, trace)?)
+        }
+        (_, _, None, Some(private_key_bytes), _) => {
+            DecryptionKey::PrivateKey(private_key_decryptor(&private_key_bytes, trace)?)
+        }
+        _ if command.key_shares => {
+            DecryptionKey::PrivateKey(private_key_decryptor(&read_key_shares()?, trace)?)
+        }
+        (_, _, None, None, Some(filename)) => {
+            let private_key_bytes = match (command.kms_key_id, command.gcp_kms_key) {
+                (Some(key_id), _) => Zeroizing::new(kms::unwrap_aws(&key_id, &filename)?),
+                (None, Some(key)) => Zeroizing::new(kms::unwrap_gcp(&key, &filename)?),
+                (None, None) => read_private_key(&filename, strict, trace)?,
+            };
+            DecryptionKey::PrivateKey(private_key_decryptor(&private_key_bytes, trace)?)
+        }
+        (_, _, None, None, None) => {
+            return Err(CliError::new(
+                ErrorCode::PrivateKeyMissing,
+                "No private key provided, pass -k or set private_key in the configuration file",
+            ))
+        }
+    };
+
+    let public_key_bytes = match &key {
+        DecryptionKey::PrivateKey(decryptor) => decryptor.public_key().to_bytes().to_vec(),
+        DecryptionKey::YubiKey {
+            public_key_bytes, ..
+        } => public_key_bytes.clone(),
+    };
+    let audit_log = audit_log_filename
+        .map(|filename| {
+            audit::AuditLog::open_with_public_key(filename, "decrypt", &public_key_bytes)
+        })
+        .transpose()?;
+    let sender_public_key_bytes = command
+        .sender_public_key
+        .as_deref()
+        .map(|filename| read_public_key(filename, strict))
+        .transpose()?;
+    let psk = command
+        .psk_file
+        .as_deref()
+        .map(|filename| read_psk(filename, strict))
+        .transpose()?;
+    let aad = read_aad(command.aad, command.aad_file.as_deref())?;
+    let mode = matched_data::Mode {
+        sender_public_key: sender_public_key_bytes.as_deref(),
+        psk: psk_bundle(
+            psk.as_ref().map(|psk| psk.as_slice()),
+            command.psk_id.as_deref(),
+        ),
+        aad: &aad,
+    };
+    let redactor = redact::Redactor::new(&command.redact, command.redact_pii)?;
+    let output_format = config.output_format(command.output_format)?;
+
+    let is_raw = matches!(command.input_format, DecryptInputFormat::Raw);
+    if is_raw && (command.data.is_some() || command.clipboard) {
+        return Err(
+            "--data and --clipboard can't be combined with --input-format raw, pass a file".into(),
+        );
+    }
+    // The clipboard is decrypted as if its contents were passed with --data
+    if command.clipboard {
+        command.data = Some(clipboard::paste()?);
+    }
+
+    if porcelain.is_some() && (command.concat || command.output_file.is_some()) {
+        return Err("--concat and --output-file can't be combined with --porcelain".into());
+    }
+
+    // One payload split across the files, decrypted and written out segment by segment
+    if command.concat {
+        let mut decrypt_segment = |filename: &str| {
+            let input = read_matched_data_input(filename, is_raw, command.max_input_size)?;
+            check_input_size(&input, command.max_input_size)?;
+            decrypt_input(
+                &input,
+                &command.input_format,
+                &key,
+                &mode,
+                audit_log.as_ref(),
+                strict,
+                trace,
+            )
+        };
+        return match &command.output_file {
+            Some(output_filename) => {
+                let path = Path::new(output_filename);
+                let mut file = create_output_file(path, command.force)?;
+                let result = concat_segments(
+                    &command.matched_data_filenames,
+                    &mut file,
+                    false,
+                    &mut decrypt_segment,
+                );
+                // Never leave a truncated payload behind
+                if result.is_err() {
+                    let _ = fs::remove_file(path);
+                }
+                result
+            }
+            None => concat_segments(
+                &command.matched_data_filenames,
+                &mut stdout().lock(),
+                stdout().is_terminal() && !command.force,
+                &mut decrypt_segment,
+            ),
+        };
+    }
+
+    // One JSON line per input, each line of each file being an input, or each file if raw
+    if porcelain.is_some()
+        || (command.output_file.is_none() && output_format.as_deref() == Some(output::NDJSON))
+    {
+        if !command.select.is_empty() || command.output_dir.is_some() || command.copy {
+            return Err(
+                "--select, --output-dir and --copy can't be combined with -o ndjson or --porcelain"
+                    .into(),
+            );
+        }
+        let extractor = command
+            .extract_dir
+            .as_deref()
+            .map(extract::Extractor::open)
+            .transpose()?;
+        return batch::decrypt_all(
+            &command.matched_data_filenames,
+            command.data.as_deref(),
+            &batch::BatchOptions {
+                continue_on_error: command.continue_on_error,
+                verify_only: command.verify_only,
+                raw: is_raw,
+                null: command.null,
+                max_input_size: command.max_input_size,
+                porcelain: porcelain.map(Porcelain::name),
+            },
+            extractor.as_ref(),
+            &mut progress::Progress::new(!quiet && porcelain.is_none()),
+            &|input| {
+                check_input_size(input, command.max_input_size)?;
+                let matched_data = decrypt_input(
+                    input,
+                    &command.input_format,
+                    &key,
+                    &mode,
+                    audit_log.as_ref(),
+                    strict,
+                    &mut DecryptTrace::new(false),
+                )?;
+                Ok(if redactor.is_empty() {
+                    matched_data
+                } else {
+                    redactor.redact(&matched_data)
+                })
+            },
+        );
+    }
+    if command.matched_data_filenames.len() > 1 {
+        return Err("Several matched data files can only be decrypted with -o ndjson".into());
+    }
+    if command.extract_dir.is_some() {
+        return Err("--extract-dir can only be combined with -o ndjson, use --output-file to write a single plaintext".into());
+    }
+    let matched_data_filename = command.matched_data_filenames.first();
+
+    // Files written to --output-dir are named after the input file
+    let output_stem = matched_data_filename
+        .filter(|filename| *filename != "-")
+        .and_then(|filename| Path::new(filename).file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "matched-data".to_string());
+
+    // Validate and construct matched data from input
+    let matched_data_input = match (command.data, matched_data_filename) {
+        (Some(data), _) => data.into_bytes(),
+        (None, Some(filename)) => {
+            read_matched_data_input(filename, is_raw, command.max_input_size)?
+        }
+        (None, None) => {
+            return Err(CliError::new(
+                ErrorCode::MatchedDataMissing,
+                "No matched data provided",
+            ))
+        }
+    };
+
+    check_input_size(&matched_data_input, command.max_input_size)?;
+
+    let mut matched_data = decrypt_input(
+        &matched_data_input,
+        &command.input_format,
+        &key,
+        &mode,
+        audit_log.as_ref(),
+        strict,
+        trace,
+    )?;
+    if !redactor.is_empty() {
+        matched_data = redactor.redact(&matched_data);
+    }
+
+    if command.verify_only {
+        println!(
+            "Matched data decrypts, the plaintext is {} bytes",
+            matched_data.len()
+        );
+        return Ok(());
+    }
+
+    if let Some(output_filename) = command.output_file {
+        write_output_file(Path::new(&output_filename), &matched_data, command.force)?;
+        return Ok(());
+    }
+
+    let content_type = content_type::sniff(&matched_data);
+    if command.copy {
+        if content_type.is_binary() {
+            return Err(format!(
+                "Refusing to copy {} matched data to the clipboard, pass --output-file",
+                content_type.mime_type()
+            )
+            .into());
+        }
+        clipboard::copy(&String::from_utf8_lossy(&matched_data))?;
+        eprintln!(
+            "Copied {} bytes of matched data to the clipboard",
+            matched_data.len()
+        );
+        return Ok(());
+    }
+    // Each plaintext or field ends with a NUL byte with --null, for xargs -0
+    let end = if command.null { '\0' } else { '\n' };
+    if let Some(output_dir) = command.output_dir {
+        if content_type.is_binary() {
+            fs::create_dir_all(&output_dir).map_err(|_| "Failed to create output directory")?;
+            let output_filename = Path::new(&output_dir).join(format!(
+                "{}.{}",
+                output_stem,
+                content_type.extension()
+            ));
+            write_output_file(&output_filename, &matched_data, command.force)?;
+            print!(
+                "Wrote {} bytes of {} to {}{}",
+                matched_data.len(),
+                content_type.mime_type(),
+                output_filename.display(),
+                end
+            );
+
+            return Ok(());
+        }
+    }
+
+    if !command.select.is_empty() {
+        // Strings are printed as is, other values as JSON
+        for field in select::select_fields(&matched_data, &command.select)? {
+            match (field, output_format.as_deref()) {
+                (serde_json::Value::String(field), _) => print!("{}{}", field, end),
+                (field, Some("json-pretty")) => print!(
+                    "{}{}",
+                    serde_json::to_string_pretty(&field).expect("Failed to output matched data"),
+                    end
+                ),
+                (field, _) => print!("{}{}", field, end),
+            }
+        }
+
+        return Ok(());
+    }
+
+    let formatter = output_format
+        .as_deref()
+        .and_then(output::find)
+        .unwrap_or(&output::Utf8Lossy);
+    if formatter.is_raw() {
+        // The Windows console only takes UTF-8, even with --force. Redirected output gets the
+        // bytes unchanged on every platform, as line breaks are never translated
+        if cfg!(windows) && stdout().is_terminal() && str::from_utf8(&matched_data).is_err() {
+            return Err(
+                "The Windows console can't show binary matched data, pass --output-file or redirect the output to a file"
+                    .into(),
+            );
+        }
+        // Control bytes written to a terminal can leave it unusable
+        if stdout().is_terminal() && !content_type::is_text(&matched_data) && !command.force {
+            return Err(format!(
+                "Refusing to write {} matched data to a terminal, pass --output-file, pipe it to hexdump -C or pass --force",
+                content_type.mime_type()
+            )
+            .into());
+        }
+    } else if formatter.is_lossy() && content_type.is_binary() {
+        eprintln!(
+            "Matched data looks like {}, pass --output-dir to write it to a file",
+            content_type.mime_type()
+        );
+    }
+    let mut out = stdout();
+    out.write_all(&formatter.format_record(&matched_data, command.null))
+        .and_then(|_| out.flush())
+        .map_err(|_| "Failed to output matched data")?;
+
+    Ok(())
+}
+
+// Rejects matched data larger than --max-input-size before decoding it
+fn check_input_size(input: &[u8], max_input_size: u64) -> Result<(), CliError> {
+    if input.len() as u64 > max_input_size {
+        return Err(CliError::new(
+            ErrorCode::MatchedDataTooLarge,
+            format!("Matched data is larger than {} bytes", max_input_size),
+        ));
+    }
+    Ok(())
+}
+
+// Reads the matched data of a file, or the first line of stdin for - unless raw, stopping one
+// byte past the maximum size so larger input is never read whole
+fn read_matched_data_input(
+    filename: &str,
+    is_raw: bool,
+    max_input_size: u64,
+) -> Result<Vec<u8>, CliError> {
+    let mut buffer = Vec::new();
+    if filename != "-" {
+        fs::File::open(filename)
+            .and_then(|file| file.take(max_input_size + 1).read_to_end(&mut buffer))
+            .map_err(|_| {
+                CliError::new(
+                    ErrorCode::MatchedDataUnreadable,
+                    "Failed to read matched data from file",
+                )
+            })?;
+        return Ok(buffer);
+    }
+
+    let mut input = stdin().lock().take(max_input_size + 1);
+    let read = if is_raw {
+        input.read_to_end(&mut buffer)
+    } else {
+        input.read_until(b'\n', &mut buffer)
+    };
+    read.map_err(|_| {
+        CliError::new(
+            ErrorCode::MatchedDataUnreadable,
+            "Failed to read matched data from stdin",
+        )
+    })?;
+    Ok(buffer)
+}
+
+// Decrypts the segment of a payload in a file
+type DecryptSegment<'a> = dyn FnMut(&str) -> Result<Zeroizing<Vec<u8>>, CliError> + 'a;
+
+// Decrypts the segments of a payload in order, writing each plaintext once it is decrypted so
+// only one segment is held in memory
+fn concat_segments(
+    filenames: &[String],
+    out: &mut dyn Write,
+    refuse_binary: bool,
+    decrypt_segment: &mut DecryptSegment<'_>,
+) -> Result<(), CliError> {
+    for (index, filename) in filenames.iter().enumerate() {
+        let plaintext = decrypt_segment(filename).map_err(|mut e| {
+            e.message = format!("Segment {} ({}) failed, {}", index + 1, filename, e.message);
+            e
+        })?;
+        // Control bytes written to a terminal can leave it unusable
+        if refuse_binary && !content_type::is_text(&plaintext) {
+            return Err(
+                "Refusing to write binary segments to a terminal, pass --output-file or --force"
+                    .into(),
+            );
+        }
+        out.write_all(&plaintext)
+            .map_err(|_| "Failed to output matched data")?;
+    }
+    out.flush().map_err(|_| "Failed to output matched data")?;
+    Ok(())
+}
+
+// Creates an output file, only replacing an existing one when forced
+fn create_output_file(path: &Path, force: bool) -> Result<fs::File, String> {
+    if force {
+        fs::File::create(path)
+    } else {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+    }
+    .map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => format!(
+            "Output file {} already exists, pass --force to overwrite it",
+            path.display()
+        ),
+        _ => "Failed to create output file".to_string(),
+    })
+}
+
+// Writes decrypted matched data to a file, only replacing an existing one when forced
+fn write_output_file(path: &Path, matched_data: &[u8], force: bool) -> Result<(), String> {
+    let mut file = create_output_file(path, force)?;
+    file.write_all(matched_data)
+        .map_err(|_| "Failed to write matched data to file".to_string())
+}
+
+fn run(options: Options) -> Result<(), CliError> {
+    let config = config::load(options.config.as_deref())?;
+    let audit_log_filename = options.audit_log.or_else(|| config.audit_log.clone());
+    let cache_dir = options.cache_dir.or_else(|| config.cache_dir.clone());
+    let strict = options.strict;
+    let quiet = options.quiet;
+    let porcelain = options.porcelain;
+    memory::set_locking(!options.no_mlock);
+    expiry::set_enforced(options.enforce_expiry || config.enforce_expiry);
+
+    if options.supported_versions {
+        print_versions(&TextJsonFormat::Text);
+        return Ok(());
+    }
+
+    let command = match options.command {
+        Some(command) => command,
+        None => Options::command()
+            .error(
+                clap::ErrorKind::MissingSubcommand,
+                "A subcommand is required unless --supported-versions is passed",
+            )
+            .exit(),
+    };
+
+    match command {
+        Command::GenerateKeyPair(command) => {
+            if command.qr_file.is_some()
+                && !matches!(command.output_format, KeyPairOutputFormat::Qr)
+            {
+                return Err("--qr-file requires -o qr".into());
+            }
+            if porcelain.is_some() && !matches!(command.output_format, KeyPairOutputFormat::Json) {
+                return Err("--porcelain can only output key pairs as JSON".into());
+            }
+            if (command.label.is_some() || command.expires_in.is_some())
+                && (!matches!(command.output_format, KeyPairOutputFormat::Json)
+                    || command.private_key_file.is_some())
+            {
+                return Err(
+                    "--label and --expires-in are stored in the JSON key pair, they can't be combined with -o or --private-key-file"
+                        .into(),
+                );
+            }
+
+            // Generate key pair
+            let (private_key, public_key) = generate_key_pair();
+
+            let fingerprint = key_format::fingerprint(&public_key.to_bytes());
+            let key_pair = KeyPair {
+                private_key: radix64::STD.encode(&private_key.to_bytes()),
+                public_key: radix64::STD.encode(&public_key.to_bytes()),
+                label: command.label,
+                key_id: Some(fingerprint.clone()),
+                expires_at: command
+                    .expires_in
+                    .map(|valid_for| expiry::expires_at(valid_for, SystemTime::now())),
+            };
+            // On stderr so the printed key pair can still be parsed or redirected
+            if porcelain.is_none() {
+                eprintln!("Key fingerprint: {}", fingerprint);
+            }
+            // Copied before the key pair is output, so a missing clipboard tool fails early
+            if command.copy {
+                clipboard::copy(&key_pair.public_key)?;
+                if porcelain.is_none() {
+                    eprintln!("Copied public key to the clipboard");
+                }
+            }
+            let print_porcelain = |porcelain: Porcelain, private_key: Option<&str>| {
+                let record = Zeroizing::new(
+                    serde_json::to_string(&PorcelainKeyPair {
+                        porcelain: porcelain.name(),
+                        private_key,
+                        public_key: &key_pair.public_key,
+                        fingerprint: fingerprint.clone(),
+                        label: key_pair.label.as_deref(),
+                        expires_at: key_pair.expires_at.as_deref(),
+                    })
+                    .expect("Failed to output key pair"),
+                );
+                println!("{}", *record);
+            };
+
+            if let (Some(private_key_filename), Some(public_key_filename)) =
+                (command.private_key_file, command.public_key_file)
+            {
+                // Create both files first so neither is left behind if one already exists
+                let mut private_key_file = create_private_file(&private_key_filename)?;
+                let mut public_key_file =
+                    create_private_file(&public_key_filename).inspect_err(|_| {
+                        let _ = fs::remove_file(&private_key_filename);
+                    })?;
+                writeln!(private_key_file, "{}", key_pair.private_key)
+                    .map_err(|_| "Failed to write private key to file")?;
+                writeln!(public_key_file, "{}", key_pair.public_key)
+                    .map_err(|_| "Failed to write public key to file")?;
+
+                if let Some(porcelain) = porcelain {
+                    print_porcelain(porcelain, None);
+                }
+                return Ok(());
+            }
+            if let Some(porcelain) = porcelain {
+                print_porcelain(porcelain, Some(&key_pair.private_key));
+                return Ok(());
+            }
+
+            match command.output_format {
+                KeyPairOutputFormat::Json => {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&key_pair).expect("Failed to output key pair")
+                    );
+                }
+                KeyPairOutputFormat::Mnemonic => {
+                    println!(
+                        "Private key: {}",
+                        key_format::to_mnemonic(&private_key.to_bytes())
+                    );
+                    println!("Public key: {}", key_pair.public_key);
+                }
+                KeyPairOutputFormat::Env => {
+                    println!("export PRIVATE_KEY='{}'", key_pair.private_key);
+                    println!("export PUBLIC_KEY='{}'", key_pair.public_key);
+                }
+                KeyPairOutputFormat::Dotenv => {
+                    println!("PRIVATE_KEY=\"{}\"", key_pair.private_key);
+                    println!("PUBLIC_KEY=\"{}\"", key_pair.public_key);
+                }
+                KeyPairOutputFormat::Pem => {
+                    println!(
+                        "{}",
+                        key_format::to_pem_private_key(&private_key.to_bytes())
+                    );
+                    println!("{}", key_format::to_pem_public_key(&public_key.to_bytes()));
+                }
+                KeyPairOutputFormat::Qr => {
+                    let passphrase = passphrase::read(true)?;
+                    let encrypted_key =
+                        key_format::to_encrypted_key(&private_key.to_bytes(), &passphrase)?;
+                    match command.qr_file {
+                        Some(qr_filename) => {
+                            qr::write_png(&encrypted_key, create_private_file(&qr_filename)?)
+                                .inspect_err(|_| {
+                                    let _ = fs::remove_file(&qr_filename);
+                                })?
+                        }
+                        None => println!("{}", qr::to_terminal(&encrypted_key)?),
+                    }
+                    println!("Public key: {}", key_pair.public_key);
+                }
+            }
+        }
+        Command::Decrypt(command) => {
+            let mut trace = DecryptTrace::new(command.verbose);
+            decrypt(
+                command,
+                &config,
+                audit_log_filename.as_deref(),
+                strict,
+                quiet,
+                porcelain,
+                &mut trace,
+            )?;
+        }
+        Command::GenerateYubikeyKey(command) => {
+            let public_key_bytes = yubikey::generate(&yubikey::Slot::parse(&command.slot)?)?;
+            println!("{}", radix64::STD.encode(&public_key_bytes));
+        }
+        Command::Encrypt(command) => {
+            let public_keys = read_public_keys(&command.public_key_filenames, strict)?;
+            let sender_private_key = match &command.sender_key {
+                Some(filename) => Some(
+                    matched_data::get_private_key_from_bytes(&read_private_key(
+                        filename,
+                        strict,
+                        &mut DecryptTrace::new(false),
+                    )?)
+                    .map_err(|_| "Provided sender private key is invalid")?,
+                ),
+                None => None,
+            };
+            let psk = command
+                .psk_file
+                .as_deref()
+                .map(|filename| read_psk(filename, strict))
+                .transpose()?;
+            let psk = psk_bundle(
+                psk.as_ref().map(|psk| psk.as_slice()),
+                command.psk_id.as_deref(),
+            );
+            let aad = read_aad(command.aad, command.aad_file.as_deref())?;
+
+            let mut matched_data = Vec::new();
+            if command.matched_data_filename == "-" {
+                io::Read::read_to_end(&mut stdin(), &mut matched_data)
+                    .map_err(|_| "Failed to read matched data from stdin")?;
+            } else {
+                matched_data = fs::read(&command.matched_data_filename)
+                    .map_err(|_| "Failed to read matched data from file")?;
+            }
+
+            // One blob per recipient, in the order the public keys were given
+            for public_key in &public_keys {
+                let encrypted_data = matched_data::encrypt_data_in_mode(
+                    &matched_data,
+                    public_key,
+                    sender_private_key.as_ref(),
+                    psk.as_ref(),
+                    &aad,
+                )
+                .map_err(|_| "Failed to encrypt matched data")?;
+                let serialized = matched_data::serialize_encrypted_data(&encrypted_data);
+                if command.armor {
+                    println!("{}", encoding::armor(&serialized));
+                } else {
+                    println!("{}", radix64::STD.encode(&serialized));
+                }
+            }
+        }
+        Command::Reencrypt(command) => {
+            let public_keys = read_public_keys(&command.public_key_filenames, strict)?;
+            let private_key_bytes = read_private_key(
+                &config.private_key_filename(command.private_key_filename)?,
+                strict,
+                &mut DecryptTrace::new(false),
+            )?;
+
+            let audit_log = open_audit_log(
+                audit_log_filename.as_deref(),
+                "reencrypt",
+                &private_key_bytes,
+            )?;
+            let decryptor =
+                private_key_decryptor(&private_key_bytes, &mut DecryptTrace::new(false))?;
+
+            let mut encrypted_matched_data = Zeroizing::new(String::new());
+            if command.matched_data_filename == "-" {
+                io::Read::read_to_string(&mut stdin(), &mut encrypted_matched_data)
+                    .map_err(|_| "Failed to read matched data from stdin")?;
+            } else {
+                *encrypted_matched_data = fs::read_to_string(&command.matched_data_filename)
+                    .map_err(|_| "Failed to read matched data from file")?;
+            }
+
+            for public_key in &public_keys {
+                println!(
+                    "{}",
+                    reencrypt_matched_data(
+                        &decryptor,
+                        encrypted_matched_data.trim(),
+                        public_key,
+                        audit_log.as_ref(),
+                        strict,
+                    )?
+                );
+            }
+        }
+        Command::Rewrap(command) => {
+            let public_keys = read_public_keys(&[command.public_key_filename], strict)?;
+            let private_key_bytes = read_private_key(
+                &config.private_key_filename(command.private_key_filename)?,
+                strict,
+                &mut DecryptTrace::new(false),
+            )?;
+
+            let audit_log =
+                open_audit_log(audit_log_filename.as_deref(), "rewrap", &private_key_bytes)?;
+            let decryptor =
+                private_key_decryptor(&private_key_bytes, &mut DecryptTrace::new(false))?;
+
+            let report = rewrap::rewrap(
+                Path::new(&command.input),
+                Path::new(&command.output),
+                &|encrypted_matched_data| {
+                    reencrypt_matched_data(
+                        &decryptor,
+                        encrypted_matched_data,
+                        &public_keys[0],
+                        audit_log.as_ref(),
+                        strict,
+                    )
+                    .map_err(|e| e.message)
+                },
+            )?;
+            tracing::info!(
+                lines = report.lines,
+                rewrapped = report.rewrapped,
+                failures = report.failures,
+                "Rewrapped archive"
+            );
+            if report.failures > 0 {
+                return Err(format!(
+                    "Failed to rewrap {} of {} lines, left unchanged",
+                    report.failures, report.lines
+                )
+                .into());
+            }
+        }
+        Command::ImportKey(command) => {
+            let key =
+                read_text_file(&command.key_filename).ok_or("Failed to read key from file")?;
+
+            let from = match command.from {
+                ImportKeyFormat::Auto if key.contains("AGE-SECRET-KEY-1") => ImportKeyFormat::Age,
+                ImportKeyFormat::Auto if key.contains(key_format::ENCRYPTED_KEY_PREFIX) => {
+                    ImportKeyFormat::Qr
+                }
+                ImportKeyFormat::Auto => ImportKeyFormat::Ssh,
+                from => from,
+            };
+            let private_key_bytes = match from {
+                ImportKeyFormat::Age => Zeroizing::new(key_format::from_age_identity(&key)?),
+                ImportKeyFormat::Qr => {
+                    key_format::from_encrypted_key(&key, &passphrase::read(false)?)?
+                }
+                _ => Zeroizing::new(key_format::from_openssh_ed25519(&key)?),
+            };
+
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&key_pair_from_private_key(&private_key_bytes)?)
+                    .expect("Failed to output key pair")
+            );
+        }
+        Command::ExportKey(command) => {
+            let private_key_bytes = read_private_key(
+                &config.private_key_filename(command.private_key_filename)?,
+                strict,
+                &mut DecryptTrace::new(false),
+            )?;
+            matched_data::get_private_key_from_bytes(&private_key_bytes)
+                .map_err(|_| "Provided private key is invalid")?;
+
+            println!("{}", key_format::to_age_identity(&private_key_bytes));
+        }
+        Command::SplitKey(command) => {
+            let private_key_bytes = read_private_key(
+                &config.private_key_filename(command.private_key_filename)?,
+                strict,
+                &mut DecryptTrace::new(false),
+            )?;
+
+            let shares =
+                key_format::split_key(&private_key_bytes, command.threshold, command.shares)?;
+            for share in shares.iter() {
+                println!("{}", share);
+            }
+        }
+        Command::CombineKey(command) => {
+            let shares = Zeroizing::new(
+                command
+                    .share_filenames
+                    .iter()
+                    .map(|filename| {
+                        fs::read_to_string(filename).map_err(|_| {
+                            format!("Failed to read private key share from {}", filename)
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+            let private_key_bytes = key_format::combine_key(&shares)?;
+
+            println!("{}", radix64::STD.encode(private_key_bytes.as_slice()));
+        }
+        Command::Fingerprint(command) => {
+            let public_key_bytes = match command.public_key_filename {
+                Some(public_key_filename) => {
+                    let public_key_bytes = read_public_key(&public_key_filename, strict)?;
+                    matched_data::get_public_key_from_bytes(&public_key_bytes)
+                        .map_err(|_| "Provided public key is invalid")?;
+                    public_key_bytes
+                }
+                None => {
+                    let private_key_bytes = read_private_key(
+                        &config.private_key_filename(command.private_key_filename)?,
+                        strict,
+                        &mut DecryptTrace::new(false),
+                    )?;
+                    let private_key = matched_data::get_private_key_from_bytes(&private_key_bytes)
+                        .map_err(|_| "Provided private key is invalid")?;
+                    matched_data::get_public_key(&private_key)
+                        .to_bytes()
+                        .to_vec()
+                }
+            };
+
+            println!("{}", key_format::fingerprint(&public_key_bytes));
+        }
+        Command::Key(command) => match command.command {
+            KeyCommand::Store(command) => {
+                let private_key_bytes = read_private_key(
+                    &config.private_key_filename(command.private_key_filename)?,
+                    strict,
+                    &mut DecryptTrace::new(false),
+                )?;
+                let private_key = matched_data::get_private_key_from_bytes(&private_key_bytes)
+                    .map_err(|_| "Provided private key is invalid")?;
+                let private_key_base64 =
+                    Zeroizing::new(radix64::STD.encode(private_key_bytes.as_slice()));
+                keychain::store(&command.label, &private_key_base64)?;
+
+                eprintln!(
+                    "Saved private key {} with fingerprint {}, decrypt with --key-id {} and delete the private key file",
+                    command.label,
+                    key_format::fingerprint(&matched_data::get_public_key(&private_key).to_bytes()),
+                    command.label
+                );
+            }
+            KeyCommand::Load(command) => {
+                let private_key_base64 = keychain::load(&command.label)?;
+                println!("{}", private_key_base64.as_str());
+            }
+            KeyCommand::Export(command) => {
+                if command.private_key_filenames.is_empty() && command.key_ids.is_empty() {
+                    return Err("No keys to export, pass -k or --key-id".into());
+                }
+
+                let mut keys = Vec::new();
+                for filename in &command.private_key_filenames {
+                    let label = Path::new(filename)
+                        .file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    keychain::check_label(&label)?;
+                    let private_key_bytes =
+                        read_private_key(filename, strict, &mut DecryptTrace::new(false))?;
+                    // Key files are written once, so their modification time is a fallback for
+                    // file systems that don't record creation times
+                    let created = fs::metadata(filename)
+                        .and_then(|metadata| metadata.created().or_else(|_| metadata.modified()))
+                        .ok()
+                        .map(|time| humantime::format_rfc3339_seconds(time).to_string());
+                    let key_pair = key_pair_from_private_key(&private_key_bytes)?;
+                    let expires_at = read_key_pair_file(filename)?
+                        .and_then(|key_pair| key_pair.expires_at.clone());
+                    keys.push(key_archive::ArchivedKey {
+                        label,
+                        private_key: key_pair.private_key.clone(),
+                        public_key: key_pair.public_key.clone(),
+                        key_id: key_pair.key_id.clone(),
+                        created,
+                        expires_at,
+                    });
+                }
+                for label in &command.key_ids {
+                    let private_key_bytes = keychain::read_private_key(label, strict)?;
+                    let key_pair = key_pair_from_private_key(&private_key_bytes)?;
+                    keys.push(key_archive::ArchivedKey {
+                        label: label.clone(),
+                        private_key: key_pair.private_key.clone(),
+                        public_key: key_pair.public_key.clone(),
+                        key_id: key_pair.key_id.clone(),
+                        created: None,
+                        expires_at: None,
+                    });
+                }
+
+                let archive = key_archive::seal(&keys, &passphrase::read(true)?)?;
+                let mut file = create_private_file(&command.out)?;
+                file.write_all(&archive).map_err(|_| {
+                    let _ = fs::remove_file(&command.out);
+                    "Failed to write key archive to file"
+                })?;
+                eprintln!("Exported {} keys to {}", keys.len(), command.out);
+            }
+            KeyCommand::Import(command) => {
+                let archive = fs::read(&command.archive_filename)
+                    .map_err(|_| "Failed to read key archive from file")?;
+                let keys = key_archive::open(&archive, &passphrase::read(false)?)?;
+
+                if command.keychain {
+                    for key in &keys {
+                        keychain::store(&key.label, &key.private_key)?;
+                        eprintln!(
+                            "Restored private key {} with fingerprint {}",
+                            key.label,
+                            key_format::fingerprint(
+                                &radix64::STD
+                                    .decode(&key.public_key)
+                                    .map_err(|_| "Provided key archive is invalid")?
+                            )
+                        );
+                    }
+                } else {
+                    println!(
+                        "{}",
+                        Zeroizing::new(
+                            serde_json::to_string_pretty(&keys).expect("Failed to output keys")
+                        )
+                        .as_str()
+                    );
+                }
+            }
+            KeyCommand::List(command) => list_keys(command, strict)?,
+        },
+        Command::Cache(command) => match command.command {
+            CacheCommand::Purge => {
+                let cache_dir = cache_dir.ok_or(
+                    "No cache directory provided, pass --cache-dir or set cache_dir in the configuration file",
+                )?;
+                let (entries, bytes) = cache::purge(&cache_dir)?;
+                eprintln!(
+                    "Purged {} entries, {} bytes, from {}",
+                    entries, bytes, cache_dir
+                );
+            }
+        },
+        #[cfg(feature = "api")]
+        Command::VerifyRemoteKey(command) => {
+            let private_key_bytes = read_private_key(
+                &config.private_key_filename(command.private_key_filename)?,
+                strict,
+                &mut DecryptTrace::new(false),
+            )?;
+            let key_pair = key_pair_from_private_key(&private_key_bytes)?;
+
+            let zone_id = command.zone_id.or(config.zone_id).ok_or(
+                "No zone provided, pass --zone-id or set zone_id in the configuration file",
+            )?;
+            let api_token = std::env::var("CLOUDFLARE_API_TOKEN")
+                .ok()
+                .or(config.api_token)
+                .ok_or("No API token provided, set CLOUDFLARE_API_TOKEN or api_token in the configuration file")?;
+
+            let public_keys = cloudflare::fetch_payload_logging_public_keys(&api_token, &zone_id)?;
+            if public_keys.is_empty() {
+                return Err("Payload logging is not configured on the zone".into());
+            }
+            if !public_keys.contains(&key_pair.public_key) {
+                return Err(format!(
+                    "Private key cannot decrypt new events, its public key {} does not match the zone's public key {}",
+                    key_pair.public_key,
+                    public_keys.join(", ")
+                )
+                .into());
+            }
+
+            println!(
+                "Private key can decrypt new events, its public key {} matches the zone's",
+                key_pair.public_key
+            );
+        }
+        #[cfg(feature = "self-update")]
+        Command::SelfUpdate(command) => {
+            let release = self_update::fetch_release(command.tag.as_deref())?;
+            if command.tag.is_none() && !release.is_newer() {
+                println!(
+                    "matched-data-cli {} is the latest release",
+                    env!("CARGO_PKG_VERSION")
+                );
+                return Ok(());
+            }
+            if command.check {
+                println!("Release {} is available", release.tag);
+                return Ok(());
+            }
+
+            let binary = self_update::download_binary(&release)?;
+            let executable = self_update::replace_executable(&binary)?;
+            println!("Updated {} to {}", executable.display(), release.tag);
+        }
+        Command::SelfTest => {
+            let steps = self_test::run(&|private_key_bytes, encrypted_matched_data_bytes| {
+                let mut trace = DecryptTrace::new(false);
+                decrypt_matched_data(
+                    &*private_key_decryptor(private_key_bytes, &mut trace)?,
+                    encrypted_matched_data_bytes,
+                    &matched_data::Mode::default(),
+                    &mut trace,
+                )
+                .map_err(String::from)
+            });
+
+            let failed = steps.iter().filter(|step| step.result.is_err()).count();
+            for step in &steps {
+                match &step.result {
+                    Ok(()) => println!("pass: {}", step.name),
+                    Err(e) => println!("FAIL: {} ({})", step.name, e),
+                }
+            }
+            if failed > 0 {
+                return Err(format!("{} self-test step(s) failed", failed).into());
+            }
+        }
+        Command::Bench(command) => {
+            let jobs = command.jobs.unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, |jobs| jobs.get())
+            });
+            let report = bench::run(command.count, command.size, jobs)?;
+            match command.output_format {
+                TextJsonFormat::Text => print!("{}", report.to_text()),
+                TextJsonFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).expect("Failed to output benchmark")
+                ),
+            }
+        }
+        Command::Versions(command) => print_versions(&command.output_format),
+        Command::GenerateTestVectors => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&test_vectors::generate())
+                    .expect("Failed to output test vectors")
+            );
+        }
+        Command::Doctor(command) => {
+            let private_key_filename = config.private_key_filename(command.private_key_filename)?;
+            let private_key = read_text_file(&private_key_filename)
+                .ok_or("Failed to read private key from file")?;
+            let private_key = match parse_key_pair_file(&private_key)? {
+                Some(key_pair) => Zeroizing::new(key_pair.private_key.clone()),
+                None => private_key,
+            };
+            let matched_data = read_text_file(&command.matched_data_filename)
+                .ok_or("Failed to read matched data from file")?;
+
+            let findings = doctor::diagnose(
+                &private_key_filename,
+                &private_key,
+                &command.matched_data_filename,
+                &matched_data,
+            );
+
+            report_findings(
+                &findings,
+                "No problems found, the matched data decrypts with this private key",
+            )?;
+        }
+        Command::Diff(command) => {
+            let private_key_bytes = read_private_key(
+                &config.private_key_filename(command.private_key_filename)?,
+                strict,
+                &mut DecryptTrace::new(false),
+            )?;
+            let audit_log =
+                open_audit_log(audit_log_filename.as_deref(), "diff", &private_key_bytes)?;
+            let key = DecryptionKey::PrivateKey(private_key_decryptor(
+                &private_key_bytes,
+                &mut DecryptTrace::new(false),
+            )?);
+
+            let mut matched_data_input = Vec::new();
+            if command.matched_data_filename == "-" {
+                io::Read::read_to_end(&mut stdin(), &mut matched_data_input)
+                    .map_err(|_| "Failed to read matched data from stdin")?;
+            } else {
+                matched_data_input = fs::read(&command.matched_data_filename)
+                    .map_err(|_| "Failed to read matched data from file")?;
+            }
+            let expected = Zeroizing::new(
+                fs::read(&command.expected_filename)
+                    .map_err(|_| "Failed to read expected plaintext from file")?,
+            );
+
+            let matched_data = decrypt_input(
+                &matched_data_input,
+                &DecryptInputFormat::Auto,
+                &key,
+                &matched_data::Mode::default(),
+                audit_log.as_ref(),
+                strict,
+                &mut DecryptTrace::new(false),
+            )?;
+
+            // Exits with an error when they differ, as diff does, so scripts can check the status
+            match diff::first_difference(&expected, &matched_data) {
+                None => {
+                    if !quiet {
+                        eprintln!(
+                            "Matched data is identical to {}, {} bytes",
+                            command.expected_filename,
+                            matched_data.len()
+                        );
+                    }
+                }
+                Some(offset) => {
+                    let diff = diff::diff(
+                        &expected,
+                        &matched_data,
+                        (&command.expected_filename, "decrypted matched data"),
+                        command.bytes,
+                    );
+                    stdout()
+                        .write_all(diff.as_bytes())
+                        .map_err(|_| "Failed to output diff")?;
+                    return Err(format!(
+                        "Matched data differs from {} at byte {}, {} bytes decrypted and {} expected",
+                        command.expected_filename,
+                        offset,
+                        matched_data.len(),
+                        expected.len()
+                    )
+                    .into());
+                }
+            }
+        }
+        Command::ValidatePublicKey(command) => {
+            let public_key = read_text_file(&command.public_key_filename)
+                .ok_or("Failed to read public key from file")?;
+
+            let findings = doctor::diagnose_public_key(&command.public_key_filename, &public_key);
+
+            report_findings(
+                &findings,
+                "No problems found, matched data can be encrypted to this public key",
+            )?;
+        }
+        Command::Logs(command) => {
+            let log_keys = LogKeys::open(
+                &config,
+                command.private_key_filename,
+                "logs",
+                audit_log_filename.as_deref(),
+                cache_dir.as_deref(),
+                strict,
+            )?;
+            let keys = log_keys.decrypts(strict);
+
+            if command.follow && archive::is_archive(&command.log_filename) {
+                return Err("Archives can't be followed, pass a log file with --follow".into());
+            }
+            let r2_account_id = std::env::var("R2_ACCOUNT_ID").ok().or(config.r2_account_id);
+            let log = open_log(&command.log_filename, r2_account_id.as_deref())?;
+            let extractor = command
+                .extract_dir
+                .as_deref()
+                .map(extract::Extractor::open)
+                .transpose()?;
+            #[cfg(feature = "api")]
+            let mut sink = open_sink(command.sink)?;
+            #[cfg(not(feature = "api"))]
+            let mut sink: Box<dyn sink::Sink> = Box::new(stdout().lock());
+            logs::process_log(
+                log,
+                &logs::LogOptions {
+                    follow: command.follow,
+                    jobs: command.jobs,
+                    continue_on_error: command.continue_on_error,
+                },
+                extractor.as_ref(),
+                &mut progress::Progress::new(!quiet),
+                sink.as_mut(),
+                &keys,
+            )?;
+        }
+        Command::Pipe(command) => {
+            let log_keys = LogKeys::open(
+                &config,
+                command.private_key_filename,
+                "pipe",
+                audit_log_filename.as_deref(),
+                cache_dir.as_deref(),
+                strict,
+            )?;
+            let keys = log_keys.decrypts(strict);
+
+            #[cfg(feature = "api")]
+            let mut sink = open_sink(command.sink)?;
+            #[cfg(not(feature = "api"))]
+            let mut sink: Box<dyn sink::Sink> = Box::new(stdout().lock());
+            logs::pipe(
+                stdin().lock(),
+                sink.as_mut(),
+                command.continue_on_error,
+                &keys,
+            )?;
+        }
+        Command::Csv(command) => {
+            let private_key_bytes = read_private_key(
+                &config.private_key_filename(command.private_key_filename)?,
+                strict,
+                &mut DecryptTrace::new(false),
+            )?;
+
+            let audit_log =
+                open_audit_log(audit_log_filename.as_deref(), "csv", &private_key_bytes)?;
+            let cache = open_cache(cache_dir.as_deref(), &private_key_bytes)?;
+            let decryptor =
+                private_key_decryptor(&private_key_bytes, &mut DecryptTrace::new(false))?;
+            let decrypt = |encrypted_matched_data: &str| {
+                decrypt_base64_matched_data(
+                    &decryptor,
+                    encrypted_matched_data,
+                    audit_log.as_ref(),
+                    cache.as_ref(),
+                    strict,
+                )
+                .map_err(String::from)
+            };
+
+            if command.csv_filename == "-" {
+                csv::process_csv(
+                    stdin().lock(),
+                    command.column.as_deref(),
+                    command.continue_on_error,
+                    &mut progress::Progress::new(!quiet),
+                    &decrypt,
+                )?;
+            } else {
+                let file =
+                    fs::File::open(&command.csv_filename).map_err(|_| "Failed to read CSV file")?;
+                csv::process_csv(
+                    BufReader::new(file),
+                    command.column.as_deref(),
+                    command.continue_on_error,
+                    &mut progress::Progress::new(!quiet),
+                    &decrypt,
+                )?;
+            }
+        }
+        Command::Stats(command) => {
+            let key_filenames = match command.private_key_filenames.is_empty() {
+                true => config.private_key.clone().into_iter().collect(),
+                false => command.private_key_filenames,
+            };
+            let mut decryptors = Vec::new();
+            for filename in &key_filenames {
+                let private_key_bytes =
+                    read_private_key(filename, strict, &mut DecryptTrace::new(false))?;
+                let audit_log =
+                    open_audit_log(audit_log_filename.as_deref(), "stats", &private_key_bytes)?;
+                decryptors.push((
+                    private_key_decryptor(&private_key_bytes, &mut DecryptTrace::new(false))?,
+                    audit_log,
+                ));
+            }
+            // Plaintexts are only measured, never cached or printed
+            let decrypts: Vec<Box<logs::Decrypt<'_>>> = decryptors
+                .iter()
+                .map(|(decryptor, audit_log)| {
+                    Box::new(move |encrypted_matched_data: &str| {
+                        decrypt_base64_matched_data(
+                            decryptor,
+                            encrypted_matched_data,
+                            audit_log.as_ref(),
+                            None,
+                            strict,
+                        )
+                        .map_err(String::from)
+                    }) as Box<logs::Decrypt<'_>>
+                })
+                .collect();
+            let keys: Vec<&logs::Decrypt<'_>> =
+                decrypts.iter().map(|decrypt| decrypt.as_ref()).collect();
+
+            let r2_account_id = std::env::var("R2_ACCOUNT_ID").ok().or(config.r2_account_id);
+            let mut stats = stats::Stats::new(&key_filenames);
+            for log_filename in &command.log_filenames {
+                stats
+                    .add_log(open_log(log_filename, r2_account_id.as_deref())?, &keys)
+                    .map_err(|e| format!("{}: {}", log_filename, e))?;
+            }
+
+            match command.output_format {
+                TextJsonFormat::Text => print!("{}", stats.to_text()),
+                TextJsonFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&stats).expect("Failed to output stats")
+                ),
+            }
+        }
+        #[cfg(feature = "browse")]
+        Command::Browse(command) => {
+            let private_key_bytes = read_private_key(
+                &config.private_key_filename(command.private_key_filename)?,
+                strict,
+                &mut DecryptTrace::new(false),
+            )?;
+
+            let file = fs::File::open(&command.events_filename)
+                .map_err(|_| "Failed to read events file")?;
+            let events = browse::load_events(logs::decompress(BufReader::new(file))?)?;
+
+            let audit_log =
+                open_audit_log(audit_log_filename.as_deref(), "browse", &private_key_bytes)?;
+            let cache = open_cache(cache_dir.as_deref(), &private_key_bytes)?;
+            let decryptor =
+                private_key_decryptor(&private_key_bytes, &mut DecryptTrace::new(false))?;
+            let decrypt = |encrypted_matched_data: &str| {
+                decrypt_base64_matched_data(
+                    &decryptor,
+                    encrypted_matched_data,
+                    audit_log.as_ref(),
+                    cache.as_ref(),
+                    strict,
+                )
+                .map_err(String::from)
+            };
+
+            browse::browse(&events, &decrypt)?;
+        }
+        #[cfg(feature = "serve")]
+        Command::Serve(command) => {
+            let private_key_bytes = read_private_key(
+                &config.private_key_filename(command.private_key_filename)?,
+                strict,
+                &mut DecryptTrace::new(false),
+            )?;
+
+            let audit_log =
+                open_audit_log(audit_log_filename.as_deref(), "serve", &private_key_bytes)?;
+            let cache = open_cache(cache_dir.as_deref(), &private_key_bytes)?;
+            let decryptor =
+                private_key_decryptor(&private_key_bytes, &mut DecryptTrace::new(false))?;
+            let formatter =
+                output::find(&command.output_format).expect("Output format was validated");
+            let decrypt = |encrypted_matched_data: &str| {
+                decrypt_base64_matched_data(
+                    &decryptor,
+                    encrypted_matched_data,
+                    audit_log.as_ref(),
+                    cache.as_ref(),
+                    strict,
+                )
+                .map(|matched_data| formatter.format(&matched_data))
+            };
+
+            let limits = serve::Limits {
+                max_in_flight: command.max_in_flight,
+                rate_limit: command.rate_limit,
+                max_body_size: command.max_body_size,
+            };
+
+            #[cfg(unix)]
+            if let Some(path) = command.unix_socket {
+                return serve::serve_unix(&path, &limits, &decrypt).map_err(CliError::from);
+            }
+
+            let tls = command
+                .tls_cert
+                .as_deref()
+                .zip(command.tls_key.as_deref())
+                .map(
+                    |(certificate_filename, private_key_filename)| serve::TlsFiles {
+                        certificate_filename,
+                        private_key_filename,
+                    },
+                );
+            let auth_token = command
+                .auth_token_file
+                .as_deref()
+                .map(serve::read_auth_token)
+                .transpose()?;
+            serve::serve(
+                &command.listen,
+                command.allow_remote,
+                tls,
+                auth_token.as_deref().map(String::as_str),
+                &limits,
+                &decrypt,
+            )?;
+        }
+        #[cfg(feature = "serve")]
+        Command::Watch(command) => {
+            let log_keys = LogKeys::open(
+                &config,
+                command.private_key_filename,
+                "watch",
+                audit_log_filename.as_deref(),
+                cache_dir.as_deref(),
+                strict,
+            )?;
+            let keys = log_keys.decrypts(strict);
+
+            // Checked before any notification is received, as they would all fail
+            if command.jobs == 0 {
+                return Err("Number of jobs must be at least 1".into());
+            }
+            let r2_account_id = std::env::var("R2_ACCOUNT_ID").ok().or(config.r2_account_id);
+            let options = watch::WatchOptions {
+                buckets: &command.bucket,
+                r2_account_id: r2_account_id.as_deref(),
+                log: logs::LogOptions {
+                    follow: false,
+                    jobs: command.jobs,
+                    continue_on_error: command.continue_on_error,
+                },
+            };
+            #[cfg(feature = "api")]
+            let mut sink = open_sink(command.sink)?;
+            #[cfg(not(feature = "api"))]
+            let mut sink: Box<dyn sink::Sink> = Box::new(stdout().lock());
+            if let Some(queue_url) = command.sqs_queue_url {
+                watch::watch_sqs(
+                    &queue_url,
+                    command.until_empty,
+                    &options,
+                    sink.as_mut(),
+                    &keys,
+                )?;
+            } else {
+                let address = command
+                    .webhook
+                    .expect("Webhook address is required without a queue URL");
+                let auth_token = command
+                    .auth_token_file
+                    .as_deref()
+                    .map(serve::read_auth_token)
+                    .transpose()?;
+                watch::watch_webhook(
+                    &address,
+                    command.allow_remote,
+                    auth_token.as_deref().map(String::as_str),
+                    &options,
+                    sink.as_mut(),
+                    &keys,
+                )?;
+            }
+        }
+        #[cfg(feature = "kafka")]
+        Command::Kafka(command) => {
+            let mut properties = match &command.properties_file {
+                Some(filename) => kafka::parse_properties_file(&Zeroizing::new(
+                    fs::read_to_string(filename).map_err(|_| {
+                        format!("Failed to read Kafka properties file {}", filename)
+                    })?,
+                ))?,
+                None => Vec::new(),
+            };
+            for property in &command.properties {
+                properties.push(kafka::parse_property(property)?);
+            }
+
+            let log_keys = LogKeys::open(
+                &config,
+                command.private_key_filename,
+                "kafka",
+                audit_log_filename.as_deref(),
+                cache_dir.as_deref(),
+                strict,
+            )?;
+            let keys = log_keys.decrypts(strict);
+
+            kafka::run(
+                &kafka::KafkaOptions {
+                    brokers: &command.brokers,
+                    input_topic: &command.input_topic,
+                    output_topic: &command.output_topic,
+                    group_id: &command.group_id,
+                    properties: &properties,
+                },
+                command.continue_on_error,
+                &keys,
+            )?;
+        }
+        #[cfg(feature = "serve")]
+        Command::ServeUi(command) => serve::serve_ui(&command.listen, &command.wasm_dir)?,
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), String> {
+    let options = Options::parse();
+    // Wrapper scripts parsing porcelain output get errors they can parse too
+    let error_format = match options.porcelain {
+        Some(_) => ErrorFormat::Json,
+        None => options.error_format.clone(),
+    };
+    logging::init(options.log_level, options.log_format);
+
+    run(options).or_else(|e| match error_format {
+        ErrorFormat::Human => Err(e.message),
+        ErrorFormat::Json => {
+            eprintln!("{}", e.to_json());
+            process::exit(1)
+        }
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::needless_borrows_for_generic_args)]
+mod tests {
+    use super::*;
+    use assert_cmd::Command;
+    use assert_fs::prelude::*;
+
+    // Private key of the public key matched data is encrypted to in tests
+    const TEST_PRIVATE_KEY: &str = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+
+    // Encrypts matched data to the public key of TEST_PRIVATE_KEY, base64 encoded
+    fn encrypt_test_matched_data(matched_data: &[u8]) -> String {
+        let public_key = matched_data::get_public_key(
+            &matched_data::get_private_key_from_bytes(
+                &radix64::STD.decode(TEST_PRIVATE_KEY).unwrap(),
+            )
+            .unwrap(),
+        );
+        let encrypted_data = matched_data::encrypt_data(matched_data, &public_key).unwrap();
+        radix64::STD.encode(&matched_data::serialize_encrypted_data(&encrypted_data))
+    }
+
+    // Decrypts base64 encoded matched data with TEST_PRIVATE_KEY and extra arguments
+    fn decrypt_test_matched_data(
+        encrypted_matched_data: &str,
+        extra_args: &[&str],
+    ) -> std::process::Output {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "--data",
+                encrypted_matched_data,
+            ])
+            .args(extra_args)
+            .output()
+            .unwrap();
+        temp_dir.close().unwrap();
+        out
+    }
+
+    #[test]
+    fn test_help() {
+        let command = Options::command();
+        command.clone().debug_assert();
+        for subcommand in command.get_subcommands() {
+            assert!(
+                subcommand
+                    .get_after_help()
+                    .is_some_and(|help| help.starts_with("EXAMPLES:")),
+                "{} has no examples",
+                subcommand.get_name()
+            );
+        }
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["decrypt", "--help"])
+            .output()
+            .unwrap();
+        let help = str::from_utf8(&out.stdout).unwrap();
+        for section in [
+            "KEY SOURCE OPTIONS:",
+            "HPKE MODE OPTIONS:",
+            "OUTPUT OPTIONS:",
+            "GLOBAL OPTIONS:",
+            "EXAMPLES:",
+        ] {
+            assert!(help.contains(section), "{} is missing", section);
+        }
+    }
+
+    #[test]
+    fn test_generate_key_pair() {
+        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
+        let out = cmd.args(&["generate-key-pair"]).output().unwrap();
+
+        let key_pair: KeyPair =
+            serde_json::from_str(std::str::from_utf8(&out.stdout).unwrap()).unwrap();
+
+        radix64::STD.decode(&key_pair.private_key).unwrap();
+        radix64::STD.decode(&key_pair.public_key).unwrap();
+    }
+
+    #[test]
+    fn test_generate_key_pair_label() {
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(&["generate-key-pair"])
+            .output()
+            .unwrap();
+        let key_pair: KeyPair = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(None, key_pair.label);
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(&["generate-key-pair", "--label", "zone-a 2024"])
+            .output()
+            .unwrap();
+        let key_pair: KeyPair = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(Some("zone-a 2024"), key_pair.label.as_deref());
+        assert_eq!(
+            key_format::fingerprint(&radix64::STD.decode(&key_pair.public_key).unwrap()),
+            key_pair.key_id.as_deref().unwrap()
+        );
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(&["generate-key-pair", "--label", "zone-a", "-o", "env"])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+    }
+
+    #[test]
+    fn test_decrypt() {
+        let matched_data = "test matched data";
+        // Encrypted with public key:
+        // Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=
+        let encrypted_matched_data = "AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=";
+        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let encrypted_matched_data_file = temp_dir.child("encrypted_matched_data.txt");
+        encrypted_matched_data_file
+            .write_str(encrypted_matched_data)
+            .unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(private_key).unwrap();
+
+        // Matched data key in file
+        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
+        let out = cmd
+            .args(&[
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                encrypted_matched_data_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+
+        assert_eq!(
+            format!("{}\n", matched_data),
+            str::from_utf8(&out.stdout).unwrap()
+        );
+
+        // Matched data key in stdin
+        cmd = Command::cargo_bin("matched-data-cli").unwrap();
+        let out = cmd
+            .args(&[
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "-",
+            ])
+            .write_stdin(encrypted_matched_data)
+            .output()
+            .unwrap();
+
+        assert_eq!(
+            format!("{}\n", matched_data),
+            str::from_utf8(&out.stdout).unwrap()
+        );
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_base64_variants() {
+        let matched_data = "test matched data";
+        let encrypted_matched_data = "AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=";
+        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let encrypted_matched_data_file = temp_dir.child("encrypted_matched_data.txt");
+        encrypted_matched_data_file
+            .write_str(encrypted_matched_data)
+            .unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(private_key).unwrap();
+
+        // Matched data as base64
+        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
+        let out = cmd
+            .args([
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "-o",
+                "base64",
+                encrypted_matched_data_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+
+        assert_eq!(
+            format!("{}\n", radix64::STD.encode(matched_data)),
+            str::from_utf8(&out.stdout).unwrap()
+        );
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_data() {
+        let matched_data = "test matched data";
+        let encrypted_matched_data = "AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=";
+        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(private_key).unwrap();
+
+        // Matched data inline
+        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
+        let out = cmd
+            .args([
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "--data",
+                encrypted_matched_data,
+            ])
+            .output()
+            .unwrap();
+
+        assert_eq!(
+            format!("{}\n", matched_data),
+            str::from_utf8(&out.stdout).unwrap()
+        );
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_truncation() {
+        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let encrypted_matched_data_file = temp_dir.child("encrypted_matched_data.txt");
+        encrypted_matched_data_file.write_str(TRUNCATED).unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(private_key).unwrap();
+        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
+        let out = cmd
+            .args(&[
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                encrypted_matched_data_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert_eq!(
+            format!("{}\n", "Error: \"The payload match for this event is unavailable because it was too large.\""),
+            str::from_utf8(&out.stderr).unwrap()
+        );
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_verbose() {
+        let encrypted_matched_data = "AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=";
+        // Not the private key the matched data was encrypted for
+        let private_key = "uBS5eBttHrqkdZ41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let encrypted_matched_data_file = temp_dir.child("encrypted_matched_data.txt");
+        encrypted_matched_data_file
+            .write_str(encrypted_matched_data)
+            .unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(private_key).unwrap();
+        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
+        let out = cmd
+            .args([
+                "decrypt",
+                "-v",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                encrypted_matched_data_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert_eq!(
+            "private key base64 decode: ok (32 bytes)\n\
+             base64 decode: ok (74 bytes)\n\
+             version byte: ok (3)\n\
+             deserialization: ok (encapped key 32 bytes, ciphertext 17 bytes, tag 16 bytes)\n\
+             HPKE decapsulation: ok (shared secret derived)\n\
+             AEAD open: failed (OpenError)\n\
+             Error: \"Failed to decrypt matched data, tried key id 4e31be93\"\n",
+            str::from_utf8(&out.stderr).unwrap()
+        );
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_error_format_json() {
+        let out = decrypt_test_matched_data("BA==", &["--error-format", "json"]);
+        assert!(!out.status.success());
+        assert_eq!(
+            "{\"code\":\"unsupported_version\",\
+             \"message\":\"Encryption format not supported, expected '3', got '4'\",\
+             \"stage\":\"version byte\",\"hint\":null}\n",
+            str::from_utf8(&out.stderr).unwrap()
+        );
+
+        let encrypted_matched_data = encrypt_test_matched_data(b"test matched data");
+        let out = decrypt_test_matched_data(
+            &encrypted_matched_data,
+            &["--error-format", "json", "--redact", "("],
+        );
+        let error: serde_json::Value = serde_json::from_slice(&out.stderr).unwrap();
+        assert_eq!("error", error["code"]);
+        assert!(error["stage"].is_null());
+    }
+
+    #[test]
+    fn test_porcelain() {
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(&["--porcelain", "generate-key-pair"])
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert!(out.stderr.is_empty());
+        let key_pair: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!("v1", key_pair["porcelain"]);
+        assert!(key_pair["private_key"].is_string());
+        assert_eq!(
+            key_format::fingerprint(
+                &radix64::STD
+                    .decode(key_pair["public_key"].as_str().unwrap())
+                    .unwrap()
+            ),
+            key_pair["fingerprint"]
+        );
+
+        let encrypted_matched_data = encrypt_test_matched_data(b"test matched data");
+        let out = decrypt_test_matched_data(&encrypted_matched_data, &["--porcelain=v1"]);
+        assert!(out.status.success());
+        assert_eq!(
+            "{\"porcelain\":\"v1\",\"source\":\"data\",\"status\":\"ok\",\
+             \"plaintext_length\":17,\"plaintext_base64\":\"dGVzdCBtYXRjaGVkIGRhdGE=\",\
+             \"plaintext_utf8\":\"test matched data\"}\n",
+            str::from_utf8(&out.stdout).unwrap()
+        );
+
+        // Failures are both a record and a JSON error
+        let out = decrypt_test_matched_data("BA==", &["--porcelain"]);
+        assert!(!out.status.success());
+        let record: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!("unsupported_version", record["code"]);
+        let error: serde_json::Value = serde_json::from_slice(&out.stderr).unwrap();
+        assert_eq!("unsupported_version", error["code"]);
+
+        let out = decrypt_test_matched_data(
+            &encrypted_matched_data,
+            &["--porcelain", "--output-file", "plaintext.txt"],
+        );
+        assert!(!out.status.success());
+    }
+
+    #[test]
+    fn test_decrypt_input_encoding() {
+        let encrypted_matched_data = radix64::STD
+            .decode("AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=")
+            .unwrap();
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let raw_file = temp_dir.child("matched_data.bin");
+        raw_file.write_binary(&encrypted_matched_data).unwrap();
+        let hex_file = temp_dir.child("matched_data.hex");
+        hex_file
+            .write_str(
+                &encrypted_matched_data
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<String>(),
+            )
+            .unwrap();
+
+        let decrypt = |args: &[&str]| {
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args(["decrypt", "-k", private_key_file.path().to_str().unwrap()])
+                .args(args)
+                .output()
+                .unwrap()
+        };
+        for (encoding, file) in [("raw", &raw_file), ("hex", &hex_file)] {
+            let out = decrypt(&["--input-encoding", encoding, file.path().to_str().unwrap()]);
+            assert_eq!("test matched data\n", str::from_utf8(&out.stdout).unwrap());
+        }
+
+        // Raw bytes aren't taken for text
+        let out = decrypt(&[raw_file.path().to_str().unwrap()]);
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("--input-format raw"));
+
+        // Each raw file is one input in batch mode
+        let out = decrypt(&[
+            "--input-format",
+            "raw",
+            "-o",
+            "ndjson",
+            raw_file.path().to_str().unwrap(),
+            raw_file.path().to_str().unwrap(),
+        ]);
+        assert!(out.status.success());
+        let records: Vec<serde_json::Value> = str::from_utf8(&out.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, records.len());
+        assert_eq!(raw_file.path().to_str().unwrap(), records[0]["source"]);
+        assert_eq!("test matched data", records[1]["plaintext_utf8"]);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_concat() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let segments: Vec<_> = [&b"first segment, "[..], b"second segment"]
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                let file = temp_dir.child(format!("segment{}.txt", index));
+                file.write_str(&encrypt_test_matched_data(segment)).unwrap();
+                file.path().to_str().unwrap().to_string()
+            })
+            .collect();
+        let invalid_segment = temp_dir.child("invalid.txt");
+        invalid_segment.write_str("AAAA").unwrap();
+        let output_file = temp_dir.child("payload.bin");
+
+        let decrypt = |args: &[&str]| {
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args([
+                    "decrypt",
+                    "--concat",
+                    "-k",
+                    private_key_file.path().to_str().unwrap(),
+                ])
+                .args(args)
+                .output()
+                .unwrap()
+        };
+        let out = decrypt(&[&segments[0], &segments[1]]);
+        assert!(out.status.success());
+        assert_eq!(b"first segment, second segment", out.stdout.as_slice());
+
+        // A failed segment leaves no output file behind
+        let out = decrypt(&[
+            "--output-file",
+            output_file.path().to_str().unwrap(),
+            &segments[0],
+            invalid_segment.path().to_str().unwrap(),
+        ]);
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr).unwrap().contains("Segment 2"));
+        assert!(!output_file.path().exists());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_max_input_size() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let matched_data = encrypt_test_matched_data(b"test matched data");
+        let matched_data_file = temp_dir.child("matched_data.txt");
+        matched_data_file
+            .write_str(&format!("{}\n{}\n", "A".repeat(1000), matched_data))
+            .unwrap();
+
+        let decrypt = |args: &[&str]| {
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args([
+                    "decrypt",
+                    "--max-input-size",
+                    "200",
+                    "-k",
+                    private_key_file.path().to_str().unwrap(),
+                ])
+                .args(args)
+                .output()
+                .unwrap()
+        };
+        let out = decrypt(&[
+            "--error-format",
+            "json",
+            matched_data_file.path().to_str().unwrap(),
+        ]);
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("\"code\":\"matched_data_too_large\""));
+
+        // Only the oversized line fails in batch mode
+        let out = decrypt(&[
+            "-o",
+            "ndjson",
+            "--continue-on-error",
+            matched_data_file.path().to_str().unwrap(),
+        ]);
+        let records: Vec<serde_json::Value> = str::from_utf8(&out.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, records.len());
+        assert_eq!("matched_data_too_large", records[0]["code"]);
+        assert_eq!("test matched data", records[1]["plaintext_utf8"]);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_null() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let matched_data_file = temp_dir.child("matched_data.txt");
+        matched_data_file
+            .write_str(&encrypt_test_matched_data(b"line one\nline two"))
+            .unwrap();
+
+        let decrypt = |args: &[&str]| {
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args([
+                    "decrypt",
+                    "-0",
+                    "-k",
+                    private_key_file.path().to_str().unwrap(),
+                ])
+                .args(args)
+                .output()
+                .unwrap()
+        };
+        let out = decrypt(&[matched_data_file.path().to_str().unwrap()]);
+        assert_eq!(b"line one\nline two\0", out.stdout.as_slice());
+        let out = decrypt(&["-o", "raw", matched_data_file.path().to_str().unwrap()]);
+        assert_eq!(b"line one\nline two\0", out.stdout.as_slice());
+
+        // Batch records are separated by NUL bytes too
+        let out = decrypt(&[
+            "-o",
+            "ndjson",
+            matched_data_file.path().to_str().unwrap(),
+            matched_data_file.path().to_str().unwrap(),
+        ]);
+        assert!(out.status.success());
+        let records: Vec<&[u8]> = out.stdout.split(|&byte| byte == 0).collect();
+        assert_eq!(3, records.len());
+        assert!(records[2].is_empty());
+        let record: serde_json::Value = serde_json::from_slice(records[1]).unwrap();
+        assert_eq!("line one\nline two", record["plaintext_utf8"]);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_key_pair_file() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let matched_data_file = temp_dir.child("matched_data.txt");
+        matched_data_file
+            .write_str(&encrypt_test_matched_data(b"test matched data"))
+            .unwrap();
+        let decrypt = |key_pair: serde_json::Value| {
+            let key_file = temp_dir.child("key_pair.json");
+            key_file
+                .write_str(&serde_json::to_string_pretty(&key_pair).unwrap())
+                .unwrap();
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args(["decrypt", "-k"])
+                .arg(key_file.path())
+                .arg(matched_data_file.path())
+                .output()
+                .unwrap()
+        };
+
+        let out = decrypt(serde_json::json!({
+            "private_key": TEST_PRIVATE_KEY,
+            "public_key": "Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=",
+            "key_id": "064f06a2",
+        }));
+        assert!(out.status.success());
+        assert_eq!("test matched data\n", str::from_utf8(&out.stdout).unwrap());
+
+        let out = decrypt(serde_json::json!({ "private_key": TEST_PRIVATE_KEY }));
+        assert!(out.status.success());
+        assert_eq!("test matched data\n", str::from_utf8(&out.stdout).unwrap());
+
+        let out = decrypt(serde_json::json!({
+            "private_key": "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=",
+            "public_key": "cPs9c+2BmNjnfXFzvIUeDblpKMWJkctva92Sm0ec+3E=",
+        }));
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("doesn't match its public key"));
+
+        let out = decrypt(serde_json::json!({
+            "porcelain": "v1",
+            "public_key": "Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=",
+            "fingerprint": "064f06a2",
+        }));
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("without its private_key"));
+
+        // doctor diagnoses the private key of the key pair rather than its JSON
+        let key_file = temp_dir.child("key_pair.json");
+        key_file
+            .write_str(&serde_json::json!({ "private_key": TEST_PRIVATE_KEY }).to_string())
+            .unwrap();
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["doctor", "-k"])
+            .arg(key_file.path())
+            .arg(matched_data_file.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_windows_files() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        // As written by PowerShell 5's > redirection, UTF-16 with a byte order mark and CRLF
+        let private_key_file = temp_dir.child("private_key.txt");
+        let utf16: Vec<u8> = format!("{}\r\n", TEST_PRIVATE_KEY)
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        private_key_file
+            .write_binary(&[b"\xff\xfe".as_slice(), &utf16].concat())
+            .unwrap();
+        // As saved by Notepad, UTF-8 with a byte order mark, wrapped with CRLF
+        let plaintext = b"\r\n\x00\xff\x1a";
+        let encrypted_matched_data = encrypt_test_matched_data(plaintext);
+        let (first, second) = encrypted_matched_data.split_at(40);
+        let matched_data_file = temp_dir.child("matched_data.txt");
+        matched_data_file
+            .write_str(&format!("\u{feff}{}\r\n{}\r\n", first, second))
+            .unwrap();
+
+        // Raw output keeps every byte, line breaks included
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["decrypt", "-o", "raw", "-k"])
+            .arg(private_key_file.path())
+            .arg(matched_data_file.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert_eq!(plaintext, out.stdout.as_slice());
+
+        // Base64 wrapped over several lines is still rejected with --strict
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["--strict", "decrypt", "-o", "base64", "-k"])
+            .arg(private_key_file.path())
+            .arg(matched_data_file.path())
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_no_mlock() {
+        let out = decrypt_test_matched_data(
+            &encrypt_test_matched_data(b"test matched data"),
+            &["--no-mlock"],
+        );
+        assert!(out.status.success());
+        assert_eq!("test matched data\n", str::from_utf8(&out.stdout).unwrap());
+    }
+
+    // The YubiKey path neither decrypts in PSK mode nor takes another key source
+    #[test]
+    fn test_decrypt_yubikey_conflicts() {
+        for extra_args in [
+            &["--psk-file", "psk.txt", "--psk-id", "test"][..],
+            &["--key-id", "key-a"][..],
+            &["--vault-path", "secret/data/matched-data"][..],
+            &["--azure-key-vault", "vault/secret"][..],
+        ] {
+            let out = Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args([
+                    "decrypt",
+                    "--yubikey-slot",
+                    "9d",
+                    "--yubikey-public-key",
+                    "public_key.txt",
+                    "matched_data.txt",
+                ])
+                .args(extra_args)
+                .output()
+                .unwrap();
+            assert!(!out.status.success());
+            assert!(str::from_utf8(&out.stderr)
+                .unwrap()
+                .contains("cannot be used with"));
+        }
+    }
+
+    #[test]
+    fn test_decrypt_bytes_json() {
+        let matched_data = "test matched data";
+        let encrypted_matched_data = radix64::STD
+            .decode("AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=")
+            .unwrap();
+        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let encrypted_matched_data_file = temp_dir.child("encrypted_matched_data.txt");
+        encrypted_matched_data_file
+            .write_str(&serde_json::to_string(&encrypted_matched_data).unwrap())
+            .unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(private_key).unwrap();
+
+        // Detected automatically and selected explicitly
+        for extra_args in [&[][..], &["--input-format", "bytes-json"][..]] {
+            let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
+            let out = cmd
+                .args([
+                    "decrypt",
+                    "-k",
+                    private_key_file.path().to_str().unwrap(),
+                    encrypted_matched_data_file.path().to_str().unwrap(),
+                ])
+                .args(extra_args)
+                .output()
+                .unwrap();
+
+            assert_eq!(
+                format!("{}\n", matched_data),
+                str::from_utf8(&out.stdout).unwrap()
+            );
+        }
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_lenient_base64() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file
+            .write_str(&TEST_PRIVATE_KEY.replace('/', "_"))
+            .unwrap();
+        // URL-safe and wrapped at 40 characters, as pasted from an email
+        let encrypted_matched_data = encrypt_test_matched_data(b"test matched data")
+            .replace('+', "-")
+            .replace('/', "_");
+        let wrapped: Vec<_> = encrypted_matched_data
+            .as_bytes()
+            .chunks(40)
+            .map(|line| str::from_utf8(line).unwrap())
+            .collect();
+        let matched_data_file = temp_dir.child("matched_data.txt");
+        matched_data_file.write_str(&wrapped.join("\r\n")).unwrap();
+
+        let args = [
+            "decrypt",
+            "-k",
+            private_key_file.path().to_str().unwrap(),
+            matched_data_file.path().to_str().unwrap(),
+        ];
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert_eq!(b"test matched data\n", out.stdout.as_slice());
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(args)
+            .arg("--strict")
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+    }
+
+    #[test]
+    fn test_decrypt_verify_only() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let encrypted_matched_data = encrypt_test_matched_data(b"test matched data");
+
+        Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "--verify-only",
+                "--data",
+                &encrypted_matched_data,
+            ])
+            .assert()
+            .success()
+            .stdout("Matched data decrypts, the plaintext is 17 bytes\n");
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "--verify-only",
+                "-o",
+                "ndjson",
+                "-",
+            ])
+            .write_stdin(format!("{}\n", encrypted_matched_data))
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let record: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(
+            serde_json::json!({"source": "stdin:1", "status": "ok", "plaintext_length": 17}),
+            record
+        );
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_ndjson() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let first_file = temp_dir.child("first.txt");
+        first_file
+            .write_str(&format!(
+                "{}\n\n{}\n",
+                encrypt_test_matched_data(b"first"),
+                encrypt_test_matched_data(&[0xff])
+            ))
+            .unwrap();
+        let second_file = temp_dir.child("second.txt");
+        second_file.write_str("not base64!\n").unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "-o",
+                "ndjson",
+                first_file.path().to_str().unwrap(),
+                second_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+
+        let records: Vec<serde_json::Value> = str::from_utf8(&out.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(3, records.len());
+        assert_eq!(
+            format!("{}:1", first_file.path().to_str().unwrap()),
+            records[0]["source"]
+        );
+        assert_eq!("ok", records[0]["status"]);
+        assert_eq!("first", records[0]["plaintext_utf8"]);
+        assert_eq!("Zmlyc3Q=", records[0]["plaintext_base64"]);
+        assert_eq!(
+            format!("{}:3", first_file.path().to_str().unwrap()),
+            records[1]["source"]
+        );
+        assert_eq!("/w==", records[1]["plaintext_base64"]);
+        assert!(records[1].get("plaintext_utf8").is_none());
+        assert_eq!("error", records[2]["status"]);
+        assert_eq!("matched_data_not_base64", records[2]["code"]);
+
+        // Inputs after a failure are still decrypted with --continue-on-error
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "-o",
+                "ndjson",
+                "--continue-on-error",
+                second_file.path().to_str().unwrap(),
+                first_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert_eq!(3, str::from_utf8(&out.stdout).unwrap().lines().count());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("Failed to decrypt 1 of 3 inputs"));
+
+        // Several files need -o ndjson
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                first_file.path().to_str().unwrap(),
+                second_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+    }
+
+    #[test]
+    fn test_csv() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let csv_file = temp_dir.child("events.csv");
+        csv_file
+            .write_str(&format!(
+                "Ray ID,Rule,Payload\n1,100000,{}\n2,100001,\n",
+                encrypt_test_matched_data(b"a=\"1\", b=2")
+            ))
+            .unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "csv",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "--column",
+                "Payload",
+                csv_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let rows: Vec<_> = str::from_utf8(&out.stdout).unwrap().lines().collect();
+        assert_eq!("Ray ID,Rule,Payload,decrypted_matched_data", rows[0]);
+        assert!(rows[1].ends_with(",\"a=\"\"1\"\", b=2\""));
+        assert_eq!("2,100001,,", rows[2]);
+
+        // Without a matched data column name, the column must be selected
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "csv",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                csv_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+    }
+
+    #[test]
+    fn test_decrypt_output_dir() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let jpeg: &[u8] = &[0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10, b'J', b'F', b'I', b'F'];
+        let matched_data_file = temp_dir.child("event_1.txt");
+        matched_data_file
+            .write_str(&encrypt_test_matched_data(jpeg))
+            .unwrap();
+        let output_dir = temp_dir.child("payloads");
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                matched_data_file.path().to_str().unwrap(),
+                "--output-dir",
+                output_dir.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert!(str::from_utf8(&out.stdout)
+            .unwrap()
+            .starts_with("Wrote 10 bytes of image/jpeg to "));
+        output_dir.child("event_1.jpg").assert(jpeg);
+
+        // Text is still printed
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "--data",
+                &encrypt_test_matched_data(b"test matched data"),
+                "--output-dir",
+                output_dir.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert_eq!(b"test matched data\n", out.stdout.as_slice());
+    }
+
+    #[test]
+    fn test_decrypt_output_file() {
+        let matched_data = "test matched data";
+        let encrypted_matched_data = "AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=";
+        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(private_key).unwrap();
+        let output_file = temp_dir.child("matched_data.bin");
+
+        let args = [
+            "decrypt",
+            "-k",
+            private_key_file.path().to_str().unwrap(),
+            "--data",
+            encrypted_matched_data,
+            "--output-file",
+            output_file.path().to_str().unwrap(),
+        ];
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert!(out.stdout.is_empty());
+        output_file.assert(matched_data);
+
+        // Existing files are only overwritten with --force
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(args)
+            .arg("--force")
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        output_file.assert(matched_data);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_logs() {
+        let matched_data = "test matched data";
+        let encrypted_matched_data = "AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=";
+        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let log_file = temp_dir.child("firewall_events.log");
+        log_file
+            .write_str(&format!(
+                "{{\"RayID\":\"1\",\"Metadata\":{{\"encrypted_matched_data\":\"{}\"}}}}\n\
+                 {{\"RayID\":\"2\",\"Metadata\":{{}}}}\n",
+                encrypted_matched_data
+            ))
+            .unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(private_key).unwrap();
+
+        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
+        let out = cmd
+            .args([
+                "logs",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                log_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+
+        let events: Vec<serde_json::Value> = str::from_utf8(&out.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(1, events.len());
+        assert_eq!("1", events[0]["RayID"]);
+        assert_eq!(matched_data, events[0]["decrypted_matched_data"]);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_logs_zone_keys() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp.child("zone-a.json");
+        private_key_file
+            .write_str(&format!(
+                "{{\"private_key\":\"{}\",\"public_key\":\"Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=\",\"label\":\"zone-a\"}}",
+                TEST_PRIVATE_KEY
+            ))
+            .unwrap();
+        let other_private_key_file = temp.child("zone-b.key");
+        other_private_key_file
+            .write_str("cPs9c+2BmNjnfXFzvIUeDblpKMWJkctva92Sm0ec+3E=")
+            .unwrap();
+        let encrypted_matched_data = encrypt_test_matched_data(b"test matched data");
+        let log_file = temp.child("events.log");
+        log_file
+            .write_str(
+                &["a", "b", "c"]
+                    .map(|zone_id| {
+                        format!(
+                            "{{\"ZoneTag\":\"{}\",\"Metadata\":{{\"encrypted_matched_data\":\"{}\"}}}}\n",
+                            zone_id, encrypted_matched_data
+                        )
+                    })
+                    .concat(),
+            )
+            .unwrap();
+        let config_file = temp.child("config.toml");
+        let logs = |keys: &str| {
+            config_file
+                .write_str(&format!(
+                    "[keys]\na = '{}'\nb = '{}'\n{}",
+                    private_key_file.path().display(),
+                    other_private_key_file.path().display(),
+                    keys
+                ))
+                .unwrap();
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .arg("--config")
+                .arg(config_file.path())
+                .args(["logs", "--continue-on-error"])
+                .arg(log_file.path())
+                .output()
+                .unwrap()
+        };
+
+        let out = logs("");
+        assert!(!out.status.success());
+        let events: Vec<serde_json::Value> = str::from_utf8(&out.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!("test matched data", events[0]["decrypted_matched_data"]);
+        assert_eq!(
+            serde_json::json!({"key_id": "064f06a2", "label": "zone-a"}),
+            events[0]["decryption_key"]
+        );
+        assert!(events[1]["decryption_error"]
+            .as_str()
+            .unwrap()
+            .contains("Failed to decrypt matched data"));
+        assert_eq!(
+            "No private key for zone c, add it to the keys of the configuration file",
+            events[2]["decryption_error"]
+        );
+
+        // Keys configured by fingerprint decrypt the events of other zones, but not those of zones
+        // with a key of their own
+        let out = logs(&format!(
+            "064f06a2 = '{}'\n",
+            private_key_file.path().display()
+        ));
+        let stdout = str::from_utf8(&out.stdout).unwrap();
+        assert_eq!(2, stdout.matches("\"decrypted_matched_data\"").count());
+        assert_eq!(1, stdout.matches("\"decryption_error\"").count());
+
+        let out = logs(&format!(
+            "4e31be93 = '{}'\n",
+            private_key_file.path().display()
+        ));
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("has key id 064f06a2"));
+        temp.close().unwrap();
+    }
+
+    #[test]
+    fn test_logs_archive() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        // The first member has no final line break, the second is gzipped as delivered by Logpush
+        let first_log = format!(
+            "{{\"RayID\":\"1\",\"encrypted_matched_data\":\"{}\"}}",
+            encrypt_test_matched_data(b"first")
+        );
+        let mut second_log = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        writeln!(
+            second_log,
+            "{{\"RayID\":\"2\",\"encrypted_matched_data\":\"{}\"}}",
+            encrypt_test_matched_data(b"second")
+        )
+        .unwrap();
+        let second_log = second_log.finish().unwrap();
+
+        let zip_file = temp_dir.child("logs.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(zip_file.path()).unwrap());
+        let options = zip::write::FileOptions::default();
+        zip.add_directory("logs/", options).unwrap();
+        zip.start_file("logs/a.log", options).unwrap();
+        zip.write_all(first_log.as_bytes()).unwrap();
+        zip.start_file("logs/b.log.gz", options).unwrap();
+        zip.write_all(&second_log).unwrap();
+        zip.start_file("logs/.DS_Store", options).unwrap();
+        zip.write_all(b"\0\0\0\x01Bud1").unwrap();
+        zip.finish().unwrap();
+
+        let tar_file = temp_dir.child("logs.tar.gz");
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+            fs::File::create(tar_file.path()).unwrap(),
+            Default::default(),
+        ));
+        for (name, contents) in [("a.log", first_log.as_bytes()), ("b.log.gz", &second_log)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_cksum();
+            tar.append_data(&mut header, name, contents).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+
+        for archive in [&zip_file, &tar_file] {
+            let out = Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args(["logs", "-k", private_key_file.path().to_str().unwrap()])
+                .arg(archive.path())
+                .output()
+                .unwrap();
+            assert!(out.status.success());
+            let events: Vec<serde_json::Value> = str::from_utf8(&out.stdout)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            assert_eq!(2, events.len());
+            assert_eq!("first", events[0]["decrypted_matched_data"]);
+            assert_eq!("second", events[1]["decrypted_matched_data"]);
+        }
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "logs",
+                "-f",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+            ])
+            .arg(zip_file.path())
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+
+        let not_zip_file = temp_dir.child("not.zip");
+        not_zip_file.write_str("not a zip file").unwrap();
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["logs", "-k", private_key_file.path().to_str().unwrap()])
+            .arg(not_zip_file.path())
+            .output()
+            .unwrap();
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("not a valid zip file"));
+    }
+
+    #[test]
+    fn test_cache() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let log_file = temp_dir.child("firewall_events.log");
+        log_file
+            .write_str(&format!(
+                "{{\"RayID\":\"1\",\"encrypted_matched_data\":\"{}\"}}\n",
+                encrypt_test_matched_data(b"test matched data")
+            ))
+            .unwrap();
+        let cache_dir = temp_dir.child("cache");
+        let entries = || {
+            fs::read_dir(cache_dir.path())
+                .unwrap()
+                .flat_map(|subdir| fs::read_dir(subdir.unwrap().path()).unwrap())
+                .count()
+        };
+
+        for _ in 0..2 {
+            let out = Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args(["logs", "-k", private_key_file.path().to_str().unwrap()])
+                .arg(log_file.path())
+                .arg("--cache-dir")
+                .arg(cache_dir.path())
+                .output()
+                .unwrap();
+            assert!(out.status.success());
+            let event: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+            assert_eq!("test matched data", event["decrypted_matched_data"]);
+            assert_eq!(1, entries());
+        }
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["cache", "purge", "--cache-dir"])
+            .arg(cache_dir.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .starts_with("Purged 1 entries"));
+        assert_eq!(0, entries());
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["cache", "purge"])
+            .env("XDG_CONFIG_HOME", temp_dir.path())
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+    }
+
+    #[test]
+    fn test_logs_jobs() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let log_file = temp_dir.child("firewall_events.log");
+        let log: String = (0..100)
+            .map(|ray_id| {
+                format!(
+                    "{{\"RayID\":\"{}\",\"Metadata\":{{\"encrypted_matched_data\":\"{}\"}}}}\n",
+                    ray_id,
+                    encrypt_test_matched_data(ray_id.to_string().as_bytes())
+                )
+            })
+            .collect();
+        log_file.write_str(&log).unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+
+        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
+        let out = cmd
+            .args([
+                "logs",
+                "--jobs",
+                "4",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                log_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+
+        // Events are printed in the order of the log whichever thread decrypted them
+        let events: Vec<serde_json::Value> = str::from_utf8(&out.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(100, events.len());
+        for (ray_id, event) in events.iter().enumerate() {
+            assert_eq!(ray_id.to_string(), event["RayID"]);
+            assert_eq!(ray_id.to_string(), event["decrypted_matched_data"]);
+        }
+
+        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
+        cmd.args([
+            "logs",
+            "--jobs",
+            "0",
+            "-k",
+            private_key_file.path().to_str().unwrap(),
+            log_file.path().to_str().unwrap(),
+        ])
+        .assert()
+        .failure();
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_key_mnemonic() {
+        let matched_data = "test matched data";
+        let encrypted_matched_data = "AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=";
+        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+        let mnemonic = key_format::to_mnemonic(&radix64::STD.decode(private_key).unwrap());
+        assert_eq!(24, mnemonic.split(' ').count());
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let mnemonic_file = temp_dir.child("private_key_mnemonic.txt");
+        mnemonic_file.write_str(&format!("{}\n", mnemonic)).unwrap();
+
+        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
+        let out = cmd
+            .args([
+                "decrypt",
+                "--key-mnemonic",
+                mnemonic_file.path().to_str().unwrap(),
+                "--data",
+                encrypted_matched_data,
+            ])
+            .output()
+            .unwrap();
+
+        assert_eq!(
+            format!("{}\n", matched_data),
+            str::from_utf8(&out.stdout).unwrap()
+        );
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_export_import_key() {
+        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+        let public_key = "Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=";
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(private_key).unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "export-key",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        let identity_file = temp_dir.child("identity.txt");
+        identity_file.write_binary(&out.stdout).unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["import-key", identity_file.path().to_str().unwrap()])
+            .output()
+            .unwrap();
+        let key_pair: KeyPair = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(private_key, key_pair.private_key);
+        assert_eq!(public_key, key_pair.public_key);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_authenticated() {
+        let (sender_private_key, sender_public_key) = matched_data::generate_key_pair();
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let public_key_file = temp_dir.child("public_key.txt");
+        public_key_file
+            .write_str("Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=")
+            .unwrap();
+        let sender_key_file = temp_dir.child("sender_key.txt");
+        sender_key_file
+            .write_str(&radix64::STD.encode(&sender_private_key.to_bytes()))
+            .unwrap();
+        let sender_public_key_file = temp_dir.child("sender_public_key.txt");
+        sender_public_key_file
+            .write_str(&radix64::STD.encode(&sender_public_key.to_bytes()))
+            .unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "encrypt",
+                "-p",
+                public_key_file.path().to_str().unwrap(),
+                "--sender-key",
+                sender_key_file.path().to_str().unwrap(),
+                "-",
+            ])
+            .write_stdin("test matched data")
+            .output()
+            .unwrap();
+        let blob = str::from_utf8(&out.stdout).unwrap().trim_end().to_string();
+
+        let decrypt = |sender_public_key: Option<&str>| {
+            let mut args = vec![
+                "decrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "--data",
+                &blob,
+            ];
+            if let Some(sender_public_key) = sender_public_key {
+                args.extend(["--sender-public-key", sender_public_key]);
+            }
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args(args)
+                .assert()
+        };
+        decrypt(Some(sender_public_key_file.path().to_str().unwrap()))
+            .success()
+            .stdout("test matched data\n");
+        // Neither in base mode nor with another sender
+        decrypt(None).failure();
+        decrypt(Some(public_key_file.path().to_str().unwrap())).failure();
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_reencrypt() {
+        let (new_private_key, new_public_key) = matched_data::generate_key_pair();
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let new_private_key_file = temp_dir.child("new_private_key.txt");
+        new_private_key_file
+            .write_str(&radix64::STD.encode(&new_private_key.to_bytes()))
+            .unwrap();
+        let new_public_key_file = temp_dir.child("new_public_key.txt");
+        new_public_key_file
+            .write_str(&radix64::STD.encode(&new_public_key.to_bytes()))
+            .unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "reencrypt",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "-p",
+                new_public_key_file.path().to_str().unwrap(),
+                "-",
+            ])
+            .write_stdin(encrypt_test_matched_data(b"test matched data"))
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let blob = str::from_utf8(&out.stdout).unwrap().trim_end().to_string();
+
+        let decrypt = |private_key_file: &assert_fs::fixture::ChildPath| {
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args([
+                    "decrypt",
+                    "-k",
+                    private_key_file.path().to_str().unwrap(),
+                    "--data",
+                    &blob,
+                ])
+                .assert()
+        };
+        decrypt(&new_private_key_file)
+            .success()
+            .stdout("test matched data\n");
+        decrypt(&private_key_file).failure();
+
+        // Matched data the private key can't decrypt isn't re-encrypted
+        Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "reencrypt",
+                "-k",
+                new_private_key_file.path().to_str().unwrap(),
+                "-p",
+                new_public_key_file.path().to_str().unwrap(),
+                "-",
+            ])
+            .write_stdin(encrypt_test_matched_data(b"test matched data"))
+            .assert()
+            .failure()
+            .stdout("");
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_rewrap() {
+        let (new_private_key, new_public_key) = matched_data::generate_key_pair();
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let new_private_key_file = temp_dir.child("new_private_key.txt");
+        new_private_key_file
+            .write_str(&radix64::STD.encode(&new_private_key.to_bytes()))
+            .unwrap();
+        let new_public_key_file = temp_dir.child("new_public_key.txt");
+        new_public_key_file
+            .write_str(&radix64::STD.encode(&new_public_key.to_bytes()))
+            .unwrap();
+        let log_file = temp_dir.child("archive/2024-05-01/events.log");
+        log_file
+            .write_str(&format!(
+                "{{\"RayID\":\"1\",\"Metadata\":{{\"encrypted_matched_data\":\"{}\"}}}}\n\
+                 {{\"RayID\":\"2\",\"Metadata\":{{\"encrypted_matched_data\":\"AAAA\"}}}}\n",
+                encrypt_test_matched_data(b"test matched data")
+            ))
+            .unwrap();
+        let output = temp_dir.child("rewrapped");
+
+        // The line that fails is reported and left unchanged, the other one is rewrapped
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "rewrap",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "-p",
+                new_public_key_file.path().to_str().unwrap(),
+                "-o",
+                output.path().to_str().unwrap(),
+                temp_dir.child("archive").path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stdout)
+            .unwrap()
+            .contains("events.log:2"));
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("Failed to rewrap 1 of 2 lines"));
+
+        let rewrapped = fs::read_to_string(output.child("2024-05-01/events.log").path()).unwrap();
+        let lines: Vec<&str> = rewrapped.lines().collect();
+        assert!(
+            lines[0].starts_with("{\"RayID\":\"1\",\"Metadata\":{\"encrypted_matched_data\":\"")
+        );
+        assert_eq!(
+            "{\"RayID\":\"2\",\"Metadata\":{\"encrypted_matched_data\":\"AAAA\"}}",
+            lines[1]
+        );
+        let event: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "decrypt",
+                "-k",
+                new_private_key_file.path().to_str().unwrap(),
+                "--data",
+                event["Metadata"]["encrypted_matched_data"]
+                    .as_str()
+                    .unwrap(),
+            ])
+            .assert()
+            .success()
+            .stdout("test matched data\n");
+
+        // An existing output directory isn't overwritten
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "rewrap",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "-p",
+                new_public_key_file.path().to_str().unwrap(),
+                "-o",
+                output.path().to_str().unwrap(),
+                temp_dir.child("archive").path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("already exists"));
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_log_format() {
+        let (_, new_public_key) = matched_data::generate_key_pair();
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let new_public_key_file = temp_dir.child("new_public_key.txt");
+        new_public_key_file
+            .write_str(&radix64::STD.encode(&new_public_key.to_bytes()))
+            .unwrap();
+        let log_file = temp_dir.child("events.log");
+        log_file
+            .write_str(&format!(
+                "{{\"encrypted_matched_data\":\"{}\"}}\n",
+                encrypt_test_matched_data(b"test matched data")
+            ))
+            .unwrap();
+
+        let rewrap = |output: &str, log_options: &[&str]| {
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args([
+                    "rewrap",
+                    "-k",
+                    private_key_file.path().to_str().unwrap(),
+                    "-p",
+                    new_public_key_file.path().to_str().unwrap(),
+                    "-o",
+                    temp_dir.child(output).path().to_str().unwrap(),
+                    log_file.path().to_str().unwrap(),
+                ])
+                .args(log_options)
+                .output()
+                .unwrap()
+        };
+
+        let out = rewrap("json.log", &["--log-format", "json"]);
+        assert!(out.status.success());
+        let event: serde_json::Value = serde_json::from_slice(&out.stderr).unwrap();
+        assert_eq!("INFO", event["level"]);
+        assert_eq!("Rewrapped archive", event["message"]);
+        assert_eq!(1, event["rewrapped"]);
+        assert!(!str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("test matched data"));
+
+        let out = rewrap("off.log", &["--log-level", "off"]);
+        assert!(out.status.success());
+        assert!(out.stderr.is_empty());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_psk() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let public_key_file = temp_dir.child("public_key.txt");
+        public_key_file
+            .write_str("Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=")
+            .unwrap();
+        let psk_file = temp_dir.child("psk.txt");
+        psk_file.write_str(&radix64::STD.encode(&[7; 32])).unwrap();
+        let short_psk_file = temp_dir.child("short_psk.txt");
+        short_psk_file
+            .write_str(&radix64::STD.encode(&[7; 16]))
+            .unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "encrypt",
+                "-p",
+                public_key_file.path().to_str().unwrap(),
+                "--psk-file",
+                psk_file.path().to_str().unwrap(),
+                "--psk-id",
+                "team-a",
+                "-",
+            ])
+            .write_stdin("test matched data")
+            .output()
+            .unwrap();
+        let blob = str::from_utf8(&out.stdout).unwrap().trim_end().to_string();
+
+        let decrypt = |psk_file: &str, psk_id: &str| {
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args([
+                    "decrypt",
+                    "-k",
+                    private_key_file.path().to_str().unwrap(),
+                    "--psk-file",
+                    psk_file,
+                    "--psk-id",
+                    psk_id,
+                    "--data",
+                    &blob,
+                ])
+                .assert()
+        };
+        decrypt(psk_file.path().to_str().unwrap(), "team-a")
+            .success()
+            .stdout("test matched data\n");
+        decrypt(psk_file.path().to_str().unwrap(), "team-b").failure();
+        let out = decrypt(short_psk_file.path().to_str().unwrap(), "team-a")
+            .failure()
+            .get_output()
+            .clone();
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("expected at least 32"));
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_aad() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let public_key_file = temp_dir.child("public_key.txt");
+        public_key_file
+            .write_str("Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=")
+            .unwrap();
+        let aad_file = temp_dir.child("aad.bin");
+        aad_file.write_binary(b"ray id").unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "encrypt",
+                "-p",
+                public_key_file.path().to_str().unwrap(),
+                "--aad",
+                "ray id",
+                "-",
+            ])
+            .write_stdin("test matched data")
+            .output()
+            .unwrap();
+        let blob = str::from_utf8(&out.stdout).unwrap().trim_end().to_string();
+
+        let decrypt = |args: &[&str]| {
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args(["decrypt", "-k", private_key_file.path().to_str().unwrap()])
+                .args(args)
+                .args(["--data", &blob])
+                .assert()
+        };
+        decrypt(&["--aad", "ray id"])
+            .success()
+            .stdout("test matched data\n");
+        decrypt(&["--aad-file", aad_file.path().to_str().unwrap()])
+            .success()
+            .stdout("test matched data\n");
+        decrypt(&["--aad", "other ray id"]).failure();
+        decrypt(&[]).failure();
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_encrypt() {
+        let matched_data = "test matched data";
+        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+        let public_key = "Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=";
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(private_key).unwrap();
+        let public_key_file = temp_dir.child("public_key.txt");
+        public_key_file.write_str(public_key).unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "encrypt",
+                "-p",
+                public_key_file.path().to_str().unwrap(),
+                "-p",
+                public_key_file.path().to_str().unwrap(),
+                "-",
+            ])
+            .write_stdin(matched_data)
+            .output()
+            .unwrap();
+        let blobs: Vec<&str> = str::from_utf8(&out.stdout).unwrap().lines().collect();
+        assert_eq!(2, blobs.len());
+        assert_ne!(blobs[0], blobs[1]);
+
+        for blob in blobs {
+            let out = Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args([
+                    "decrypt",
+                    "-k",
+                    private_key_file.path().to_str().unwrap(),
+                    "--data",
+                    blob,
+                ])
+                .output()
+                .unwrap();
+            assert_eq!(
+                format!("{}\n", matched_data),
+                str::from_utf8(&out.stdout).unwrap()
+            );
+        }
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_encrypt_armor() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let public_key_file = temp_dir.child("public_key.txt");
+        public_key_file
+            .write_str("Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=")
+            .unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["encrypt", "--armor", "-p"])
+            .arg(public_key_file.path())
+            .arg("-")
+            .write_stdin("test matched data")
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let armored = String::from_utf8(out.stdout).unwrap();
+        assert!(armored.starts_with("-----BEGIN CF MATCHED DATA-----\n"));
+        assert!(armored.ends_with("-----END CF MATCHED DATA-----\n"));
+
+        // Detected when decrypting, even when pasted in the middle of a ticket
+        let ticket_file = temp_dir.child("ticket.txt");
+        ticket_file
+            .write_str(&format!(
+                "Payload of the blocked request:\n\n{}\nThanks",
+                armored
+            ))
+            .unwrap();
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["decrypt", "-k"])
+            .arg(private_key_file.path())
+            .arg(ticket_file.path())
+            .output()
+            .unwrap();
+        assert_eq!("test matched data\n", str::from_utf8(&out.stdout).unwrap());
+
+        let altered_file = temp_dir.child("altered.txt");
+        let line = armored.lines().nth(1).unwrap();
+        let altered_line = match line.strip_prefix('A') {
+            Some(rest) => format!("B{}", rest),
+            None => format!("A{}", &line[1..]),
+        };
+        altered_file
+            .write_str(&armored.replacen(line, &altered_line, 1))
+            .unwrap();
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["--error-format", "json", "decrypt", "-k"])
+            .arg(private_key_file.path())
+            .arg(altered_file.path())
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("matched_data_armor_invalid"));
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_generate_key_pair_files() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("private_key.txt");
+        let public_key_file = temp_dir.child("public_key.txt");
+        let args = [
+            "generate-key-pair",
+            "--private-key-file",
+            private_key_file.path().to_str().unwrap(),
+            "--public-key-file",
+            public_key_file.path().to_str().unwrap(),
+        ];
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert!(out.stdout.is_empty());
+
+        let private_key = fs::read_to_string(private_key_file.path()).unwrap();
+        let public_key = fs::read_to_string(public_key_file.path()).unwrap();
+        let key_pair =
+            key_pair_from_private_key(&radix64::STD.decode(private_key.trim_end()).unwrap())
+                .unwrap();
+        assert_eq!(key_pair.public_key, public_key.trim_end());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(private_key_file.path()).unwrap();
+            assert_eq!(0o600, metadata.permissions().mode() & 0o777);
+        }
+
+        // Existing key files are never overwritten
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert_eq!(
+            private_key,
+            fs::read_to_string(private_key_file.path()).unwrap()
+        );
+
+        // Key files are always base64, so an output format is rejected rather than ignored
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["generate-key-pair", "-o", "pem"])
+            .args(&args[1..])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("cannot be used with"));
+        temp_dir.close().unwrap();
+    }
+
+    // Stands in for the aws CLI with a script receiving messages once, serving objects from files
+    // and recording deleted messages
+    #[cfg(all(unix, feature = "serve"))]
+    #[test]
+    fn test_watch_sqs() {
+        use std::os::unix::fs::PermissionsExt;
 
         let temp_dir = assert_fs::TempDir::new().unwrap();
-        let encrypted_matched_data_file = temp_dir.child("encrypted_matched_data.txt");
-        encrypted_matched_data_file
-            .write_str(encrypted_matched_data)
+        let notification = |bucket: &str, key: &str| {
+            serde_json::json!({
+                "Records": [{
+                    "eventName": "ObjectCreated:Put",
+                    "s3": {"bucket": {"name": bucket}, "object": {"key": key}}
+                }]
+            })
+            .to_string()
+        };
+        let messages = temp_dir.child("messages.json");
+        messages
+            .write_str(
+                &serde_json::json!({
+                    "Messages": [
+                        {"ReceiptHandle": "1", "Body": notification("logpush", "a.log")},
+                        {"ReceiptHandle": "2", "Body": notification("other", "b.log")},
+                        {"ReceiptHandle": "3", "Body": "not a notification"},
+                        {"ReceiptHandle": "4", "Body": notification("logpush", "missing.log")}
+                    ]
+                })
+                .to_string(),
+            )
+            .unwrap();
+        temp_dir
+            .child("a.log")
+            .write_str(&format!(
+                "{{\"RayID\":\"1\",\"encrypted_matched_data\":\"{}\"}}\n",
+                encrypt_test_matched_data(b"test matched data")
+            ))
             .unwrap();
+        let deleted = temp_dir.child("deleted");
+        let aws = temp_dir.child("aws");
+        aws.write_str(&format!(
+            "#!/bin/sh
+             cd {}
+             case \"$2\" in
+             receive-message) if [ -f messages.json ]; then cat messages.json; rm messages.json; fi ;;
+             delete-message) echo \"$6\" >> deleted ;;
+             cp) cat \"${{3#s3://logpush/}}\" ;;
+             esac
+",
+            temp_dir.path().display()
+        ))
+        .unwrap();
+        fs::set_permissions(aws.path(), fs::Permissions::from_mode(0o755)).unwrap();
         let private_key_file = temp_dir.child("private_key.txt");
-        private_key_file.write_str(private_key).unwrap();
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
 
-        // Matched data key in file
-        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
-        let out = cmd
-            .args(&[
-                "decrypt",
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .env(
+                "PATH",
+                format!(
+                    "{}:{}",
+                    temp_dir.path().display(),
+                    std::env::var("PATH").unwrap_or_default()
+                ),
+            )
+            .args([
+                "watch",
+                "--sqs-queue-url",
+                "https://sqs.example.com/queue",
+                "--bucket",
+                "logpush",
+                "--until-empty",
                 "-k",
-                private_key_file.path().to_str().unwrap(),
-                encrypted_matched_data_file.path().to_str().unwrap(),
             ])
+            .arg(private_key_file.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let event: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!("test matched data", event["decrypted_matched_data"]);
+        // The notification of the object that failed to download is left on the queue
+        assert_eq!("1\n2\n3\n", fs::read_to_string(deleted.path()).unwrap());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("Failed to process notification"));
+    }
+
+    // Stands in for xclip with a script keeping the clipboard in a file
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn test_clipboard() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let clipboard_file = temp_dir.child("clipboard");
+        clipboard_file
+            .write_str(&encrypt_test_matched_data(b"test matched data"))
+            .unwrap();
+        let xclip = temp_dir.child("xclip");
+        xclip
+            .write_str(&format!(
+                "#!/bin/sh\ncase \"$3\" in -out) cat {0} ;; -in) cat > {0} ;; esac\n",
+                clipboard_file.path().display()
+            ))
+            .unwrap();
+        fs::set_permissions(xclip.path(), fs::Permissions::from_mode(0o755)).unwrap();
+        let path = format!(
+            "{}:{}",
+            temp_dir.path().display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        let private_key_file = temp_dir.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .env("PATH", &path)
+            .env("DISPLAY", ":0")
+            .env_remove("WAYLAND_DISPLAY")
+            .args(["decrypt", "--clipboard", "--copy", "-k"])
+            .arg(private_key_file.path())
             .output()
             .unwrap();
+        assert!(out.status.success());
+        assert!(out.stdout.is_empty());
+        assert_eq!(
+            "test matched data",
+            fs::read_to_string(clipboard_file.path()).unwrap()
+        );
 
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .env("PATH", &path)
+            .env("DISPLAY", ":0")
+            .env_remove("WAYLAND_DISPLAY")
+            .args(["generate-key-pair", "--copy"])
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let key_pair: KeyPair = serde_json::from_slice(&out.stdout).unwrap();
         assert_eq!(
-            format!("{}\n", matched_data),
-            str::from_utf8(&out.stdout).unwrap()
+            key_pair.public_key,
+            fs::read_to_string(clipboard_file.path()).unwrap()
         );
 
-        // Matched data key in stdin
-        cmd = Command::cargo_bin("matched-data-cli").unwrap();
-        let out = cmd
-            .args(&[
-                "decrypt",
-                "-k",
-                private_key_file.path().to_str().unwrap(),
-                "-",
-            ])
-            .write_stdin(encrypted_matched_data)
+        // Without a display there is no clipboard to use
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .env("PATH", &path)
+            .env_remove("DISPLAY")
+            .env_remove("WAYLAND_DISPLAY")
+            .args(["generate-key-pair", "--copy"])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(out.stdout.is_empty());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("No clipboard tool found"));
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_key_export_import() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp_dir.child("zone-a.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let archive_file = temp_dir.child("backup.mdk");
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .env("MATCHED_DATA_PASSPHRASE", "correct horse")
+            .args(["key", "export", "--out"])
+            .arg(archive_file.path())
+            .arg("-k")
+            .arg(private_key_file.path())
             .output()
             .unwrap();
+        assert!(out.status.success());
+        assert!(!fs::read_to_string(archive_file.path())
+            .unwrap_or_default()
+            .contains(TEST_PRIVATE_KEY));
 
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .env("MATCHED_DATA_PASSPHRASE", "correct horse")
+            .args(["key", "import"])
+            .arg(archive_file.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let keys: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!("zone-a", keys[0]["label"]);
+        assert_eq!(TEST_PRIVATE_KEY, keys[0]["private_key"]);
         assert_eq!(
-            format!("{}\n", matched_data),
-            str::from_utf8(&out.stdout).unwrap()
+            "Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=",
+            keys[0]["public_key"]
         );
+        assert!(keys[0]["created"].is_string());
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .env("MATCHED_DATA_PASSPHRASE", "wrong horse")
+            .args(["key", "import"])
+            .arg(archive_file.path())
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("Wrong passphrase"));
+
+        // Existing archives are never overwritten
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .env("MATCHED_DATA_PASSPHRASE", "correct horse")
+            .args(["key", "export", "--out"])
+            .arg(archive_file.path())
+            .arg("-k")
+            .arg(private_key_file.path())
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("refusing to overwrite"));
         temp_dir.close().unwrap();
     }
 
     #[test]
-    fn test_decrypt_truncation() {
-        let private_key = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+    fn test_key_expiry() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let key_pair = |expires_at: &str| {
+            serde_json::json!({
+                "private_key": TEST_PRIVATE_KEY,
+                "public_key": "Ycig/Zr/pZmklmFUN99nr+taURlYItL91g+NcHGYpB8=",
+                "label": "zone-a",
+                "key_id": "064f06a2",
+                "expires_at": expires_at,
+            })
+            .to_string()
+        };
+        let expired_file = temp_dir.child("expired.json");
+        expired_file
+            .write_str(&key_pair("2020-01-01T00:00:00Z"))
+            .unwrap();
+        let valid_file = temp_dir.child("valid.json");
+        valid_file
+            .write_str(&key_pair(&expiry::expires_at(
+                Duration::from_secs(10 * 24 * 60 * 60 + 60),
+                SystemTime::now(),
+            )))
+            .unwrap();
+        let encrypted_matched_data = encrypt_test_matched_data(b"test matched data");
+
+        // Expired keys still decrypt, with a warning
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["decrypt", "--data", &encrypted_matched_data, "-k"])
+            .arg(expired_file.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert_eq!("test matched data\n", str::from_utf8(&out.stdout).unwrap());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("Using an expired private key"));
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "decrypt",
+                "--enforce-expiry",
+                "--data",
+                &encrypted_matched_data,
+            ])
+            .arg("-k")
+            .arg(expired_file.path())
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("Private key 064f06a2 expired on 2020-01-01T00:00:00Z"));
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "decrypt",
+                "--enforce-expiry",
+                "--data",
+                &encrypted_matched_data,
+            ])
+            .arg("-k")
+            .arg(valid_file.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["key", "list", "-o", "json"])
+            .arg(valid_file.path())
+            .arg(expired_file.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let keys: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!("064f06a2", keys[0]["key_id"]);
+        assert_eq!("zone-a", keys[0]["label"]);
+        assert_eq!(10, keys[0]["days_remaining"]);
+        assert!(keys[1]["days_remaining"].as_i64().unwrap() < 0);
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["key", "list", "--enforce-expiry"])
+            .arg(valid_file.path())
+            .arg(expired_file.path())
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stdout).unwrap().contains("expired"));
+        temp_dir.close().unwrap();
+    }
 
+    #[test]
+    fn test_key_qr() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let qr_file = temp_dir.child("key.png");
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .env("MATCHED_DATA_PASSPHRASE", "correct horse")
+            .args(["generate-key-pair", "-o", "qr", "--qr-file"])
+            .arg(qr_file.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert!(str::from_utf8(&out.stdout)
+            .unwrap()
+            .starts_with("Public key: "));
+        assert!(fs::read(qr_file.path())
+            .unwrap()
+            .starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        // Scanning the image is left to other tools, the import reads the text they output
+        let private_key = radix64::STD.decode(TEST_PRIVATE_KEY).unwrap();
+        let scanned_file = temp_dir.child("key.txt");
+        scanned_file
+            .write_str(&key_format::to_encrypted_key(&private_key, "correct horse").unwrap())
+            .unwrap();
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .env("MATCHED_DATA_PASSPHRASE", "correct horse")
+            .arg("import-key")
+            .arg(scanned_file.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let key_pair: KeyPair = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(TEST_PRIVATE_KEY, key_pair.private_key);
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .env("MATCHED_DATA_PASSPHRASE", "wrong horse")
+            .arg("import-key")
+            .arg(scanned_file.path())
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stderr)
+            .unwrap()
+            .contains("Wrong passphrase"));
+
+        // The image is only written for QR codes
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["generate-key-pair", "--qr-file"])
+            .arg(temp_dir.child("other.png").path())
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(!temp_dir.child("other.png").path().exists());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_fingerprint() {
         let temp_dir = assert_fs::TempDir::new().unwrap();
-        let encrypted_matched_data_file = temp_dir.child("encrypted_matched_data.txt");
-        encrypted_matched_data_file.write_str(TRUNCATED).unwrap();
         let private_key_file = temp_dir.child("private_key.txt");
-        private_key_file.write_str(private_key).unwrap();
-        let mut cmd = Command::cargo_bin("matched-data-cli").unwrap();
-        let out = cmd
-            .args(&[
-                "decrypt",
+        let public_key_file = temp_dir.child("public_key.txt");
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "generate-key-pair",
+                "--private-key-file",
+                private_key_file.path().to_str().unwrap(),
+                "--public-key-file",
+                public_key_file.path().to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        let stderr = str::from_utf8(&out.stderr).unwrap();
+        let fingerprint = stderr.strip_prefix("Key fingerprint: ").unwrap().trim_end();
+        assert_eq!(8, fingerprint.len());
+
+        // The private and public key of a pair have the same fingerprint
+        for args in [
+            [
+                "fingerprint",
                 "-k",
                 private_key_file.path().to_str().unwrap(),
-                encrypted_matched_data_file.path().to_str().unwrap(),
+            ],
+            [
+                "fingerprint",
+                "-p",
+                public_key_file.path().to_str().unwrap(),
+            ],
+        ] {
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args(args)
+                .assert()
+                .success()
+                .stdout(format!("{}\n", fingerprint));
+        }
+
+        Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "fingerprint",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "-p",
+                public_key_file.path().to_str().unwrap(),
             ])
+            .assert()
+            .failure();
+        temp_dir.close().unwrap();
+    }
+    #[test]
+    fn test_self_test() {
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["self-test"])
+            .output()
+            .unwrap();
+
+        assert!(out.status.success());
+        assert!(!str::from_utf8(&out.stdout).unwrap().contains("FAIL"));
+    }
+
+    #[test]
+    fn test_bench() {
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["bench", "-n", "4", "-s", "16", "-j", "2", "-o", "json"])
+            .output()
+            .unwrap();
+
+        assert!(out.status.success());
+        let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(4, report["count"]);
+        assert_eq!(2, report["results"][1]["threads"]);
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["bench", "-j", "0"])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+    }
+
+    #[test]
+    fn test_supported_versions() {
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["--supported-versions"])
+            .output()
+            .unwrap();
+
+        assert!(out.status.success());
+        assert!(str::from_utf8(&out.stdout).unwrap().starts_with("3\tHPKE"));
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["versions", "-o", "json"])
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let versions: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(3, versions[0]["version"]);
+        assert_eq!(true, versions[0]["current"]);
+        assert_eq!(0x0020, versions[0]["hpke_suite"]["kem_id"]);
+        assert_eq!(0x0003, versions[0]["hpke_suite"]["aead_id"]);
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+    }
+
+    #[test]
+    fn test_generate_test_vectors() {
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["generate-test-vectors"])
+            .output()
+            .unwrap();
+        let vectors: Vec<test_vectors::TestVector> = serde_json::from_slice(&out.stdout).unwrap();
+        assert!(!vectors.is_empty());
+
+        // Test vectors are deterministic
+        let out_again = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["generate-test-vectors"])
             .output()
             .unwrap();
+        assert_eq!(out.stdout, out_again.stdout);
+
+        for vector in vectors {
+            let private_key_bytes = radix64::STD.decode(&vector.private_key).unwrap();
+            assert_eq!(
+                radix64::STD.decode(&vector.plaintext).unwrap(),
+                *decrypt_base64_matched_data(
+                    &matched_data::Decryptor::new(&private_key_bytes).unwrap(),
+                    &vector.matched_data,
+                    None,
+                    None,
+                    false
+                )
+                .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_decrypt_json_pretty() {
+        let encrypted_matched_data =
+            encrypt_test_matched_data(br#"{"http.request.body.raw":"a=1"}"#);
+        let out = decrypt_test_matched_data(&encrypted_matched_data, &["-o", "json-pretty"]);
         assert_eq!(
-            format!("{}\n", "Error: \"The payload match for this event is unavailable because it was too large.\""),
-            str::from_utf8(&out.stderr).unwrap()
+            "{\n  \"http.request.body.raw\": \"a=1\"\n}\n",
+            str::from_utf8(&out.stdout).unwrap()
+        );
+
+        // Plaintext that isn't JSON is printed as is
+        let encrypted_matched_data = encrypt_test_matched_data(b"test matched data");
+        let out = decrypt_test_matched_data(&encrypted_matched_data, &["-o", "json-pretty"]);
+        assert_eq!("test matched data\n", str::from_utf8(&out.stdout).unwrap());
+    }
+
+    #[test]
+    fn test_decrypt_hexdump() {
+        let encrypted_matched_data = encrypt_test_matched_data(b"\x00\x01test");
+        let out = decrypt_test_matched_data(&encrypted_matched_data, &["-o", "hexdump"]);
+        assert!(out.status.success());
+        assert_eq!(
+            "00000000  00 01 74 65 73 74                                 |..test|\n00000006\n",
+            str::from_utf8(&out.stdout).unwrap()
+        );
+
+        let out = decrypt_test_matched_data(&encrypted_matched_data, &["-o", "hex"]);
+        assert!(!out.status.success());
+    }
+
+    #[test]
+    fn test_diff() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let matched_data_file = temp.child("matched_data.txt");
+        matched_data_file
+            .write_str(&encrypt_test_matched_data(b"test\nmatched data\n"))
+            .unwrap();
+        let expected_file = temp.child("expected.txt");
+
+        let diff = |args: &[&str]| {
+            Command::cargo_bin("matched-data-cli")
+                .unwrap()
+                .args(["diff", "-k", private_key_file.path().to_str().unwrap()])
+                .arg(matched_data_file.path())
+                .arg(expected_file.path())
+                .args(args)
+                .output()
+                .unwrap()
+        };
+
+        expected_file.write_str("test\nmatched data\n").unwrap();
+        let out = diff(&[]);
+        assert!(out.status.success());
+        assert!(out.stdout.is_empty());
+        assert!(str::from_utf8(&out.stderr).unwrap().contains("identical"));
+
+        expected_file.write_str("test\nmatched date\n").unwrap();
+        let out = diff(&[]);
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stdout)
+            .unwrap()
+            .ends_with("@@ -1,2 +1,2 @@\n test\n-matched date\n+matched data\n"));
+        assert!(str::from_utf8(&out.stderr).unwrap().contains("at byte 16"));
+
+        let out = diff(&["--bytes"]);
+        assert!(!out.status.success());
+        assert!(str::from_utf8(&out.stdout).unwrap().contains(
+            "-00000010  65 0a                                             |e.|\n\
+             +00000010  61 0a                                             |a.|\n"
+        ));
+    }
+
+    #[test]
+    fn test_stats() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let other_private_key_file = temp.child("other_private_key.txt");
+        other_private_key_file
+            .write_str("cPs9c+2BmNjnfXFzvIUeDblpKMWJkctva92Sm0ec+3E=")
+            .unwrap();
+        let log_file = temp.child("events.log");
+        log_file
+            .write_str(&format!(
+                "{{\"RayID\":\"1\",\"Metadata\":{{\"encrypted_matched_data\":\"{}\"}}}}\n\
+                 {{\"RayID\":\"2\",\"Metadata\":{{\"encrypted_matched_data\":\"truncated\"}}}}\n\
+                 {{\"RayID\":\"3\"}}\n",
+                encrypt_test_matched_data(b"test matched data")
+            ))
+            .unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["stats", "-o", "json", "-k"])
+            .arg(private_key_file.path())
+            .arg("-k")
+            .arg(other_private_key_file.path())
+            .arg(log_file.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        // Only counted, the plaintext is never printed
+        assert!(!str::from_utf8(&out.stdout)
+            .unwrap()
+            .contains("test matched data"));
+        let stats: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(3, stats["events"]);
+        assert_eq!(2, stats["events_with_matched_data"]);
+        assert_eq!(1, stats["truncated"]);
+        assert_eq!(1, stats["versions"]["3"]);
+        assert_eq!(1, stats["decrypted"]);
+        assert_eq!(1, stats["keys"][0]["decrypted"]);
+        assert_eq!(0, stats["keys"][1]["decrypted"]);
+        assert_eq!(
+            serde_json::json!([{"min": 16, "max": 31, "count": 1}]),
+            stats["plaintext_sizes"]
+        );
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .arg("stats")
+            .arg(log_file.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert!(str::from_utf8(&out.stdout)
+            .unwrap()
+            .starts_with("Events                                 3\n"));
+        assert!(!str::from_utf8(&out.stdout).unwrap().contains("Decrypted"));
+    }
+
+    #[test]
+    fn test_pipe() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+
+        let out = Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args(["pipe", "-k", private_key_file.path().to_str().unwrap()])
+            .write_stdin(format!(
+                "{{\"RayID\":\"1\",\"encrypted_matched_data\":\"{}\"}}\n{{\"RayID\":\"2\"}}\n",
+                encrypt_test_matched_data(b"test matched data")
+            ))
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+
+        let events: Vec<serde_json::Value> = str::from_utf8(&out.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, events.len());
+        assert_eq!("test matched data", events[0]["decrypted_matched_data"]);
+        assert_eq!(serde_json::json!({"RayID": "2"}), events[1]);
+    }
+
+    #[test]
+    fn test_logs_extract_dir() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let private_key_file = temp.child("private_key.txt");
+        private_key_file.write_str(TEST_PRIVATE_KEY).unwrap();
+        let log_file = temp.child("events.log");
+        log_file
+            .write_str(&format!(
+                "{{\"RayID\":\"1\",\"Metadata\":{{\"encrypted_matched_data\":\"{}\"}}}}\n\
+                 {{\"Metadata\":{{\"encrypted_matched_data\":\"{}\"}}}}\n",
+                encrypt_test_matched_data(b"\x00\xffsample"),
+                encrypt_test_matched_data(b"\x00\xffsample")
+            ))
+            .unwrap();
+        let extract_dir = temp.child("samples");
+
+        Command::cargo_bin("matched-data-cli")
+            .unwrap()
+            .args([
+                "logs",
+                "-k",
+                private_key_file.path().to_str().unwrap(),
+                "--extract-dir",
+                extract_dir.path().to_str().unwrap(),
+                log_file.path().to_str().unwrap(),
+            ])
+            .assert()
+            .success();
+
+        // The identical payloads are stored once, each event being recorded in the index
+        let index = fs::read_to_string(extract_dir.child("index.ndjson").path()).unwrap();
+        let records: Vec<serde_json::Value> = index
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, records.len());
+        assert_eq!("1", records[0]["event"]);
+        assert_eq!("line 2", records[1]["event"]);
+        assert_eq!(records[0]["file"], records[1]["file"]);
+        let file = records[0]["file"].as_str().unwrap();
+        assert!(file.ends_with(".bin"));
+        assert_eq!(
+            b"\x00\xffsample".to_vec(),
+            fs::read(extract_dir.child(file).path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decrypt_http() {
+        let encrypted_matched_data = encrypt_test_matched_data(
+            br#"{"http.request.headers":{"content-type":["application/x-www-form-urlencoded"]},"http.request.body.raw":"q=a+b%21"}"#,
+        );
+        let out = decrypt_test_matched_data(&encrypted_matched_data, &["-o", "http"]);
+        assert_eq!(
+            "# Request line not in matched data\ncontent-type: application/x-www-form-urlencoded\n\n# Form fields, URL decoded\nq: a b!\n",
+            str::from_utf8(&out.stdout).unwrap()
+        );
+
+        // Plaintext that isn't an HTTP request is printed as is
+        let encrypted_matched_data = encrypt_test_matched_data(b"test matched data");
+        let out = decrypt_test_matched_data(&encrypted_matched_data, &["-o", "http"]);
+        assert_eq!("test matched data\n", str::from_utf8(&out.stdout).unwrap());
+    }
+
+    #[test]
+    fn test_decrypt_select() {
+        let encrypted_matched_data = encrypt_test_matched_data(
+            br#"{"http.request.headers":{"x-token":["abc"]},"http.request.body.raw":"a=1"}"#,
+        );
+        let out = decrypt_test_matched_data(
+            &encrypted_matched_data,
+            &[
+                "--select",
+                "$['http.request.body.raw']",
+                "--select",
+                "$['http.request.headers']",
+            ],
+        );
+        assert_eq!(
+            "a=1\n{\"x-token\":[\"abc\"]}\n",
+            str::from_utf8(&out.stdout).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decrypt_redact() {
+        let encrypted_matched_data = encrypt_test_matched_data(
+            br#"{"http.request.body.raw":"email=a@example.com&cc=4111111111111111&token=abc"}"#,
+        );
+        let out = decrypt_test_matched_data(
+            &encrypted_matched_data,
+            &["--redact-pii", "--redact", "token=\\w+"],
+        );
+        assert_eq!(
+            "{\"http.request.body.raw\":\"email=[REDACTED]&cc=[REDACTED]&[REDACTED]\"}\n",
+            str::from_utf8(&out.stdout).unwrap()
         );
+    }
+
+    #[test]
+    fn test_decrypt_audit_log() {
+        let encrypted_matched_data = encrypt_test_matched_data(b"test matched data");
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let audit_log_file = temp_dir.child("audit.log");
+
+        for _ in 0..2 {
+            let out = decrypt_test_matched_data(
+                &encrypted_matched_data,
+                &["--audit-log", audit_log_file.path().to_str().unwrap()],
+            );
+            assert!(out.status.success());
+        }
+
+        let audit_log = fs::read_to_string(audit_log_file.path()).unwrap();
+        let records: Vec<serde_json::Value> = audit_log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, records.len());
+        assert_eq!("decrypt", records[0]["command"]);
+        assert_eq!("success", records[0]["outcome"]);
+        assert_eq!(64, records[0]["input_sha256"].as_str().unwrap().len());
+        assert!(!audit_log.contains("test matched data"));
         temp_dir.close().unwrap();
     }
 }
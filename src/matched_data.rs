@@ -1,32 +1,293 @@
-use bincode::ErrorKind;
+use crate::versions::{self, find_decryptor, supported_versions};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use chacha20poly1305::aead::generic_array::typenum::Unsigned;
+use chacha20poly1305::aead::{AeadInPlace, NewAead};
+use core::fmt;
+use hkdf::{Hkdf, HkdfExtract};
 use hpke::{
-    aead::{AeadTag, ChaCha20Poly1305},
-    kdf::HkdfSha256,
+    aead::{Aead as AeadTrait, AeadTag, ChaCha20Poly1305},
+    kdf::{HkdfSha256, Kdf as KdfTrait},
     kem::X25519HkdfSha256,
-    setup_receiver, Deserializable, HpkeError, Kem as KemTrait, OpModeR,
+    setup_receiver, setup_sender, Deserializable, HpkeError, Kem as KemTrait, OpModeR, OpModeS,
+    PskBundle, Serializable,
 };
+#[cfg(feature = "std")]
 use rand::{rngs::StdRng, SeedableRng};
+use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zeroize::Zeroizing;
 
-type Kem = X25519HkdfSha256;
-type Aead = ChaCha20Poly1305;
-type Kdf = HkdfSha256;
+// KEM, KDF and AEAD of an encryption format version. A format using other algorithms, such as
+// P-256 or AES-GCM, is supported by adding a suite and registering its decryptor in versions.rs
+pub trait CipherSuite {
+    // Version byte prefixing matched data encrypted with this suite
+    const VERSION: u8;
 
-type PrivateKey = <Kem as KemTrait>::PrivateKey;
-type PublicKey = <Kem as KemTrait>::PublicKey;
-type EncappedKey = <Kem as KemTrait>::EncappedKey;
+    type Kem: KemTrait;
+    type Kdf: KdfTrait;
+    type Aead: AeadTrait;
+}
+
+// HPKE with DHKEM(X25519, HKDF-SHA256), HKDF-SHA256 and ChaCha20Poly1305, format version 3
+pub struct X25519HkdfSha256ChaCha20Poly1305;
+
+impl CipherSuite for X25519HkdfSha256ChaCha20Poly1305 {
+    const VERSION: u8 = 3;
+
+    type Kem = X25519HkdfSha256;
+    type Kdf = HkdfSha256;
+    type Aead = ChaCha20Poly1305;
+}
+
+// Suite keys are generated for and matched data is encrypted with
+pub type CurrentSuite = X25519HkdfSha256ChaCha20Poly1305;
+
+type Kem = <CurrentSuite as CipherSuite>::Kem;
+
+// RFC 9180 suite identifiers of DHKEM(X25519, HKDF-SHA256), and of HPKE with it, HKDF-SHA256 and
+// ChaCha20Poly1305, used when the Diffie-Hellman step happens outside of hpke
+const KEM_SUITE_ID: &[u8] = b"KEM\x00\x20";
+const HPKE_SUITE_ID: &[u8] = b"HPKE\x00\x20\x00\x01\x00\x03";
+
+pub type PrivateKey = <Kem as KemTrait>::PrivateKey;
+pub type PublicKey = <Kem as KemTrait>::PublicKey;
 
 #[derive(Serialize, Deserialize)]
-pub struct EncryptedData {
-    encapped_key: EncappedKey,
+#[serde(bound(
+    serialize = "<S::Kem as KemTrait>::EncappedKey: Serialize, AeadTag<S::Aead>: Serialize",
+    deserialize = "<S::Kem as KemTrait>::EncappedKey: Deserialize<'de>, AeadTag<S::Aead>: Deserialize<'de>"
+))]
+pub struct EncryptedData<S: CipherSuite = CurrentSuite> {
+    encapped_key: <S::Kem as KemTrait>::EncappedKey,
     ciphertext: Vec<u8>,
-    tag: AeadTag<Aead>,
+    tag: AeadTag<S::Aead>,
+}
+
+impl<S: CipherSuite> EncryptedData<S> {
+    // Assembles encrypted data from its components, such as ones stored separately
+    pub fn new(
+        encapped_key: <S::Kem as KemTrait>::EncappedKey,
+        ciphertext: Vec<u8>,
+        tag: AeadTag<S::Aead>,
+    ) -> Self {
+        EncryptedData {
+            encapped_key,
+            ciphertext,
+            tag,
+        }
+    }
+
+    // Returns the encapsulated ephemeral public key of the sender
+    pub fn encapped_key(&self) -> &<S::Kem as KemTrait>::EncappedKey {
+        &self.encapped_key
+    }
+
+    // Returns the ciphertext, as long as the plaintext
+    pub fn ciphertext(&self) -> &[u8] {
+        &self.ciphertext
+    }
+
+    // Returns the AEAD authentication tag
+    pub fn tag(&self) -> &AeadTag<S::Aead> {
+        &self.tag
+    }
+
+    // Returns the lengths of the encapsulated key, ciphertext and tag
+    pub fn component_lengths(&self) -> (usize, usize, usize) {
+        (
+            self.encapped_key.to_bytes().len(),
+            self.ciphertext.len(),
+            self.tag.to_bytes().len(),
+        )
+    }
+
+    // Returns the ephemeral public key of the sender
+    pub fn encapped_key_bytes(&self) -> Vec<u8> {
+        self.encapped_key.to_bytes().to_vec()
+    }
+}
+
+// Pre-shared key mixed into the key schedule in PSK and auth PSK modes, identified by its ID
+#[derive(Clone, Copy)]
+pub struct Psk<'a> {
+    pub psk: &'a [u8],
+    pub psk_id: &'a [u8],
+}
+
+impl<'a> Psk<'a> {
+    fn bundle(&self) -> PskBundle<'a> {
+        PskBundle {
+            psk: self.psk,
+            psk_id: self.psk_id,
+        }
+    }
+}
+
+// Parameters of the HPKE mode matched data was encrypted in and its associated data, the base
+// mode without associated data used by Cloudflare when none are set
+#[derive(Clone, Copy, Default)]
+pub struct Mode<'a> {
+    // Public key of the sender in auth mode, verifying the matched data was encrypted by the
+    // holder of its private key
+    pub sender_public_key: Option<&'a [u8]>,
+    // Shared secret in PSK mode, or auth PSK mode with a sender public key
+    pub psk: Option<Psk<'a>>,
+    // Associated data authenticated along with the ciphertext but not encrypted, such as event
+    // metadata the matched data is bound to
+    pub aad: &'a [u8],
+}
+
+impl<'a> Mode<'a> {
+    // Receiver mode of a suite, with the sender public key deserialized
+    pub fn receiver<K: KemTrait>(&self) -> Result<OpModeR<'a, K>, DecryptAnyVersionError> {
+        let sender_public_key = self
+            .sender_public_key
+            .map(K::PublicKey::from_bytes)
+            .transpose()
+            .map_err(DecryptAnyVersionError::InvalidSenderPublicKey)?;
+
+        Ok(match (sender_public_key, self.psk) {
+            (None, None) => OpModeR::Base,
+            (None, Some(psk)) => OpModeR::Psk(psk.bundle()),
+            (Some(sender_public_key), None) => OpModeR::Auth(sender_public_key),
+            (Some(sender_public_key), Some(psk)) => {
+                OpModeR::AuthPsk(sender_public_key, psk.bundle())
+            }
+        })
+    }
+}
+
+// Decryption step that failed
+#[derive(Debug)]
+pub enum DecryptError {
+    // Deriving the shared secret from the encapsulated key failed
+    Decapsulation(HpkeError),
+    // Authenticating and decrypting the ciphertext failed
+    Open(HpkeError),
+}
+
+// Why serialized encrypted data couldn't be deserialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MalformedData {
+    // There is no version byte
+    MissingVersion,
+    // The data ends within a component, the encapsulated key, ciphertext length, ciphertext or
+    // tag, of the expected length, short of the bytes missing
+    Truncated {
+        component: &'static str,
+        length: u64,
+        missing: u64,
+    },
+    // The encapsulated key has the expected length but isn't valid for the suite's KEM
+    InvalidEncappedKey,
+    // Bytes follow the tag, such as another matched data pasted after it
+    TrailingBytes(u64),
+}
+
+// Plural suffix of a number of bytes
+fn bytes(count: u64) -> &'static str {
+    if count == 1 {
+        "byte"
+    } else {
+        "bytes"
+    }
+}
+
+impl fmt::Display for MalformedData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MalformedData::MissingVersion => write!(f, "there is no version byte"),
+            MalformedData::Truncated {
+                component,
+                length,
+                missing,
+            } => write!(
+                f,
+                "the {} of {} {} is {} {} short",
+                component,
+                length,
+                bytes(*length),
+                missing,
+                bytes(*missing)
+            ),
+            MalformedData::InvalidEncappedKey => {
+                write!(f, "the encapsulated key is not a valid public key")
+            }
+            MalformedData::TrailingBytes(count) => {
+                write!(f, "{} unexpected {} follow the tag", count, bytes(*count))
+            }
+        }
+    }
+}
+
+// Reason serialized matched data couldn't be decrypted
+#[derive(Debug)]
+pub enum DecryptAnyVersionError {
+    // There is no version byte
+    Empty,
+    // No decryptor is registered for the encryption format version
+    UnsupportedVersion(u8),
+    InvalidPrivateKey(HpkeError),
+    InvalidSenderPublicKey(HpkeError),
+    InvalidMatchedData(MalformedData),
+    // The encrypted data was deserialized but couldn't be decrypted
+    Decrypt {
+        component_lengths: (usize, usize, usize),
+        error: DecryptError,
+    },
+}
+
+impl fmt::Display for DecryptAnyVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecryptAnyVersionError::Empty => write!(f, "Provided matched data is empty"),
+            DecryptAnyVersionError::UnsupportedVersion(version) => {
+                let expected: Vec<String> = supported_versions()
+                    .iter()
+                    .map(|supported| format!("'{}'", supported))
+                    .collect();
+                write!(
+                    f,
+                    "Encryption format not supported, expected {}, got '{}'",
+                    expected.join(", "),
+                    version
+                )
+            }
+            DecryptAnyVersionError::InvalidPrivateKey(_) => {
+                write!(f, "Provided private key is invalid")
+            }
+            DecryptAnyVersionError::InvalidSenderPublicKey(_) => {
+                write!(f, "Provided sender public key is invalid")
+            }
+            DecryptAnyVersionError::InvalidMatchedData(e) => {
+                write!(f, "Provided matched data is invalid, {}", e)
+            }
+            DecryptAnyVersionError::Decrypt { .. } => write!(f, "Failed to decrypt matched data"),
+        }
+    }
 }
 
 // Generates a public-private key pair
+#[cfg(feature = "std")]
 pub fn generate_key_pair() -> (PrivateKey, PublicKey) {
-    let mut csprng = StdRng::from_entropy();
-    Kem::gen_keypair(&mut csprng)
+    generate_key_pair_with_rng(&mut StdRng::from_entropy())
+}
+
+// Generates a public-private key pair from provided RNG, such as a hardware one where there is no
+// operating system to draw entropy from
+pub fn generate_key_pair_with_rng<R: CryptoRng + RngCore>(
+    csprng: &mut R,
+) -> (PrivateKey, PublicKey) {
+    Kem::gen_keypair(csprng)
+}
+
+// Deterministically derives a public-private key pair from input keying material
+pub fn derive_key_pair(ikm: &[u8]) -> (PrivateKey, PublicKey) {
+    Kem::derive_keypair(ikm)
 }
 
 // Constructs a PrivateKey from an array of bytes
@@ -34,22 +295,46 @@ pub fn get_private_key_from_bytes(private_key_bytes: &[u8]) -> Result<PrivateKey
     PrivateKey::from_bytes(private_key_bytes)
 }
 
-// Decrypts data with provided private key
-pub fn decrypt_data(
-    encrypted_data: &EncryptedData,
-    private_key: &PrivateKey,
-) -> Result<Vec<u8>, HpkeError> {
+// Constructs a PublicKey from an array of bytes
+pub fn get_public_key_from_bytes(public_key_bytes: &[u8]) -> Result<PublicKey, HpkeError> {
+    PublicKey::from_bytes(public_key_bytes)
+}
+
+// Derives the public key of a private key
+pub fn get_public_key(private_key: &PrivateKey) -> PublicKey {
+    Kem::sk_to_pk(private_key)
+}
+
+// Decrypts data with provided private key, with the algorithms of its cipher suite
+pub fn decrypt_data<S: CipherSuite>(
+    encrypted_data: &EncryptedData<S>,
+    private_key: &<S::Kem as KemTrait>::PrivateKey,
+) -> Result<Zeroizing<Vec<u8>>, DecryptError> {
+    decrypt_data_in_mode(encrypted_data, private_key, &OpModeR::Base, &[])
+}
+
+// Decrypts data with provided private key, encrypted in an HPKE mode such as auth mode and with
+// associated data
+pub fn decrypt_data_in_mode<S: CipherSuite>(
+    encrypted_data: &EncryptedData<S>,
+    private_key: &<S::Kem as KemTrait>::PrivateKey,
+    mode: &OpModeR<'_, S::Kem>,
+    aad: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, DecryptError> {
     // Decapsulate and derive the shared secret. Create a shared AEAD context.
-    let mut aead_ctx = setup_receiver::<Aead, Kdf, Kem>(
-        &OpModeR::Base,
+    let mut aead_ctx = setup_receiver::<S::Aead, S::Kdf, S::Kem>(
+        mode,
         private_key,
         &encrypted_data.encapped_key,
         &[],
-    )?;
+    )
+    .map_err(DecryptError::Decapsulation)?;
 
-    // Decrypt ciphertext in place
-    let mut ciphertext_copy = encrypted_data.ciphertext.clone();
-    aead_ctx.open_in_place_detached(&mut ciphertext_copy, &[], &encrypted_data.tag)?;
+    // Decrypt ciphertext in place, wiping the plaintext once the caller drops it
+    let mut ciphertext_copy = Zeroizing::new(encrypted_data.ciphertext.clone());
+    aead_ctx
+        .open_in_place_detached(&mut ciphertext_copy, aad, &encrypted_data.tag)
+        .map_err(DecryptError::Open)?;
 
     // Rename for clarity
     let plaintext = ciphertext_copy;
@@ -57,9 +342,594 @@ pub fn decrypt_data(
     Ok(plaintext)
 }
 
-// Deserializes an array of bytes using bincode into encrypted data
-pub fn deserialize_encrypted_data(
+// Decrypts data with the X25519 shared secret of the recipient private key and the encapsulated
+// key, computed by a device holding the private key such as a YubiKey. Only format version 3 is
+// supported, as the rest of its key schedule and ChaCha20Poly1305 are implemented here
+pub fn decrypt_data_with_dh(
+    encrypted_data: &EncryptedData<X25519HkdfSha256ChaCha20Poly1305>,
+    public_key: &PublicKey,
+    dh: &[u8],
+    aad: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, DecryptError> {
+    // An all-zero shared secret means the encapsulated key is a low order point
+    if dh.len() != 32 || dh.iter().all(|&byte| byte == 0) {
+        return Err(DecryptError::Decapsulation(HpkeError::ValidationError));
+    }
+
+    // Derive the shared secret as DHKEM Decap does
+    let kem_context = [
+        encrypted_data.encapped_key.to_bytes().as_slice(),
+        public_key.to_bytes().as_slice(),
+    ]
+    .concat();
+    let eae_prk = labeled_extract(KEM_SUITE_ID, &[], b"eae_prk", dh);
+    let mut shared_secret = Zeroizing::new([0; 32]);
+    labeled_expand(
+        KEM_SUITE_ID,
+        &eae_prk,
+        b"shared_secret",
+        &kem_context,
+        shared_secret.as_mut(),
+    );
+
+    // Run the base mode key schedule with empty info
+    let psk_id_hash = labeled_extract(HPKE_SUITE_ID, &[], b"psk_id_hash", &[]);
+    let info_hash = labeled_extract(HPKE_SUITE_ID, &[], b"info_hash", &[]);
+    let key_schedule_context = [&[0][..], psk_id_hash.as_slice(), info_hash.as_slice()].concat();
+    let secret = labeled_extract(HPKE_SUITE_ID, shared_secret.as_ref(), b"secret", &[]);
+    let mut key = Zeroizing::new([0; 32]);
+    labeled_expand(
+        HPKE_SUITE_ID,
+        &secret,
+        b"key",
+        &key_schedule_context,
+        key.as_mut(),
+    );
+    let mut base_nonce = [0; 12];
+    labeled_expand(
+        HPKE_SUITE_ID,
+        &secret,
+        b"base_nonce",
+        &key_schedule_context,
+        &mut base_nonce,
+    );
+
+    // The first message uses the base nonce as is
+    let mut plaintext = Zeroizing::new(encrypted_data.ciphertext.clone());
+    chacha20poly1305::ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key.as_ref()))
+        .decrypt_in_place_detached(
+            chacha20poly1305::Nonce::from_slice(&base_nonce),
+            aad,
+            &mut plaintext,
+            chacha20poly1305::Tag::from_slice(&encrypted_data.tag.to_bytes()),
+        )
+        .map_err(|_| DecryptError::Open(HpkeError::OpenError))?;
+
+    Ok(plaintext)
+}
+
+// LabeledExtract of RFC 9180 with HKDF-SHA256
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> Zeroizing<Vec<u8>> {
+    let mut extract = HkdfExtract::<Sha256>::new(Some(salt));
+    for input in [&b"HPKE-v1"[..], suite_id, label, ikm] {
+        extract.input_ikm(input);
+    }
+    Zeroizing::new(extract.finalize().0.to_vec())
+}
+
+// LabeledExpand of RFC 9180 with HKDF-SHA256, filling okm
+fn labeled_expand(suite_id: &[u8], prk: &[u8], label: &[u8], info: &[u8], okm: &mut [u8]) {
+    let length = (okm.len() as u16).to_be_bytes();
+    Hkdf::<Sha256>::from_prk(prk)
+        .expect("Pseudorandom key is shorter than the hash")
+        .expand_multi_info(&[&length, b"HPKE-v1", suite_id, label, info], okm)
+        .expect("Output is too long for HKDF-SHA256");
+}
+
+// Encrypts data to provided public key
+#[cfg(feature = "std")]
+pub fn encrypt_data(plaintext: &[u8], public_key: &PublicKey) -> Result<EncryptedData, HpkeError> {
+    encrypt_data_with_rng(plaintext, public_key, &mut StdRng::from_entropy())
+}
+
+// Encrypts data to provided public key, drawing the ephemeral key from provided RNG
+pub fn encrypt_data_with_rng<R: CryptoRng + RngCore>(
+    plaintext: &[u8],
+    public_key: &PublicKey,
+    csprng: &mut R,
+) -> Result<EncryptedData, HpkeError> {
+    encrypt_data_with_suite::<CurrentSuite, R>(plaintext, public_key, &OpModeS::Base, &[], csprng)
+}
+
+// Encrypts data to provided public key in auth mode with a sender private key, so the recipient
+// can verify it was encrypted by its holder, in PSK mode with a pre-shared key, and with
+// associated data the recipient has to provide too
+#[cfg(feature = "std")]
+pub fn encrypt_data_in_mode(
+    plaintext: &[u8],
+    public_key: &PublicKey,
+    sender_private_key: Option<&PrivateKey>,
+    psk: Option<&Psk<'_>>,
+    aad: &[u8],
+) -> Result<EncryptedData, HpkeError> {
+    let sender_key_pair =
+        sender_private_key.map(|private_key| (private_key.clone(), get_public_key(private_key)));
+    let mode = match (sender_key_pair, psk) {
+        (None, None) => OpModeS::Base,
+        (None, Some(psk)) => OpModeS::Psk(psk.bundle()),
+        (Some(sender_key_pair), None) => OpModeS::Auth(sender_key_pair),
+        (Some(sender_key_pair), Some(psk)) => OpModeS::AuthPsk(sender_key_pair, psk.bundle()),
+    };
+
+    encrypt_data_with_suite::<CurrentSuite, _>(
+        plaintext,
+        public_key,
+        &mode,
+        aad,
+        &mut StdRng::from_entropy(),
+    )
+}
+
+// Encrypts data to provided public key in an HPKE mode with associated data and the algorithms of
+// a cipher suite
+pub fn encrypt_data_with_suite<S: CipherSuite, R: CryptoRng + RngCore>(
+    plaintext: &[u8],
+    public_key: &<S::Kem as KemTrait>::PublicKey,
+    mode: &OpModeS<'_, S::Kem>,
+    aad: &[u8],
+    csprng: &mut R,
+) -> Result<EncryptedData<S>, HpkeError> {
+    // Encapsulate a fresh shared secret for the recipient. Create a shared AEAD context.
+    let (encapped_key, mut aead_ctx) =
+        setup_sender::<S::Aead, S::Kdf, S::Kem, _>(mode, public_key, &[], csprng)?;
+
+    // Encrypt plaintext in place
+    let mut ciphertext = plaintext.to_vec();
+    let tag = aead_ctx.seal_in_place_detached(&mut ciphertext, aad)?;
+
+    Ok(EncryptedData::new(encapped_key, ciphertext, tag))
+}
+
+// Serializes encrypted data, prefixed with the format version of its cipher suite, as bincode
+// with fixed size integers does: the encapsulated key, the length of the ciphertext as a
+// little-endian u64 followed by it, then the tag. Written out rather than with bincode, which
+// needs std
+pub fn serialize_encrypted_data<S: CipherSuite>(encrypted_data: &EncryptedData<S>) -> Vec<u8> {
+    let mut serialized_encrypted_data = vec![S::VERSION];
+    serialized_encrypted_data.extend_from_slice(&encrypted_data.encapped_key.to_bytes());
+    serialized_encrypted_data
+        .extend_from_slice(&(encrypted_data.ciphertext.len() as u64).to_le_bytes());
+    serialized_encrypted_data.extend_from_slice(&encrypted_data.ciphertext);
+    serialized_encrypted_data.extend_from_slice(&encrypted_data.tag.to_bytes());
+    serialized_encrypted_data
+}
+
+// Deserializes encrypted data of a cipher suite serialized by serialize_encrypted_data, checking
+// the length of each component against the bytes left before reading it, and that none follow the
+// tag. The ciphertext is only copied once its length is known to fit in the input, so a hostile
+// length can't make it allocate more
+pub fn deserialize_encrypted_data<S: CipherSuite>(
+    serialized_encrypted_data: &[u8],
+) -> Result<EncryptedData<S>, MalformedData> {
+    let mut remaining = serialized_encrypted_data
+        .get(1..)
+        .ok_or(MalformedData::MissingVersion)?;
+    let mut take = |component: &'static str, length: u64| {
+        if length > remaining.len() as u64 {
+            return Err(MalformedData::Truncated {
+                component,
+                length,
+                missing: length - remaining.len() as u64,
+            });
+        }
+        let (taken, rest) = remaining.split_at(length as usize);
+        remaining = rest;
+        Ok(taken)
+    };
+
+    let encapped_key = take(
+        "encapsulated key",
+        serialized_length::<<S::Kem as KemTrait>::EncappedKey>(),
+    )?;
+    let encapped_key = <S::Kem as KemTrait>::EncappedKey::from_bytes(encapped_key)
+        .map_err(|_| MalformedData::InvalidEncappedKey)?;
+    let ciphertext_length = take("ciphertext length", 8)?;
+    let ciphertext_length = u64::from_le_bytes(
+        ciphertext_length
+            .try_into()
+            .expect("Ciphertext length is 8 bytes"),
+    );
+    let ciphertext = take("ciphertext", ciphertext_length)?.to_vec();
+    let tag = take("tag", serialized_length::<AeadTag<S::Aead>>())?;
+    let tag = AeadTag::<S::Aead>::from_bytes(tag).expect("Tag has the length of the suite's tags");
+    if !remaining.is_empty() {
+        return Err(MalformedData::TrailingBytes(remaining.len() as u64));
+    }
+
+    Ok(EncryptedData::new(encapped_key, ciphertext, tag))
+}
+
+// Length in bytes of a serialized component of encrypted data
+fn serialized_length<T: Serializable>() -> u64 {
+    T::OutputSize::U64
+}
+
+// Decrypts serialized matched data according to its encryption format version
+pub fn decrypt_any_version(
+    private_key_bytes: &[u8],
     serialized_encrypted_data: &[u8],
-) -> Result<EncryptedData, Box<ErrorKind>> {
-    bincode::deserialize(&serialized_encrypted_data[1..])
+) -> Result<Zeroizing<Vec<u8>>, DecryptAnyVersionError> {
+    decrypt_any_version_in_mode(
+        private_key_bytes,
+        serialized_encrypted_data,
+        &Mode::default(),
+    )
+}
+
+// Decrypts serialized matched data encrypted in an HPKE mode according to its encryption format
+// version
+pub fn decrypt_any_version_in_mode(
+    private_key_bytes: &[u8],
+    serialized_encrypted_data: &[u8],
+    mode: &Mode<'_>,
+) -> Result<Zeroizing<Vec<u8>>, DecryptAnyVersionError> {
+    let version = *serialized_encrypted_data
+        .first()
+        .ok_or(DecryptAnyVersionError::Empty)?;
+    let decryptor =
+        find_decryptor(version).ok_or(DecryptAnyVersionError::UnsupportedVersion(version))?;
+
+    decryptor.decrypt(private_key_bytes, serialized_encrypted_data, mode)
+}
+
+// Decrypts any number of matched data with a private key deserialized and validated once, rather
+// than for each of them as decrypt_any_version does
+pub struct Decryptor {
+    // Deserialized again by the decryptors of format versions of other suites
+    private_key_bytes: Zeroizing<Vec<u8>>,
+    private_key: PrivateKey,
+}
+
+impl Decryptor {
+    pub fn new(private_key_bytes: &[u8]) -> Result<Self, DecryptAnyVersionError> {
+        let private_key = get_private_key_from_bytes(private_key_bytes)
+            .map_err(DecryptAnyVersionError::InvalidPrivateKey)?;
+
+        Ok(Decryptor {
+            private_key_bytes: Zeroizing::new(private_key_bytes.to_vec()),
+            private_key,
+        })
+    }
+
+    // Public key of the private key, such as to fingerprint it
+    pub fn public_key(&self) -> PublicKey {
+        get_public_key(&self.private_key)
+    }
+
+    // Address and length of the memory holding the private key, the decryptor itself and its copy
+    // of the key bytes, such as to lock it in RAM. The decryptor must not move afterwards, which
+    // boxing it ensures
+    pub fn memory_regions(&self) -> [(*const u8, usize); 2] {
+        [
+            ((self as *const Self).cast(), core::mem::size_of::<Self>()),
+            (
+                self.private_key_bytes.as_ptr(),
+                self.private_key_bytes.len(),
+            ),
+        ]
+    }
+
+    // Decrypts serialized matched data according to its encryption format version
+    pub fn decrypt(
+        &self,
+        serialized_encrypted_data: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, DecryptAnyVersionError> {
+        self.decrypt_in_mode(serialized_encrypted_data, &Mode::default())
+    }
+
+    // Decrypts serialized matched data encrypted in an HPKE mode according to its encryption
+    // format version
+    pub fn decrypt_in_mode(
+        &self,
+        serialized_encrypted_data: &[u8],
+        mode: &Mode<'_>,
+    ) -> Result<Zeroizing<Vec<u8>>, DecryptAnyVersionError> {
+        let version = *serialized_encrypted_data
+            .first()
+            .ok_or(DecryptAnyVersionError::Empty)?;
+        if version == CurrentSuite::VERSION {
+            return versions::CURRENT.decrypt_with_private_key(
+                &self.private_key,
+                serialized_encrypted_data,
+                mode,
+            );
+        }
+        let decryptor =
+            find_decryptor(version).ok_or(DecryptAnyVersionError::UnsupportedVersion(version))?;
+
+        decryptor.decrypt(&self.private_key_bytes, serialized_encrypted_data, mode)
+    }
+}
+
+// Keys are generated and data encrypted with the operating system's random source
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypt_in_mode() {
+        let (private_key, public_key) = generate_key_pair();
+        let (sender_private_key, sender_public_key) = generate_key_pair();
+        let (_, other_public_key) = generate_key_pair();
+        let psk = Psk {
+            psk: &[7; 32],
+            psk_id: b"test",
+        };
+        let other_psk = Psk {
+            psk: &[8; 32],
+            psk_id: b"test",
+        };
+        let encrypt = |sender_private_key: Option<&PrivateKey>, psk: Option<&Psk<'_>>| {
+            serialize_encrypted_data(
+                &encrypt_data_in_mode(
+                    b"test matched data",
+                    &public_key,
+                    sender_private_key,
+                    psk,
+                    &[],
+                )
+                .unwrap(),
+            )
+        };
+        let decrypt = |serialized_encrypted_data: &[u8], mode: Mode<'_>| {
+            decrypt_any_version_in_mode(&private_key.to_bytes(), serialized_encrypted_data, &mode)
+        };
+        let sender_public_key_bytes = sender_public_key.to_bytes();
+        let other_public_key_bytes = other_public_key.to_bytes();
+
+        // Auth mode
+        let auth = encrypt(Some(&sender_private_key), None);
+        assert_eq!(
+            b"test matched data".to_vec(),
+            *decrypt(
+                &auth,
+                Mode {
+                    sender_public_key: Some(&sender_public_key_bytes),
+                    psk: None,
+                    ..Mode::default()
+                }
+            )
+            .unwrap()
+        );
+        assert!(matches!(
+            decrypt(
+                &auth,
+                Mode {
+                    sender_public_key: Some(&other_public_key_bytes),
+                    psk: None,
+                    ..Mode::default()
+                }
+            ),
+            Err(DecryptAnyVersionError::Decrypt { .. })
+        ));
+        assert!(matches!(
+            decrypt(&auth, Mode::default()),
+            Err(DecryptAnyVersionError::Decrypt { .. })
+        ));
+        assert!(matches!(
+            decrypt(
+                &auth,
+                Mode {
+                    sender_public_key: Some(&[1; 3]),
+                    psk: None,
+                    ..Mode::default()
+                }
+            ),
+            Err(DecryptAnyVersionError::InvalidSenderPublicKey(_))
+        ));
+
+        // PSK and auth PSK modes
+        let psk_mode = encrypt(None, Some(&psk));
+        let psk_mode_decrypt = |psk| Mode {
+            sender_public_key: None,
+            psk: Some(psk),
+            ..Mode::default()
+        };
+        assert!(decrypt(&psk_mode, psk_mode_decrypt(psk)).is_ok());
+        assert!(decrypt(&psk_mode, psk_mode_decrypt(other_psk)).is_err());
+        assert!(decrypt(&psk_mode, Mode::default()).is_err());
+
+        let auth_psk = encrypt(Some(&sender_private_key), Some(&psk));
+        assert!(decrypt(
+            &auth_psk,
+            Mode {
+                sender_public_key: Some(&sender_public_key_bytes),
+                psk: Some(psk),
+                ..Mode::default()
+            }
+        )
+        .is_ok());
+        assert!(decrypt(&auth_psk, psk_mode_decrypt(psk)).is_err());
+
+        // Associated data
+        let aad = serialize_encrypted_data(
+            &encrypt_data_in_mode(b"test matched data", &public_key, None, None, b"ray id")
+                .unwrap(),
+        );
+        let aad_decrypt = |aad| Mode {
+            aad,
+            ..Mode::default()
+        };
+        assert!(decrypt(&aad, aad_decrypt(b"ray id")).is_ok());
+        assert!(decrypt(&aad, aad_decrypt(b"other ray id")).is_err());
+        assert!(decrypt(&aad, Mode::default()).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_data() {
+        let (private_key, public_key) = generate_key_pair();
+
+        let serialized_encrypted_data =
+            serialize_encrypted_data(&encrypt_data(b"test matched data", &public_key).unwrap());
+        assert_eq!(
+            Some(&CurrentSuite::VERSION),
+            serialized_encrypted_data.first()
+        );
+        assert_eq!(
+            b"test matched data".to_vec(),
+            *decrypt_any_version(&private_key.to_bytes(), &serialized_encrypted_data).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encrypted_data_components() {
+        let (private_key, public_key) = generate_key_pair();
+        let encrypted_data = encrypt_data(b"test matched data", &public_key).unwrap();
+        assert_eq!(32, encrypted_data.encapped_key().to_bytes().len());
+        assert_eq!(17, encrypted_data.ciphertext().len());
+        assert_eq!(16, encrypted_data.tag().to_bytes().len());
+
+        let reassembled = EncryptedData::<CurrentSuite>::new(
+            Deserializable::from_bytes(&encrypted_data.encapped_key().to_bytes()).unwrap(),
+            encrypted_data.ciphertext().to_vec(),
+            Deserializable::from_bytes(&encrypted_data.tag().to_bytes()).unwrap(),
+        );
+        assert_eq!(
+            b"test matched data".to_vec(),
+            *decrypt_data(&reassembled, &private_key).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_deserialize_encrypted_data() {
+        let (_, public_key) = generate_key_pair();
+        let serialized_encrypted_data =
+            serialize_encrypted_data(&encrypt_data(b"test matched data", &public_key).unwrap());
+        assert!(deserialize_encrypted_data::<CurrentSuite>(&serialized_encrypted_data).is_ok());
+
+        // Ciphertext length prefix far beyond the input, failing without allocating that much
+        let mut hostile = serialized_encrypted_data[..33].to_vec();
+        hostile.extend(u64::MAX.to_le_bytes());
+        hostile.extend([0; 16]);
+        assert_eq!(
+            Some(MalformedData::Truncated {
+                component: "ciphertext",
+                length: u64::MAX,
+                missing: u64::MAX - 16
+            }),
+            deserialize_encrypted_data::<CurrentSuite>(&hostile).err()
+        );
+        assert_eq!(
+            Some(MalformedData::Truncated {
+                component: "encapsulated key",
+                length: 32,
+                missing: 32
+            }),
+            deserialize_encrypted_data::<CurrentSuite>(&[3]).err()
+        );
+        assert_eq!(
+            Some(MalformedData::MissingVersion),
+            deserialize_encrypted_data::<CurrentSuite>(&[]).err()
+        );
+        assert_eq!(
+            Some(MalformedData::Truncated {
+                component: "tag",
+                length: 16,
+                missing: 1
+            }),
+            deserialize_encrypted_data::<CurrentSuite>(
+                &serialized_encrypted_data[..serialized_encrypted_data.len() - 1]
+            )
+            .err()
+        );
+        assert_eq!(
+            Some(MalformedData::TrailingBytes(2)),
+            deserialize_encrypted_data::<CurrentSuite>(
+                &[serialized_encrypted_data.as_slice(), &[0, 0]].concat()
+            )
+            .err()
+        );
+        assert_eq!(
+            "Provided matched data is invalid, the tag of 16 bytes is 1 byte short",
+            DecryptAnyVersionError::InvalidMatchedData(MalformedData::Truncated {
+                component: "tag",
+                length: 16,
+                missing: 1
+            })
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_decryptor() {
+        let private_key_bytes = radix64::STD
+            .decode("uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=")
+            .unwrap();
+        let decryptor = Decryptor::new(&private_key_bytes).unwrap();
+        let serialized_encrypted_data = radix64::STD
+            .decode("AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=")
+            .unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(
+                b"test matched data".to_vec(),
+                *decryptor.decrypt(&serialized_encrypted_data).unwrap()
+            );
+        }
+        assert!(matches!(
+            decryptor.decrypt(&[]),
+            Err(DecryptAnyVersionError::Empty)
+        ));
+        assert!(matches!(
+            decryptor.decrypt(&[2]),
+            Err(DecryptAnyVersionError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            decryptor.decrypt(&[3, 1]),
+            Err(DecryptAnyVersionError::InvalidMatchedData(_))
+        ));
+        assert!(matches!(
+            Decryptor::new(&[1; 3]),
+            Err(DecryptAnyVersionError::InvalidPrivateKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_data_with_dh() {
+        let private_key_bytes = radix64::STD
+            .decode("uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=")
+            .unwrap();
+        let encrypted_data = deserialize_encrypted_data(
+            &radix64::STD
+                .decode("AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=")
+                .unwrap(),
+        )
+        .unwrap();
+        let public_key = get_public_key(&get_private_key_from_bytes(&private_key_bytes).unwrap());
+
+        // Stands in for the device holding the private key
+        let mut private_key_array = [0; 32];
+        private_key_array.copy_from_slice(&private_key_bytes);
+        let mut encapped_key_array = [0; 32];
+        encapped_key_array.copy_from_slice(&encrypted_data.encapped_key_bytes());
+        let dh = x25519_dalek::StaticSecret::from(private_key_array)
+            .diffie_hellman(&x25519_dalek::PublicKey::from(encapped_key_array));
+
+        assert_eq!(
+            b"test matched data".to_vec(),
+            *decrypt_data_with_dh(&encrypted_data, &public_key, dh.as_bytes(), &[]).unwrap()
+        );
+        assert!(matches!(
+            decrypt_data_with_dh(&encrypted_data, &public_key, &[1; 32], &[]),
+            Err(DecryptError::Open(_))
+        ));
+        assert!(matches!(
+            decrypt_data_with_dh(&encrypted_data, &public_key, dh.as_bytes(), b"ray id"),
+            Err(DecryptError::Open(_))
+        ));
+        assert!(matches!(
+            decrypt_data_with_dh(&encrypted_data, &public_key, &[0; 32], &[]),
+            Err(DecryptError::Decapsulation(_))
+        ));
+    }
 }
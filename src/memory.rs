@@ -0,0 +1,104 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+// Cleared by --no-mlock, for containers where memory can't be locked
+static LOCKING: AtomicBool = AtomicBool::new(true);
+
+static DISABLE_CORE_DUMPS: Once = Once::new();
+
+pub fn set_locking(locking: bool) {
+    LOCKING.store(locking, Ordering::Relaxed);
+}
+
+// Keeps the pages of a region holding a private key in RAM so they are never swapped to disk, and
+// disables core dumps from the first key loaded on, as they would contain it. Pages stay locked
+// until the process exits, the key being wiped from them when dropped
+pub fn lock(address: *const u8, len: usize) -> Result<(), String> {
+    DISABLE_CORE_DUMPS.call_once(disable_core_dumps);
+    if len == 0 || !LOCKING.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    let (start, len) = page_range(address as usize, len, page_size());
+    lock_pages(start as *const u8, len).map_err(|e| {
+        format!(
+            "Failed to lock private key memory, {}, raise the locked memory limit or pass --no-mlock",
+            e
+        )
+    })
+}
+
+pub fn lock_bytes(bytes: &[u8]) -> Result<(), String> {
+    lock(bytes.as_ptr(), bytes.len())
+}
+
+// Start and length of the whole pages spanning a region
+fn page_range(address: usize, len: usize, page_size: usize) -> (usize, usize) {
+    let start = address - address % page_size;
+    let end = (address + len).div_ceil(page_size) * page_size;
+    (start, end - start)
+}
+
+#[cfg(unix)]
+fn page_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+#[cfg(not(unix))]
+fn page_size() -> usize {
+    4096
+}
+
+#[cfg(unix)]
+fn lock_pages(start: *const u8, len: usize) -> io::Result<()> {
+    match unsafe { libc::mlock(start.cast(), len) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(windows)]
+fn lock_pages(start: *const u8, len: usize) -> io::Result<()> {
+    match unsafe { windows_sys::Win32::System::Memory::VirtualLock(start.cast(), len) } {
+        0 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn lock_pages(_start: *const u8, _len: usize) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn disable_core_dumps() {
+    let limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) };
+    // Also keeps other processes of the user from attaching to read the key
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0)
+    };
+}
+
+#[cfg(not(unix))]
+fn disable_core_dumps() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_range() {
+        assert_eq!((4096, 4096), page_range(4100, 32, 4096));
+        assert_eq!((4096, 8192), page_range(8180, 32, 4096));
+        assert_eq!((0, 4096), page_range(0, 4096, 4096));
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+// Upper bounds in seconds of the request latency histogram buckets, decrypting taking well under a
+// millisecond
+const LATENCY_BUCKETS: [f64; 10] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0,
+];
+
+// Counters of the requests served, exposed in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    requests: u64,
+    successes: u64,
+    failures: BTreeMap<&'static str, u64>,
+    latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+}
+
+impl Metrics {
+    // Records a request that succeeded, or failed with an error class
+    pub fn record(&mut self, failure: Option<&'static str>, latency: Duration) {
+        self.requests += 1;
+        match failure {
+            Some(class) => *self.failures.entry(class).or_default() += 1,
+            None => self.successes += 1,
+        }
+
+        let latency = latency.as_secs_f64();
+        for (count, bound) in self.latency_buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if latency <= bound {
+                *count += 1;
+            }
+        }
+        self.latency_sum += latency;
+    }
+
+    // Formats the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        let _ = writeln!(
+            output,
+            "# HELP matched_data_requests_total Requests received, other than for metrics."
+        );
+        let _ = writeln!(output, "# TYPE matched_data_requests_total counter");
+        let _ = writeln!(output, "matched_data_requests_total {}", self.requests);
+
+        let _ = writeln!(
+            output,
+            "# HELP matched_data_successes_total Requests answered with decrypted matched data."
+        );
+        let _ = writeln!(output, "# TYPE matched_data_successes_total counter");
+        let _ = writeln!(output, "matched_data_successes_total {}", self.successes);
+
+        let _ = writeln!(
+            output,
+            "# HELP matched_data_failures_total Requests that failed, by error class."
+        );
+        let _ = writeln!(output, "# TYPE matched_data_failures_total counter");
+        for (class, count) in &self.failures {
+            let _ = writeln!(
+                output,
+                "matched_data_failures_total{{class=\"{}\"}} {}",
+                class, count
+            );
+        }
+
+        let _ = writeln!(
+            output,
+            "# HELP matched_data_request_duration_seconds Time taken to answer requests."
+        );
+        let _ = writeln!(
+            output,
+            "# TYPE matched_data_request_duration_seconds histogram"
+        );
+        for (count, bound) in self.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+            let _ = writeln!(
+                output,
+                "matched_data_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, count
+            );
+        }
+        let _ = writeln!(
+            output,
+            "matched_data_request_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.requests
+        );
+        let _ = writeln!(
+            output,
+            "matched_data_request_duration_seconds_sum {}",
+            self.latency_sum
+        );
+        let _ = writeln!(
+            output,
+            "matched_data_request_duration_seconds_count {}",
+            self.requests
+        );
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut metrics = Metrics::default();
+        metrics.record(None, Duration::from_micros(300));
+        metrics.record(Some("decryption_failed"), Duration::from_millis(2));
+        metrics.record(Some("decryption_failed"), Duration::from_secs(2));
+
+        let output = metrics.render();
+        assert!(output.contains("matched_data_requests_total 3\n"));
+        assert!(output.contains("matched_data_successes_total 1\n"));
+        assert!(output.contains("matched_data_failures_total{class=\"decryption_failed\"} 2\n"));
+        assert!(output.contains("matched_data_request_duration_seconds_bucket{le=\"0.0005\"} 1\n"));
+        assert!(output.contains("matched_data_request_duration_seconds_bucket{le=\"0.0025\"} 2\n"));
+        assert!(output.contains("matched_data_request_duration_seconds_bucket{le=\"1\"} 2\n"));
+        assert!(output.contains("matched_data_request_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(output.contains("matched_data_request_duration_seconds_count 3\n"));
+    }
+}
@@ -0,0 +1,87 @@
+use std::io::{self, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+// Streams an object downloaded by the aws CLI, failing at the end if the download failed
+pub struct ObjectReader {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stdout.read(buf)?;
+        if read == 0 && !buf.is_empty() && !self.child.wait()?.success() {
+            return Err(io::Error::other("Failed to download object"));
+        }
+        Ok(read)
+    }
+}
+
+// Returns whether a path is an R2 or S3 URL rather than a local file
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("s3://") || path.starts_with("r2://")
+}
+
+// Arguments of the aws CLI streaming an object to stdout, R2 objects going through the account's endpoint
+fn aws_args(url: &str, r2_account_id: Option<&str>) -> Result<Vec<String>, String> {
+    if let Some(path) = url.strip_prefix("r2://") {
+        let account_id = r2_account_id.ok_or(
+            "No R2 account ID provided, set R2_ACCOUNT_ID or r2_account_id in the configuration file",
+        )?;
+        return Ok(vec![
+            "s3".to_string(),
+            "cp".to_string(),
+            "--endpoint-url".to_string(),
+            format!("https://{}.r2.cloudflarestorage.com", account_id),
+            format!("s3://{}", path),
+            "-".to_string(),
+        ]);
+    }
+
+    Ok(vec![
+        "s3".to_string(),
+        "cp".to_string(),
+        url.to_string(),
+        "-".to_string(),
+    ])
+}
+
+// Opens an R2 or S3 object, authenticating with the standard AWS credentials of the aws CLI
+pub fn open(url: &str, r2_account_id: Option<&str>) -> Result<ObjectReader, String> {
+    let mut child = Command::new("aws")
+        .args(aws_args(url, r2_account_id)?)
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|_| "Failed to run the aws CLI, check that it is installed")?;
+    let stdout = child
+        .stdout
+        .take()
+        .expect("Failed to capture aws CLI output");
+
+    Ok(ObjectReader { child, stdout })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aws_args() {
+        assert_eq!(
+            vec!["s3", "cp", "s3://logs/2022/firewall.log.gz", "-"],
+            aws_args("s3://logs/2022/firewall.log.gz", None).unwrap()
+        );
+        assert_eq!(
+            vec![
+                "s3",
+                "cp",
+                "--endpoint-url",
+                "https://abc.r2.cloudflarestorage.com",
+                "s3://logs/firewall.log.gz",
+                "-"
+            ],
+            aws_args("r2://logs/firewall.log.gz", Some("abc")).unwrap()
+        );
+        assert!(aws_args("r2://logs/firewall.log.gz", None).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use crate::http;
+use clap::PossibleValue;
+use std::fmt::Write;
+use zeroize::Zeroizing;
+
+// Formats decrypted matched data for output, by decrypt and serve alike. A new format only needs
+// an implementation added to FORMATTERS to be accepted by -o
+pub trait OutputFormatter: Sync {
+    // Name of the format, as passed to -o or set in the configuration file
+    fn name(&self) -> &'static str;
+
+    // Formats a plaintext, without a line break at the end
+    fn format(&self, plaintext: &[u8]) -> Zeroizing<Vec<u8>>;
+
+    // Formats a plaintext as a record printed by decrypt, ended with a line break, or a NUL byte
+    // with --null
+    fn format_record(&self, plaintext: &[u8], null: bool) -> Zeroizing<Vec<u8>> {
+        let mut record = self.format(plaintext);
+        record.push(if null { b'\0' } else { b'\n' });
+        record
+    }
+
+    // Whether the plaintext is written as is, so binary plaintexts can garble a terminal
+    fn is_raw(&self) -> bool {
+        false
+    }
+
+    // Whether bytes of binary plaintexts are replaced rather than encoded
+    fn is_lossy(&self) -> bool {
+        false
+    }
+}
+
+// Name of decrypt's batch mode, printing a JSON line per input rather than formatting one
+// plaintext, so it has no formatter
+pub const NDJSON: &str = "ndjson";
+
+pub const FORMATTERS: &[&dyn OutputFormatter] =
+    &[&Raw, &Utf8Lossy, &Base64, &JsonPretty, &Http, &Hexdump];
+
+pub fn find(name: &str) -> Option<&'static dyn OutputFormatter> {
+    FORMATTERS
+        .iter()
+        .find(|formatter| formatter.name() == name)
+        .copied()
+}
+
+pub fn names<'a>() -> impl Iterator<Item = &'a str> {
+    FORMATTERS.iter().map(|formatter| formatter.name())
+}
+
+// Names accepted by -o, with decrypt's batch mode if supported
+pub fn possible_values<'help>(ndjson: bool) -> impl Iterator<Item = PossibleValue<'help>> {
+    names()
+        .chain(ndjson.then_some(NDJSON))
+        .map(PossibleValue::new)
+}
+
+// The plaintext as is, ended with a NUL byte with --null but never a line break
+pub struct Raw;
+
+impl OutputFormatter for Raw {
+    fn name(&self) -> &'static str {
+        "raw"
+    }
+
+    fn format(&self, plaintext: &[u8]) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(plaintext.to_vec())
+    }
+
+    fn format_record(&self, plaintext: &[u8], null: bool) -> Zeroizing<Vec<u8>> {
+        let mut record = self.format(plaintext);
+        if null {
+            record.push(b'\0');
+        }
+        record
+    }
+
+    fn is_raw(&self) -> bool {
+        true
+    }
+}
+
+// The plaintext as UTF-8, invalid sequences replaced with U+FFFD
+pub struct Utf8Lossy;
+
+impl OutputFormatter for Utf8Lossy {
+    fn name(&self) -> &'static str {
+        "utf8-lossy"
+    }
+
+    fn format(&self, plaintext: &[u8]) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(String::from_utf8_lossy(plaintext).into_owned().into_bytes())
+    }
+
+    fn is_lossy(&self) -> bool {
+        true
+    }
+}
+
+pub struct Base64;
+
+impl OutputFormatter for Base64 {
+    fn name(&self) -> &'static str {
+        "base64"
+    }
+
+    fn format(&self, plaintext: &[u8]) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(radix64::STD.encode(plaintext).into_bytes())
+    }
+}
+
+// JSON plaintexts indented, others as utf8-lossy
+pub struct JsonPretty;
+
+impl OutputFormatter for JsonPretty {
+    fn name(&self) -> &'static str {
+        "json-pretty"
+    }
+
+    fn format(&self, plaintext: &[u8]) -> Zeroizing<Vec<u8>> {
+        match serde_json::from_slice::<serde_json::Value>(plaintext) {
+            Ok(value) => Zeroizing::new(
+                serde_json::to_vec_pretty(&value).expect("Failed to output matched data"),
+            ),
+            Err(_) => Utf8Lossy.format(plaintext),
+        }
+    }
+
+    fn is_lossy(&self) -> bool {
+        true
+    }
+}
+
+// HTTP requests rendered by http::render, others as utf8-lossy
+pub struct Http;
+
+impl OutputFormatter for Http {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn format(&self, plaintext: &[u8]) -> Zeroizing<Vec<u8>> {
+        match http::render(plaintext) {
+            Some(request) => Zeroizing::new(request.into_bytes()),
+            None => Utf8Lossy.format(plaintext),
+        }
+    }
+
+    fn format_record(&self, plaintext: &[u8], null: bool) -> Zeroizing<Vec<u8>> {
+        match http::render(plaintext) {
+            // The rendered request ends with its own line break
+            Some(request) => {
+                let mut record = Zeroizing::new(request.into_bytes());
+                if null {
+                    record.push(b'\0');
+                }
+                record
+            }
+            None => Utf8Lossy.format_record(plaintext, null),
+        }
+    }
+
+    fn is_lossy(&self) -> bool {
+        true
+    }
+}
+
+// Offsets, hex bytes and printable characters as printed by hexdump -C, for binary plaintexts
+pub struct Hexdump;
+
+impl OutputFormatter for Hexdump {
+    fn name(&self) -> &'static str {
+        "hexdump"
+    }
+
+    fn format(&self, plaintext: &[u8]) -> Zeroizing<Vec<u8>> {
+        let mut dump = Zeroizing::new(String::new());
+        for (i, row) in plaintext.chunks(16).enumerate() {
+            write_hexdump_row(&mut dump, i * 16, row);
+            dump.push('\n');
+        }
+        write!(dump, "{:08x}", plaintext.len()).expect("Failed to format hexdump");
+        Zeroizing::new(dump.as_bytes().to_vec())
+    }
+}
+
+// Writes a row of up to 16 bytes at an offset as hexdump -C does, without a line break
+pub fn write_hexdump_row(dump: &mut String, offset: usize, row: &[u8]) {
+    write!(dump, "{:08x} ", offset).expect("Failed to format hexdump");
+    for j in 0..16 {
+        // Bytes are grouped by 8, with an extra space in the middle
+        if j == 8 {
+            dump.push(' ');
+        }
+        match row.get(j) {
+            Some(byte) => write!(dump, " {:02x}", byte).expect("Failed to format hexdump"),
+            None => dump.push_str("   "),
+        }
+    }
+    dump.push_str("  |");
+    dump.extend(row.iter().map(|&byte| match byte {
+        0x20..=0x7e => byte as char,
+        _ => '.',
+    }));
+    dump.push('|');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        assert_eq!("base64", find("base64").unwrap().name());
+        assert!(find(NDJSON).is_none());
+        assert_eq!(FORMATTERS.len(), names().count());
+    }
+
+    #[test]
+    fn test_format_record() {
+        assert_eq!(
+            b"dGVzdA==\n".to_vec(),
+            *Base64.format_record(b"test", false)
+        );
+        assert_eq!(b"test\0".to_vec(), *Utf8Lossy.format_record(b"test", true));
+        assert_eq!(b"\x00\xff".to_vec(), *Raw.format_record(b"\x00\xff", false));
+        assert_eq!(
+            b"\x00\xff\0".to_vec(),
+            *Raw.format_record(b"\x00\xff", true)
+        );
+        assert_eq!(
+            b"{\n  \"a\": 1\n}\n".to_vec(),
+            *JsonPretty.format_record(b"{\"a\":1}", false)
+        );
+        assert_eq!(
+            b"not json\n".to_vec(),
+            *JsonPretty.format_record(b"not json", false)
+        );
+    }
+
+    #[test]
+    fn test_hexdump() {
+        assert_eq!(
+            "00000000  74 65 73 74 20 6d 61 74  63 68 65 64 20 64 61 74  |test matched dat|\n\
+             00000010  61 0a                                             |a.|\n\
+             00000012",
+            std::str::from_utf8(&Hexdump.format(b"test matched data\n")).unwrap()
+        );
+        assert_eq!(
+            "00000000",
+            std::str::from_utf8(&Hexdump.format(b"")).unwrap()
+        );
+    }
+}
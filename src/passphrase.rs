@@ -0,0 +1,102 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+// Read instead of prompting when set, for scripts
+const PASSPHRASE_ENV: &str = "MATCHED_DATA_PASSPHRASE";
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+// Argon2id parameters, the minimum recommended by OWASP: 19 MiB of memory and 2 iterations. Fixed
+// rather than the crate defaults so data sealed by one build can be opened by any other
+const ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const ARGON2_ITERATIONS: u32 = 2;
+
+// Reads the passphrase from MATCHED_DATA_PASSPHRASE, or prompts for it without echoing it, twice
+// when confirming a new passphrase
+pub fn read(confirm: bool) -> Result<Zeroizing<String>, String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+
+    let passphrase = Zeroizing::new(
+        rpassword::prompt_password("Passphrase: ").map_err(|_| "Failed to read passphrase")?,
+    );
+    if confirm {
+        let confirmation = Zeroizing::new(
+            rpassword::prompt_password("Confirm passphrase: ")
+                .map_err(|_| "Failed to read passphrase")?,
+        );
+        if confirmation != passphrase {
+            return Err("Passphrases do not match".to_string());
+        }
+    }
+    Ok(passphrase)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Zeroizing<[u8; 32]> {
+    let params = Params::new(ARGON2_MEMORY_KIB, ARGON2_ITERATIONS, 1, Some(32))
+        .expect("Argon2 parameters are invalid");
+    let mut key = Zeroizing::new([0; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .expect("Failed to derive key from passphrase");
+    key
+}
+
+// Encrypts data with a key derived from a passphrase with Argon2id, as the salt, then the nonce,
+// then the ChaCha20Poly1305 ciphertext and tag
+pub fn seal(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    if passphrase.is_empty() {
+        return Err("Passphrase is empty".to_string());
+    }
+
+    let mut salt = [0; SALT_LENGTH];
+    let mut nonce = [0; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let key = derive_key(passphrase, &salt);
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| "Failed to encrypt with passphrase")?;
+
+    Ok([&salt[..], &nonce, &ciphertext].concat())
+}
+
+// Decrypts data sealed with a passphrase
+pub fn open(sealed: &[u8], passphrase: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    if sealed.len() < SALT_LENGTH + NONCE_LENGTH + TAG_LENGTH {
+        return Err("Passphrase protected data is truncated".to_string());
+    }
+    let (salt, rest) = sealed.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+
+    let key = derive_key(passphrase, salt);
+    ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map(Zeroizing::new)
+        .map_err(|_| "Wrong passphrase or corrupted data".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let sealed = seal(b"private key", "correct horse").unwrap();
+        assert_eq!(SALT_LENGTH + NONCE_LENGTH + 11 + TAG_LENGTH, sealed.len());
+        assert_eq!(
+            b"private key".to_vec(),
+            *open(&sealed, "correct horse").unwrap()
+        );
+        assert!(open(&sealed, "wrong horse").is_err());
+        assert!(open(&sealed[..20], "correct horse").is_err());
+        assert!(seal(b"private key", "").is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use std::io::{stderr, stdout, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+// How often the stats line is refreshed on a terminal, and printed again otherwise
+const TERMINAL_INTERVAL: Duration = Duration::from_millis(500);
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+// Periodic stats of a long-running job, redrawn in place on a terminal and logged otherwise. Only shown
+// when the output is redirected, as records printed on the terminal show the progress already
+pub struct Progress {
+    enabled: bool,
+    terminal: bool,
+    start: Instant,
+    last_report: Option<Instant>,
+    processed: u64,
+    failures: u64,
+}
+
+impl Progress {
+    pub fn new(enabled: bool) -> Self {
+        Progress {
+            enabled: enabled && !stdout().is_terminal(),
+            terminal: stderr().is_terminal(),
+            start: Instant::now(),
+            last_report: None,
+            processed: 0,
+            failures: 0,
+        }
+    }
+
+    // Counts a processed record, reporting the stats if it's time to
+    pub fn record(&mut self, failed: bool) {
+        self.processed += 1;
+        if failed {
+            self.failures += 1;
+        }
+
+        let interval = if self.terminal {
+            TERMINAL_INTERVAL
+        } else {
+            LOG_INTERVAL
+        };
+        let since = self.last_report.unwrap_or(self.start);
+        if self.enabled && since.elapsed() >= interval {
+            self.report();
+        }
+    }
+
+    // Reports the final stats if any were reported, ending the line redrawn on a terminal
+    pub fn finish(&mut self) {
+        if self.enabled && self.last_report.is_some() {
+            self.report();
+            if self.terminal {
+                eprintln!();
+            }
+        }
+    }
+
+    fn report(&mut self) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = self.processed as f64 / elapsed.max(0.001);
+        // Redrawn in place on a terminal, logged as an event otherwise
+        if self.terminal {
+            let mut err = stderr();
+            let _ = write!(
+                err,
+                "\r\x1b[2K{} records, {} failed, {:.0} records/s",
+                self.processed, self.failures, rate
+            );
+            let _ = err.flush();
+        } else {
+            tracing::info!(
+                records = self.processed,
+                failed = self.failures,
+                records_per_s = rate.round() as u64,
+                "Progress"
+            );
+        }
+        self.last_report = Some(Instant::now());
+    }
+}
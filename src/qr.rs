@@ -0,0 +1,68 @@
+use qrcode::render::unicode::Dense1x2;
+use qrcode::{Color, QrCode};
+use std::io::Write;
+
+// Pixels per module of PNG QR codes, and modules of light border around them as scanners need
+const PNG_MODULE_SIZE: usize = 8;
+const QUIET_ZONE: usize = 4;
+
+fn encode(text: &str) -> Result<QrCode, String> {
+    QrCode::new(text).map_err(|e| format!("Failed to encode QR code: {}", e))
+}
+
+// Renders text as a QR code of half-height block characters, drawn light on dark so it scans
+// from terminals with a dark background
+pub fn to_terminal(text: &str) -> Result<String, String> {
+    Ok(encode(text)?
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build())
+}
+
+// Writes text as a QR code in a grayscale PNG image
+pub fn write_png(text: &str, writer: impl Write) -> Result<(), String> {
+    let code = encode(text)?;
+    let modules = code.width();
+    let size = (modules + 2 * QUIET_ZONE) * PNG_MODULE_SIZE;
+
+    let colors = code.to_colors();
+    let mut pixels = vec![0xff; size * size];
+    for (y, row) in pixels.chunks_mut(size).enumerate() {
+        let module_y = (y / PNG_MODULE_SIZE).wrapping_sub(QUIET_ZONE);
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let module_x = (x / PNG_MODULE_SIZE).wrapping_sub(QUIET_ZONE);
+            if module_x < modules
+                && module_y < modules
+                && colors[module_y * modules + module_x] == Color::Dark
+            {
+                *pixel = 0;
+            }
+        }
+    }
+
+    let mut encoder = png::Encoder::new(writer, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|e| format!("Failed to write QR code image: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_png() {
+        let mut image = Vec::new();
+        write_png("matched-data", &mut image).unwrap();
+        assert!(image.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        // Version 1 QR codes are 21 modules wide
+        let decoder = png::Decoder::new(&image[..]);
+        let reader = decoder.read_info().unwrap();
+        assert_eq!((21 + 8) * 8, reader.info().width);
+    }
+}
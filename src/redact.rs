@@ -0,0 +1,114 @@
+use regex::bytes::{Captures, Regex, Replacer};
+use zeroize::Zeroizing;
+
+// Replacement for redacted matches
+const REDACTED: &[u8] = b"[REDACTED]";
+
+// Email addresses
+const EMAIL_PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}";
+
+// Runs of 13 to 19 digits, optionally separated by spaces or dashes, checked with Luhn
+const CARD_NUMBER_PATTERN: &str = r"\b\d(?:[ -]?\d){12,18}\b";
+
+// Masks matches of user provided patterns and, optionally, built-in PII patterns
+pub struct Redactor {
+    patterns: Vec<Regex>,
+    card_number: Option<Regex>,
+}
+
+impl Redactor {
+    pub fn new(patterns: &[String], builtin: bool) -> Result<Self, String> {
+        let mut patterns = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .map_err(|e| format!("Provided redaction pattern is invalid: {}", e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let card_number = if builtin {
+            patterns.push(Regex::new(EMAIL_PATTERN).expect("Email pattern is invalid"));
+            Some(Regex::new(CARD_NUMBER_PATTERN).expect("Card number pattern is invalid"))
+        } else {
+            None
+        };
+
+        Ok(Redactor {
+            patterns,
+            card_number,
+        })
+    }
+
+    // Returns whether any redaction applies
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && self.card_number.is_none()
+    }
+
+    // Replaces every match in matched data with a redaction marker, wiping intermediate copies
+    pub fn redact(&self, matched_data: &[u8]) -> Zeroizing<Vec<u8>> {
+        let mut redacted = Zeroizing::new(matched_data.to_vec());
+
+        for pattern in &self.patterns {
+            redacted = Zeroizing::new(pattern.replace_all(&redacted, REDACTED).into_owned());
+        }
+        if let Some(card_number) = &self.card_number {
+            redacted = Zeroizing::new(
+                card_number
+                    .replace_all(&redacted, LuhnReplacer)
+                    .into_owned(),
+            );
+        }
+
+        redacted
+    }
+}
+
+// Replaces numbers passing the Luhn check, appending other matches as is without copying them
+struct LuhnReplacer;
+
+impl Replacer for LuhnReplacer {
+    fn replace_append(&mut self, captures: &Captures<'_>, dst: &mut Vec<u8>) {
+        if passes_luhn(&captures[0]) {
+            dst.extend_from_slice(REDACTED);
+        } else {
+            dst.extend_from_slice(&captures[0]);
+        }
+    }
+}
+
+// Checks the Luhn checksum of a number, ignoring separators
+fn passes_luhn(number: &[u8]) -> bool {
+    let sum: u32 = number
+        .iter()
+        .rev()
+        .filter(|c| c.is_ascii_digit())
+        .map(|c| u32::from(c - b'0'))
+        .enumerate()
+        .map(|(i, digit)| match (i % 2, digit * 2) {
+            (0, _) => digit,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let redactor = Redactor::new(&["token=\\w+".to_string()], true).unwrap();
+
+        assert_eq!(
+            b"card=[REDACTED]&order=1234567890123&email=[REDACTED]&[REDACTED]".to_vec(),
+            *redactor.redact(
+                b"card=4111 1111 1111 1111&order=1234567890123&email=a.b@example.com&token=abc"
+            )
+        );
+        assert!(Redactor::new(&[], false).unwrap().is_empty());
+        assert!(Redactor::new(&["(".to_string()], false).is_err());
+    }
+}
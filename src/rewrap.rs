@@ -0,0 +1,210 @@
+use crate::logs;
+use crate::TRUNCATED;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// Decrypts base64 encoded matched data and encrypts it to the new public key
+pub type Rewrap<'a> = dyn Fn(&str) -> Result<String, String> + 'a;
+
+// Line that couldn't be rewrapped, printed as a JSON line of the report
+#[derive(Serialize)]
+struct Failure<'a> {
+    source: &'a str,
+    error: &'a str,
+}
+
+// Counts of a rewrap, failures being reported as they happen
+#[derive(Default)]
+pub struct Report {
+    pub lines: usize,
+    pub rewrapped: usize,
+    pub failures: usize,
+}
+
+// Rewraps the matched data of an NDJSON archive, gzipped or not, or of every file in a directory
+// of them, writing the result under a temporary name renamed to the output once complete, so the
+// output never holds a partial archive. Lines that fail are written unchanged and reported
+pub fn rewrap(input: &Path, output: &Path, rewrap: &Rewrap<'_>) -> Result<Report, String> {
+    let mut report = Report::default();
+    let partial_output = partial_path(output);
+
+    if input.is_dir() {
+        if output.exists() {
+            return Err(format!(
+                "Output directory {} already exists",
+                output.display()
+            ));
+        }
+        let _ = fs::remove_dir_all(&partial_output);
+        for file in files(input)? {
+            let relative = file.strip_prefix(input).expect("File is outside of input");
+            let file_output = partial_output.join(relative);
+            if let Some(parent) = file_output.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|_| format!("Failed to create {}", parent.display()))?;
+            }
+            rewrap_file(&file, &file_output, &mut report, rewrap)?;
+        }
+    } else {
+        rewrap_file(input, &partial_output, &mut report, rewrap)?;
+    }
+
+    fs::rename(&partial_output, output)
+        .map_err(|_| format!("Failed to write {}", output.display()))?;
+
+    Ok(report)
+}
+
+// Rewraps an archive into a new file, gzipped if the archive is
+fn rewrap_file(
+    input: &Path,
+    output: &Path,
+    report: &mut Report,
+    rewrap: &Rewrap<'_>,
+) -> Result<(), String> {
+    let mut file = BufReader::new(
+        fs::File::open(input).map_err(|_| format!("Failed to read {}", input.display()))?,
+    );
+    let is_gzip = file
+        .fill_buf()
+        .map_err(|_| format!("Failed to read {}", input.display()))?
+        .starts_with(logs::GZIP_MAGIC);
+    let reader = logs::decompress(file)?;
+
+    let output_file =
+        fs::File::create(output).map_err(|_| format!("Failed to write {}", output.display()))?;
+    let mut writer: Box<dyn Write> = if is_gzip {
+        Box::new(GzEncoder::new(
+            BufWriter::new(output_file),
+            Compression::default(),
+        ))
+    } else {
+        Box::new(BufWriter::new(output_file))
+    };
+    let write_error = |_| format!("Failed to write {}", output.display());
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|_| format!("Failed to read {}", input.display()))?;
+        report.lines += 1;
+        let rewrapped = match rewrap_line(&line, rewrap) {
+            Ok((rewrapped, count)) => {
+                report.rewrapped += count;
+                rewrapped
+            }
+            Err(e) => {
+                report.failures += 1;
+                let source = format!("{}:{}", input.display(), i + 1);
+                println!(
+                    "{}",
+                    serde_json::to_string(&Failure {
+                        source: &source,
+                        error: &e,
+                    })
+                    .expect("Failed to serialize failure")
+                );
+                line
+            }
+        };
+        writeln!(writer, "{}", rewrapped).map_err(write_error)?;
+    }
+    writer.flush().map_err(write_error)?;
+
+    Ok(())
+}
+
+// Replaces every encrypted matched data of an event line in the line itself, so the rest of the
+// event is kept byte for byte, returning the line and the number of matched data rewrapped
+fn rewrap_line(line: &str, rewrap: &Rewrap<'_>) -> Result<(String, usize), String> {
+    if line.trim().is_empty() {
+        return Ok((line.to_string(), 0));
+    }
+    let event: Value =
+        serde_json::from_str(line).map_err(|_| "Line is not a JSON event".to_string())?;
+
+    let mut rewrapped_line = line.to_string();
+    let mut count = 0;
+    for (_, encrypted_matched_data) in logs::find_all_encrypted_matched_data(&event) {
+        if encrypted_matched_data == TRUNCATED {
+            continue;
+        }
+        let quoted = format!("\"{}\"", encrypted_matched_data);
+        if !rewrapped_line.contains(&quoted) {
+            return Err(
+                "Encrypted matched data is escaped, rewrite the line without escapes".to_string(),
+            );
+        }
+        let rewrapped = rewrap(encrypted_matched_data)?;
+        rewrapped_line = rewrapped_line.replace(&quoted, &format!("\"{}\"", rewrapped));
+        count += 1;
+    }
+
+    Ok((rewrapped_line, count))
+}
+
+// Files of a directory and its subdirectories, in a stable order
+fn files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|_| format!("Failed to read directory {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Failed to read directory {}", dir.display()))?;
+    entries.sort();
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        if entry.is_dir() {
+            paths.extend(files(&entry)?);
+        } else {
+            paths.push(entry);
+        }
+    }
+
+    Ok(paths)
+}
+
+// Temporary path the output is written to before being renamed
+fn partial_path(output: &Path) -> PathBuf {
+    let mut partial = output.as_os_str().to_owned();
+    partial.push(".partial");
+    PathBuf::from(partial)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrap_line() {
+        let rewrap = |encrypted_matched_data: &str| match encrypted_matched_data {
+            "old" => Ok("new".to_string()),
+            _ => Err("Failed to decrypt matched data".to_string()),
+        };
+
+        assert_eq!(
+            (
+                r#"{"RayID":"1","Metadata":{"encrypted_matched_data":"new"}}"#.to_string(),
+                1
+            ),
+            rewrap_line(
+                r#"{"RayID":"1","Metadata":{"encrypted_matched_data":"old"}}"#,
+                &rewrap
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            (r#"{"RayID":"2"}"#.to_string(), 0),
+            rewrap_line(r#"{"RayID":"2"}"#, &rewrap).unwrap()
+        );
+        assert_eq!(
+            (r#"{"encrypted_matched_data":"truncated"}"#.to_string(), 0),
+            rewrap_line(r#"{"encrypted_matched_data":"truncated"}"#, &rewrap).unwrap()
+        );
+        assert!(rewrap_line(r#"{"encrypted_matched_data":"other"}"#, &rewrap).is_err());
+        assert!(rewrap_line("not JSON", &rewrap).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+use serde_json::Value;
+use serde_json_path::JsonPath;
+
+// Selects the fields of JSON matched data matching any of the JSONPath expressions, in order
+pub fn select_fields(matched_data: &[u8], paths: &[String]) -> Result<Vec<Value>, String> {
+    let matched_data: Value = serde_json::from_slice(matched_data)
+        .map_err(|_| "Decrypted matched data is not JSON, fields can't be selected")?;
+
+    let mut fields = Vec::new();
+    for path in paths {
+        let json_path = JsonPath::parse(path)
+            .map_err(|e| format!("Provided JSONPath '{}' is invalid: {}", path, e))?;
+        fields.extend(json_path.query(&matched_data).all().into_iter().cloned());
+    }
+
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_fields() {
+        let matched_data =
+            br#"{"http.request.headers":{"user-agent":["curl"]},"http.request.body.raw":"a=1"}"#;
+
+        assert_eq!(
+            vec![
+                Value::String("a=1".to_string()),
+                Value::String("curl".to_string())
+            ],
+            select_fields(
+                matched_data,
+                &[
+                    "$['http.request.body.raw']".to_string(),
+                    "$['http.request.headers']['user-agent'][0]".to_string()
+                ]
+            )
+            .unwrap()
+        );
+        assert!(select_fields(b"not json", &["$".to_string()]).is_err());
+        assert!(select_fields(matched_data, &["invalid".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use hpke::Serializable;
+use matched_data_cli::matched_data::{
+    encrypt_data, generate_key_pair, get_private_key_from_bytes, get_public_key,
+    serialize_encrypted_data,
+};
+use matched_data_cli::versions::supported_versions;
+use zeroize::Zeroizing;
+
+// Payload encrypted and decrypted by the round trip steps
+const PAYLOAD: &[u8] = b"test matched data";
+
+// Sample matched data from the README, encrypted to the public key of TEST_VECTOR_PRIVATE_KEY
+const TEST_VECTOR: &str = "AzTY6FHajXYXuDMUte82wrd+1n5CEHPoydYiyd3FMg5IEQAAAAAAAAA0lOhGXBclw8pWU5jbbYuepSIJN5JohTtZekLliJBlVWk=";
+const TEST_VECTOR_PRIVATE_KEY: &str = "uBS5eBttHrqkdY41kbZPdvYnNz8Vj0TvKIUpjB1y/GA=";
+
+// Decrypts matched data bytes with private key bytes
+type Decrypt = dyn Fn(&[u8], &[u8]) -> Result<Zeroizing<Vec<u8>>, String>;
+
+// Outcome of a self-test step
+pub struct Step {
+    pub name: String,
+    pub result: Result<(), String>,
+}
+
+// Checks that decrypted plaintext is the expected payload
+fn check_payload(plaintext: Zeroizing<Vec<u8>>) -> Result<(), String> {
+    if plaintext.as_slice() == PAYLOAD {
+        Ok(())
+    } else {
+        Err("Decrypted payload differs from the expected one".to_string())
+    }
+}
+
+// Checks that decryption failed
+fn check_rejected(result: Result<Zeroizing<Vec<u8>>, String>) -> Result<(), String> {
+    match result {
+        Ok(_) => Err("Decryption succeeded".to_string()),
+        Err(_) => Ok(()),
+    }
+}
+
+// Runs each self-test step with an ephemeral key pair, decrypting with the CLI's decryption path
+pub fn run(decrypt: &Decrypt) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let mut step = |name: &str, result: Result<(), String>| {
+        steps.push(Step {
+            name: name.to_string(),
+            result,
+        })
+    };
+
+    let (private_key, public_key) = generate_key_pair();
+    let private_key_bytes = private_key.to_bytes().to_vec();
+    step("generate key pair", Ok(()));
+
+    step(
+        "derive public key",
+        match get_private_key_from_bytes(&private_key_bytes) {
+            Ok(private_key) if get_public_key(&private_key).to_bytes() == public_key.to_bytes() => {
+                Ok(())
+            }
+            Ok(_) => Err("Derived public key differs from the generated one".to_string()),
+            Err(e) => Err(format!("{:?}", e)),
+        },
+    );
+
+    // Round trip each format version the library decrypts, a version added there fails here
+    // until it can be encrypted too
+    let mut latest_encrypted_data = None;
+    for version in supported_versions() {
+        let encrypted_data = match version {
+            3 => encrypt_data(PAYLOAD, &public_key)
+                .map(|encrypted_data| serialize_encrypted_data(&encrypted_data))
+                .map_err(|e| format!("{:?}", e)),
+            _ => Err(format!(
+                "Encrypting format version {} is not supported",
+                version
+            )),
+        };
+
+        match encrypted_data {
+            Ok(encrypted_data) => {
+                step(&format!("encrypt format version {}", version), Ok(()));
+                step(
+                    &format!("decrypt format version {}", version),
+                    decrypt(&private_key_bytes, &encrypted_data).and_then(check_payload),
+                );
+                latest_encrypted_data = Some(encrypted_data);
+            }
+            Err(e) => step(&format!("encrypt format version {}", version), Err(e)),
+        }
+    }
+    let encrypted_data = match latest_encrypted_data {
+        Some(encrypted_data) => encrypted_data,
+        None => return steps,
+    };
+
+    let (other_private_key, _) = generate_key_pair();
+    step(
+        "reject wrong private key",
+        check_rejected(decrypt(&other_private_key.to_bytes(), &encrypted_data)),
+    );
+
+    let mut tampered_data = encrypted_data;
+    let last = tampered_data.len() - 1;
+    tampered_data[last] ^= 1;
+    step(
+        "reject tampered matched data",
+        check_rejected(decrypt(&private_key_bytes, &tampered_data)),
+    );
+
+    let test_vector_private_key = radix64::STD
+        .decode(TEST_VECTOR_PRIVATE_KEY)
+        .expect("Test vector private key is not base64 encoded");
+    let test_vector = radix64::STD
+        .decode(TEST_VECTOR)
+        .expect("Test vector is not base64 encoded");
+    step(
+        "decrypt test vector",
+        decrypt(&test_vector_private_key, &test_vector).and_then(check_payload),
+    );
+
+    steps
+}
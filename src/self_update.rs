@@ -0,0 +1,316 @@
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// Releases of the CLI, built by .github/workflows/release.yml
+const RELEASES_URL: &str = "https://api.github.com/repos/cloudflare/matched-data-cli/releases";
+
+// Asset of each release listing the SHA-256 of its tarballs, as printed by sha256sum
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+// Largest tarball downloaded, well above the size of a release
+const MAX_DOWNLOAD_BYTES: u64 = 100 * 1024 * 1024;
+
+// Target of the release tarball matching this build
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const TARGET: Option<&str> = Some("x86_64-unknown-linux-musl");
+#[cfg(all(target_os = "macos", target_arch = "x86_64"))]
+const TARGET: Option<&str> = Some("x86_64-apple-darwin");
+#[cfg(all(target_os = "windows", target_arch = "x86_64"))]
+const TARGET: Option<&str> = Some("x86_64-pc-windows-msvc");
+#[cfg(not(all(
+    target_arch = "x86_64",
+    any(target_os = "linux", target_os = "macos", target_os = "windows")
+)))]
+const TARGET: Option<&str> = None;
+
+// Name of the executable in release tarballs
+#[cfg(windows)]
+const BINARY_NAME: &str = "matched-data-cli.exe";
+#[cfg(not(windows))]
+const BINARY_NAME: &str = "matched-data-cli";
+
+// GitHub release, with the download URL of each asset by name
+pub struct Release {
+    pub tag: String,
+    assets: Vec<(String, String)>,
+}
+
+impl Release {
+    fn from_json(release: &Value) -> Result<Self, String> {
+        let tag = release["tag_name"]
+            .as_str()
+            .ok_or("GitHub release has no tag")?
+            .to_string();
+        let assets = release["assets"]
+            .as_array()
+            .map(|assets| {
+                assets
+                    .iter()
+                    .filter_map(|asset| {
+                        Some((
+                            asset["name"].as_str()?.to_string(),
+                            asset["browser_download_url"].as_str()?.to_string(),
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Release { tag, assets })
+    }
+
+    fn asset_url(&self, name: &str) -> Option<&str> {
+        self.assets
+            .iter()
+            .find(|(asset_name, _)| asset_name == name)
+            .map(|(_, url)| url.as_str())
+    }
+
+    // Whether the release is more recent than the running build
+    pub fn is_newer(&self) -> bool {
+        parse_version(&self.tag) > parse_version(env!("CARGO_PKG_VERSION"))
+    }
+}
+
+// Numeric components of a version or tag such as v0.6.0
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|component| component.parse().unwrap_or(0))
+        .collect()
+}
+
+// Fetches the latest release, or the one with the given tag
+pub fn fetch_release(tag: Option<&str>) -> Result<Release, String> {
+    let url = match tag {
+        Some(tag) => format!("{}/tags/{}", RELEASES_URL, tag),
+        None => format!("{}/latest", RELEASES_URL),
+    };
+    let release: Value = match ureq::get(&url)
+        .set("Accept", "application/vnd.github+json")
+        .call()
+    {
+        Ok(response) => response
+            .into_json()
+            .map_err(|_| "GitHub API response is not JSON")?,
+        Err(ureq::Error::Status(404, _)) => {
+            return Err(format!("No release {}", tag.unwrap_or("published")))
+        }
+        Err(ureq::Error::Status(status, _)) => {
+            return Err(format!("GitHub API request failed with status {}", status))
+        }
+        Err(e) => return Err(format!("GitHub API request failed: {}", e)),
+    };
+    Release::from_json(&release)
+}
+
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(url)
+        .call()
+        .map_err(|e| format!("Failed to download {}: {}", url, e))?;
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD_BYTES)
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to download {}: {}", url, e))?;
+    Ok(bytes)
+}
+
+// Finds the checksum of a file in the output of sha256sum
+fn find_checksum<'a>(checksums: &'a str, name: &str) -> Option<&'a str> {
+    checksums.lines().find_map(|line| {
+        let (checksum, file) = line.split_once(char::is_whitespace)?;
+        // sha256sum marks files read in binary mode with *
+        (file.trim_start().trim_start_matches('*') == name).then_some(checksum)
+    })
+}
+
+// Checks a downloaded asset against the checksum published with the release
+fn verify_checksum(checksums: &str, name: &str, bytes: &[u8]) -> Result<(), String> {
+    let expected = find_checksum(checksums, name)
+        .ok_or_else(|| format!("{} has no checksum for {}", CHECKSUMS_ASSET, name))?;
+    let actual: String = Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if !expected.eq_ignore_ascii_case(&actual) {
+        return Err(format!(
+            "Checksum of {} is {}, expected {} from {}",
+            name, actual, expected, CHECKSUMS_ASSET
+        ));
+    }
+    Ok(())
+}
+
+// Extracts the executable from a gzipped release tarball
+fn extract_binary(tarball: &[u8]) -> Result<Vec<u8>, String> {
+    let mut archive = tar::Archive::new(GzDecoder::new(tarball));
+    let entries = archive
+        .entries()
+        .map_err(|_| "Release tarball is not a gzipped tar archive")?;
+    for entry in entries {
+        let mut entry = entry.map_err(|_| "Failed to read release tarball")?;
+        let path = entry
+            .path()
+            .map_err(|_| "Failed to read release tarball")?
+            .into_owned();
+        if path.file_name().is_some_and(|name| name == BINARY_NAME) {
+            let mut binary = Vec::new();
+            entry
+                .read_to_end(&mut binary)
+                .map_err(|_| "Failed to read release tarball")?;
+            return Ok(binary);
+        }
+    }
+    Err(format!("Release tarball has no {}", BINARY_NAME))
+}
+
+// Downloads the executable of a release for this platform, verified against its checksum
+pub fn download_binary(release: &Release) -> Result<Vec<u8>, String> {
+    let target =
+        TARGET.ok_or("No release is built for this platform, build from source instead")?;
+    let name = format!("matched-data-cli-{}-{}.tar.gz", release.tag, target);
+    let tarball_url = release
+        .asset_url(&name)
+        .ok_or_else(|| format!("Release {} has no {}", release.tag, name))?;
+    // Older releases were published without checksums, never install them unverified
+    let checksums_url = release.asset_url(CHECKSUMS_ASSET).ok_or_else(|| {
+        format!(
+            "Release {} has no {}, refusing to install an unverified binary",
+            release.tag, CHECKSUMS_ASSET
+        )
+    })?;
+
+    let checksums = String::from_utf8(download(checksums_url)?)
+        .map_err(|_| format!("{} is not text", CHECKSUMS_ASSET))?;
+    let tarball = download(tarball_url)?;
+    verify_checksum(&checksums, &name, &tarball)?;
+    extract_binary(&tarball)
+}
+
+// Replaces the running executable, writing the new one next to it first so it is swapped in
+// with a rename and never left half written
+pub fn replace_executable(binary: &[u8]) -> Result<PathBuf, String> {
+    let executable = std::env::current_exe()
+        .and_then(fs::canonicalize)
+        .map_err(|_| "Failed to find the running executable")?;
+    let staged = sibling(&executable, "new");
+    fs::write(&staged, binary).map_err(|e| {
+        format!(
+            "Failed to write {}, {}, run as a user who can replace the executable",
+            staged.display(),
+            e
+        )
+    })?;
+    let permissions = fs::metadata(&executable)
+        .map_err(|_| "Failed to read the permissions of the running executable")?
+        .permissions();
+    fs::set_permissions(&staged, permissions)
+        .map_err(|_| "Failed to set the permissions of the new executable")?;
+
+    // Windows doesn't allow replacing a running executable, but allows renaming it
+    #[cfg(windows)]
+    fs::rename(&executable, sibling(&executable, "old"))
+        .map_err(|e| format!("Failed to move the running executable aside, {}", e))?;
+    fs::rename(&staged, &executable)
+        .map_err(|e| format!("Failed to replace {}, {}", executable.display(), e))?;
+    Ok(executable)
+}
+
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    #[test]
+    fn test_release_from_json() {
+        let release = Release::from_json(&serde_json::json!({
+            "tag_name": "v99.0.0",
+            "assets": [{
+                "name": "SHA256SUMS",
+                "browser_download_url": "https://github.com/cloudflare/matched-data-cli/releases/download/v99.0.0/SHA256SUMS"
+            }]
+        }))
+        .unwrap();
+        assert_eq!("v99.0.0", release.tag);
+        assert!(release.is_newer());
+        assert_eq!(
+            Some("https://github.com/cloudflare/matched-data-cli/releases/download/v99.0.0/SHA256SUMS"),
+            release.asset_url(CHECKSUMS_ASSET)
+        );
+        assert_eq!(None, release.asset_url("matched-data-cli-v99.0.0-x.tar.gz"));
+
+        assert!(Release::from_json(&serde_json::json!({})).is_err());
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(vec![0, 6, 0], parse_version("v0.6.0"));
+        assert!(parse_version("v0.10.0") > parse_version("0.9.1"));
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let checksums = "\
+            2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae  matched-data-cli-v99.0.0-x86_64-apple-darwin.tar.gz\n\
+            fcde2b2edba56bf408601fb721fe9b5c338d10ee429ea04fae5511b68fbf8fb9 *matched-data-cli-v99.0.0-x86_64-unknown-linux-musl.tar.gz\n";
+        assert!(verify_checksum(
+            checksums,
+            "matched-data-cli-v99.0.0-x86_64-apple-darwin.tar.gz",
+            b"foo"
+        )
+        .is_ok());
+        assert!(verify_checksum(
+            checksums,
+            "matched-data-cli-v99.0.0-x86_64-unknown-linux-musl.tar.gz",
+            b"bar"
+        )
+        .is_ok());
+        assert!(verify_checksum(
+            checksums,
+            "matched-data-cli-v99.0.0-x86_64-apple-darwin.tar.gz",
+            b"bar"
+        )
+        .unwrap_err()
+        .contains("expected 2c26b46b"));
+        assert!(verify_checksum(
+            checksums,
+            "matched-data-cli-v99.0.0-x86_64-pc-windows-msvc.tar.gz",
+            b"foo"
+        )
+        .unwrap_err()
+        .contains("no checksum"));
+    }
+
+    #[test]
+    fn test_extract_binary() {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, contents) in [
+            ("dist/README.md", &b"readme"[..]),
+            (&format!("dist/{}", BINARY_NAME)[..], b"binary"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, path, contents).unwrap();
+        }
+        let tarball = builder.into_inner().unwrap().finish().unwrap();
+
+        assert_eq!(b"binary".to_vec(), extract_binary(&tarball).unwrap());
+        assert!(extract_binary(b"not a tarball").is_err());
+    }
+}
@@ -0,0 +1,721 @@
+use crate::error::CliError;
+use crate::metrics::Metrics;
+#[cfg(unix)]
+use serde::Deserialize;
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::io::{self, BufRead, BufReader, Write};
+use std::io::{Cursor, Read};
+use std::net::{IpAddr, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::{
+    fs::FileTypeExt,
+    net::{UnixListener, UnixStream},
+};
+use std::sync::Mutex;
+use std::time::Instant;
+use tiny_http::{Header, Method, Request, Response, Server, SslConfig, StatusCode};
+use zeroize::Zeroizing;
+
+// Page served by serve-ui and its script, decrypting with the WebAssembly module in the browser
+const UI_PAGE: &str = include_str!("../matched-data-wasm/ui/index.html");
+const UI_SCRIPT: &str = include_str!("../matched-data-wasm/ui/index.js");
+
+// Files of the WebAssembly module built by wasm-pack for the web, served under /pkg/
+const WASM_SCRIPT_FILENAME: &str = "matched_data_wasm.js";
+const WASM_MODULE_FILENAME: &str = "matched_data_wasm_bg.wasm";
+
+// Only allows the page to load its own scripts and WebAssembly module and to make no requests, so
+// nothing it decrypts can be sent anywhere
+const UI_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; script-src 'self' 'wasm-unsafe-eval'; connect-src 'self'; style-src 'unsafe-inline'; form-action 'none'; frame-ancestors 'none'";
+
+// Number of clients whose rate limit is tracked before idle ones are forgotten
+const MAX_RATE_LIMITED_CLIENTS: usize = 10_000;
+
+// Decrypts base64 encoded matched data, failing with the error code of the failure
+pub type Decrypt<'a> = dyn Fn(&str) -> Result<Zeroizing<Vec<u8>>, CliError> + Sync + 'a;
+
+// Response to a request, with the class of its failure for metrics
+#[derive(Debug, PartialEq)]
+struct Reply {
+    status: u16,
+    body: Zeroizing<Vec<u8>>,
+    failure: Option<&'static str>,
+}
+
+impl Reply {
+    fn ok(body: Zeroizing<Vec<u8>>) -> Self {
+        Reply {
+            status: 200,
+            body,
+            failure: None,
+        }
+    }
+
+    fn error(status: u16, failure: &'static str, message: impl Into<Vec<u8>>) -> Self {
+        Reply {
+            status,
+            body: Zeroizing::new(message.into()),
+            failure: Some(failure),
+        }
+    }
+}
+
+// Request sent as a JSON line over a Unix socket
+#[cfg(unix)]
+#[derive(Deserialize)]
+struct SocketRequest {
+    data: String,
+}
+
+// Bounds on the resources requests can use
+pub struct Limits {
+    // Requests handled at the same time, others waiting for one to finish
+    pub max_in_flight: usize,
+    // Requests per second allowed from each client address, in bursts of up to as many
+    pub rate_limit: Option<u32>,
+    // Largest request body accepted
+    pub max_body_size: u64,
+}
+
+impl Limits {
+    // Checks that the limits allow at least some requests
+    fn validate(&self) -> Result<(), String> {
+        if self.max_in_flight == 0 {
+            return Err("Number of requests in flight must be at least 1".to_string());
+        }
+        if self.rate_limit == Some(0) {
+            return Err("Rate limit must be at least 1 request per second".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+// Token bucket of each client address, refilled at the rate limit
+struct RateLimiter {
+    rate: f64,
+    clients: Mutex<HashMap<IpAddr, (f64, Instant)>>,
+}
+
+impl RateLimiter {
+    fn new(rate: u32) -> Self {
+        RateLimiter {
+            rate: rate as f64,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Takes a token from the bucket of a client, if it has one left
+    fn allow(&self, client: IpAddr, now: Instant) -> bool {
+        let mut clients = self.clients.lock().expect("Rate limiter lock is poisoned");
+        // Clients whose bucket has refilled are forgotten, so many addresses can't exhaust memory
+        if clients.len() >= MAX_RATE_LIMITED_CLIENTS {
+            let rate = self.rate;
+            clients.retain(|_, (tokens, updated)| {
+                *tokens + now.duration_since(*updated).as_secs_f64() * rate < rate
+            });
+        }
+
+        let (tokens, updated) = clients.entry(client).or_insert((self.rate, now));
+        *tokens = (*tokens + now.duration_since(*updated).as_secs_f64() * self.rate).min(self.rate);
+        *updated = now;
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+
+        true
+    }
+}
+
+// Certificate chain and private key files, in PEM format, to serve HTTPS with
+pub struct TlsFiles<'a> {
+    pub certificate_filename: &'a str,
+    pub private_key_filename: &'a str,
+}
+
+// Serves decryption requests over HTTP, or HTTPS, until the process is stopped, requiring a bearer
+// token if one is given
+pub fn serve(
+    address: &str,
+    allow_remote: bool,
+    tls: Option<TlsFiles<'_>>,
+    auth_token: Option<&str>,
+    limits: &Limits,
+    decrypt: &Decrypt<'_>,
+) -> Result<(), String> {
+    limits.validate()?;
+
+    let is_loopback = is_loopback(address)?;
+    // Other hosts can only be allowed without --allow-remote when they can neither read the
+    // traffic nor decrypt without the token
+    let is_protected = tls.is_some() && auth_token.is_some();
+    if !is_loopback && !allow_remote && !is_protected {
+        return Err(format!(
+            "Refusing to listen on {}, which is reachable from other hosts, pass --tls-cert, --tls-key and --auth-token-file, or --allow-remote to allow it",
+            address
+        ));
+    }
+
+    let (server, scheme) = match tls {
+        Some(tls) => (Server::https(address, read_tls_files(&tls)?), "https"),
+        None => (Server::http(address), "http"),
+    };
+    let server = server.map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+    tracing::info!(%address, scheme, "Listening");
+
+    let rate_limiter = limits.rate_limit.map(RateLimiter::new);
+    let metrics = Mutex::new(Metrics::default());
+    // Each worker answers one request at a time, bounding the requests in flight
+    std::thread::scope(|scope| {
+        for _ in 0..limits.max_in_flight {
+            scope.spawn(|| {
+                for request in server.incoming_requests() {
+                    answer(
+                        request,
+                        auth_token,
+                        limits,
+                        rate_limiter.as_ref(),
+                        &metrics,
+                        decrypt,
+                    );
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+// Answers an HTTP request, recording it in the metrics
+fn answer(
+    mut request: Request,
+    auth_token: Option<&str>,
+    limits: &Limits,
+    rate_limiter: Option<&RateLimiter>,
+    metrics: &Mutex<Metrics>,
+    decrypt: &Decrypt<'_>,
+) {
+    let started = Instant::now();
+    let is_allowed = match (rate_limiter, request.remote_addr()) {
+        (Some(rate_limiter), Some(client)) => rate_limiter.allow(client.ip(), started),
+        _ => true,
+    };
+    let authorization = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .map(|header| header.value.as_str());
+    let mut body = String::new();
+    let reply = if !is_allowed {
+        Reply::error(429, "rate_limited", "Too many requests")
+    } else if !is_authorized(authorization, auth_token) {
+        Reply::error(401, "unauthorized", "Unauthorized")
+    } else if *request.method() == Method::Get && path(request.url()) == "/metrics" {
+        // Metrics requests aren't counted, so scraping doesn't skew the request metrics
+        let metrics = metrics.lock().expect("Metrics lock is poisoned").render();
+        let _ = request.respond(
+            Response::from_string(metrics).with_header(
+                Header::from_bytes("Content-Type", "text/plain; version=0.0.4")
+                    .expect("Invalid header"),
+            ),
+        );
+        return;
+    } else if request
+        .body_length()
+        .is_some_and(|length| length as u64 > limits.max_body_size)
+    {
+        // Refused before reading a body announced to be too large
+        Reply::error(413, "body_too_large", "Request body is too large")
+    } else {
+        match request
+            .as_reader()
+            .take(limits.max_body_size + 1)
+            .read_to_string(&mut body)
+        {
+            Ok(length) if length as u64 > limits.max_body_size => {
+                Reply::error(413, "body_too_large", "Request body is too large")
+            }
+            Ok(_) => handle(request.method(), request.url(), &body, decrypt),
+            Err(_) => Reply::error(400, "body_not_utf8", "Request body is not valid UTF-8"),
+        }
+    };
+
+    let mut headers = Vec::new();
+    match reply.status {
+        401 => {
+            headers.push(Header::from_bytes("WWW-Authenticate", "Bearer").expect("Invalid header"))
+        }
+        429 => headers.push(Header::from_bytes("Retry-After", "1").expect("Invalid header")),
+        _ => {}
+    }
+
+    // The response is read from the wiped buffer rather than moved into tiny_http, and a client
+    // that went away doesn't affect other requests
+    let _ = request.respond(Response::new(
+        StatusCode(reply.status),
+        headers,
+        Cursor::new(reply.body.as_slice()),
+        Some(reply.body.len()),
+        None,
+    ));
+    let latency = started.elapsed();
+    match reply.failure {
+        Some(failure) => tracing::warn!(
+            status = reply.status,
+            failure,
+            latency_ms = latency.as_millis() as u64,
+            "Request failed"
+        ),
+        None => tracing::debug!(
+            status = reply.status,
+            latency_ms = latency.as_millis() as u64,
+            "Request answered"
+        ),
+    }
+    metrics
+        .lock()
+        .expect("Metrics lock is poisoned")
+        .record(reply.failure, latency);
+}
+
+// WebAssembly module files served by serve-ui
+struct WasmFiles {
+    script: Vec<u8>,
+    module: Vec<u8>,
+}
+
+// Serves a page decrypting matched data in the browser with the WebAssembly module built in a
+// directory, on a loopback address only, until the process is stopped. The private key is read by
+// the page and never sent to the server
+pub fn serve_ui(address: &str, wasm_dir: &str) -> Result<(), String> {
+    let is_loopback = address
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", address, e))?
+        .all(|socket_address| socket_address.ip().is_loopback());
+    if !is_loopback {
+        return Err(format!(
+            "Refusing to listen on {}, the page can only be served on a loopback address",
+            address
+        ));
+    }
+
+    let read = |filename: &str| {
+        std::fs::read(std::path::Path::new(wasm_dir).join(filename)).map_err(|_| {
+            format!(
+                "Failed to read {} in {}, build the WebAssembly module with wasm-pack build --release --target web --no-pack in matched-data-wasm or pass --wasm-dir",
+                filename, wasm_dir
+            )
+        })
+    };
+    let wasm_files = WasmFiles {
+        script: read(WASM_SCRIPT_FILENAME)?,
+        module: read(WASM_MODULE_FILENAME)?,
+    };
+
+    let server =
+        Server::http(address).map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+    tracing::info!("Open http://{} in a browser", address);
+
+    for request in server.incoming_requests() {
+        let response = match (request.method(), ui_file(request.url(), &wasm_files)) {
+            (Method::Get, Some((content_type, contents))) => Response::from_data(contents)
+                .with_header(
+                    Header::from_bytes("Content-Type", content_type).expect("Invalid header"),
+                ),
+            (Method::Get, None) => Response::from_string("Not found").with_status_code(404),
+            _ => Response::from_string("Method not allowed").with_status_code(405),
+        };
+        let response = [
+            ("Content-Security-Policy", UI_CONTENT_SECURITY_POLICY),
+            ("X-Content-Type-Options", "nosniff"),
+            ("Referrer-Policy", "no-referrer"),
+            ("Cache-Control", "no-store"),
+        ]
+        .iter()
+        .fold(response, |response, (field, value)| {
+            response.with_header(Header::from_bytes(*field, *value).expect("Invalid header"))
+        });
+
+        let _ = request.respond(response);
+    }
+
+    Ok(())
+}
+
+// Content type and contents of a file of the page
+fn ui_file<'a>(url: &str, wasm_files: &'a WasmFiles) -> Option<(&'static str, &'a [u8])> {
+    match path(url) {
+        "/" | "/index.html" => Some(("text/html; charset=utf-8", UI_PAGE.as_bytes())),
+        "/index.js" => Some(("text/javascript", UI_SCRIPT.as_bytes())),
+        "/pkg/matched_data_wasm.js" => Some(("text/javascript", &wasm_files.script)),
+        "/pkg/matched_data_wasm_bg.wasm" => Some(("application/wasm", &wasm_files.module)),
+        _ => None,
+    }
+}
+
+// Reads the certificate chain and private key to serve HTTPS with
+fn read_tls_files(tls: &TlsFiles<'_>) -> Result<SslConfig, String> {
+    let certificate = std::fs::read(tls.certificate_filename).map_err(|_| {
+        format!(
+            "Failed to read TLS certificate {}",
+            tls.certificate_filename
+        )
+    })?;
+    let private_key = std::fs::read(tls.private_key_filename).map_err(|_| {
+        format!(
+            "Failed to read TLS private key {}",
+            tls.private_key_filename
+        )
+    })?;
+
+    // tiny_http panics on files without a PEM block it can load, so they are checked beforehand
+    let contains = |pem: &[u8], label: &str| {
+        let begin = format!("-----BEGIN {}-----", label);
+        pem.windows(begin.len())
+            .any(|window| window == begin.as_bytes())
+    };
+    if !contains(&certificate, "CERTIFICATE") {
+        return Err(format!(
+            "TLS certificate {} has no PEM certificate",
+            tls.certificate_filename
+        ));
+    }
+    if !contains(&private_key, "PRIVATE KEY") && !contains(&private_key, "RSA PRIVATE KEY") {
+        return Err(format!(
+            "TLS private key {} has no PEM PKCS #8 or RSA private key",
+            tls.private_key_filename
+        ));
+    }
+
+    Ok(SslConfig {
+        certificate,
+        private_key,
+    })
+}
+
+// Whether every address a listen address resolves to is only reachable from this host
+pub fn is_loopback(address: &str) -> Result<bool, String> {
+    Ok(address
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", address, e))?
+        .all(|socket_address| socket_address.ip().is_loopback()))
+}
+
+// Reads the bearer token clients must send, ignoring surrounding whitespace
+pub fn read_auth_token(filename: &str) -> Result<Zeroizing<String>, String> {
+    let token = Zeroizing::new(
+        std::fs::read_to_string(filename)
+            .map_err(|_| format!("Failed to read auth token file {}", filename))?,
+    );
+    let token = Zeroizing::new(token.trim().to_string());
+    if token.is_empty() {
+        return Err(format!("Auth token file {} is empty", filename));
+    }
+
+    Ok(token)
+}
+
+// Whether the Authorization header of a request carries the bearer token, if one is required,
+// compared in constant time so the token can't be guessed byte by byte from response times
+pub fn is_authorized(authorization: Option<&str>, auth_token: Option<&str>) -> bool {
+    let auth_token = match auth_token {
+        Some(auth_token) => auth_token,
+        None => return true,
+    };
+    let token = match authorization.and_then(|authorization| authorization.split_once(' ')) {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("Bearer") => token.trim(),
+        _ => return false,
+    };
+
+    token.len() == auth_token.len()
+        && token
+            .bytes()
+            .zip(auth_token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+// Path of a request URL, without its query string
+fn path(url: &str) -> &str {
+    url.split('?').next().unwrap_or(url)
+}
+
+// Handles a decryption request
+fn handle(method: &Method, url: &str, body: &str, decrypt: &Decrypt<'_>) -> Reply {
+    match (method, path(url)) {
+        (Method::Post, "/decrypt") => match decrypt(body.trim()) {
+            Ok(matched_data) => Reply::ok(matched_data),
+            Err(e) => Reply::error(400, e.code.name(), e.message),
+        },
+        (_, "/decrypt") => Reply::error(405, "method_not_allowed", "Method not allowed"),
+        _ => Reply::error(404, "not_found", "Not found"),
+    }
+}
+
+// Serves decryption requests as JSON lines over a Unix socket only accessible to the current user,
+// with request lines limited to the largest body accepted over HTTP
+#[cfg(unix)]
+pub fn serve_unix(path: &str, limits: &Limits, decrypt: &Decrypt<'_>) -> Result<(), String> {
+    limits.validate()?;
+    remove_stale_socket(path)?;
+
+    // The socket is created with the permissions left by the umask, so no other user can
+    // connect between creating it and restricting its permissions
+    let previous_umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(previous_umask) };
+    let listener = listener.map_err(|e| format!("Failed to listen on {}: {}", path, e))?;
+    tracing::info!(path, "Listening");
+
+    // Each worker answers one connection at a time, bounding the connections in flight, and a
+    // client that went away doesn't affect other connections
+    std::thread::scope(|scope| {
+        for _ in 0..limits.max_in_flight {
+            scope.spawn(|| {
+                for stream in listener.incoming().flatten() {
+                    let _ = handle_connection(stream, limits.max_body_size, decrypt);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+// Removes a socket left behind by a server that didn't shut down, refusing to remove other files
+#[cfg(unix)]
+fn remove_stale_socket(path: &str) -> Result<(), String> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(()),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(format!("{} already exists and is not a socket", path));
+    }
+    if UnixStream::connect(path).is_ok() {
+        return Err(format!("Another server is already listening on {}", path));
+    }
+
+    std::fs::remove_file(path).map_err(|e| format!("Failed to remove stale socket {}: {}", path, e))
+}
+
+// Answers each request line of a connection with a response line, rejecting lines larger than the
+// maximum size without buffering them
+#[cfg(unix)]
+fn handle_connection(
+    stream: UnixStream,
+    max_line_size: u64,
+    decrypt: &Decrypt<'_>,
+) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = Zeroizing::new(Vec::new());
+    loop {
+        line.clear();
+        let read = reader
+            .by_ref()
+            .take(max_line_size + 1)
+            .read_until(b'\n', &mut line)?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        let response = if read as u64 > max_line_size && line.last() != Some(&b'\n') {
+            // The rest of an oversized line is skipped
+            reader.skip_until(b'\n')?;
+            error_line("Request is too large")
+        } else {
+            match std::str::from_utf8(&line) {
+                Ok(line) => handle_line(line.trim_end(), decrypt),
+                Err(_) => error_line("Request is not valid UTF-8"),
+            }
+        };
+        writeln!(writer, "{}", response.as_str())?;
+    }
+}
+
+// Handles a JSON request line, returning the JSON response line
+#[cfg(unix)]
+fn handle_line(line: &str, decrypt: &Decrypt<'_>) -> Zeroizing<String> {
+    let response = match serde_json::from_str::<SocketRequest>(line) {
+        Ok(request) => match decrypt(&request.data) {
+            // Base64 needs no escaping, so the response is formatted directly into a wiped buffer
+            Ok(matched_data) => {
+                let matched_data_base64 =
+                    Zeroizing::new(radix64::STD.encode(matched_data.as_slice()));
+                return Zeroizing::new(format!(
+                    "{{\"matched_data\":\"{}\"}}",
+                    matched_data_base64.as_str()
+                ));
+            }
+            Err(e) => serde_json::json!({ "error": e.message }),
+        },
+        Err(_) => return error_line("Request is not a JSON object with a data field"),
+    };
+
+    Zeroizing::new(response.to_string())
+}
+
+// JSON response line of a failed request
+#[cfg(unix)]
+fn error_line(message: &str) -> Zeroizing<String> {
+    Zeroizing::new(serde_json::json!({ "error": message }).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    // Decrypts "valid" only
+    fn decrypt(encrypted_matched_data: &str) -> Result<Zeroizing<Vec<u8>>, CliError> {
+        match encrypted_matched_data {
+            "valid" => Ok(Zeroizing::new(b"test matched data".to_vec())),
+            _ => Err(CliError::new(
+                ErrorCode::DecryptionFailed,
+                "Failed to decrypt matched data",
+            )),
+        }
+    }
+
+    #[test]
+    fn test_handle() {
+        assert_eq!(
+            Reply::ok(Zeroizing::new(b"test matched data".to_vec())),
+            handle(&Method::Post, "/decrypt", "valid\n", &decrypt)
+        );
+        assert_eq!(
+            Reply::error(400, "decryption_failed", "Failed to decrypt matched data"),
+            handle(&Method::Post, "/decrypt", "invalid", &decrypt)
+        );
+        assert_eq!(405, handle(&Method::Get, "/decrypt", "", &decrypt).status);
+        assert_eq!(
+            200,
+            handle(&Method::Post, "/decrypt?source=test", "valid", &decrypt).status
+        );
+        assert_eq!(404, handle(&Method::Post, "/", "valid", &decrypt).status);
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        assert!(is_authorized(None, None));
+        assert!(is_authorized(Some("Bearer secret"), None));
+        assert!(is_authorized(Some("Bearer secret"), Some("secret")));
+        assert!(is_authorized(Some("bearer secret"), Some("secret")));
+        assert!(!is_authorized(None, Some("secret")));
+        assert!(!is_authorized(Some("Bearer secre"), Some("secret")));
+        assert!(!is_authorized(Some("Bearer secreT"), Some("secret")));
+        assert!(!is_authorized(Some("Basic secret"), Some("secret")));
+        assert!(!is_authorized(Some("secret"), Some("secret")));
+    }
+
+    #[test]
+    fn test_ui_file() {
+        let wasm_files = WasmFiles {
+            script: b"script".to_vec(),
+            module: b"module".to_vec(),
+        };
+
+        assert_eq!(
+            Some(("text/html; charset=utf-8", UI_PAGE.as_bytes())),
+            ui_file("/", &wasm_files)
+        );
+        assert_eq!(
+            Some(("text/javascript", UI_SCRIPT.as_bytes())),
+            ui_file("/index.js?v=1", &wasm_files)
+        );
+        assert_eq!(
+            Some(("application/wasm", b"module".as_slice())),
+            ui_file("/pkg/matched_data_wasm_bg.wasm", &wasm_files)
+        );
+        assert_eq!(None, ui_file("/pkg/../Cargo.toml", &wasm_files));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let rate_limiter = RateLimiter::new(2);
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let other_client: IpAddr = "192.0.2.2".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(rate_limiter.allow(client, now));
+        assert!(rate_limiter.allow(client, now));
+        assert!(!rate_limiter.allow(client, now));
+        assert!(rate_limiter.allow(other_client, now));
+
+        let later = now + std::time::Duration::from_millis(500);
+        assert!(rate_limiter.allow(client, later));
+        assert!(!rate_limiter.allow(client, later));
+
+        let much_later = now + std::time::Duration::from_secs(60);
+        assert!(rate_limiter.allow(client, much_later));
+        assert!(rate_limiter.allow(client, much_later));
+        assert!(!rate_limiter.allow(client, much_later));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_handle_line() {
+        assert_eq!(
+            r#"{"matched_data":"dGVzdCBtYXRjaGVkIGRhdGE="}"#,
+            handle_line(r#"{"data":"valid"}"#, &decrypt).as_str()
+        );
+        assert_eq!(
+            r#"{"error":"Failed to decrypt matched data"}"#,
+            handle_line(r#"{"data":"invalid"}"#, &decrypt).as_str()
+        );
+        assert_eq!(
+            r#"{"error":"Request is not a JSON object with a data field"}"#,
+            handle_line("invalid", &decrypt).as_str()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_handle_connection() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client
+            .write_all(
+                b"{\"data\":\"valid\"}\n{\"data\":\"valid\",\"padding\":\"........\"}\n{\"data\":\"valid\"}",
+            )
+            .unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        handle_connection(server, 20, &decrypt).unwrap();
+
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).unwrap();
+        assert_eq!(
+            concat!(
+                r#"{"matched_data":"dGVzdCBtYXRjaGVkIGRhdGE="}"#,
+                "\n",
+                r#"{"error":"Request is too large"}"#,
+                "\n",
+                r#"{"matched_data":"dGVzdCBtYXRjaGVkIGRhdGE="}"#,
+                "\n"
+            ),
+            responses
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_stale_socket() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let path = temp_dir.path().join("matched-data.sock");
+        let path = path.to_str().unwrap();
+
+        assert!(remove_stale_socket(path).is_ok());
+
+        let listener = UnixListener::bind(path).unwrap();
+        assert!(remove_stale_socket(path).is_err());
+        drop(listener);
+        assert!(remove_stale_socket(path).is_ok());
+        assert!(UnixListener::bind(path).is_ok());
+
+        let file_path = temp_dir.path().join("file.txt");
+        std::fs::write(&file_path, "not a socket").unwrap();
+        assert!(remove_stale_socket(file_path.to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use std::io::{self, Write};
+#[cfg(feature = "api")]
+use std::thread;
+#[cfg(feature = "api")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "api")]
+use zeroize::{Zeroize, Zeroizing};
+
+// Destination of the events enriched by logs and pipe, stdout unless a SIEM sink is chosen
+pub trait Sink {
+    fn write_event(&mut self, event: &str) -> io::Result<()>;
+
+    // Called after each batch of events, so a sink can deliver them or keep batching small ones
+    fn flush_events(&mut self) -> io::Result<()>;
+
+    // Delivers every event written, before exiting
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush_events()
+    }
+}
+
+// Writers such as stdout get a line per event
+impl<W: Write> Sink for W {
+    fn write_event(&mut self, event: &str) -> io::Result<()> {
+        writeln!(self, "{}", event)
+    }
+
+    fn flush_events(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+// Most events posted to a SIEM in one request
+#[cfg(feature = "api")]
+const MAX_BATCH_EVENTS: usize = 500;
+
+// Most bytes of events posted to a SIEM in one request
+#[cfg(feature = "api")]
+const MAX_BATCH_BYTES: usize = 4 << 20;
+
+// How long a partial batch is held for more events before being posted
+#[cfg(feature = "api")]
+const MAX_BATCH_AGE: Duration = Duration::from_secs(1);
+
+// Times a request failing with a network error or a 429 or 5xx status is sent again
+#[cfg(feature = "api")]
+const RETRIES: u32 = 5;
+
+// Delay before the first retry, doubled before each next one
+#[cfg(feature = "api")]
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// Events waiting to be posted to a SIEM in one request, wiped once posted
+#[cfg(feature = "api")]
+pub struct Batch {
+    pub body: Zeroizing<Vec<u8>>,
+    events: usize,
+    started: Option<Instant>,
+}
+
+#[cfg(feature = "api")]
+impl Batch {
+    pub fn new() -> Self {
+        Batch {
+            body: Zeroizing::new(Vec::new()),
+            events: 0,
+            started: None,
+        }
+    }
+
+    // Adds an event already formatted for the SIEM's bulk API
+    pub fn push(&mut self, event: &[u8]) {
+        self.body.extend_from_slice(event);
+        self.events += 1;
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.events >= MAX_BATCH_EVENTS || self.body.len() >= MAX_BATCH_BYTES
+    }
+
+    // Whether the batch has been held long enough to be posted partial
+    pub fn is_due(&self) -> bool {
+        self.started
+            .is_some_and(|started| started.elapsed() >= MAX_BATCH_AGE)
+    }
+
+    pub fn clear(&mut self) {
+        self.body.zeroize();
+        self.events = 0;
+        self.started = None;
+    }
+}
+
+// Posts a body, sending it again with exponential backoff while the SIEM is unreachable,
+// overloaded or failing
+#[cfg(feature = "api")]
+pub fn post_with_retry(
+    request: impl Fn() -> ureq::Request,
+    body: &[u8],
+    siem: &str,
+) -> io::Result<ureq::Response> {
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        let error = match request().send_bytes(body) {
+            Ok(response) => return Ok(response),
+            Err(ureq::Error::Status(status, response)) if status != 429 && status < 500 => {
+                return Err(io::Error::other(format!(
+                    "{} request failed with status {}: {}",
+                    siem,
+                    status,
+                    response.into_string().unwrap_or_default().trim()
+                )))
+            }
+            Err(ureq::Error::Status(status, _)) => format!("status {}", status),
+            Err(e) => e.to_string(),
+        };
+
+        attempt += 1;
+        if attempt > RETRIES {
+            return Err(io::Error::other(format!(
+                "{} request failed after {} attempts: {}",
+                siem, attempt, error
+            )));
+        }
+        tracing::warn!(
+            siem,
+            %error,
+            retry_in_s = delay.as_secs(),
+            "Request failed, retrying"
+        );
+        thread::sleep(delay);
+        delay *= 2;
+    }
+}
@@ -0,0 +1,118 @@
+use crate::sink::{self, Batch, Sink};
+use serde::de::IgnoredAny;
+use std::io;
+use zeroize::Zeroizing;
+
+// Path of the HEC endpoint taking JSON events
+const EVENT_PATH: &str = "/services/collector/event";
+
+// Posts events to a Splunk HTTP Event Collector in batches
+pub struct SplunkHec {
+    url: String,
+    token: Zeroizing<String>,
+    batch: Batch,
+}
+
+impl SplunkHec {
+    // Events go to the index and source type configured for the token
+    pub fn new(url: &str, token: Zeroizing<String>) -> Self {
+        SplunkHec {
+            url: event_url(url),
+            token,
+            batch: Batch::new(),
+        }
+    }
+
+    fn post(&mut self) -> io::Result<()> {
+        let authorization = Zeroizing::new(format!("Splunk {}", self.token.as_str()));
+        sink::post_with_retry(
+            || {
+                ureq::post(&self.url)
+                    .set("Authorization", &authorization)
+                    .set("Content-Type", "application/json")
+            },
+            &self.batch.body,
+            "Splunk HEC",
+        )?;
+        self.batch.clear();
+
+        Ok(())
+    }
+}
+
+impl Sink for SplunkHec {
+    fn write_event(&mut self, event: &str) -> io::Result<()> {
+        self.batch.push(hec_event(event).as_bytes());
+        if self.batch.is_full() {
+            self.post()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush_events(&mut self) -> io::Result<()> {
+        if self.batch.is_full() || self.batch.is_due() {
+            self.post()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.batch.is_empty() {
+            self.post()?;
+        }
+
+        Ok(())
+    }
+}
+
+// URL of the JSON event endpoint, from the collector's base URL or the full endpoint URL
+fn event_url(url: &str) -> String {
+    if url.contains("/services/collector") {
+        url.to_string()
+    } else {
+        format!("{}{}", url.trim_end_matches('/'), EVENT_PATH)
+    }
+}
+
+// Wraps an event for HEC, lines passed through by pipe that aren't JSON becoming a string event
+fn hec_event(event: &str) -> Zeroizing<String> {
+    Zeroizing::new(match serde_json::from_str::<IgnoredAny>(event) {
+        Ok(_) => format!("{{\"event\":{}}}\n", event),
+        Err(_) => format!(
+            "{{\"event\":{}}}\n",
+            Zeroizing::new(serde_json::to_string(event).expect("Failed to serialize event"))
+                .as_str()
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_url() {
+        assert_eq!(
+            "https://splunk.example.com:8088/services/collector/event",
+            event_url("https://splunk.example.com:8088/")
+        );
+        assert_eq!(
+            "https://splunk.example.com:8088/services/collector",
+            event_url("https://splunk.example.com:8088/services/collector")
+        );
+    }
+
+    #[test]
+    fn test_hec_event() {
+        assert_eq!(
+            "{\"event\":{\"RayID\":\"1\"}}\n",
+            hec_event(r#"{"RayID":"1"}"#).as_str()
+        );
+        assert_eq!(
+            "{\"event\":\"not \\\"JSON\\\"\"}\n",
+            hec_event(r#"not "JSON""#).as_str()
+        );
+    }
+}
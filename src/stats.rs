@@ -0,0 +1,264 @@
+use crate::encoding;
+use crate::logs::{self, Decrypt};
+use crate::TRUNCATED;
+use matched_data_cli::versions;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::BufRead;
+
+// Counts of the events of Logpush logs and of their matched data, never including a plaintext
+#[derive(Default, Serialize)]
+pub struct Stats {
+    pub events: usize,
+    // Lines that are not JSON events
+    pub invalid_lines: usize,
+    pub events_with_matched_data: usize,
+    pub matched_data: usize,
+    pub truncated: usize,
+    pub not_base64: usize,
+    // Matched data per encryption format version, from their first byte
+    pub versions: BTreeMap<u8, usize>,
+    // Matched data decrypted by any of the keys, None if no key was provided
+    pub decrypted: Option<usize>,
+    pub keys: Vec<KeyStats>,
+    pub encrypted_sizes: Histogram,
+    pub plaintext_sizes: Histogram,
+}
+
+#[derive(Serialize)]
+pub struct KeyStats {
+    pub key: String,
+    pub decrypted: usize,
+}
+
+// Counts of sizes in bytes by power of two, the bucket of a size being its bit length, so bucket
+// n counts sizes from 2^(n-1) to 2^n - 1 and bucket 0 empty payloads
+#[derive(Default)]
+pub struct Histogram {
+    buckets: Vec<usize>,
+}
+
+impl Histogram {
+    pub fn add(&mut self, size: usize) {
+        let bucket = (usize::BITS - size.leading_zeros()) as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+    }
+
+    // Smallest and largest size of each bucket holding any, with their count
+    pub fn buckets(&self) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(bucket, &count)| match bucket {
+                0 => (0, 0, count),
+                _ => (1 << (bucket - 1), (1 << bucket) - 1, count),
+            })
+    }
+}
+
+impl Serialize for Histogram {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            self.buckets().map(
+                |(min, max, count)| serde_json::json!({"min": min, "max": max, "count": count}),
+            ),
+        )
+    }
+}
+
+impl Stats {
+    // Stats to gather, trying each matched data with the decrypt functions of the named keys
+    pub fn new(key_names: &[String]) -> Self {
+        Stats {
+            decrypted: (!key_names.is_empty()).then_some(0),
+            keys: key_names
+                .iter()
+                .map(|key| KeyStats {
+                    key: key.clone(),
+                    decrypted: 0,
+                })
+                .collect(),
+            ..Stats::default()
+        }
+    }
+
+    // Adds the events of a log, NDJSON lines decompressed already
+    pub fn add_log<R: BufRead>(&mut self, log: R, keys: &[&Decrypt<'_>]) -> Result<(), String> {
+        for line in log.lines() {
+            let line = line.map_err(logs::read_error)?;
+            if !line.trim().is_empty() {
+                self.add_line(&line, keys);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_line(&mut self, line: &str, keys: &[&Decrypt<'_>]) {
+        let event: Value = match serde_json::from_str(line) {
+            Ok(event) => event,
+            Err(_) => {
+                self.invalid_lines += 1;
+                return;
+            }
+        };
+        self.events += 1;
+
+        let all_matched_data = logs::find_all_encrypted_matched_data(&event);
+        if !all_matched_data.is_empty() {
+            self.events_with_matched_data += 1;
+        }
+        for (_, encrypted_matched_data) in all_matched_data {
+            self.matched_data += 1;
+            if encrypted_matched_data == TRUNCATED {
+                self.truncated += 1;
+                continue;
+            }
+            let bytes = match encoding::decode_base64(encrypted_matched_data, false) {
+                Ok(bytes) if !bytes.is_empty() => bytes,
+                _ => {
+                    self.not_base64 += 1;
+                    continue;
+                }
+            };
+            *self.versions.entry(bytes[0]).or_default() += 1;
+            self.encrypted_sizes.add(bytes.len());
+
+            // The first key that decrypts it gives the plaintext size, every key is still tried
+            // so each one's count is complete
+            let mut plaintext_size = None;
+            for (key, decrypt) in self.keys.iter_mut().zip(keys) {
+                if let Ok(plaintext) = decrypt(encrypted_matched_data) {
+                    key.decrypted += 1;
+                    plaintext_size.get_or_insert(plaintext.len());
+                }
+            }
+            if let Some(size) = plaintext_size {
+                *self.decrypted.get_or_insert(0) += 1;
+                self.plaintext_sizes.add(size);
+            }
+        }
+    }
+
+    // Formats the stats as aligned lines for a terminal
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        // Headings have an empty value, and no padding after them
+        let mut line = |label: &str, value: &dyn Display| {
+            let line = format!("{:<28}{:>12}", label, value);
+            text.push_str(line.trim_end());
+            text.push('\n');
+        };
+        line("Events", &self.events);
+        if self.invalid_lines > 0 {
+            line("Invalid lines", &self.invalid_lines);
+        }
+        line("Events with matched data", &self.events_with_matched_data);
+        line("Matched data", &self.matched_data);
+        line("  truncated", &self.truncated);
+        line("  not base64", &self.not_base64);
+
+        line("Format versions", &"");
+        for (version, count) in &self.versions {
+            let label = match versions::find_decryptor(*version) {
+                Some(_) => format!("  {}", version),
+                None => format!("  {} (unsupported)", version),
+            };
+            line(&label, count);
+        }
+
+        if let Some(decrypted) = self.decrypted {
+            let encrypted: usize = self.versions.values().sum();
+            line("Decrypted", &format!("{} of {}", decrypted, encrypted));
+            for key in &self.keys {
+                line(&format!("  {}", key.key), &key.decrypted);
+            }
+        }
+
+        for (title, histogram) in [
+            ("Encrypted sizes", &self.encrypted_sizes),
+            ("Plaintext sizes", &self.plaintext_sizes),
+        ] {
+            if histogram.buckets().next().is_none() {
+                continue;
+            }
+            line(title, &"");
+            for (min, max, count) in histogram.buckets() {
+                line(&format!("  {}-{} B", min, max), &count);
+            }
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zeroize::Zeroizing;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        for size in [0, 1, 3, 300, 511, 512] {
+            histogram.add(size);
+        }
+        assert_eq!(
+            vec![
+                (0, 0, 1),
+                (1, 1, 1),
+                (2, 3, 1),
+                (256, 511, 2),
+                (512, 1023, 1)
+            ],
+            histogram.buckets().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_add_log() {
+        // Matched data decrypting to its own base64 decoding, for the key that knows version 3
+        let decrypt = |encrypted_matched_data: &str| match encoding::decode_base64(
+            encrypted_matched_data,
+            false,
+        ) {
+            Ok(bytes) if bytes[0] == 3 => Ok(Zeroizing::new(bytes[1..].to_vec())),
+            _ => Err("Failed to decrypt matched data".to_string()),
+        };
+        let never = |_: &str| Err("Failed to decrypt matched data".to_string());
+        let log = [
+            r#"{"RayID":"1","Metadata":{"encrypted_matched_data":"A3Rlc3Q="}}"#,
+            r#"{"RayID":"2","Metadata":{"encrypted_matched_data":"BHRlc3Q="}}"#,
+            r#"{"RayID":"3","Metadata":{"encrypted_matched_data":"truncated"}}"#,
+            r#"{"RayID":"4"}"#,
+            "",
+            "not json",
+        ]
+        .join("\n");
+
+        let mut stats = Stats::new(&["a.txt".to_string(), "b.txt".to_string()]);
+        stats.add_log(log.as_bytes(), &[&decrypt, &never]).unwrap();
+        assert_eq!(4, stats.events);
+        assert_eq!(1, stats.invalid_lines);
+        assert_eq!(3, stats.events_with_matched_data);
+        assert_eq!(1, stats.truncated);
+        assert_eq!(BTreeMap::from([(3, 1), (4, 1)]), stats.versions);
+        assert_eq!(Some(1), stats.decrypted);
+        assert_eq!(1, stats.keys[0].decrypted);
+        assert_eq!(0, stats.keys[1].decrypted);
+        assert_eq!(
+            vec![(4, 7, 2)],
+            stats.encrypted_sizes.buckets().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(4, 7, 1)],
+            stats.plaintext_sizes.buckets().collect::<Vec<_>>()
+        );
+        assert!(stats.to_text().contains("  4 (unsupported)"));
+    }
+}
@@ -0,0 +1,47 @@
+use hpke::Serializable;
+use matched_data_cli::matched_data::{
+    derive_key_pair, encrypt_data_with_rng, serialize_encrypted_data,
+};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use serde::{Deserialize, Serialize};
+
+// Plaintexts covering empty, text, binary and multi-block payloads
+const PLAINTEXTS: &[&[u8]] = &[
+    b"",
+    b"test matched data",
+    b"\x00\x01\x02\xfe\xff",
+    &[0x61; 300],
+];
+
+// A key pair, plaintext and matched data encrypted with a given format version
+#[derive(Serialize, Deserialize)]
+pub struct TestVector {
+    pub version: u8,
+    pub private_key: String,
+    pub public_key: String,
+    pub plaintext: String,
+    pub matched_data: String,
+}
+
+// Generates deterministic test vectors for each supported format version, all values base64 encoded
+pub fn generate() -> Vec<TestVector> {
+    PLAINTEXTS
+        .iter()
+        .enumerate()
+        .map(|(i, plaintext)| {
+            // Seeding both the key pair and the ephemeral key makes the output reproducible
+            let (private_key, public_key) = derive_key_pair(&[i as u8; 32]);
+            let mut rng = ChaCha20Rng::seed_from_u64(i as u64);
+            let encrypted_data = encrypt_data_with_rng(plaintext, &public_key, &mut rng)
+                .expect("Failed to encrypt test vector");
+
+            TestVector {
+                version: 3,
+                private_key: radix64::STD.encode(&private_key.to_bytes()),
+                public_key: radix64::STD.encode(&public_key.to_bytes()),
+                plaintext: radix64::STD.encode(plaintext),
+                matched_data: radix64::STD.encode(&serialize_encrypted_data(&encrypted_data)),
+            }
+        })
+        .collect()
+}
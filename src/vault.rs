@@ -0,0 +1,126 @@
+use crate::config;
+use crate::encoding;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use zeroize::Zeroizing;
+
+// File in the home directory holding the token of the last `vault login`, used when VAULT_TOKEN
+// isn't set
+const TOKEN_FILENAME: &str = ".vault-token";
+
+// Fetches a base64 encoded private key from a field of a Vault KV secret, version 2 or 1, using
+// the server in VAULT_ADDR and the token in VAULT_TOKEN, so it is never stored in a local file
+pub fn read_private_key(
+    path: &str,
+    field: &str,
+    strict: bool,
+) -> Result<Zeroizing<Vec<u8>>, String> {
+    let address = env::var("VAULT_ADDR")
+        .map_err(|_| "VAULT_ADDR is not set, set it to the address of the Vault server")?;
+    let token = token()?;
+    let path = path.trim_matches('/');
+
+    // KV version 2 secrets are read under data/ after their mount, falling back to the path of a
+    // version 1 secret
+    let (mut secret, data_pointer) = match read_secret(&address, &token, &kv2_path(path)) {
+        Ok(Some(secret)) if secret["data"]["data"].is_object() => (secret, "/data/data"),
+        _ => match read_secret(&address, &token, path)? {
+            Some(secret) => (secret, "/data"),
+            None => return Err(format!("Vault has no secret at {}", path)),
+        },
+    };
+    let private_key_base64 = match secret
+        .pointer_mut(data_pointer)
+        .and_then(|data| data.get_mut(field))
+        .map(Value::take)
+    {
+        Some(Value::String(private_key_base64)) => Zeroizing::new(private_key_base64),
+        _ => return Err(format!("Vault secret {} has no {} field", path, field)),
+    };
+
+    encoding::decode_base64(&private_key_base64, strict)
+        .map(Zeroizing::new)
+        .map_err(|_| "Private key in Vault is not base64 encoded".to_string())
+}
+
+// Token from VAULT_TOKEN, or from the token file left by the vault CLI
+fn token() -> Result<Zeroizing<String>, String> {
+    if let Ok(token) = env::var("VAULT_TOKEN") {
+        return Ok(Zeroizing::new(token));
+    }
+
+    config::home_dir()
+        .and_then(|home| fs::read_to_string(home.join(TOKEN_FILENAME)).ok())
+        .map(|token| Zeroizing::new(token.trim().to_string()))
+        .ok_or_else(|| "VAULT_TOKEN is not set, set it or log in with vault login".to_string())
+}
+
+// Reads a secret, or None if there is none at the path
+fn read_secret(address: &str, token: &str, path: &str) -> Result<Option<Value>, String> {
+    let url = format!("{}/v1/{}", address.trim_end_matches('/'), path);
+    let mut request = ureq::get(&url).set("X-Vault-Token", token);
+    if let Ok(namespace) = env::var("VAULT_NAMESPACE") {
+        request = request.set("X-Vault-Namespace", &namespace);
+    }
+
+    match request.call() {
+        Ok(response) => response
+            .into_json()
+            .map(Some)
+            .map_err(|_| "Vault response is not JSON".to_string()),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(ureq::Error::Status(status, response)) => Err(format!(
+            "Vault request failed with status {}: {}",
+            status,
+            vault_errors(&response.into_json().unwrap_or(Value::Null))
+        )),
+        Err(e) => Err(format!("Vault request failed: {}", e)),
+    }
+}
+
+// Path of a KV version 2 secret's data, under data/ after the mount
+fn kv2_path(path: &str) -> String {
+    match path.split_once('/') {
+        Some((mount, secret)) => format!("{}/data/{}", mount, secret),
+        None => path.to_string(),
+    }
+}
+
+// Joins the error messages of a Vault response
+fn vault_errors(response: &Value) -> String {
+    response["errors"]
+        .as_array()
+        .map(|errors| {
+            errors
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .filter(|errors| !errors.is_empty())
+        .unwrap_or_else(|| "no error message".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kv2_path() {
+        assert_eq!(
+            "secret/data/waf/matched-data",
+            kv2_path("secret/waf/matched-data")
+        );
+        assert_eq!("secret", kv2_path("secret"));
+    }
+
+    #[test]
+    fn test_vault_errors() {
+        assert_eq!(
+            "permission denied",
+            vault_errors(&serde_json::json!({"errors": ["permission denied"]}))
+        );
+        assert_eq!("no error message", vault_errors(&Value::Null));
+    }
+}
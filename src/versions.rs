@@ -0,0 +1,150 @@
+use crate::matched_data::{
+    decrypt_data_in_mode, deserialize_encrypted_data, CipherSuite, CurrentSuite,
+    DecryptAnyVersionError, Mode, X25519HkdfSha256ChaCha20Poly1305,
+};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use hpke::aead::Aead;
+use hpke::kdf::Kdf;
+use hpke::{Deserializable, Kem};
+use zeroize::Zeroizing;
+
+// RFC 9180 identifiers of the KEM, KDF and AEAD of an HPKE format version
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HpkeSuiteIds {
+    pub kem: u16,
+    pub kdf: u16,
+    pub aead: u16,
+}
+
+// Decrypts one encryption format version of serialized matched data, including its version byte
+pub trait VersionedDecryptor: Sync {
+    // Version byte prefixing matched data in this format
+    fn version(&self) -> u8;
+
+    // Algorithms and serialization of the format, listed by the versions command
+    fn description(&self) -> &'static str;
+
+    // Cipher suite of the format, for formats built on HPKE
+    fn hpke_suite(&self) -> Option<HpkeSuiteIds> {
+        None
+    }
+
+    fn decrypt(
+        &self,
+        private_key_bytes: &[u8],
+        serialized_encrypted_data: &[u8],
+        mode: &Mode<'_>,
+    ) -> Result<Zeroizing<Vec<u8>>, DecryptAnyVersionError>;
+}
+
+// HPKE decryptor of the format version of a cipher suite, serialized by serialize_encrypted_data
+pub struct HpkeDecryptor<S> {
+    description: &'static str,
+    suite: PhantomData<fn() -> S>,
+}
+
+impl<S> HpkeDecryptor<S> {
+    pub const fn new(description: &'static str) -> Self {
+        HpkeDecryptor {
+            description,
+            suite: PhantomData,
+        }
+    }
+}
+
+impl<S: CipherSuite> HpkeDecryptor<S> {
+    // Decrypts with a private key of the suite's KEM deserialized already
+    pub fn decrypt_with_private_key(
+        &self,
+        private_key: &<S::Kem as Kem>::PrivateKey,
+        serialized_encrypted_data: &[u8],
+        mode: &Mode<'_>,
+    ) -> Result<Zeroizing<Vec<u8>>, DecryptAnyVersionError> {
+        let op_mode = mode.receiver::<S::Kem>()?;
+        let encrypted_data = deserialize_encrypted_data::<S>(serialized_encrypted_data)
+            .map_err(DecryptAnyVersionError::InvalidMatchedData)?;
+
+        decrypt_data_in_mode(&encrypted_data, private_key, &op_mode, mode.aad).map_err(|error| {
+            DecryptAnyVersionError::Decrypt {
+                component_lengths: encrypted_data.component_lengths(),
+                error,
+            }
+        })
+    }
+}
+
+impl<S: CipherSuite> VersionedDecryptor for HpkeDecryptor<S> {
+    fn version(&self) -> u8 {
+        S::VERSION
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn hpke_suite(&self) -> Option<HpkeSuiteIds> {
+        Some(HpkeSuiteIds {
+            kem: S::Kem::KEM_ID,
+            kdf: S::Kdf::KDF_ID,
+            aead: S::Aead::AEAD_ID,
+        })
+    }
+
+    fn decrypt(
+        &self,
+        private_key_bytes: &[u8],
+        serialized_encrypted_data: &[u8],
+        mode: &Mode<'_>,
+    ) -> Result<Zeroizing<Vec<u8>>, DecryptAnyVersionError> {
+        let private_key = <S::Kem as Kem>::PrivateKey::from_bytes(private_key_bytes)
+            .map_err(DecryptAnyVersionError::InvalidPrivateKey)?;
+
+        self.decrypt_with_private_key(&private_key, serialized_encrypted_data, mode)
+    }
+}
+
+pub const VERSION_3: HpkeDecryptor<X25519HkdfSha256ChaCha20Poly1305> =
+    HpkeDecryptor::new("HPKE X25519-HKDF-SHA256, HKDF-SHA256, ChaCha20Poly1305");
+
+// Decryptor of the suite keys are generated for, whose private key Decryptor deserializes once
+pub const CURRENT: &HpkeDecryptor<CurrentSuite> = &VERSION_3;
+
+// Every format version that can be decrypted, a new version is added here
+pub const DECRYPTORS: &[&dyn VersionedDecryptor] = &[&VERSION_3];
+
+// Finds the decryptor of an encryption format version
+pub fn find_decryptor(version: u8) -> Option<&'static dyn VersionedDecryptor> {
+    DECRYPTORS
+        .iter()
+        .find(|decryptor| decryptor.version() == version)
+        .copied()
+}
+
+// Encryption format versions that can be decrypted, in registration order
+pub fn supported_versions() -> Vec<u8> {
+    DECRYPTORS
+        .iter()
+        .map(|decryptor| decryptor.version())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_decryptor() {
+        assert_eq!(vec![3], supported_versions());
+        assert_eq!(3, find_decryptor(3).unwrap().version());
+        assert!(find_decryptor(2).is_none());
+        assert_eq!(
+            Some(HpkeSuiteIds {
+                kem: 0x0020,
+                kdf: 0x0001,
+                aead: 0x0003
+            }),
+            find_decryptor(3).unwrap().hpke_suite()
+        );
+    }
+}
@@ -0,0 +1,352 @@
+use crate::http;
+use crate::logs::{self, Keys, LogOptions};
+use crate::object_storage;
+use crate::progress::Progress;
+use crate::serve;
+use crate::sink::Sink;
+use serde::Deserialize;
+use serde_json::Value;
+use std::io::{BufReader, Read};
+use std::process::{Command, Output};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Method, Request, Response, Server, StatusCode};
+
+// Most messages received from SQS at once, and how long a receive waits for one, the maximums
+// SQS allows
+const SQS_MAX_MESSAGES: &str = "10";
+const SQS_WAIT_SECONDS: &str = "20";
+
+// How long to wait before receiving again after the aws CLI failed
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+// Largest notification accepted by the webhook, S3 sending one object per notification
+const MAX_NOTIFICATION_SIZE: u64 = 1 << 20;
+
+// R2 actions that create an object
+const R2_CREATE_ACTIONS: &[&str] = &["PutObject", "CopyObject", "CompleteMultipartUpload"];
+
+// How new objects are processed once announced
+pub struct WatchOptions<'a> {
+    // Buckets objects are accepted from, any if empty
+    pub buckets: &'a [String],
+    pub r2_account_id: Option<&'a str>,
+    pub log: LogOptions,
+}
+
+#[derive(Deserialize)]
+struct ReceivedMessages {
+    #[serde(rename = "Messages", default)]
+    messages: Vec<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    #[serde(rename = "ReceiptHandle")]
+    receipt_handle: String,
+    #[serde(rename = "Body")]
+    body: String,
+}
+
+// URLs of the objects created according to an S3 or R2 event notification, possibly wrapped in
+// an SNS envelope or batched in an array
+pub fn object_urls(notification: &Value) -> Vec<String> {
+    if let Some(notifications) = notification.as_array() {
+        return notifications.iter().flat_map(object_urls).collect();
+    }
+    if notification["Type"] == "Notification" {
+        return notification["Message"]
+            .as_str()
+            .and_then(|message| serde_json::from_str(message).ok())
+            .map(|message| object_urls(&message))
+            .unwrap_or_default();
+    }
+
+    // S3 notifications, keys URL encoded
+    if let Some(records) = notification["Records"].as_array() {
+        return records
+            .iter()
+            .filter(|record| {
+                record["eventName"]
+                    .as_str()
+                    .is_some_and(|name| name.starts_with("ObjectCreated:"))
+            })
+            .filter_map(|record| {
+                Some(format!(
+                    "s3://{}/{}",
+                    record["s3"]["bucket"]["name"].as_str()?,
+                    http::percent_decode(record["s3"]["object"]["key"].as_str()?)
+                ))
+            })
+            .collect();
+    }
+
+    // R2 notifications, as sent to a Cloudflare Queue
+    match (
+        notification["bucket"].as_str(),
+        notification["object"]["key"].as_str(),
+        notification["action"].as_str(),
+    ) {
+        (Some(bucket), Some(key), Some(action)) if R2_CREATE_ACTIONS.contains(&action) => {
+            vec![format!("r2://{}/{}", bucket, key)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Whether an object is in one of the buckets objects are accepted from
+fn is_allowed(url: &str, buckets: &[String]) -> bool {
+    let bucket = url
+        .split_once("://")
+        .and_then(|(_, path)| path.split('/').next())
+        .unwrap_or_default();
+    buckets.is_empty() || buckets.iter().any(|allowed| allowed == bucket)
+}
+
+// Downloads the objects of a notification one after the other, decrypting their events to the
+// sink, stopping at the first that fails so the notification can be retried
+fn process_notification(
+    notification: &Value,
+    options: &WatchOptions<'_>,
+    sink: &mut dyn Sink,
+    keys: &Keys<'_>,
+) -> Result<(), String> {
+    for url in object_urls(notification) {
+        if !is_allowed(&url, options.buckets) {
+            tracing::warn!(url, "Skipping object of a bucket not passed with --bucket");
+            continue;
+        }
+
+        let object = object_storage::open(&url, options.r2_account_id)?;
+        let summary = logs::decrypt_log(
+            logs::decompress(BufReader::new(object))?,
+            &options.log,
+            None,
+            &mut Progress::new(false),
+            sink,
+            keys,
+        )
+        .map_err(|e| format!("{}: {}", url, e))?;
+        tracing::info!(
+            url,
+            lines = summary.lines,
+            failures = summary.failures,
+            "Processed object"
+        );
+    }
+    Ok(())
+}
+
+fn aws(args: &[&str]) -> Result<Output, String> {
+    Command::new("aws")
+        .args(args)
+        .output()
+        .map_err(|_| "Failed to run the aws CLI, check that it is installed".to_string())
+}
+
+// Receives S3 or R2 event notifications from an SQS queue until the process is stopped, or until
+// the queue is empty with until_empty. A message is deleted once its objects are processed, so
+// messages of objects that fail are received again after their visibility timeout
+pub fn watch_sqs(
+    queue_url: &str,
+    until_empty: bool,
+    options: &WatchOptions<'_>,
+    sink: &mut dyn Sink,
+    keys: &Keys<'_>,
+) -> Result<(), String> {
+    tracing::info!(queue_url, "Receiving event notifications");
+    loop {
+        let output = aws(&[
+            "sqs",
+            "receive-message",
+            "--queue-url",
+            queue_url,
+            "--max-number-of-messages",
+            SQS_MAX_MESSAGES,
+            "--wait-time-seconds",
+            SQS_WAIT_SECONDS,
+            "--output",
+            "json",
+        ])?;
+        if !output.status.success() {
+            tracing::warn!(
+                error = %String::from_utf8_lossy(&output.stderr).trim(),
+                "Failed to receive messages"
+            );
+            thread::sleep(RETRY_DELAY);
+            continue;
+        }
+
+        // Nothing is printed when no message arrived
+        let received: ReceivedMessages = match output.stdout.trim_ascii() {
+            [] => ReceivedMessages {
+                messages: Vec::new(),
+            },
+            stdout => serde_json::from_slice(stdout)
+                .map_err(|_| "Failed to parse the messages received from SQS")?,
+        };
+        if received.messages.is_empty() && until_empty {
+            return Ok(());
+        }
+
+        for message in received.messages {
+            match serde_json::from_str(&message.body) {
+                Ok(notification) => {
+                    if let Err(e) = process_notification(&notification, options, sink, keys) {
+                        tracing::warn!(error = %e, "Failed to process notification, leaving it to be received again");
+                        continue;
+                    }
+                }
+                // Receiving it again wouldn't help
+                Err(_) => tracing::warn!("Deleting message that is not a JSON notification"),
+            }
+
+            let output = aws(&[
+                "sqs",
+                "delete-message",
+                "--queue-url",
+                queue_url,
+                "--receipt-handle",
+                &message.receipt_handle,
+            ])?;
+            if !output.status.success() {
+                tracing::warn!(
+                    error = %String::from_utf8_lossy(&output.stderr).trim(),
+                    "Failed to delete message"
+                );
+            }
+        }
+    }
+}
+
+// Receives S3 or R2 event notifications POSTed to an address until the process is stopped, one
+// at a time, answering once their objects are processed, or with a 500 status if one failed so
+// the sender retries. Requires an auth token unless listening on a loopback address
+pub fn watch_webhook(
+    address: &str,
+    allow_remote: bool,
+    auth_token: Option<&str>,
+    options: &WatchOptions<'_>,
+    sink: &mut dyn Sink,
+    keys: &Keys<'_>,
+) -> Result<(), String> {
+    if !serve::is_loopback(address)? && !allow_remote && auth_token.is_none() {
+        return Err(format!(
+            "Refusing to listen on {}, which is reachable from other hosts, pass --auth-token-file, or --allow-remote to allow it",
+            address
+        ));
+    }
+
+    let server =
+        Server::http(address).map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+    tracing::info!(%address, "Listening for event notifications");
+    for mut request in server.incoming_requests() {
+        let status = answer(&mut request, auth_token, options, sink, keys);
+        if status != 200 {
+            tracing::warn!(status, "Notification request failed");
+        }
+        let _ = request.respond(Response::empty(StatusCode(status)));
+    }
+
+    Ok(())
+}
+
+// Processes a notification request, returning the status to answer it with
+fn answer(
+    request: &mut Request,
+    auth_token: Option<&str>,
+    options: &WatchOptions<'_>,
+    sink: &mut dyn Sink,
+    keys: &Keys<'_>,
+) -> u16 {
+    let authorization = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .map(|header| header.value.as_str());
+    if !serve::is_authorized(authorization, auth_token) {
+        return 401;
+    }
+    if *request.method() != Method::Post {
+        return 405;
+    }
+
+    let mut body = Vec::new();
+    match request
+        .as_reader()
+        .take(MAX_NOTIFICATION_SIZE + 1)
+        .read_to_end(&mut body)
+    {
+        Ok(length) if length as u64 > MAX_NOTIFICATION_SIZE => return 413,
+        Ok(_) => {}
+        Err(_) => return 400,
+    }
+    let notification: Value = match serde_json::from_slice(&body) {
+        Ok(notification) => notification,
+        Err(_) => return 400,
+    };
+
+    // SNS HTTP subscriptions are confirmed by opening a URL, left to the operator so the server
+    // never fetches URLs it is sent
+    if notification["Type"] == "SubscriptionConfirmation" {
+        tracing::warn!(
+            subscribe_url = notification["SubscribeURL"].as_str().unwrap_or_default(),
+            "Open the SubscribeURL to confirm the SNS subscription"
+        );
+        return 200;
+    }
+
+    match process_notification(&notification, options, sink, keys) {
+        Ok(()) => 200,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to process notification");
+            500
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_urls() {
+        let s3 = serde_json::json!({
+            "Records": [
+                {
+                    "eventName": "ObjectCreated:Put",
+                    "s3": {
+                        "bucket": {"name": "logs"},
+                        "object": {"key": "20220101/firewall+events%3D1.log.gz"}
+                    }
+                },
+                {
+                    "eventName": "ObjectRemoved:Delete",
+                    "s3": {"bucket": {"name": "logs"}, "object": {"key": "old.log.gz"}}
+                }
+            ]
+        });
+        assert_eq!(
+            vec!["s3://logs/20220101/firewall events=1.log.gz"],
+            object_urls(&s3)
+        );
+
+        let sns = serde_json::json!({"Type": "Notification", "Message": s3.to_string()});
+        assert_eq!(object_urls(&s3), object_urls(&sns));
+
+        let r2 = serde_json::json!([
+            {"account": "abc", "bucket": "logs", "object": {"key": "a b.log.gz"}, "action": "PutObject"},
+            {"account": "abc", "bucket": "logs", "object": {"key": "b.log.gz"}, "action": "DeleteObject"}
+        ]);
+        assert_eq!(vec!["r2://logs/a b.log.gz"], object_urls(&r2));
+
+        assert!(object_urls(&serde_json::json!({"Event": "s3:TestEvent"})).is_empty());
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        assert!(is_allowed("s3://logs/a.log.gz", &[]));
+        assert!(is_allowed("r2://logs/a.log.gz", &["logs".to_string()]));
+        assert!(!is_allowed("s3://other/a.log.gz", &["logs".to_string()]));
+    }
+}
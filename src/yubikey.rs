@@ -0,0 +1,177 @@
+use crate::key_format;
+use rand::RngCore;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::{env, fs};
+use zeroize::Zeroizing;
+
+// PKCS#11 module of the YubiKey PIV applet installed with yubico-piv-tool, overridden by YKCS11_MODULE
+#[cfg(target_os = "macos")]
+const DEFAULT_PKCS11_MODULE: &str = "libykcs11.dylib";
+#[cfg(windows)]
+const DEFAULT_PKCS11_MODULE: &str = "libykcs11.dll";
+#[cfg(not(any(target_os = "macos", windows)))]
+const DEFAULT_PKCS11_MODULE: &str = "libykcs11.so";
+
+// Environment variable passing the PIN to pkcs11-tool, so it doesn't appear in its arguments
+const PIN_VARIABLE: &str = "MATCHED_DATA_YUBIKEY_PIN";
+
+// PIV slot holding an X25519 key, such as 9d for key management
+pub struct Slot {
+    name: String,
+    // Object id of the slot in the YubiKey PKCS#11 module
+    pkcs11_id: u8,
+}
+
+impl Slot {
+    pub fn parse(slot: &str) -> Result<Self, String> {
+        let name = slot.to_lowercase();
+        let pkcs11_id = match u8::from_str_radix(&name, 16) {
+            Ok(0x9a) => 1,
+            Ok(0x9c) => 2,
+            Ok(0x9d) => 3,
+            Ok(0x9e) => 4,
+            // Retired key management slots
+            Ok(retired @ 0x82..=0x95) => retired - 0x82 + 5,
+            _ => {
+                return Err(format!(
+                    "Unknown PIV slot {}, expected 9a, 9c, 9d, 9e or 82 to 95",
+                    slot
+                ))
+            }
+        };
+
+        Ok(Slot { name, pkcs11_id })
+    }
+}
+
+// Generates an X25519 key pair in a slot, requiring a touch for each use, and returns its public key
+pub fn generate(slot: &Slot) -> Result<Vec<u8>, String> {
+    let output = Command::new("yubico-piv-tool")
+        .args([
+            "--action=generate",
+            &format!("--slot={}", slot.name),
+            "--algorithm=X25519",
+            "--pin-policy=once",
+            "--touch-policy=always",
+        ])
+        .output()
+        .map_err(|_| "Failed to run yubico-piv-tool, check that it is installed")?;
+    check_status(&output, "Failed to generate a key on the YubiKey")?;
+
+    key_format::from_pem_public_key(&String::from_utf8_lossy(&output.stdout))
+}
+
+// Computes the X25519 shared secret of the key in a slot and a peer public key on the YubiKey
+pub fn diffie_hellman(
+    slot: &Slot,
+    pin: &str,
+    peer_public_key_bytes: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, String> {
+    let directory = create_private_directory()?;
+    let result = diffie_hellman_in(&directory, slot, pin, peer_public_key_bytes);
+    fs::remove_dir_all(&directory).map_err(|_| "Failed to remove temporary directory")?;
+
+    result
+}
+
+// Runs pkcs11-tool with its input and output files in a directory only the current user can read
+fn diffie_hellman_in(
+    directory: &Path,
+    slot: &Slot,
+    pin: &str,
+    peer_public_key_bytes: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, String> {
+    let peer_filename = directory.join("peer.der");
+    let shared_secret_filename = directory.join("shared-secret");
+    fs::write(
+        &peer_filename,
+        key_format::to_der_public_key(peer_public_key_bytes),
+    )
+    .map_err(|_| "Failed to write encapsulated key to temporary file")?;
+
+    let module = env::var("YKCS11_MODULE").unwrap_or_else(|_| DEFAULT_PKCS11_MODULE.to_string());
+    tracing::info!("Touch the YubiKey to decrypt");
+    let output = Command::new("pkcs11-tool")
+        .arg("--module")
+        .arg(module)
+        .args(["--login", "--pin"])
+        .arg(format!("env:{}", PIN_VARIABLE))
+        .args(["--derive", "--mechanism", "ECDH1-DERIVE", "--id"])
+        .arg(format!("{:02x}", slot.pkcs11_id))
+        .arg("--input-file")
+        .arg(&peer_filename)
+        .arg("--output-file")
+        .arg(&shared_secret_filename)
+        .env(PIN_VARIABLE, pin)
+        .output()
+        .map_err(|_| "Failed to run pkcs11-tool, check that OpenSC is installed")?;
+    check_status(&output, "Failed to derive the shared secret on the YubiKey")?;
+
+    let shared_secret = Zeroizing::new(
+        fs::read(&shared_secret_filename)
+            .map_err(|_| "Failed to read shared secret from temporary file")?,
+    );
+    // Overwrite the shared secret before the file is removed
+    fs::write(&shared_secret_filename, vec![0; shared_secret.len()])
+        .map_err(|_| "Failed to wipe temporary file")?;
+
+    // An all-zero shared secret means the encapsulated key is a low order point
+    if shared_secret.len() != 32 || shared_secret.iter().all(|&byte| byte == 0) {
+        return Err(
+            "The YubiKey derived an invalid shared secret from the encapsulated key".to_string(),
+        );
+    }
+
+    Ok(shared_secret)
+}
+
+// Creates a uniquely named temporary directory, readable only by the current user on Unix
+fn create_private_directory() -> Result<PathBuf, String> {
+    let mut suffix = [0; 8];
+    rand::thread_rng().fill_bytes(&mut suffix);
+    let directory = env::temp_dir().join(format!(
+        "matched-data-cli-{}",
+        suffix
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+    ));
+
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(&directory)
+        .map_err(|_| "Failed to create temporary directory")?;
+
+    Ok(directory)
+}
+
+// Turns the failure of a tool into an error including what it printed
+fn check_status(output: &Output, message: &str) -> Result<(), String> {
+    if output.status.success() {
+        return Ok(());
+    }
+
+    Err(format!(
+        "{}: {}",
+        message,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_slot() {
+        assert_eq!(3, Slot::parse("9d").unwrap().pkcs11_id);
+        assert_eq!(1, Slot::parse("9A").unwrap().pkcs11_id);
+        assert_eq!(5, Slot::parse("82").unwrap().pkcs11_id);
+        assert_eq!(24, Slot::parse("95").unwrap().pkcs11_id);
+        assert!(Slot::parse("96").is_err());
+        assert!(Slot::parse("f9").is_err());
+    }
+}